@@ -0,0 +1,11 @@
+// A failed assert exits with 100 + the index of the value in its function (saturating at 255),
+// so the exit code alone says which value of a single-function test was wrong.
+
+#include <stdlib.h>
+
+static void assert_failed(const char* side, uint32_t func_idx, uint32_t val_idx, const char* path) {
+    fprintf(stderr, "abi-cafe assert failed: %s func %" PRIu32 " val %" PRIu32 " (%s)\n", side, func_idx, val_idx, path);
+    exit(100 + (val_idx < 155 ? val_idx : 155));
+}
+#define check_val(side, func_idx, val_idx, path, val, expected) \
+    if (memcmp(&(val), &(expected), sizeof(expected)) != 0) { assert_failed(side, func_idx, val_idx, path); }
@@ -8,3 +8,10 @@ extern void (*SET_FUNC)(WriteBuffer, uint32_t);
 
 #define set_func(vals, func_idx) SET_FUNC(vals, func_idx);
 #define write_val(vals, val_idx, val) WRITE_VAL(vals, val_idx, (char*)&val, (uint32_t)sizeof(val))
+#define write_enum_val(vals, val_idx, tag, val) do { \
+    char _buf[sizeof(uint32_t) + sizeof(val)]; \
+    uint32_t _tag = (tag); \
+    memcpy(_buf, &_tag, sizeof(_tag)); \
+    memcpy(_buf + sizeof(_tag), &(val), sizeof(val)); \
+    WRITE_VAL(vals, val_idx, _buf, (uint32_t)sizeof(_buf)); \
+} while (0)
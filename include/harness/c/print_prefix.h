@@ -0,0 +1,21 @@
+// Values are printed as themselves (for humans) followed by their raw bytes
+// (for abi-cafe, which parses this output back to compare what the caller and callee saw).
+
+static void set_func(const char* side, uint32_t func_idx, const char* func_name) {
+    printf("%s func %" PRIu32 " (%s)\n", side, func_idx, func_name);
+    fflush(stdout);
+}
+static void print_bytes(const void* val, size_t size) {
+    const unsigned char* bytes = (const unsigned char*)val;
+    printf("[");
+    for (size_t i = 0; i < size; i++) {
+        printf(i == 0 ? "%02x" : " %02x", bytes[i]);
+    }
+    printf("]\n");
+    fflush(stdout);
+}
+// `fmt` and `typed` are how to printf the value, `val` is the lvalue to dump the bytes of
+#define print_val(side, val_idx, path, fmt, typed, val) do { \
+    printf("%s val %" PRIu32 " (%s) = " fmt " ", side, (uint32_t)(val_idx), path, typed); \
+    print_bytes(&(val), sizeof(val)); \
+} while (0)
@@ -4,7 +4,8 @@
 //! the caller and callee. The caller is expected to define the function `do_test`,
 //! and call a bunch of functions defined by the callee. The cdylib
 //! is run by the harness `dlopen`ing it and running `test_start`, passing in various
-//! buffers and callbacks for instrumenting the result of the execution.
+//! buffers and callbacks for instrumenting the result of the execution. `test_start`
+//! returns whether an unwind escaped `do_test`.
 //!
 //! This instrumentation is only used in the default mode of `WriteImpl::HarnessCallback`.
 //! Otherwise the caller/callee may use things like asserts/prints.
@@ -17,6 +18,7 @@ unsafe impl Sync for WriteBuffer {}
 
 type SetFuncCallback = unsafe extern fn(WriteBuffer, u32) -> ();
 type WriteValCallback = unsafe extern fn(WriteBuffer, u32, *const u8, u32) -> ();
+type MarkUnwindCallback = unsafe extern fn(WriteBuffer) -> ();
 
 #[no_mangle]
 pub static mut CALLER_VALS: WriteBuffer = WriteBuffer(core::ptr::null_mut());
@@ -26,8 +28,10 @@ pub static mut CALLEE_VALS: WriteBuffer = WriteBuffer(core::ptr::null_mut());
 pub static mut SET_FUNC: Option<SetFuncCallback> = None;
 #[no_mangle]
 pub static mut WRITE_VAL: Option<WriteValCallback> = None;
+#[no_mangle]
+pub static mut MARK_UNWIND: Option<MarkUnwindCallback> = None;
 
-extern {
+extern "C-unwind" {
     fn do_test();
 }
 
@@ -35,15 +39,18 @@ extern {
 pub extern fn test_start(
     set_func_callback: SetFuncCallback,
     write_val_callback: WriteValCallback,
+    mark_unwind_callback: MarkUnwindCallback,
     caller_vals: WriteBuffer,
     callee_vals: WriteBuffer,
-) {
+) -> bool {
     unsafe {
         CALLER_VALS = caller_vals;
         CALLEE_VALS = callee_vals;
         SET_FUNC = Some(set_func_callback);
         WRITE_VAL = Some(write_val_callback);
+        MARK_UNWIND = Some(mark_unwind_callback);
 
-        do_test();
+        // Report whether an unwind escaped the caller
+        std::panic::catch_unwind(|| do_test()).is_err()
     }
-}
\ No newline at end of file
+}
\ No newline at end of file
@@ -1,75 +1,136 @@
-//! This is the primary file for the abi-cafe harness main that all tests are compiled into.
-//!
-//! This will be statically linked into a cdylib with two other static libraries:
-//! the caller and callee. The caller is expected to define the function `do_test`,
-//! and call a bunch of functions defined by the callee. The cdylib
-//! is run by the harness `dlopen`ing it and running `test_start`, passing in various
-//! buffers and callbacks for instrumenting the result of the execution.
-//!
-//! This instrumentation is only used in the default mode of `WriteImpl::HarnessCallback`.
-//! Otherwise the caller/callee may use things like asserts/prints.
-
-/// Tests write back the raw bytes of their values to a WriteBuffer.
-pub struct WriteBuffer {
-    pub identity: &'static str,
-}
-
-impl WriteBuffer {
-    fn new(identity: &'static str) -> Self {
-        // Preload the hierarchy for the first test.
-        WriteBuffer {
-            identity,
-        }
-    }
-}
-
-// The signatures of the interface from our perspective.
-// From the test's perspective the WriteBuffers are totally opaque.
-pub type SetFuncCallback = unsafe extern "C" fn(&mut WriteBuffer, u32) -> ();
-pub type WriteValCallback = unsafe extern "C" fn(&mut WriteBuffer, u32, *const u8, u32) -> ();
-pub type TestInit =
-    unsafe extern "C" fn(SetFuncCallback, WriteValCallback, &mut WriteBuffer, &mut WriteBuffer) -> ();
-
-pub unsafe extern "C" fn set_func(test: &mut WriteBuffer, func: u32) {
-    let ident = &test.identity;
-    println!(r#"{{ "info": "func", "id": "{ident}", "func": {func} }}"#);
-}
-
-pub unsafe extern "C" fn write_val(
-    test: &mut WriteBuffer,
-    val_idx: u32,
-    input: *const u8,
-    size: u32,
-) {
-    let data = std::slice::from_raw_parts(input, size as usize);
-    let ident = &test.identity;
-    println!(r#"{{ "info": "val", "id": "{ident}", "val": {val_idx}, "bytes": {data:?} }}"#);
-}
-
-
-#[no_mangle]
-pub static mut CALLER_VALS: *mut () = core::ptr::null_mut();
-#[no_mangle]
-pub static mut CALLEE_VALS: *mut () = core::ptr::null_mut();
-#[no_mangle]
-pub static mut SET_FUNC: Option<SetFuncCallback> = None;
-#[no_mangle]
-pub static mut WRITE_VAL: Option<WriteValCallback> = None;
-
-extern {
-    fn do_test();
-}
-
-pub fn main() {
-    unsafe {
-        let mut caller_vals = WriteBuffer::new("caller");
-        let mut callee_vals = WriteBuffer::new("callee");
-        CALLER_VALS = &mut caller_vals as *mut _ as *mut _;
-        CALLEE_VALS = &mut callee_vals as *mut _ as *mut _;
-        SET_FUNC = Some(set_func);
-        WRITE_VAL = Some(write_val);
-
-        do_test();
-        println!(r#"{{ "info": "done" }}"#);
-    }
-}
\ No newline at end of file
+//! This is the primary file for the abi-cafe harness main that all tests are compiled into.
+//!
+//! This will be statically linked into a cdylib with two other static libraries:
+//! the caller and callee. The caller is expected to define the function `do_test`,
+//! and call a bunch of functions defined by the callee. The cdylib
+//! is run by the harness `dlopen`ing it and running `test_start`, passing in various
+//! buffers and callbacks for instrumenting the result of the execution.
+//!
+//! This instrumentation is only used in the default mode of `WriteImpl::HarnessCallback`.
+//! Otherwise the caller/callee may use things like asserts/prints.
+
+use std::fs::File;
+use std::io::Write;
+use std::sync::OnceLock;
+
+/// The channel we report our execution to (see HarnessMessage in abi-cafe's run.rs for the format).
+///
+/// This is kept separate from stdout so that the test can print whatever it wants.
+static HARNESS_CHANNEL: OnceLock<File> = OnceLock::new();
+
+const MSG_FUNC: u8 = 0;
+const MSG_VAL: u8 = 1;
+const MSG_UNWIND: u8 = 2;
+const MSG_ESCAPED: u8 = 3;
+const MSG_DONE: u8 = 4;
+
+/// Tests write back the raw bytes of their values to a WriteBuffer.
+pub struct WriteBuffer {
+    /// 0 for the caller, 1 for the callee
+    pub side: u8,
+}
+
+impl WriteBuffer {
+    fn new(side: u8) -> Self {
+        WriteBuffer { side }
+    }
+}
+
+/// Send one complete message to the harness (in a single write, so that
+/// crashing can't leave a message half-written).
+fn report(message: &[u8]) {
+    let mut channel = HARNESS_CHANNEL.get().expect("harness channel wasn't opened");
+    channel.write_all(message).expect("failed to write to harness channel");
+}
+
+// The signatures of the interface from our perspective.
+// From the test's perspective the WriteBuffers are totally opaque.
+pub type SetFuncCallback = unsafe extern "C" fn(&mut WriteBuffer, u32) -> ();
+pub type WriteValCallback = unsafe extern "C" fn(&mut WriteBuffer, u32, *const u8, u32) -> ();
+pub type MarkUnwindCallback = unsafe extern "C" fn(&mut WriteBuffer) -> ();
+/// Returns whether an unwind escaped the caller
+pub type TestInit = unsafe extern "C" fn(
+    SetFuncCallback,
+    WriteValCallback,
+    MarkUnwindCallback,
+    &mut WriteBuffer,
+    &mut WriteBuffer,
+) -> bool;
+
+pub unsafe extern "C" fn set_func(test: &mut WriteBuffer, func: u32) {
+    let mut message = vec![MSG_FUNC, test.side];
+    message.extend_from_slice(&func.to_le_bytes());
+    report(&message);
+}
+
+pub unsafe extern "C" fn write_val(
+    test: &mut WriteBuffer,
+    val_idx: u32,
+    input: *const u8,
+    size: u32,
+) {
+    let data = std::slice::from_raw_parts(input, size as usize);
+    let mut message = vec![MSG_VAL, test.side];
+    message.extend_from_slice(&val_idx.to_le_bytes());
+    message.extend_from_slice(&size.to_le_bytes());
+    message.extend_from_slice(data);
+    report(&message);
+}
+
+pub unsafe extern "C" fn mark_unwind(test: &mut WriteBuffer) {
+    report(&[MSG_UNWIND, test.side]);
+}
+
+
+#[no_mangle]
+pub static mut CALLER_VALS: *mut () = core::ptr::null_mut();
+#[no_mangle]
+pub static mut CALLEE_VALS: *mut () = core::ptr::null_mut();
+#[no_mangle]
+pub static mut SET_FUNC: Option<SetFuncCallback> = None;
+#[no_mangle]
+pub static mut WRITE_VAL: Option<WriteValCallback> = None;
+#[no_mangle]
+pub static mut MARK_UNWIND: Option<MarkUnwindCallback> = None;
+
+extern "C-unwind" {
+    fn do_test();
+}
+
+/// On unix the channel is a pipe we inherited the fd of
+#[cfg(unix)]
+fn open_channel(channel: &str) -> File {
+    use std::os::fd::FromRawFd;
+    let fd = channel.parse().expect("harness channel isn't an fd");
+    unsafe { File::from_raw_fd(fd) }
+}
+
+/// Elsewhere the channel is a file for us to make
+#[cfg(not(unix))]
+fn open_channel(channel: &str) -> File {
+    File::create(channel).expect("failed to create harness channel")
+}
+
+pub fn main() {
+    let channel = std::env::var("ABI_CAFE_HARNESS_CHANNEL")
+        .expect("ABI_CAFE_HARNESS_CHANNEL wasn't set (this program must be run by abi-cafe)");
+    HARNESS_CHANNEL.set(open_channel(&channel)).unwrap();
+
+    unsafe {
+        let mut caller_vals = WriteBuffer::new(0);
+        let mut callee_vals = WriteBuffer::new(1);
+        CALLER_VALS = &mut caller_vals as *mut _ as *mut _;
+        CALLEE_VALS = &mut callee_vals as *mut _ as *mut _;
+        SET_FUNC = Some(set_func);
+        WRITE_VAL = Some(write_val);
+        MARK_UNWIND = Some(mark_unwind);
+
+        // If the caller fails to catch an unwind, let the harness know
+        // (rather than just aborting).
+        if std::panic::catch_unwind(|| do_test()).is_err() {
+            report(&[MSG_ESCAPED]);
+            return;
+        }
+        report(&[MSG_DONE]);
+    }
+}
@@ -9,10 +9,12 @@
 //! In theory this could be replaced with just making `caller::do_test` into `main`
 //! but this might be a bit easier..?
 
-extern {
+extern "C-unwind" {
     fn do_test();
 }
 
 fn main() {
-    do_test();
+    unsafe {
+        do_test();
+    }
 }
\ No newline at end of file
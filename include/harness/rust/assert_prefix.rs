@@ -0,0 +1,15 @@
+// A failed assert exits with 100 + the index of the value in its function (saturating at 255),
+// so the exit code alone says which value of a single-function test was wrong.
+
+fn val_bytes<T>(val: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(val as *const T as *const u8, core::mem::size_of_val(val)) }
+}
+fn assert_failed(side: &str, func_idx: u32, val_idx: u32, path: &str) -> ! {
+    eprintln!("abi-cafe assert failed: {side} func {func_idx} val {val_idx} ({path})");
+    std::process::exit(100 + val_idx.min(155) as i32);
+}
+fn check_val<T>(side: &str, func_idx: u32, val_idx: u32, path: &str, val: &T, expected: &T) {
+    if val_bytes(val) != val_bytes(expected) {
+        assert_failed(side, func_idx, val_idx, path);
+    }
+}
@@ -6,12 +6,14 @@ unsafe impl Sync for WriteBuffer {}
 
 type SetFuncCallback = unsafe extern fn(WriteBuffer, u32) -> ();
 type WriteValCallback = unsafe extern fn(WriteBuffer, u32, *const u8, u32) -> ();
+type MarkUnwindCallback = unsafe extern fn(WriteBuffer) -> ();
 
 extern {
     pub static mut CALLER_VALS: WriteBuffer;
     pub static mut CALLEE_VALS: WriteBuffer;
     pub static mut SET_FUNC: Option<SetFuncCallback>;
     pub static mut WRITE_VAL: Option<WriteValCallback>;
+    pub static mut MARK_UNWIND: Option<MarkUnwindCallback>;
 }
 
 unsafe fn write_val<T>(vals: WriteBuffer, val_idx: u32, val: &T) {
@@ -22,6 +24,15 @@ unsafe fn write_val<T>(vals: WriteBuffer, val_idx: u32, val: &T) {
         core::mem::size_of_val(val) as u32
     );
 }
+unsafe fn write_enum_val<T>(vals: WriteBuffer, val_idx: u32, tag: u32, val: &T) {
+    let raw = core::slice::from_raw_parts(val as *const T as *const u8, core::mem::size_of_val(val));
+    let mut bytes = tag.to_ne_bytes().to_vec();
+    bytes.extend_from_slice(raw);
+    WRITE_VAL.unwrap()(vals, val_idx, bytes.as_ptr(), bytes.len() as u32);
+}
 unsafe fn set_func(vals: WriteBuffer, func_idx: u32) {
     SET_FUNC.unwrap()(vals, func_idx);
 }
+unsafe fn mark_unwind(vals: WriteBuffer) {
+    MARK_UNWIND.unwrap()(vals);
+}
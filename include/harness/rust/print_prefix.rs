@@ -0,0 +1,16 @@
+// Values are printed as themselves (for humans) followed by their raw bytes
+// (for abi-cafe, which parses this output back to compare what the caller and callee saw).
+
+fn val_bytes<T>(val: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(val as *const T as *const u8, core::mem::size_of_val(val)) }
+}
+fn set_func(side: &str, func_idx: u32, func_name: &str) {
+    println!("{side} func {func_idx} ({func_name})");
+}
+fn print_val<T: core::fmt::Debug>(side: &str, val_idx: u32, path: &str, val: &T) {
+    let bytes = val_bytes(val).iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" ");
+    println!("{side} val {val_idx} ({path}) = {val:?} [{bytes}]");
+}
+fn mark_unwind(side: &str) {
+    println!("{side} unwound");
+}
@@ -0,0 +1,22 @@
+/// `&'a [T]` isn't FFI-safe, so pass it as the (pointer, length) pair C expects
+#[repr(C)]
+pub struct Slice<'a, T> {
+    ptr: *const T,
+    len: usize,
+    _marker: core::marker::PhantomData<&'a [T]>,
+}
+impl<'a, T> Slice<'a, T> {
+    #[allow(dead_code)]
+    fn new(slice: &'a [T]) -> Self {
+        Slice { ptr: slice.as_ptr(), len: slice.len(), _marker: core::marker::PhantomData }
+    }
+    #[allow(dead_code)]
+    fn as_slice(&self) -> &'a [T] {
+        // C passes NULL for empty slices
+        if self.len == 0 {
+            &[]
+        } else {
+            unsafe { core::slice::from_raw_parts(self.ptr, self.len) }
+        }
+    }
+}
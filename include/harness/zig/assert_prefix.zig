@@ -0,0 +1,12 @@
+// A failed assert exits with 100 + the index of the value in its function (saturating at 255),
+// so the exit code alone says which value of a single-function test was wrong.
+
+fn assert_failed(side: []const u8, func_idx: u32, val_idx: u32, path: []const u8) noreturn {
+    std.debug.print("abi-cafe assert failed: {s} func {} val {} ({s})\n", .{ side, func_idx, val_idx, path });
+    std.process.exit(@intCast(100 + @min(val_idx, 155)));
+}
+fn check_val(side: []const u8, func_idx: u32, val_idx: u32, path: []const u8, val: anytype, expected: @TypeOf(val)) void {
+    if (!std.mem.eql(u8, std.mem.asBytes(&val), std.mem.asBytes(&expected))) {
+        assert_failed(side, func_idx, val_idx, path);
+    }
+}
@@ -0,0 +1,22 @@
+
+const WriteBuffer = ?*anyopaque;
+
+extern var CALLER_VALS: WriteBuffer;
+extern var CALLEE_VALS: WriteBuffer;
+extern var WRITE_VAL: ?*const fn (WriteBuffer, u32, [*]const u8, u32) callconv(.c) void;
+extern var SET_FUNC: ?*const fn (WriteBuffer, u32) callconv(.c) void;
+
+fn set_func(vals: WriteBuffer, func_idx: u32) void {
+    SET_FUNC.?(vals, func_idx);
+}
+fn write_val(vals: WriteBuffer, val_idx: u32, val: anytype) void {
+    const bytes = std.mem.asBytes(&val);
+    WRITE_VAL.?(vals, val_idx, bytes, @intCast(bytes.len));
+}
+fn write_enum_val(vals: WriteBuffer, val_idx: u32, tag: u32, val: anytype) void {
+    const raw = std.mem.asBytes(&val);
+    var bytes: [@sizeOf(u32) + raw.len]u8 = undefined;
+    @memcpy(bytes[0..@sizeOf(u32)], std.mem.asBytes(&tag));
+    @memcpy(bytes[@sizeOf(u32)..], raw);
+    WRITE_VAL.?(vals, val_idx, &bytes, @intCast(bytes.len));
+}
@@ -0,0 +1,23 @@
+// Values are printed as themselves (for humans) followed by their raw bytes
+// (for abi-cafe, which parses this output back to compare what the caller and callee saw).
+// This goes through libc's stdout so it interleaves properly with the other side of the test.
+
+extern fn printf(format: [*:0]const u8, ...) c_int;
+extern fn fflush(stream: ?*anyopaque) c_int;
+
+fn set_func(side: [*:0]const u8, func_idx: u32, func_name: [*:0]const u8) void {
+    _ = printf("%s func %u (%s)\n", side, @as(c_uint, func_idx), func_name);
+    _ = fflush(null);
+}
+fn print_val(side: [*:0]const u8, val_idx: u32, path: [*:0]const u8, val: anytype) void {
+    const bytes = std.mem.asBytes(&val);
+    var buf: [128]u8 = undefined;
+    const typed: [*:0]const u8 = if (std.fmt.bufPrintZ(&buf, "{any}", .{val})) |text| text.ptr else |_| "?";
+    _ = printf("%s val %u (%s) = %s [", side, @as(c_uint, val_idx), path, typed);
+    for (bytes, 0..) |byte, i| {
+        const sep: [*:0]const u8 = if (i == 0) "" else " ";
+        _ = printf("%s%02x", sep, @as(c_uint, byte));
+    }
+    _ = printf("]\n");
+    _ = fflush(null);
+}
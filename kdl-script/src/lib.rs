@@ -1,11 +1,18 @@
 #![doc = include_str!("../README.md")]
 
-use std::{fs::File, io::Read, path::Path, sync::Arc};
+use std::{
+    fs::File,
+    io::Read,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
 
 use kdl::KdlDocument;
 use miette::{Diagnostic, NamedSource};
 use thiserror::Error;
 
+use spanned::Spanned;
+
 pub use parse::{KdlScriptParseError, ParsedProgram, PunEnv};
 pub use types::{Definition, DefinitionGraph, KdlScriptTypeError, TypedProgram};
 
@@ -50,11 +57,18 @@ pub enum ErrorStyle {
     Json,
 }
 
+/// Gets the source of an `import`ed file from its (resolved) path.
+pub type ImportLoader = dyn Fn(&str) -> std::io::Result<String> + Send + Sync;
+
 pub struct Compiler {
     // error_handler: ErrorHandler,
     pub source: Option<Arc<NamedSource>>,
     pub parsed: Option<Arc<ParsedProgram>>,
     pub typed: Option<Arc<TypedProgram>>,
+    /// How `import "path.kdl"` gets the source of `path.kdl` (defaults to reading the filesystem)
+    pub import_loader: Arc<ImportLoader>,
+    /// The files that are in the middle of importing the one being compiled (to catch cycles)
+    import_stack: Vec<String>,
 }
 
 pub type Result<T> = std::result::Result<T, KdlScriptError>;
@@ -71,9 +85,20 @@ impl Compiler {
             source: None,
             parsed: None,
             typed: None,
+            import_loader: Arc::new(|path| std::fs::read_to_string(path)),
+            import_stack: vec![],
         }
     }
 
+    /// Use something other than the filesystem to get the source of `import`ed files.
+    pub fn with_import_loader(
+        mut self,
+        loader: impl Fn(&str) -> std::io::Result<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.import_loader = Arc::new(loader);
+        self
+    }
+
     pub fn compile_path(
         &mut self,
         src_path: impl AsRef<Path>,
@@ -106,6 +131,48 @@ impl Compiler {
         Ok(typed)
     }
 
+    /// Compile a file that `importer` imported, as a program of its own.
+    ///
+    /// Returns the source of the import (named after its resolved path, since relative
+    /// imports are relative to the importer) and the program, which has already been
+    /// typechecked (so errors in it point into its own source).
+    fn compile_import(
+        &self,
+        importer: &str,
+        path: &Spanned<String>,
+    ) -> std::result::Result<(Arc<NamedSource>, Arc<ParsedProgram>), KdlScriptError> {
+        let resolved = resolve_import(importer, path);
+        let import_error = |message: String| KdlScriptParseError {
+            message,
+            src: self.source.clone().unwrap(),
+            span: Spanned::span(path),
+            help: None,
+        };
+        if resolved == importer || self.import_stack.contains(&resolved) {
+            let mut cycle = self.import_stack.clone();
+            cycle.push(importer.to_owned());
+            cycle.push(resolved.clone());
+            Err(import_error(format!(
+                "import cycle: {}",
+                cycle.join(" -> ")
+            )))?;
+        }
+        let input = (self.import_loader)(&resolved)
+            .map_err(|e| import_error(format!("couldn't import {resolved}: {e}")))?;
+
+        let mut import_stack = self.import_stack.clone();
+        import_stack.push(importer.to_owned());
+        let mut compiler = Compiler {
+            source: None,
+            parsed: None,
+            typed: None,
+            import_loader: self.import_loader.clone(),
+            import_stack,
+        };
+        compiler.compile_string(&resolved, input)?;
+        Ok((compiler.source.unwrap(), compiler.parsed.unwrap()))
+    }
+
     pub fn eval(&mut self) -> std::result::Result<Option<i64>, KdlScriptError> {
         if let (Some(src), Some(parsed)) = (&self.source, &self.parsed) {
             if parsed.funcs.contains_key("main") {
@@ -117,6 +184,28 @@ impl Compiler {
     }
 }
 
+/// Resolve an import path relative to the file that imported it.
+///
+/// `..` and `.` are resolved lexically so that the same file always gets the same name.
+fn resolve_import(importer: &str, path: &str) -> String {
+    let base = Path::new(importer).parent().unwrap_or(Path::new(""));
+    let mut resolved = PathBuf::new();
+    for component in base.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if let Some(Component::Normal(_)) = resolved.components().next_back() {
+                    resolved.pop();
+                } else {
+                    resolved.push(component);
+                }
+            }
+            _ => resolved.push(component),
+        }
+    }
+    resolved.display().to_string()
+}
+
 impl Default for Compiler {
     fn default() -> Self {
         Self::new()
@@ -6,6 +6,7 @@
 //!
 //! Things like name resolution are handled by the [type checker](`crate::types`).
 
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use kdl::{KdlDocument, KdlEntry, KdlNode};
@@ -16,7 +17,7 @@ use nom::character::complete::{alpha1, alphanumeric1};
 use nom::combinator::{all_consuming, cut, opt, recognize};
 use nom::error::{context, VerboseError};
 use nom::multi::{many0, many0_count, separated_list1};
-use nom::sequence::{delimited, pair, preceded, separated_pair};
+use nom::sequence::{delimited, pair, preceded, separated_pair, terminated};
 use nom::{Finish, IResult};
 use thiserror::Error;
 use tracing::trace;
@@ -131,6 +132,49 @@ pub struct ParsedProgram {
     pub funcs: StableMap<Ident, FuncDecl>,
     /// Where in funcs builtins like `+` start (if at all).
     pub builtin_funcs_start: usize,
+    /// Metadata about the program as a whole
+    pub meta: ProgramMeta,
+    /// The paths given to `import` nodes, as written
+    pub imports: Vec<Spanned<String>>,
+    /// Which file each type that came from an `import` was declared in
+    ///
+    /// The decls themselves are merged into `tys` (and their spans point into that file).
+    pub imported_tys: HashMap<String, String>,
+    /// The source of each file in `imported_tys`, for reporting errors in those decls
+    pub imported_srcs: HashMap<String, Arc<NamedSource>>,
+}
+
+/// Metadata about a program, from top-level nodes like `tags "float"`.
+///
+/// None of this affects the meaning of the program, it's just there for
+/// whoever is running it (abi-cafe uses it to decide which tests to run).
+#[derive(Debug, Clone, Default)]
+pub struct ProgramMeta {
+    /// `description "..."`: what the program is for
+    pub description: Option<Spanned<String>>,
+    /// `tags "a" "b"`: groups the program belongs to
+    pub tags: Vec<Spanned<String>>,
+    /// `conventions "c" "sysv64"`: the only calling conventions the program is meaningful for
+    /// (empty means all of them)
+    ///
+    /// The names aren't validated here, that's up to whoever runs the program.
+    pub conventions: Vec<Spanned<String>>,
+    /// `reprs "c"`: the only type reprs the program is meaningful for (empty means all of them)
+    pub reprs: Vec<LangRepr>,
+    /// `requires "f16"`: primitive types the program can't do without
+    /// (resolved by the type checker)
+    pub requires: Vec<Spanned<String>>,
+}
+
+impl ProgramMeta {
+    /// Whether no metadata was given at all
+    pub fn is_empty(&self) -> bool {
+        self.description.is_none()
+            && self.tags.is_empty()
+            && self.conventions.is_empty()
+            && self.reprs.is_empty()
+            && self.requires.is_empty()
+    }
 }
 
 /// A Type declaration
@@ -150,6 +194,18 @@ pub enum TyDecl {
     Pun(PunDecl),
 }
 
+impl TyDecl {
+    /// The generic parameters of the decl (empty if it isn't generic)
+    pub fn generic_params(&self) -> &[Ident] {
+        match self {
+            TyDecl::Struct(decl) => &decl.params,
+            TyDecl::Union(decl) => &decl.params,
+            TyDecl::Tagged(decl) => &decl.params,
+            TyDecl::Enum(_) | TyDecl::Alias(_) | TyDecl::Pun(_) => &[],
+        }
+    }
+}
+
 /// A type "name" (which may be structural like `[u32; 4]`).
 ///
 /// It's like an ident but, for types -- a tydent!
@@ -161,6 +217,10 @@ pub enum Tydent {
     Array(Box<Spanned<Tydent>>, u64),
     /// A by-reference type
     Ref(Box<Spanned<Tydent>>),
+    /// A borrowed slice (`&[T]`)
+    Slice(Box<Spanned<Tydent>>),
+    /// A named type with generic arguments (`Option<&T>`)
+    Generic(Ident, Vec<Spanned<Tydent>>),
     /// The empty tuple -- `()`
     Empty,
 }
@@ -186,6 +246,10 @@ pub enum Attr {
     Repr(AttrRepr),
     /// Pass this attribute through to the target language
     Passthrough(AttrPassthrough),
+    /// The function should unwind instead of returning
+    Unwind(AttrUnwind),
+    /// The function should use this calling convention
+    Convention(AttrConvention),
 }
 
 /// An attribute declaring this type should be packed (remove padding/align).
@@ -249,6 +313,26 @@ impl std::str::FromStr for LangRepr {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct AttrPassthrough(pub Spanned<String>);
 
+/// An attribute declaring that the callee of this function should unwind
+/// (panic/throw) after reporting its inputs, instead of returning.
+///
+/// Only valid on functions.
+///
+/// @unwind
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AttrUnwind {}
+
+/// An attribute declaring that this function should always use the given
+/// calling convention, instead of whichever one the test is being run with.
+///
+/// The name isn't validated here, that's up to whoever lowers the function.
+///
+/// Only valid on functions.
+///
+/// @convention "sysv64"
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AttrConvention(pub Spanned<String>);
+
 /// A struct decl.
 ///
 /// Field names may be positional by naming them underscore (`_`).
@@ -259,12 +343,25 @@ pub struct AttrPassthrough(pub Spanned<String>);
 pub struct StructDecl {
     /// Name of the struct
     pub name: Ident,
+    /// Generic parameters (`struct "Pair" "T" "U"`)
+    pub params: Vec<Ident>,
     /// Fields
-    pub fields: Vec<TypedVar>,
+    pub fields: Vec<FieldDecl>,
     /// Attributes
     pub attrs: Vec<Attr>,
 }
 
+/// A field of a struct decl.
+///
+/// This is a [`TypedVar`][] that can also be a bitfield (`flags "u32:3"`).
+#[derive(Debug, Clone)]
+pub struct FieldDecl {
+    pub name: Option<Ident>,
+    pub ty: Spanned<Tydent>,
+    /// The width of the field in bits, if it's a bitfield
+    pub bits: Option<Spanned<u32>>,
+}
+
 /// An untagged union decl.
 ///
 /// Variant names may be positional by naming them underscore (`_`).
@@ -273,6 +370,8 @@ pub struct StructDecl {
 pub struct UnionDecl {
     /// Name of the union
     pub name: Ident,
+    /// Generic parameters (`union "Either" "T" "U"`)
+    pub params: Vec<Ident>,
     /// Fields (variants)
     pub fields: Vec<TypedVar>,
     pub attrs: Vec<Attr>,
@@ -305,6 +404,8 @@ pub struct EnumVariant {
 #[derive(Debug, Clone)]
 pub struct TaggedDecl {
     pub name: Ident,
+    /// Generic parameters (`tagged "Maybe" "T"`)
+    pub params: Vec<Ident>,
     pub variants: Vec<TaggedVariant>,
     pub attrs: Vec<Attr>,
 }
@@ -395,18 +496,18 @@ pub struct FuncDecl {
 
 /// The parser, used to hold onto some global state for things like diagnostic.
 struct Parser<'a> {
-    // comp: &'a mut Compiler,
+    comp: &'a Compiler,
     src: Arc<NamedSource>,
     ast: &'a KdlDocument,
 }
 
 /// Parse a KdlScript program!
 pub fn parse_kdl_script(
-    _comp: &mut Compiler,
+    comp: &mut Compiler,
     src: Arc<NamedSource>,
     ast: &KdlDocument,
 ) -> Result<ParsedProgram> {
-    let mut parser = Parser { src, ast };
+    let mut parser = Parser { comp, src, ast };
     parser.parse()
 }
 
@@ -426,6 +527,11 @@ impl Parser<'_> {
     fn parse_module(&mut self, doc: &KdlDocument) -> Result<ParsedProgram> {
         let mut funcs = StableMap::new();
         let mut tys = StableMap::new();
+        let mut meta = ProgramMeta::default();
+        let mut meta_seen = HashSet::new();
+        let mut imports = vec![];
+        let mut imported_tys = HashMap::new();
+        let mut imported_srcs = HashMap::new();
 
         let mut cur_attrs = vec![];
         for node in doc.nodes() {
@@ -439,42 +545,96 @@ impl Parser<'_> {
 
             // Ok it's a real item, grab all the attributes, they belong to it
             let attrs = std::mem::take(&mut cur_attrs);
+            if name != "fn" {
+                for attr in &attrs {
+                    let attr_name = match attr {
+                        Attr::Unwind(_) => "@unwind",
+                        Attr::Convention(_) => "@convention",
+                        _ => continue,
+                    };
+                    return Err(KdlScriptParseError {
+                        message: format!("{attr_name} only applies to functions"),
+                        src: self.src.clone(),
+                        span: *node.name().span(),
+                        help: None,
+                    })?;
+                }
+            }
+            let mut conventions = attrs.iter().filter_map(|attr| match attr {
+                Attr::Convention(AttrConvention(convention)) => Some(convention),
+                _ => None,
+            });
+            if let (Some(_), Some(extra)) = (conventions.next(), conventions.next()) {
+                return Err(KdlScriptParseError {
+                    message: "a function can only have one @convention".to_string(),
+                    src: self.src.clone(),
+                    span: Spanned::span(extra),
+                    help: Some("remove this?".to_owned()),
+                })?;
+            }
 
             // Now parse the various kinds of top-level items
             match name {
+                "import" => {
+                    if !attrs.is_empty() {
+                        return Err(KdlScriptParseError {
+                            message: "attributes don't apply to imports".to_owned(),
+                            src: self.src.clone(),
+                            span: *node.name().span(),
+                            help: None,
+                        })?;
+                    }
+                    self.no_children(node)?;
+                    let path = self.one_string(node, "import path")?;
+                    self.import(&path, &mut tys, &mut imported_tys, &mut imported_srcs)?;
+                    imports.push(path);
+                }
+                "description" | "tags" | "conventions" | "reprs" | "requires" => {
+                    if !attrs.is_empty() {
+                        return Err(KdlScriptParseError {
+                            message: format!("attributes don't apply to {name}"),
+                            src: self.src.clone(),
+                            span: *node.name().span(),
+                            help: None,
+                        })?;
+                    }
+                    if !meta_seen.insert(name) {
+                        return Err(KdlScriptParseError {
+                            message: format!("{name} was already given"),
+                            src: self.src.clone(),
+                            span: *node.name().span(),
+                            help: Some("merge this with the other one".to_owned()),
+                        })?;
+                    }
+                    self.meta_decl(node, &mut meta)?;
+                }
                 "fn" => {
                     let func = self.func_decl(node, attrs)?;
                     funcs.insert(func.name.clone(), func);
                 }
                 "struct" => {
                     let ty = self.struct_decl(node, attrs)?;
-                    let old = tys.insert(ty.name.clone(), TyDecl::Struct(ty));
-                    assert!(old.is_none(), "duplicate type def");
+                    self.add_ty(&mut tys, &imported_tys, ty.name.clone(), TyDecl::Struct(ty))?;
                 }
                 "union" => {
                     let ty = self.union_decl(node, attrs)?;
-                    let old = tys.insert(ty.name.clone(), TyDecl::Union(ty));
-                    assert!(old.is_none(), "duplicate type def");
+                    self.add_ty(&mut tys, &imported_tys, ty.name.clone(), TyDecl::Union(ty))?;
                 }
                 "enum" => {
                     let ty = self.enum_decl(node, attrs)?;
-                    let old = tys.insert(ty.name.clone(), TyDecl::Enum(ty));
-                    assert!(old.is_none(), "duplicate type def");
+                    self.add_ty(&mut tys, &imported_tys, ty.name.clone(), TyDecl::Enum(ty))?;
                 }
                 "tagged" => {
                     let ty = self.tagged_decl(node, attrs)?;
-                    let old = tys.insert(ty.name.clone(), TyDecl::Tagged(ty));
-                    assert!(old.is_none(), "duplicate type def");
+                    self.add_ty(&mut tys, &imported_tys, ty.name.clone(), TyDecl::Tagged(ty))?;
                 }
                 "alias" => {
                     let ty = self.alias_decl(node, attrs)?;
-                    let old = tys.insert(ty.name.clone(), TyDecl::Alias(ty));
-                    assert!(old.is_none(), "duplicate type def");
+                    self.add_ty(&mut tys, &imported_tys, ty.name.clone(), TyDecl::Alias(ty))?;
                 }
                 "pun" => {
                     let ty = self.pun_decl(node, attrs)?;
-                    let old = tys.insert(ty.name.clone(), TyDecl::Pun(ty));
-                    assert!(old.is_none(), "duplicate type def");
+                    self.add_ty(&mut tys, &imported_tys, ty.name.clone(), TyDecl::Pun(ty))?;
                 }
                 x => {
                     return Err(KdlScriptParseError {
@@ -493,18 +653,143 @@ impl Parser<'_> {
             tys,
             funcs,
             builtin_funcs_start,
+            meta,
+            imports,
+            imported_tys,
+            imported_srcs,
         })
     }
 
+    /// Add a type declared in this file, making sure the name isn't taken.
+    fn add_ty(
+        &mut self,
+        tys: &mut StableMap<Ident, TyDecl>,
+        imported_tys: &HashMap<String, String>,
+        name: Ident,
+        ty: TyDecl,
+    ) -> Result<()> {
+        if tys.contains_key(&name) {
+            let (message, help) = if let Some(file) = imported_tys.get(name.as_str()) {
+                (
+                    format!("{name} was already imported"),
+                    Some(format!("{file} declares it")),
+                )
+            } else {
+                (format!("{name} is already defined"), None)
+            };
+            return Err(KdlScriptParseError {
+                message,
+                src: self.src.clone(),
+                span: Spanned::span(&name),
+                help,
+            })?;
+        }
+        tys.insert(name, ty);
+        Ok(())
+    }
+
+    /// Handle an `import "path.kdl"` node by merging in the types that file declares.
+    ///
+    /// The imported file must be a valid program on its own, and may only declare types.
+    /// Importing the same file twice (even indirectly) is fine, but two different files
+    /// can't declare the same name.
+    fn import(
+        &mut self,
+        path: &Spanned<String>,
+        tys: &mut StableMap<Ident, TyDecl>,
+        imported_tys: &mut HashMap<String, String>,
+        imported_srcs: &mut HashMap<String, Arc<NamedSource>>,
+    ) -> Result<()> {
+        trace!("import");
+        let (lib_src, lib) = self.comp.compile_import(self.src.name(), path)?;
+        let file = lib_src.name().to_owned();
+        if let Some((name, _func)) = lib.funcs.iter().take(lib.builtin_funcs_start).next() {
+            return Err(KdlScriptParseError {
+                message: "imported files can only declare types".to_owned(),
+                src: self.src.clone(),
+                span: Spanned::span(path),
+                help: Some(format!("{file} declares the function {name}")),
+            })?;
+        }
+
+        for (name, ty) in &lib.tys {
+            let origin = lib.imported_tys.get(name.as_str()).unwrap_or(&file);
+            if tys.contains_key(name) {
+                if imported_tys.get(name.as_str()) == Some(origin) {
+                    // Same decl by way of a different path, nothing to do
+                    continue;
+                }
+                let other = imported_tys
+                    .get(name.as_str())
+                    .map(|other| format!("{other} also declares it"))
+                    .unwrap_or_else(|| "this file also declares it".to_owned());
+                return Err(KdlScriptParseError {
+                    message: format!("{file} declares {name}, which is already defined"),
+                    src: self.src.clone(),
+                    span: Spanned::span(path),
+                    help: Some(other),
+                })?;
+            }
+            tys.insert(name.clone(), ty.clone());
+            imported_tys.insert(name.to_string(), origin.clone());
+        }
+        imported_srcs.insert(file, lib_src);
+        imported_srcs.extend(lib.imported_srcs.clone());
+        Ok(())
+    }
+
+    /// Parse one of the program metadata nodes (`description`, `tags`, ...).
+    fn meta_decl(&mut self, node: &KdlNode, meta: &mut ProgramMeta) -> Result<()> {
+        trace!("meta decl");
+        self.no_children(node)?;
+        let name = node.name().value();
+        if name == "description" {
+            meta.description = Some(self.one_string(node, "description")?);
+            return Ok(());
+        }
+
+        let vals = self.string_list(node.entries())?;
+        if vals.is_empty() {
+            let node_ident = node.name().span();
+            let after_ident = node_ident.offset() + node_ident.len();
+            return Err(KdlScriptParseError {
+                message: format!("{name} needs at least one value"),
+                src: self.src.clone(),
+                span: (after_ident..after_ident).into(),
+                help: None,
+            })?;
+        }
+        match name {
+            "tags" => meta.tags = vals,
+            "conventions" => meta.conventions = vals,
+            "requires" => meta.requires = vals,
+            "reprs" => {
+                meta.reprs = vals
+                    .iter()
+                    .map(|val| {
+                        val.parse().map_err(|message| KdlScriptParseError {
+                            message,
+                            src: self.src.clone(),
+                            span: Spanned::span(val),
+                            help: Some("try \"c\" or \"rust\"".to_owned()),
+                        })
+                    })
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+            }
+            _ => unreachable!("not a metadata node: {name}"),
+        }
+        Ok(())
+    }
+
     /// Parse a `struct` node.
     fn struct_decl(&mut self, node: &KdlNode, attrs: Vec<Attr>) -> Result<StructDecl> {
         trace!("struct decl");
-        let name = self.one_string(node, "type name")?;
-        let name = self.ident(name)?;
-        let fields = self.typed_var_children(node)?;
+        let (name, params) = self.generic_ty_name(node)?;
+        let fields = self.field_decl_children(node)?;
 
         Ok(StructDecl {
             name,
+            params,
             fields,
             attrs,
         })
@@ -513,12 +798,12 @@ impl Parser<'_> {
     /// Parse a `union` node.
     fn union_decl(&mut self, node: &KdlNode, attrs: Vec<Attr>) -> Result<UnionDecl> {
         trace!("union decl");
-        let name = self.one_string(node, "type name")?;
-        let name = self.ident(name)?;
+        let (name, params) = self.generic_ty_name(node)?;
         let fields = self.typed_var_children(node)?;
 
         Ok(UnionDecl {
             name,
+            params,
             fields,
             attrs,
         })
@@ -541,17 +826,61 @@ impl Parser<'_> {
     /// Parse a `tagged` node.
     fn tagged_decl(&mut self, node: &KdlNode, attrs: Vec<Attr>) -> Result<TaggedDecl> {
         trace!("enum decl");
-        let name = self.one_string(node, "type name")?;
-        let name = self.ident(name)?;
+        let (name, params) = self.generic_ty_name(node)?;
         let variants = self.tagged_variant_children(node)?;
 
         Ok(TaggedDecl {
             name,
+            params,
             variants,
             attrs,
         })
     }
 
+    /// Parse the name of a type that can have generic parameters (`struct "Pair" "T" "U"`).
+    fn generic_ty_name(&mut self, node: &KdlNode) -> Result<(Ident, Vec<Ident>)> {
+        let name = self.string_at(node, "type name", 0)?;
+        let name = self.ident(name)?;
+        let param_entries = node.entries().get(1..).unwrap_or_default();
+        if let Some(e) = param_entries
+            .iter()
+            .find(|e| e.name().is_some() || e.value().as_string().is_none())
+        {
+            return Err(KdlScriptParseError {
+                message: "You have something extra after your type name".to_owned(),
+                src: self.src.clone(),
+                span: *e.span(),
+                help: Some("generic parameters should be strings like \"T\"".to_owned()),
+            })?;
+        }
+        let params = self.string_list(param_entries)?;
+        let mut seen = HashSet::new();
+        let params = params
+            .into_iter()
+            .map(|param| {
+                let ty = self.tydent(&param)?;
+                if !matches!(&*ty, Tydent::Name(_)) {
+                    return Err(KdlScriptParseError {
+                        message: "generic parameters must be plain names".to_owned(),
+                        src: self.src.clone(),
+                        span: Spanned::span(&param),
+                        help: Some("try something like \"T\"".to_owned()),
+                    })?;
+                }
+                if !seen.insert(param.to_string()) {
+                    return Err(KdlScriptParseError {
+                        message: format!("generic parameter {param} is declared twice"),
+                        src: self.src.clone(),
+                        span: Spanned::span(&param),
+                        help: None,
+                    })?;
+                }
+                self.ident(param)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok((name, params))
+    }
+
     /// Parse a `pun` node.
     fn pun_decl(&mut self, node: &KdlNode, attrs: Vec<Attr>) -> Result<PunDecl> {
         let name = self.one_string(node, "type name")?;
@@ -619,6 +948,26 @@ impl Parser<'_> {
             // Recursively parse this block as an entire KdlScript program
             let defs = self.parse_module(doc)?;
 
+            // Don't want any imports
+            if let Some(path) = defs.imports.first() {
+                return Err(KdlScriptParseError {
+                    message: "puns can't contain imports".to_string(),
+                    src: self.src.clone(),
+                    span: Spanned::span(path),
+                    help: Some("import at the top level instead".to_owned()),
+                })?;
+            }
+
+            // Don't want any metadata
+            if !defs.meta.is_empty() {
+                return Err(KdlScriptParseError {
+                    message: "puns can't contain program metadata".to_string(),
+                    src: self.src.clone(),
+                    span: *block.name().span(),
+                    help: None,
+                })?;
+            }
+
             // Don't want any functions
             if let Some((_name, func)) = defs.funcs.iter().next() {
                 return Err(KdlScriptParseError {
@@ -635,6 +984,14 @@ impl Parser<'_> {
             for (ty_name, ty) in defs.tys {
                 if &ty_name == final_ty_name {
                     // this is the type
+                    if let Some(param) = ty.generic_params().first() {
+                        return Err(KdlScriptParseError {
+                            message: "puns can't be generic".to_string(),
+                            src: self.src.clone(),
+                            span: Spanned::span(param),
+                            help: None,
+                        })?;
+                    }
                     final_ty = Some(ty);
                 } else {
                     return Err(KdlScriptParseError {
@@ -851,6 +1208,18 @@ impl Parser<'_> {
                 }
                 Attr::Repr(AttrRepr { reprs })
             }
+            "@unwind" => {
+                trace!("unwind attr");
+                self.no_args(attr)?;
+                self.no_children(attr)?;
+                Attr::Unwind(AttrUnwind {})
+            }
+            "@convention" => {
+                trace!("convention attr");
+                self.no_children(attr)?;
+                let val = self.one_string(attr, "calling convention")?;
+                Attr::Convention(AttrConvention(val))
+            }
             "@" => {
                 trace!("passthrough attr");
                 let val = self.one_string(attr, "attribute to pass through to target language")?;
@@ -973,6 +1342,39 @@ impl Parser<'_> {
         Ok(())
     }
 
+    /// This node's children should be struct fields, which are TypedVars that can be bitfields
+    fn field_decl_children(&mut self, node: &KdlNode) -> Result<Vec<FieldDecl>> {
+        node.children()
+            .into_iter()
+            .flat_map(|d| d.nodes())
+            .map(|var| {
+                let name = self.var_name_decl(var)?;
+                let ty_str = self.one_string(var, "type")?;
+                // A trailing `:N` is the width of a bitfield
+                let (ty_str, bits) =
+                    match ty_str.rsplit_once(':').map(|(ty, bits)| (ty, bits.trim())) {
+                        Some((ty, bits))
+                            if !bits.is_empty() && bits.bytes().all(|b| b.is_ascii_digit()) =>
+                        {
+                            let span = Spanned::span(&ty_str);
+                            let bits = bits.parse::<u32>().map_err(|_| KdlScriptParseError {
+                                message: format!("bitfield is too wide: {bits}"),
+                                src: self.src.clone(),
+                                span,
+                                help: None,
+                            })?;
+                            let ty = Spanned::new(ty.trim_end().to_owned(), span);
+                            (ty, Some(Spanned::new(bits, span)))
+                        }
+                        _ => (ty_str, None),
+                    };
+                let ty = self.tydent(&ty_str)?;
+                self.no_children(var)?;
+                Ok(FieldDecl { name, ty, bits })
+            })
+            .collect()
+    }
+
     /// This node's children should be TypedVars
     fn typed_var_children(&mut self, node: &KdlNode) -> Result<Vec<TypedVar>> {
         node.children()
@@ -1123,6 +1525,15 @@ fn inherit_spans(tydent: &mut Spanned<Tydent>, input: &Spanned<String>) {
         Tydent::Ref(pointee_tydent) => {
             inherit_spans(pointee_tydent, input);
         }
+        Tydent::Slice(elem_tydent) => {
+            inherit_spans(elem_tydent, input);
+        }
+        Tydent::Generic(ident, args) => {
+            Spanned::clone_span_from(&mut ident.val, input);
+            for arg in args {
+                inherit_spans(arg, input);
+            }
+        }
         Tydent::Empty => {
             // noop
         }
@@ -1135,7 +1546,26 @@ type NomResult<I, O> = IResult<I, O, VerboseError<I>>;
 
 /// Matches the syntax for tydent ("identifier, but for types") incl structural types like arrays/references.
 fn tydent(input: &str) -> NomResult<&str, Spanned<Tydent>> {
-    alt((tydent_ref, tydent_array, tydent_empty_tuple, tydent_named))(input)
+    alt((
+        tydent_slice,
+        tydent_ref,
+        tydent_array,
+        tydent_empty_tuple,
+        tydent_named,
+    ))(input)
+}
+
+/// Matches a slice type (&[T])
+fn tydent_slice(input: &str) -> NomResult<&str, Spanned<Tydent>> {
+    let (input, elem_ty) = preceded(
+        pair(tag("&"), pair(many0(unicode_space), tag("["))),
+        // No cut, this might still be a reference to an array (&[T; N])
+        terminated(
+            delimited(many0(unicode_space), tydent, many0(unicode_space)),
+            tag("]"),
+        ),
+    )(input)?;
+    Ok((input, Spanned::from(Tydent::Slice(Box::new(elem_ty)))))
 }
 
 /// Matches a reference type (&T)
@@ -1186,25 +1616,27 @@ fn tydent_named(input: &str) -> NomResult<&str, Spanned<Tydent>> {
     let (input, (ty_name, generics)) = pair(
         ident,
         opt(delimited(
-            pair(unicode_space, tag("<")),
+            pair(many0(unicode_space), tag("<")),
             cut(separated_list1(
                 tag(","),
-                delimited(unicode_space, tydent_ref, unicode_space),
+                context(
+                    "a generic argument",
+                    delimited(many0(unicode_space), tydent, many0(unicode_space)),
+                ),
             )),
             tag(">"),
         )),
     )(input)?;
 
-    if let Some(_generics) = generics {
-        panic!("generics aren't yet implemented!");
-    }
-    Ok((
-        input,
-        Spanned::from(Tydent::Name(Ident {
-            val: Spanned::from(ty_name.to_owned()),
-            was_blank: false,
-        })),
-    ))
+    let ty_name = Ident {
+        val: Spanned::from(ty_name.to_owned()),
+        was_blank: false,
+    };
+    let tydent = match generics {
+        Some(args) => Tydent::Generic(ty_name, args),
+        None => Tydent::Name(ty_name),
+    };
+    Ok((input, Spanned::from(tydent)))
 }
 
 /// Matches an identifier
@@ -811,7 +811,20 @@ fn tyname_space() {
 fn generic() {
     let program = r##"
         struct "bad" {
-            x "Option<u32>"
+            x "Option<>"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "couldn't parse type"]
+fn generic_unclosed() {
+    let program = r##"
+        struct "bad" {
+            x "Option<&u32"
         }
     "##;
     let mut compiler = crate::Compiler::new();
@@ -1127,3 +1140,264 @@ fn aliasless_alias() {
 }
 
  */
+
+#[test]
+#[should_panic = "@unwind only applies to functions"]
+fn unwind_struct() {
+    let program = r##"
+        @unwind
+        struct "Point" {
+            x "f32"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "@convention only applies to functions"]
+fn convention_struct() {
+    let program = r##"
+        @convention "sysv64"
+        struct "Point" {
+            x "f32"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "a function can only have one @convention"]
+fn convention_twice() {
+    let program = r##"
+        @convention "sysv64"
+        @convention "win64"
+        fn "both" {
+            inputs { x "i32"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "tags was already given"]
+fn meta_twice() {
+    let program = r##"
+        tags "float"
+        tags "simd"
+        fn "func" {
+            inputs { x "f32"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "requires needs at least one value"]
+fn meta_empty() {
+    let program = r##"
+        requires
+        fn "func" {
+            inputs { x "f32"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "attributes don't apply to tags"]
+fn meta_attrs() {
+    let program = r##"
+        @align 16
+        tags "float"
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "unknown lang repr transparent"]
+fn meta_bad_repr() {
+    let program = r##"
+        reprs "c" "transparent"
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "puns can't contain program metadata"]
+fn meta_in_pun() {
+    let program = r##"
+        pun "Blah" {
+            lang "rust" {
+                tags "float"
+                alias "Blah" "f32"
+            }
+            lang "c" {
+                alias "Blah" "f32"
+            }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "couldn't import missing.kdl"]
+fn import_missing() {
+    let program = r##"
+        import "missing.kdl"
+    "##;
+    let mut compiler =
+        crate::Compiler::new().with_import_loader(|_path| Err(std::io::ErrorKind::NotFound.into()));
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "import cycle: test.kdl -> a.kdl -> test.kdl"]
+fn import_cycle() {
+    let program = r##"
+        import "a.kdl"
+    "##;
+    let mut compiler = crate::Compiler::new().with_import_loader(|path| match path {
+        "a.kdl" => Ok(r#"import "test.kdl""#.to_owned()),
+        _ => Ok(r#"import "a.kdl""#.to_owned()),
+    });
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "Point was already imported"]
+fn import_then_redefine() {
+    let program = r##"
+        import "shapes.kdl"
+        struct "Point" {
+            x "f64"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new()
+        .with_import_loader(|_path| Ok(r#"struct "Point" { x "f32"; y "f32"; }"#.to_owned()));
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "b.kdl declares Point, which is already defined"]
+fn import_conflict() {
+    let program = r##"
+        import "a.kdl"
+        import "b.kdl"
+    "##;
+    let mut compiler = crate::Compiler::new().with_import_loader(|path| match path {
+        "a.kdl" => Ok(r#"struct "Point" { x "f32"; y "f32"; }"#.to_owned()),
+        _ => Ok(r#"struct "Point" { x "f64"; y "f64"; }"#.to_owned()),
+    });
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "imported files can only declare types"]
+fn import_functions() {
+    let program = r##"
+        import "lib.kdl"
+    "##;
+    let mut compiler = crate::Compiler::new()
+        .with_import_loader(|_path| Ok(r#"fn "oops" { inputs { x "i32"; }; }"#.to_owned()));
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "puns can't contain imports"]
+fn import_in_pun() {
+    let program = r##"
+        pun "Blah" {
+            lang "rust" {
+                import "lib.kdl"
+                alias "Blah" "f32"
+            }
+            lang "c" {
+                alias "Blah" "f32"
+            }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new().with_import_loader(|_path| Ok(String::new()));
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "Point is already defined"]
+fn duplicate_type() {
+    let program = r##"
+        struct "Point" {
+            x "f32"
+        }
+        alias "Point" "f32"
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "generic parameter T is declared twice"]
+fn generic_param_twice() {
+    let program = r##"
+        struct "Pair" "T" "T" {
+            a "T"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "generic parameters must be plain names"]
+fn generic_param_not_name() {
+    let program = r##"
+        struct "Pair" "&T" {
+            a "T"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "puns can't be generic"]
+fn generic_pun() {
+    let program = r##"
+        pun "Blah" {
+            lang "rust" {
+                struct "Blah" "T" {
+                    x "T"
+                }
+            }
+            lang "c" {
+                alias "Blah" "f32"
+            }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
@@ -3,7 +3,157 @@
 fn no_primitive() {
     let program = r##"
         struct "wrong" {
-            x "i1"
+            x "i0"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "integer type is too wide"]
+fn bit_int_too_wide() {
+    let program = r##"
+        struct "wrong" {
+            x "u65536"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "bitfield width 33 doesn't fit its type"]
+fn bitfield_too_wide() {
+    let program = r##"
+        struct "wrong" {
+            x "u32:33"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "zero-width bitfields can't have a name"]
+fn bitfield_zero_width_named() {
+    let program = r##"
+        struct "wrong" {
+            x "u32:0"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "bitfields must be an integer primitive"]
+fn bitfield_not_int() {
+    let program = r##"
+        struct "wrong" {
+            x "f32:3"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "enum discriminant 10 is already used by B"]
+fn c_enum_duplicate_literals() {
+    let program = r##"
+        enum "Cases" {
+            A 8
+            B 10
+            C 10
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "enum discriminant 10 is already used by B"]
+fn c_enum_duplicate_implicit_literals() {
+    let program = r##"
+        enum "Cases" {
+            A 9
+            B
+            C 10
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "enum discriminant -1 doesn't fit in u8"]
+fn c_enum_literal_too_big_for_repr() {
+    let program = r##"
+        @repr "u8"
+        enum "Cases" {
+            A -1
+            B
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "enum discriminant overflowed"]
+fn c_enum_literal_overflow() {
+    let program = r##"
+        enum "Cases" {
+            A 0x7FFF_FFFF_FFFF_FFFF
+            B
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "Option is only supported when it has a niche"]
+fn option_without_niche() {
+    let program = r##"
+        fn "bad" {
+            inputs { x "Option<u32>"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "NonZero must be an integer primitive"]
+fn nonzero_not_int() {
+    let program = r##"
+        fn "bad" {
+            inputs { x "NonZero<f32>"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "generic types aren't supported: Vec"]
+fn unknown_generic() {
+    let program = r##"
+        fn "bad" {
+            inputs { x "Vec<u32>"; }
         }
     "##;
     let mut compiler = crate::Compiler::new();
@@ -23,3 +173,164 @@ fn no_arg_type() {
     let res = compiler.compile_string("test.kdl", program.to_owned());
     res.map_err(miette::Report::new).unwrap();
 }
+
+#[test]
+#[should_panic = "Point isn't a primitive type"]
+fn meta_requires_struct() {
+    let program = r##"
+        requires "f16" "Point"
+        struct "Point" {
+            x "f16"
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "tests/lib/shapes.kdl:2"]
+fn import_bad_lib() {
+    let program = r##"
+        import "lib/shapes.kdl"
+        fn "func" {
+            inputs { x "Point"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new().with_import_loader(|_path| {
+        Ok(r#"struct "Point" {
+    x "f32"
+    y "Nope"
+}"#
+        .to_owned())
+    });
+    let res = compiler.compile_string("tests/test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "Pair takes 2 generic arguments, but 1 were given"]
+fn generic_wrong_arity() {
+    let program = r##"
+        struct "Pair" "T" "U" {
+            a "T"
+            b "U"
+        }
+        fn "bad" {
+            inputs { x "Pair<u8>"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "Pair is generic and needs arguments"]
+fn generic_without_args() {
+    let program = r##"
+        struct "Pair" "T" "U" {
+            a "T"
+            b "U"
+        }
+        fn "bad" {
+            inputs { x "Pair"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "Point isn't generic"]
+fn not_generic() {
+    let program = r##"
+        struct "Point" {
+            x "f32"
+        }
+        fn "bad" {
+            inputs { x "Point<f32>"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "Wrap's instance would be named Wrap_u8, which is already taken"]
+fn generic_instance_name_taken() {
+    let program = r##"
+        struct "Wrap" "T" {
+            x "T"
+        }
+        struct "Wrap_u8" {
+            x "u8"
+        }
+        fn "bad" {
+            inputs { x "Wrap<u8>"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "Grow is instantiated recursively without end"]
+fn generic_infinite() {
+    let program = r##"
+        struct "Grow" "T" {
+            x "T"
+            next "Option<&Grow<[T; 2]>>"
+        }
+        fn "bad" {
+            inputs { x "Grow<u8>"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "use of undefined type name: T"]
+fn generic_params_are_scoped() {
+    let program = r##"
+        struct "Leaky" {
+            x "T"
+        }
+        struct "Wrap" "T" {
+            inner "Leaky"
+        }
+        fn "bad" {
+            inputs { x "Wrap<u8>"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let res = compiler.compile_string("test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
+
+#[test]
+#[should_panic = "tests/lib/pair.kdl:3"]
+fn import_generic_bad_body() {
+    // The body only gets checked when the importer uses it, but the error is still in the lib
+    let program = r##"
+        import "../lib/pair.kdl"
+        fn "func" {
+            inputs { x "Pair<u8, f32>"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new().with_import_loader(|_path| {
+        Ok(r#"struct "Pair" "T" "U" {
+    a "T"
+    b "U"
+    c "Missing"
+}"#
+        .to_owned())
+    });
+    let res = compiler.compile_string("tests/normal/test.kdl", program.to_owned());
+    res.map_err(miette::Report::new).unwrap();
+}
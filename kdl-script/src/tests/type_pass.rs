@@ -28,6 +28,131 @@ fn prim_struct() -> Result<(), miette::Report> {
     Ok(())
 }
 
+#[test]
+fn bit_int_struct() -> Result<(), miette::Report> {
+    let program = r##"
+        struct "BitInts" {
+            _0 "i1"
+            _1 "u1"
+            _2 "i24"
+            _3 "u24"
+            _4 "i72"
+            _5 "u65535"
+        }
+        fn "same_ty" {
+            inputs { _ "i24"; }
+            outputs { _ "[i24; 2]"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    compiler.compile_string("test.kdl", program.to_owned())?;
+    Ok(())
+}
+
+#[test]
+fn bitfield_struct() -> Result<(), miette::Report> {
+    let program = r##"
+        struct "Flags" {
+            a "u32:3"
+            b "i32:5"
+            c "u8"
+            d "u64:64"
+            _ "i8 : 1"
+            _ "u16:0"
+        }
+        fn "flags" {
+            inputs { _ "Flags"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    compiler.compile_string("test.kdl", program.to_owned())?;
+    Ok(())
+}
+
+#[test]
+fn niche_and_slice_types() -> Result<(), miette::Report> {
+    let program = r##"
+        struct "Node" {
+            id "NonZero<u32>"
+            parent "Option<&Node>"
+            children "&[Node]"
+            arrays "&[[u8; 4]]"
+        }
+        fn "nodes" {
+            inputs { _ "Node"; _ "Option<NonZero<i64>>"; _ "& [u32]"; _ "&[u8; 2]"; }
+            outputs { _ "Option < NonZero<u8> >"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    compiler.compile_string("test.kdl", program.to_owned())?;
+    Ok(())
+}
+
+#[test]
+fn generic_types() -> Result<(), miette::Report> {
+    let program = r##"
+        struct "Pair" "T" "U" {
+            a "T"
+            b "U"
+        }
+        struct "Node" "T" {
+            val "T"
+            next "Option<&Node<T>>"
+            pairs "&[Pair<T, [T; 2]>]"
+        }
+        union "Either" "L" "R" {
+            left "L"
+            right "R"
+        }
+        tagged "Maybe" "T" {
+            Nothing
+            Just { _ "T"; }
+        }
+        fn "generics" {
+            inputs {
+                _ "Pair<u8, f64>"
+                _ "Pair<u8,f64>"
+                _ "Pair<f32, Pair<u8, u16>>"
+                _ "Node<u32>"
+                _ "Either<i32, &Pair<bool, ()>>"
+                _ "Maybe<Option<NonZero<u8>>>"
+            }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let program = compiler.compile_string("test.kdl", program.to_owned())?;
+    let func = program.realize_func(program.all_funcs().next().unwrap());
+    let names = func
+        .inputs
+        .iter()
+        .map(|arg| program.format_ty(arg.ty))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "Pair_u8_f64",
+            "Pair_u8_f64",
+            "Pair_f32_Pair_u8_u16",
+            "Node_u32",
+            "Either_i32_ref_Pair_bool_unit",
+            "Maybe_Option_NonZero_u8",
+        ]
+    );
+    // The same instantiation is the same type
+    assert_eq!(func.inputs[0].ty, func.inputs[1].ty);
+    // Instances are real nominal types with the arguments substituted in
+    let crate::types::Ty::Struct(node) = program.realize_ty(func.inputs[3].ty) else {
+        panic!("Node<u32> wasn't a struct");
+    };
+    let fields = node
+        .fields
+        .iter()
+        .map(|field| program.format_ty(field.ty))
+        .collect::<Vec<_>>();
+    assert_eq!(fields, ["u32", "Option<&Node_u32>", "&[Pair_u32_u32x2]"]);
+    Ok(())
+}
+
 #[test]
 fn c_enum_simple() -> Result<(), miette::Report> {
     let program = r##"
@@ -72,12 +197,13 @@ fn c_enum_literals2() -> Result<(), miette::Report> {
 
 #[test]
 fn c_enum_literals3() -> Result<(), miette::Report> {
-    // FIXME: this one might be dubious? Check what C/C++ allow with puns here
     let program = r##"
+        @repr "i64"
         enum "Cases" {
-            A 8
-            B 10
-            C 10
+            A -1
+            B
+            C 0x1_0000_0000
+            D
         }
     "##;
     let mut compiler = crate::Compiler::new();
@@ -259,6 +385,164 @@ fn anon_vars() -> Result<(), miette::Report> {
     Ok(())
 }
 
+#[test]
+fn unwind_func() -> Result<(), miette::Report> {
+    let program = r##"
+        @unwind
+        fn "panics" {
+            inputs { x "i32"; }
+            outputs { y "i32"; }
+        }
+        fn "returns" {
+            inputs { x "i32"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let program = compiler.compile_string("test.kdl", program.to_owned())?;
+    let unwinds = program
+        .all_funcs()
+        .map(|func| program.realize_func(func).unwinds())
+        .collect::<Vec<_>>();
+    assert_eq!(unwinds, [true, false]);
+    Ok(())
+}
+
+#[test]
+fn convention_func() -> Result<(), miette::Report> {
+    let program = r##"
+        @convention "win64"
+        fn "pinned" {
+            inputs { x "i32"; }
+        }
+        fn "unpinned" {
+            inputs { x "i32"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let program = compiler.compile_string("test.kdl", program.to_owned())?;
+    let conventions = program
+        .all_funcs()
+        .map(|func| {
+            let func = program.realize_func(func);
+            func.convention().map(|convention| convention.to_string())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(conventions, [Some("win64".to_owned()), None]);
+    Ok(())
+}
+
+#[test]
+fn program_meta() -> Result<(), miette::Report> {
+    let program = r##"
+        description "halves are weird"
+        tags "float" "f16"
+        conventions "c" "sysv64"
+        reprs "c"
+        requires "f16" "i128"
+        fn "half" {
+            inputs { x "f16"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new();
+    let program = compiler.compile_string("test.kdl", program.to_owned())?;
+    let meta = program.meta();
+    let strings = |vals: &[crate::spanned::Spanned<String>]| {
+        vals.iter().map(|val| val.to_string()).collect::<Vec<_>>()
+    };
+    assert_eq!(
+        meta.description.as_deref().map(|desc| desc.as_str()),
+        Some("halves are weird")
+    );
+    assert_eq!(strings(&meta.tags), ["float", "f16"]);
+    assert_eq!(strings(&meta.conventions), ["c", "sysv64"]);
+    assert_eq!(meta.reprs, [crate::parse::LangRepr::C]);
+    assert_eq!(
+        program.required_primitives(),
+        [
+            crate::types::PrimitiveTy::F16,
+            crate::types::PrimitiveTy::I128
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn import_generic() -> Result<(), miette::Report> {
+    let program = r##"
+        import "../lib/pair.kdl"
+        struct "Local" {
+            x "u16"
+        }
+        fn "pairs" {
+            inputs { _ "Pair<u8, f32>"; _ "Pair<Local, Pair<u8, f32>>"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new().with_import_loader(|_path| {
+        Ok(r#"
+            struct "Pair" "T" "U" { a "T"; b "U"; }
+        "#
+        .to_owned())
+    });
+    let program = compiler.compile_string("tests/normal/test.kdl", program.to_owned())?;
+    let func = program.realize_func(program.all_funcs().next().unwrap());
+    let names = func
+        .inputs
+        .iter()
+        .map(|arg| program.format_ty(arg.ty))
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["Pair_u8_f32", "Pair_Local_Pair_u8_f32"]);
+    Ok(())
+}
+
+#[test]
+fn import_types() -> Result<(), miette::Report> {
+    // Both libraries import the same base, which is fine
+    let program = r##"
+        import "../lib/shapes.kdl"
+        import "../lib/colors.kdl"
+        struct "Sprite" {
+            pos "Point"
+            tint "Color"
+        }
+        fn "draw" {
+            inputs { _ "Sprite"; _ "Channel"; }
+        }
+    "##;
+    let mut compiler = crate::Compiler::new().with_import_loader(|path| match path {
+        "tests/lib/shapes.kdl" => Ok(r#"
+            import "base.kdl"
+            struct "Point" { x "Coord"; y "Coord"; }
+        "#
+        .to_owned()),
+        "tests/lib/colors.kdl" => Ok(r#"
+            import "./../lib/base.kdl"
+            struct "Color" { r "Channel"; g "Channel"; b "Channel"; }
+        "#
+        .to_owned()),
+        "tests/lib/base.kdl" => Ok(r#"
+            alias "Coord" "f32"
+            alias "Channel" "u8"
+        "#
+        .to_owned()),
+        _ => Err(std::io::ErrorKind::NotFound.into()),
+    });
+    let program = compiler.compile_string("tests/normal/test.kdl", program.to_owned())?;
+    let parsed = compiler.parsed.as_ref().unwrap();
+    let origins =
+        ["Coord", "Channel", "Point", "Color"].map(|name| parsed.imported_tys[name].as_str());
+    assert_eq!(
+        origins,
+        [
+            "tests/lib/base.kdl",
+            "tests/lib/base.kdl",
+            "tests/lib/shapes.kdl",
+            "tests/lib/colors.kdl"
+        ]
+    );
+    assert_eq!(program.all_funcs().count(), 1);
+    Ok(())
+}
+
 #[test]
 fn example_types() -> Result<(), miette::Report> {
     let mut compiler = crate::Compiler::new();
@@ -85,6 +85,8 @@ pub struct TypedProgram {
     tcx: TyCtx,
     funcs: Vec<Func>,
     builtin_funcs_start: usize,
+    meta: ProgramMeta,
+    required_primitives: Vec<PrimitiveTy>,
 }
 
 /// A type id
@@ -120,6 +122,12 @@ pub enum Ty {
     Array(ArrayTy),
     /// A reference to a type (behaves as if is the Pointee, but just passed by-ref)
     Ref(RefTy),
+    /// A borrowed slice, passed as a (pointer, length) pair
+    Slice(SliceTy),
+    /// An integer that can't be zero
+    NonZero(NonZeroTy),
+    /// An Option that uses its payload's niche (null or zero) for `None`
+    Option(OptionTy),
     /// Empty tuple -- `()`
     Empty,
 }
@@ -136,6 +144,9 @@ impl Ty {
             Ty::Pun(_) => true,
             Ty::Array(_) => false,
             Ty::Ref(_) => false,
+            Ty::Slice(_) => false,
+            Ty::NonZero(_) => false,
+            Ty::Option(_) => false,
             Ty::Empty => false,
         }
     }
@@ -157,6 +168,23 @@ pub struct Func {
     pub body: (),
 }
 
+impl Func {
+    /// Whether the callee should unwind instead of returning (`@unwind`)
+    pub fn unwinds(&self) -> bool {
+        self.attrs
+            .iter()
+            .any(|attr| matches!(attr, Attr::Unwind(_)))
+    }
+
+    /// The calling convention this function must use (`@convention`), if any
+    pub fn convention(&self) -> Option<&Spanned<String>> {
+        self.attrs.iter().find_map(|attr| match attr {
+            Attr::Convention(AttrConvention(convention)) => Some(convention),
+            _ => None,
+        })
+    }
+}
+
 /// A function argument (input or output).
 #[derive(Debug, Clone)]
 pub struct Arg {
@@ -179,8 +207,6 @@ pub enum PrimitiveTy {
     I64,
     /// `i128` / `int128_t`
     I128,
-    /// `i256` / `int256_t`
-    I256,
     /// `u8` / `uint8_t`
     U8,
     /// `u16` / `uint16_t`
@@ -191,8 +217,6 @@ pub enum PrimitiveTy {
     U64,
     /// `u128` / `uint128_t`
     U128,
-    /// `u256` / `uint256_t`
-    U256,
     /// `f16` / `half`
     F16,
     /// `f32` / `float`
@@ -201,10 +225,91 @@ pub enum PrimitiveTy {
     F64,
     /// `f128` / `quad`
     F128,
+    /// `c32` / `float _Complex`
+    C32,
+    /// `c64` / `double _Complex`
+    C64,
     /// `bool`
     Bool,
     /// An opaque pointer (like `void*`)
     Ptr,
+    /// `iN` / `_BitInt(N)`, for any width that isn't one of the above
+    BitInt(u32),
+    /// `uN` / `unsigned _BitInt(N)`, for any width that isn't one of the above
+    UBitInt(u32),
+}
+
+/// The widest `iN`/`uN` a program can use
+pub const MAX_BIT_INT_WIDTH: u32 = 65535;
+
+/// How deeply generic instantiations can nest before we assume they never end
+const MAX_GENERIC_DEPTH: usize = 16;
+
+impl PrimitiveTy {
+    /// Parse an `iN`/`uN` type name (that isn't one of the [`PRIMITIVES`][]).
+    ///
+    /// The width isn't checked against [`MAX_BIT_INT_WIDTH`][].
+    pub fn parse_bit_int(name: &str) -> Option<Self> {
+        let (signed, width) = if let Some(width) = name.strip_prefix('i') {
+            (true, width)
+        } else {
+            (false, name.strip_prefix('u')?)
+        };
+        // No sign or leading zeros, so every type has exactly one name
+        if width.starts_with('0') || !width.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let bits = width.parse().ok()?;
+        Some(if signed {
+            PrimitiveTy::BitInt(bits)
+        } else {
+            PrimitiveTy::UBitInt(bits)
+        })
+    }
+
+    /// If this is one of the fixed-width integers, get the (inclusive) range of values it holds
+    pub fn int_range(&self) -> Option<(i128, i128)> {
+        let range = match self {
+            PrimitiveTy::I8 => (i8::MIN as i128, i8::MAX as i128),
+            PrimitiveTy::I16 => (i16::MIN as i128, i16::MAX as i128),
+            PrimitiveTy::I32 => (i32::MIN as i128, i32::MAX as i128),
+            PrimitiveTy::I64 => (i64::MIN as i128, i64::MAX as i128),
+            PrimitiveTy::I128 => (i128::MIN, i128::MAX),
+            PrimitiveTy::U8 => (0, u8::MAX as i128),
+            PrimitiveTy::U16 => (0, u16::MAX as i128),
+            PrimitiveTy::U32 => (0, u32::MAX as i128),
+            PrimitiveTy::U64 => (0, u64::MAX as i128),
+            PrimitiveTy::U128 => (0, i128::MAX),
+            _ => return None,
+        };
+        Some(range)
+    }
+
+    /// If this is an `iN`/`uN`, get its width and whether it's signed
+    pub fn bit_int(&self) -> Option<(u32, bool)> {
+        match *self {
+            PrimitiveTy::BitInt(bits) => Some((bits, true)),
+            PrimitiveTy::UBitInt(bits) => Some((bits, false)),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for PrimitiveTy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrimitiveTy::BitInt(bits) => write!(f, "i{bits}"),
+            PrimitiveTy::UBitInt(bits) => write!(f, "u{bits}"),
+            prim => {
+                let name = PRIMITIVES
+                    .iter()
+                    .find(|(_, p)| p == prim)
+                    .map(|(name, _)| *name)
+                    .expect("primitive without a name!?");
+                f.write_str(name)
+            }
+        }
+    }
 }
 
 pub const PRIMITIVES: &[(&str, PrimitiveTy)] = &[
@@ -213,17 +318,17 @@ pub const PRIMITIVES: &[(&str, PrimitiveTy)] = &[
     ("i32", PrimitiveTy::I32),
     ("i64", PrimitiveTy::I64),
     ("i128", PrimitiveTy::I128),
-    ("i256", PrimitiveTy::I256),
     ("u8", PrimitiveTy::U8),
     ("u16", PrimitiveTy::U16),
     ("u32", PrimitiveTy::U32),
     ("u64", PrimitiveTy::U64),
     ("u128", PrimitiveTy::U128),
-    ("u256", PrimitiveTy::U256),
     ("f16", PrimitiveTy::F16),
     ("f32", PrimitiveTy::F32),
     ("f64", PrimitiveTy::F64),
     ("f128", PrimitiveTy::F128),
+    ("c32", PrimitiveTy::C32),
+    ("c64", PrimitiveTy::C64),
     ("bool", PrimitiveTy::Bool),
     ("ptr", PrimitiveTy::Ptr),
 ];
@@ -260,7 +365,9 @@ pub struct EnumTy {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct EnumVariantTy {
     pub name: Ident,
-    // pub val: LiteralExpr,
+    /// The discriminant of this case, either given explicitly or
+    /// implied by the previous one (C rules: start at 0, then count up).
+    pub val: i64,
 }
 
 /// The Ty of a tagged union (rust-style enum).
@@ -302,6 +409,8 @@ pub struct FieldTy {
     pub idx: usize,
     pub ident: Ident,
     pub ty: TyIdx,
+    /// The width in bits, if this is a bitfield (only in structs)
+    pub bits: Option<u32>,
 }
 
 /// The Ty of a fixed length array.
@@ -330,6 +439,38 @@ pub struct RefTy {
     pub pointee_ty: TyIdx,
 }
 
+/// The Ty of a slice (`&[T]`).
+///
+/// Rust's `&[T]` isn't FFI-safe, so this is the `#[repr(C)]` (pointer, length)
+/// struct interop code passes instead. Like [`RefTy`][] the "value" is the elements,
+/// preceded by an implicit length (which behaves like a tag).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SliceTy {
+    pub elem_ty: TyIdx,
+}
+
+/// The Ty of a `NonZero<T>`, which is always an integer primitive.
+///
+/// This is ABI-compatible with the integer, but gives [`OptionTy`][] a niche.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NonZeroTy {
+    pub int_ty: TyIdx,
+}
+
+/// The Ty of an `Option<T>`, where T is a `&T` or `NonZero<T>`.
+///
+/// These are guaranteed to use the niche, so `Option<&T>` is a nullable `T*`
+/// and `Option<NonZero<u32>>` is a `uint32_t` that's 0 for `None`.
+/// The value has an implicit tag (0 for `None`, 1 for `Some`) followed by the
+/// values of the payload if it's `Some`.
+///
+/// `Option<NonNull<T>>` and `Option<extern fn>` would also qualify, but KDLScript
+/// doesn't have `NonNull` or function pointer types (yet).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OptionTy {
+    pub some_ty: TyIdx,
+}
+
 /// The Ty of a Pun.
 ///
 /// Puns express the fact that different languages might express a type
@@ -410,6 +551,23 @@ pub(crate) struct TyCtx {
 
     ty_facts: HashMap<TyIdx, TypeFact>,
 
+    /// Generic type decls (`struct "Pair" "T" "U"`).
+    ///
+    /// These aren't types themselves, each instantiation (`Pair<u8, f64>`)
+    /// is checked on demand and becomes its own nominal type. The decl might
+    /// have been imported, so we also keep the source it was declared in.
+    generic_decls: HashMap<String, (TyDecl, Arc<NamedSource>)>,
+
+    /// The instantiations of generic decls we've made so far, so that
+    /// `Pair<u8, f64>` is the same TyIdx everywhere it occurs.
+    generic_instances: HashMap<(String, Vec<TyIdx>), TyIdx>,
+
+    /// How many generic instantiations we're in the middle of (to catch infinite ones).
+    generic_depth: usize,
+
+    /// The names of all the nominal types, which are known before the types are complete.
+    nominal_names: HashMap<TyIdx, String>,
+
     /// Scoped type info, reflecting the fact that struct definitions
     /// and variables come in and out of scope.
     ///
@@ -440,6 +598,10 @@ pub fn typeck(comp: &mut Compiler, parsed: &ParsedProgram) -> Result<TypedProgra
         tys: vec![],
         ty_map: HashMap::new(),
         ty_facts: HashMap::new(),
+        generic_decls: HashMap::new(),
+        generic_instances: HashMap::new(),
+        generic_depth: 0,
+        nominal_names: HashMap::new(),
         envs: vec![],
     };
 
@@ -454,12 +616,25 @@ pub fn typeck(comp: &mut Compiler, parsed: &ParsedProgram) -> Result<TypedProgra
         tys: HashMap::new(),
     });
 
-    // Add all the user defined types
-    for (ty_name, _ty_decl) in &parsed.tys {
-        let _ty_idx = tcx.push_nominal_decl_incomplete(ty_name.clone());
+    // Add all the user defined types (generic ones only get checked when they're used)
+    for (ty_name, ty_decl) in &parsed.tys {
+        if ty_decl.generic_params().is_empty() {
+            let _ty_idx = tcx.push_nominal_decl_incomplete(ty_name.clone());
+        } else {
+            let decl_src = parsed
+                .imported_tys
+                .get(ty_name.as_str())
+                .and_then(|file| parsed.imported_srcs.get(file))
+                .unwrap_or(&tcx.src)
+                .clone();
+            tcx.generic_decls
+                .insert(ty_name.to_string(), (ty_decl.clone(), decl_src));
+        }
     }
     for (ty_name, ty_decl) in &parsed.tys {
-        tcx.complete_nominal_decl(ty_name, ty_decl)?;
+        if ty_decl.generic_params().is_empty() {
+            tcx.complete_nominal_decl(ty_name, ty_decl)?;
+        }
     }
 
     let funcs = parsed
@@ -502,11 +677,32 @@ pub fn typeck(comp: &mut Compiler, parsed: &ParsedProgram) -> Result<TypedProgra
     // Now that everything's added, compute some facts
     tcx.compute_ty_facts()?;
 
+    let required_primitives = parsed
+        .meta
+        .requires
+        .iter()
+        .map(|name| {
+            PRIMITIVES
+                .iter()
+                .find(|(prim_name, _)| *prim_name == name.as_str())
+                .map(|(_, prim)| *prim)
+                .or_else(|| PrimitiveTy::parse_bit_int(name))
+                .ok_or_else(|| KdlScriptTypeError {
+                    message: format!("{} isn't a primitive type", &**name),
+                    src: tcx.src.clone(),
+                    span: Spanned::span(name),
+                    help: Some("requires only takes primitives, like \"f16\"".to_string()),
+                })
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
     let builtin_funcs_start = parsed.builtin_funcs_start;
     Ok(TypedProgram {
         tcx,
         funcs,
         builtin_funcs_start,
+        meta: parsed.meta.clone(),
+        required_primitives,
     })
 }
 
@@ -541,6 +737,7 @@ impl TyCtx {
         let ty_idx = self.tys.len();
         let dummy_ty = Ty::Empty;
         self.tys.push(dummy_ty);
+        self.nominal_names.insert(ty_idx, ty_name.to_string());
         self.envs.last_mut().unwrap().tys.insert(ty_name, ty_idx);
         ty_idx
     }
@@ -556,6 +753,101 @@ impl TyCtx {
         Ok(())
     }
 
+    /// Resolve the discriminants of an enum's variants, and check that they're
+    /// unique and fit in the enum's `@repr` (if it has a primitive one).
+    fn check_enum_variants(&self, decl: &EnumDecl) -> Result<Vec<EnumVariantTy>> {
+        let repr_range = decl
+            .attrs
+            .iter()
+            .filter_map(|attr| match attr {
+                Attr::Repr(AttrRepr { reprs }) => Some(reprs),
+                _ => None,
+            })
+            .flatten()
+            .find_map(|repr| match repr {
+                Repr::Primitive(prim) => Some((*prim, prim.int_range()?)),
+                _ => None,
+            });
+
+        let mut variants = Vec::<EnumVariantTy>::new();
+        let mut next_val = Some(0);
+        for variant in &decl.variants {
+            let (val, span) = if let Some(expr) = &variant.val {
+                (expr.val, expr.span)
+            } else if let Some(val) = next_val {
+                (val, Spanned::span(&variant.name))
+            } else {
+                return Err(KdlScriptTypeError {
+                    message: "enum discriminant overflowed".to_string(),
+                    src: self.src.clone(),
+                    span: Spanned::span(&variant.name),
+                    help: Some("give this case an explicit value".to_string()),
+                })?;
+            };
+            if let Some((prim, (min, max))) = repr_range {
+                if (val as i128) < min || (val as i128) > max {
+                    return Err(KdlScriptTypeError {
+                        message: format!("enum discriminant {val} doesn't fit in {prim}"),
+                        src: self.src.clone(),
+                        span,
+                        help: Some(format!("{prim} can hold {min} to {max}")),
+                    })?;
+                }
+            }
+            if let Some(other) = variants.iter().find(|v| v.val == val) {
+                return Err(KdlScriptTypeError {
+                    message: format!("enum discriminant {val} is already used by {}", other.name),
+                    src: self.src.clone(),
+                    span,
+                    help: Some("every case of an enum needs its own value".to_string()),
+                })?;
+            }
+            variants.push(EnumVariantTy {
+                name: variant.name.clone(),
+                val,
+            });
+            next_val = val.checked_add(1);
+        }
+        Ok(variants)
+    }
+
+    /// Check that a bitfield is a primitive integer that's wide enough for it.
+    ///
+    /// Unnamed (`_`) bitfields can also be 0 bits wide, which pads to the next unit of their type.
+    fn check_bitfield(&self, ty: TyIdx, bits: &Spanned<u32>, named: bool) -> Result<u32> {
+        let width = match &self.tys[ty] {
+            Ty::Primitive(PrimitiveTy::I8 | PrimitiveTy::U8) => 8,
+            Ty::Primitive(PrimitiveTy::I16 | PrimitiveTy::U16) => 16,
+            Ty::Primitive(PrimitiveTy::I32 | PrimitiveTy::U32) => 32,
+            Ty::Primitive(PrimitiveTy::I64 | PrimitiveTy::U64) => 64,
+            _ => {
+                return Err(KdlScriptTypeError {
+                    message: "bitfields must be an integer primitive".to_string(),
+                    src: self.src.clone(),
+                    span: Spanned::span(bits),
+                    help: Some("use one of i8-i64 or u8-u64".to_string()),
+                })?;
+            }
+        };
+        if **bits == 0 && named {
+            return Err(KdlScriptTypeError {
+                message: "zero-width bitfields can't have a name".to_string(),
+                src: self.src.clone(),
+                span: Spanned::span(bits),
+                help: Some("name it _ to pad to the next storage unit".to_string()),
+            })?;
+        }
+        if **bits > width {
+            return Err(KdlScriptTypeError {
+                message: format!("bitfield width {} doesn't fit its type", **bits),
+                src: self.src.clone(),
+                span: Spanned::span(bits),
+                help: Some(format!("this bitfield can be 1 to {width} bits wide")),
+            })?;
+        }
+        Ok(**bits)
+    }
+
     /// Memoize the parts of a nominal ty.
     fn memoize_nominal_parts(&mut self, ty_decl: &TyDecl) -> Result<Ty> {
         let ty = match ty_decl {
@@ -565,10 +857,17 @@ impl TyCtx {
                     .iter()
                     .enumerate()
                     .map(|(idx, f)| {
+                        let ty = self.memoize_ty(&f.ty)?;
+                        let bits = f
+                            .bits
+                            .as_ref()
+                            .map(|bits| self.check_bitfield(ty, bits, f.name.is_some()))
+                            .transpose()?;
                         Ok(FieldTy {
                             idx,
                             ident: ident_var(f.name.clone(), "field", idx, &f.ty),
-                            ty: self.memoize_ty(&f.ty)?,
+                            ty,
+                            bits,
                         })
                     })
                     .collect::<Result<Vec<_>>>()?;
@@ -590,6 +889,7 @@ impl TyCtx {
                             idx,
                             ident: ident_var(f.name.clone(), "field", idx, &f.ty),
                             ty: self.memoize_ty(&f.ty)?,
+                            bits: None,
                         })
                     })
                     .collect::<Result<Vec<_>>>()?;
@@ -600,13 +900,7 @@ impl TyCtx {
                 })
             }
             TyDecl::Enum(decl) => {
-                let variants = decl
-                    .variants
-                    .iter()
-                    .map(|v| EnumVariantTy {
-                        name: v.name.clone(),
-                    })
-                    .collect::<Vec<_>>();
+                let variants = self.check_enum_variants(decl)?;
                 Ty::Enum(EnumTy {
                     name: decl.name.clone(),
                     variants,
@@ -628,6 +922,7 @@ impl TyCtx {
                                             idx,
                                             ident: ident_var(f.name.clone(), "field", idx, &f.ty),
                                             ty: self.memoize_ty(&f.ty)?,
+                                            bits: None,
                                         })
                                     })
                                     .collect::<Result<Vec<_>>>()?,
@@ -718,10 +1013,38 @@ impl TyCtx {
                 let elem_ty = self.memoize_ty(elem_ty_ref)?;
                 self.memoize_inner(Ty::Array(ArrayTy { elem_ty, len: *len }))
             }
+            Tydent::Slice(elem_ty_ref) => {
+                let elem_ty = self.memoize_ty(elem_ty_ref)?;
+                self.memoize_inner(Ty::Slice(SliceTy { elem_ty }))
+            }
+            Tydent::Generic(name, args) => self.memoize_generic(name, args)?,
             Tydent::Name(name) => {
                 // Nominal types take a separate path because they're scoped
                 if let Some(ty_idx) = self.resolve_nominal_ty(name) {
                     ty_idx
+                } else if let Some(prim) = PrimitiveTy::parse_bit_int(name) {
+                    // There's too many of these to make them all builtins
+                    let (bits, _) = prim.bit_int().expect("parsed a non-integer!?");
+                    if bits > MAX_BIT_INT_WIDTH {
+                        return Err(KdlScriptTypeError {
+                            message: format!("integer type is too wide: {name}"),
+                            src: self.src.clone(),
+                            span: Spanned::span(name),
+                            help: Some(format!("integers can be at most {MAX_BIT_INT_WIDTH} bits")),
+                        })?;
+                    }
+                    self.memoize_inner(Ty::Primitive(prim))
+                } else if let Some((decl, _)) = self.generic_decls.get(name.as_str()) {
+                    let params = decl.generic_params().iter().map(|p| p.as_str());
+                    return Err(KdlScriptTypeError {
+                        message: format!("{name} is generic and needs arguments"),
+                        src: self.src.clone(),
+                        span: Spanned::span(name),
+                        help: Some(format!(
+                            "try {name}<{}> with real types",
+                            params.collect::<Vec<_>>().join(", ")
+                        )),
+                    })?;
                 } else {
                     return Err(KdlScriptTypeError {
                         message: format!("use of undefined type name: {name}"),
@@ -736,6 +1059,216 @@ impl TyCtx {
         Ok(ty_idx)
     }
 
+    /// Converts a generic type (`Option<T>`, `NonZero<T>`, or a user-defined `Pair<T, U>`) into a TyIdx.
+    fn memoize_generic(&mut self, name: &Ident, args: &[Spanned<Tydent>]) -> Result<TyIdx> {
+        if let Some((decl, decl_src)) = self.generic_decls.get(name.as_str()).cloned() {
+            return self.instantiate_generic(name, &decl, decl_src, args);
+        }
+        let [arg] = args else {
+            return Err(KdlScriptTypeError {
+                message: format!(
+                    "{name} takes 1 generic argument, but {} were given",
+                    args.len()
+                ),
+                src: self.src.clone(),
+                span: Spanned::span(name),
+                help: None,
+            })?;
+        };
+        let arg_ty = self.memoize_ty(arg)?;
+        let ty = match name.as_str() {
+            "NonZero" => {
+                let Ty::Primitive(
+                    PrimitiveTy::I8
+                    | PrimitiveTy::I16
+                    | PrimitiveTy::I32
+                    | PrimitiveTy::I64
+                    | PrimitiveTy::I128
+                    | PrimitiveTy::U8
+                    | PrimitiveTy::U16
+                    | PrimitiveTy::U32
+                    | PrimitiveTy::U64
+                    | PrimitiveTy::U128,
+                ) = &self.tys[arg_ty]
+                else {
+                    return Err(KdlScriptTypeError {
+                        message: "NonZero must be an integer primitive".to_string(),
+                        src: self.src.clone(),
+                        span: Spanned::span(arg),
+                        help: Some("use one of i8-i128 or u8-u128".to_string()),
+                    })?;
+                };
+                Ty::NonZero(NonZeroTy { int_ty: arg_ty })
+            }
+            "Option" => {
+                if !matches!(&self.tys[arg_ty], Ty::Ref(_) | Ty::NonZero(_)) {
+                    return Err(KdlScriptTypeError {
+                        message: "Option is only supported when it has a niche".to_string(),
+                        src: self.src.clone(),
+                        span: Spanned::span(arg),
+                        help: Some("use Option<&T> or Option<NonZero<T>>".to_string()),
+                    })?;
+                }
+                Ty::Option(OptionTy { some_ty: arg_ty })
+            }
+            _ if self.resolve_nominal_ty(name).is_some() => {
+                return Err(KdlScriptTypeError {
+                    message: format!("{name} isn't generic"),
+                    src: self.src.clone(),
+                    span: Spanned::span(name),
+                    help: Some("remove the generic arguments".to_string()),
+                })?;
+            }
+            _ => {
+                return Err(KdlScriptTypeError {
+                    message: format!("generic types aren't supported: {name}"),
+                    src: self.src.clone(),
+                    span: Spanned::span(name),
+                    help: Some(
+                        "only Option<T>, NonZero<T>, and types declared with generic parameters take generic arguments"
+                            .to_string(),
+                    ),
+                })?;
+            }
+        };
+        Ok(self.memoize_inner(ty))
+    }
+
+    /// Instantiate a user-defined generic decl (`Pair<u8, f64>`) as a nominal type.
+    ///
+    /// Each distinct list of arguments gets its own TyIdx, named after the decl
+    /// and its arguments (`Pair_u8_f64`) so that it can be emitted like any other type.
+    fn instantiate_generic(
+        &mut self,
+        name: &Ident,
+        decl: &TyDecl,
+        decl_src: Arc<NamedSource>,
+        args: &[Spanned<Tydent>],
+    ) -> Result<TyIdx> {
+        let params = decl.generic_params();
+        if params.len() != args.len() {
+            return Err(KdlScriptTypeError {
+                message: format!(
+                    "{name} takes {} generic arguments, but {} were given",
+                    params.len(),
+                    args.len()
+                ),
+                src: self.src.clone(),
+                span: Spanned::span(name),
+                help: None,
+            })?;
+        }
+        let arg_tys = args
+            .iter()
+            .map(|arg| self.memoize_ty(arg))
+            .collect::<Result<Vec<_>>>()?;
+        let key = (name.to_string(), arg_tys.clone());
+        if let Some(ty_idx) = self.generic_instances.get(&key) {
+            return Ok(*ty_idx);
+        }
+
+        let instance_name = std::iter::once(name.to_string())
+            .chain(arg_tys.iter().map(|ty| self.instance_name_part(*ty)))
+            .collect::<Vec<_>>()
+            .join("_");
+        let name_taken = self.resolve_nominal_ty(&instance_name).is_some()
+            || self.nominal_names.values().any(|n| *n == instance_name);
+        if name_taken {
+            return Err(KdlScriptTypeError {
+                message: format!(
+                    "{name}'s instance would be named {instance_name}, which is already taken"
+                ),
+                src: self.src.clone(),
+                span: Spanned::span(name),
+                help: Some("rename the other type".to_string()),
+            })?;
+        }
+
+        if self.generic_depth >= MAX_GENERIC_DEPTH {
+            return Err(KdlScriptTypeError {
+                message: format!("{name} is instantiated recursively without end"),
+                src: self.src.clone(),
+                span: Spanned::span(name),
+                help: Some(format!(
+                    "generic types can be nested at most {MAX_GENERIC_DEPTH} deep"
+                )),
+            })?;
+        }
+
+        // Register the instance before checking it, so it can refer to itself
+        let ty_idx = self.tys.len();
+        self.tys.push(Ty::Empty);
+        self.generic_instances.insert(key, ty_idx);
+        self.nominal_names.insert(ty_idx, instance_name.clone());
+
+        // The decl can only see the global types and its own parameters
+        let mut instance = decl.clone();
+        let decl_name = match &mut instance {
+            TyDecl::Struct(decl) => &mut decl.name,
+            TyDecl::Union(decl) => &mut decl.name,
+            TyDecl::Tagged(decl) => &mut decl.name,
+            TyDecl::Enum(_) | TyDecl::Alias(_) | TyDecl::Pun(_) => {
+                unreachable!("only structs, unions, and tagged unions can be generic")
+            }
+        };
+        let span = Spanned::span(&decl_name.val);
+        *decl_name = Ident::from(instance_name);
+        Spanned::update_span(
+            &mut decl_name.val,
+            span.offset(),
+            span.offset() + span.len(),
+        );
+
+        // Errors in the body should point at the decl, which may be in another file
+        let outer_src = std::mem::replace(&mut self.src, decl_src);
+        let outer_envs = self.envs.split_off(2);
+        self.envs.push(CheckEnv {
+            tys: params.iter().cloned().zip(arg_tys).collect(),
+        });
+        self.generic_depth += 1;
+        let ty = self.memoize_nominal_parts(&instance);
+        self.generic_depth -= 1;
+        self.envs.pop();
+        self.envs.extend(outer_envs);
+        self.src = outer_src;
+
+        self.tys[ty_idx] = ty?;
+        Ok(ty_idx)
+    }
+
+    /// How a generic argument shows up in the name of an instance (`u8` in `Pair_u8_f64`).
+    fn instance_name_part(&self, ty: TyIdx) -> String {
+        if let Some(name) = self.nominal_names.get(&ty) {
+            // Might not be complete yet, so don't look at the Ty
+            return name.clone();
+        }
+        match self.realize_ty(ty) {
+            Ty::Empty => "unit".to_string(),
+            Ty::Array(array_ty) => {
+                format!(
+                    "{}x{}",
+                    self.instance_name_part(array_ty.elem_ty),
+                    array_ty.len
+                )
+            }
+            Ty::Ref(ref_ty) => format!("ref_{}", self.instance_name_part(ref_ty.pointee_ty)),
+            Ty::Slice(slice_ty) => format!("slice_{}", self.instance_name_part(slice_ty.elem_ty)),
+            Ty::NonZero(nonzero_ty) => {
+                format!("NonZero_{}", self.instance_name_part(nonzero_ty.int_ty))
+            }
+            Ty::Option(option_ty) => {
+                format!("Option_{}", self.instance_name_part(option_ty.some_ty))
+            }
+            Ty::Primitive(_)
+            | Ty::Struct(_)
+            | Ty::Union(_)
+            | Ty::Enum(_)
+            | Ty::Tagged(_)
+            | Ty::Alias(_)
+            | Ty::Pun(_) => self.format_ty(ty),
+        }
+    }
+
     /// Converts a Ty (structural type with all subtypes resolved) into a TyIdx (type id).
     fn memoize_inner(&mut self, ty: Ty) -> TyIdx {
         if let Some(idx) = self.ty_map.get(&ty) {
@@ -834,7 +1367,20 @@ impl TyCtx {
                 Ty::Enum(_) => Some(TypeFact {
                     contains_ref: false,
                 }),
-                Ty::Ref(_) => Some(TypeFact { contains_ref: true }),
+                Ty::Ref(_) | Ty::Slice(_) => Some(TypeFact { contains_ref: true }),
+                Ty::NonZero(_) => Some(TypeFact {
+                    contains_ref: false,
+                }),
+                Ty::Option(ty) => {
+                    let child_tys = vec![ty.some_ty];
+                    aggregate_facts(
+                        self,
+                        &mut to_compute,
+                        &mut already_visited,
+                        ty_idx,
+                        child_tys,
+                    )?
+                }
 
                 Ty::Alias(ty) => {
                     let child_tys = vec![ty.real];
@@ -930,12 +1476,15 @@ impl TyCtx {
             Ty::Pun(ty) => Spanned::span(&ty.name),
             Ty::Array(ty) => self.span_for_ty_decl(ty.elem_ty),
             Ty::Ref(ty) => self.span_for_ty_decl(ty.pointee_ty),
+            Ty::Slice(ty) => self.span_for_ty_decl(ty.elem_ty),
+            Ty::NonZero(ty) => self.span_for_ty_decl(ty.int_ty),
+            Ty::Option(ty) => self.span_for_ty_decl(ty.some_ty),
         }
     }
     /// Stringify a type.
     pub fn format_ty(&self, ty: TyIdx) -> String {
         match self.realize_ty(ty) {
-            Ty::Primitive(prim) => format!("{:?}", prim).to_lowercase(),
+            Ty::Primitive(prim) => prim.to_string(),
             Ty::Empty => "()".to_string(),
             Ty::Struct(decl) => format!("{}", decl.name),
             Ty::Enum(decl) => format!("{}", decl.name),
@@ -951,6 +1500,18 @@ impl TyCtx {
                 let inner = self.format_ty(ref_ty.pointee_ty);
                 format!("&{}", inner)
             }
+            Ty::Slice(slice_ty) => {
+                let inner = self.format_ty(slice_ty.elem_ty);
+                format!("&[{}]", inner)
+            }
+            Ty::NonZero(nonzero_ty) => {
+                let inner = self.format_ty(nonzero_ty.int_ty);
+                format!("NonZero<{}>", inner)
+            }
+            Ty::Option(option_ty) => {
+                let inner = self.format_ty(option_ty.some_ty);
+                format!("Option<{}>", inner)
+            }
         }
     }
 }
@@ -992,6 +1553,16 @@ impl TypedProgram {
         0..self.builtin_funcs_start
     }
 
+    /// Metadata about the program (`description`, `tags`, ...)
+    pub fn meta(&self) -> &ProgramMeta {
+        &self.meta
+    }
+
+    /// The primitives the program says it `requires`
+    pub fn required_primitives(&self) -> &[PrimitiveTy] {
+        &self.required_primitives
+    }
+
     /// Resolve a [`PunTy`][] based on the current [`PunEnv`][].
     pub fn resolve_pun(&self, pun: &PunTy, env: &PunEnv) -> Result<TyIdx> {
         self.tcx.resolve_pun(pun, env)
@@ -1071,6 +1642,18 @@ impl TypedProgram {
                     let pointee_ty_node = nodes[ty.pointee_ty];
                     graph.update_edge(ty_node, pointee_ty_node, ());
                 }
+                Ty::Slice(ty) => {
+                    let elem_ty_node = nodes[ty.elem_ty];
+                    graph.update_edge(ty_node, elem_ty_node, ());
+                }
+                Ty::NonZero(ty) => {
+                    let int_ty_node = nodes[ty.int_ty];
+                    graph.update_edge(ty_node, int_ty_node, ());
+                }
+                Ty::Option(ty) => {
+                    let some_ty_node = nodes[ty.some_ty];
+                    graph.update_edge(ty_node, some_ty_node, ());
+                }
                 Ty::Enum(_) => {
                     // Arguably this can't depend on any types...
                     // BUT we should consider whether `@tag i32` is a dependency on i32!
@@ -1156,8 +1739,6 @@ impl DefinitionGraph {
     /// }
     /// ```
     ///
-    /// (Generics aren't currently supported, this is just easier to express.)
-    ///
     /// You will get recommended something like:
     ///
     /// 1. Define `u32`
@@ -1220,7 +1801,13 @@ impl DefinitionGraph {
             // Emit forward decls for everything but the first (last) node
             // Note that this cutely does The Right thing (no forward decl)
             // for the "happy" case of an SCC of one node (proper DAG).
-            for &node_idx in nodes.clone().skip(1) {
+            //
+            // The forward decls go in the original order though, so the nominal types
+            // are declared before any structural types (`&[MyType]`, `Option<&MyType>`)
+            // whose names refer to them.
+            let mut forward_decls = nodes.clone().skip(1).collect::<Vec<_>>();
+            forward_decls.reverse();
+            for &node_idx in forward_decls {
                 let node = &self.graph[node_idx];
                 match *node {
                     DefinitionGraphNode::Func(func_idx) => {
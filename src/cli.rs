@@ -1,7 +1,7 @@
 use crate::harness::test::*;
 use crate::harness::vals::*;
 use crate::toolchains::*;
-use crate::{files::Paths, Config, OutputFormat};
+use crate::{files::Paths, Config, OutputFormat, RunMode};
 
 use camino::Utf8PathBuf;
 use clap::Parser;
@@ -19,6 +19,11 @@ static DEFAULT_CONVENTIONS: &[CallingConvention] = &[
     CallingConvention::Stdcall,
     CallingConvention::Fastcall,
     CallingConvention::Vectorcall,
+    CallingConvention::System,
+    CallingConvention::Win64,
+    CallingConvention::Sysv64,
+    CallingConvention::CUnwind,
+    CallingConvention::SystemUnwind,
     // Rust!
     CallingConvention::Rust,
 ];
@@ -43,6 +48,12 @@ struct Cli {
     #[clap(long, short, value_delimiter(','))]
     tests: Vec<String>,
 
+    /// only run tests that have one of these tags (float, simd, ...)
+    ///
+    /// default: (all of them)
+    #[clap(long, value_delimiter(','))]
+    tags: Vec<String>,
+
     /// calling conventions to try for each test (c, rust, fastcall, ...)
     #[clap(long, short, value_delimiter(','))]
     #[clap(default_values_t = DEFAULT_CONVENTIONS.to_owned())]
@@ -82,8 +93,9 @@ struct Cli {
     /// "assert" uses asserts against the expected value
     /// "noop" emits no printing
     ///
-    /// Note that only "harness" mode can actually be *checked*. The other modes
-    /// exist for exporting the programs into a form that can be inspected/reported.
+    /// All of these are checked, but "harness" gives the most precise results. The other modes
+    /// mostly exist for exporting the programs into a form that can be inspected/reported
+    /// ("noop" can only tell if the program runs at all).
     #[clap(long, short, value_delimiter(','))]
     #[clap(default_values_t = vec![WriteImpl::HarnessCallback])]
     write_vals: Vec<WriteImpl>,
@@ -101,6 +113,24 @@ struct Cli {
     #[clap(default_value_t = WriteImpl::Print)]
     minimize_vals: WriteImpl,
 
+    /// when a test fails, also try to shrink the types involved in the failure
+    ///
+    /// This repeatedly rebuilds and reruns smaller versions of the failing function
+    /// (dropping fields and args, flattening nested structs, shortening arrays, ...),
+    /// and reports the smallest kdl-script program that still reproduces the failure.
+    /// This can be slow, as every attempt is a full build of the test!
+    #[clap(long)]
+    minimize_types: bool,
+
+    /// when a test fails, write its minimized version to a standalone cargo project in this dir
+    ///
+    /// Each project builds both sides with the same compilers and flags abi-cafe used,
+    /// and demonstrates the failure with `cargo run`. Its README records the compiler
+    /// versions and the expected vs observed output, for filing bug reports.
+    /// Requires --minimize-vals to be something other than "harness".
+    #[clap(long)]
+    export_repro: Option<Utf8PathBuf>,
+
     /// UNIMPLEMENTED: sugar for selecting all the test combo settings at once using
     /// the test key syntax. i.e. "mytest::conv_rust::repr_rust::rustc_calls_cc::random3"
     ///
@@ -112,6 +142,34 @@ struct Cli {
     #[clap(long, default_value_t = OutputFormat::Human)]
     output_format: OutputFormat,
 
+    /// add a rustc, with the syntax "toolchain_name:+rustup_toolchain" or "toolchain_name:path/to/rustc"
+    ///
+    /// toolchain_name here is an arbitrary id that will be used to uniquely identify
+    /// the rustc as a toolchain, for the purposes of --toolchains and --pairs.
+    /// e.g. `--add-rustc nightly:+nightly --pairs nightly_calls_rustc` checks that
+    /// nightly and the default rustc agree on the ABI (including conv_rust).
+    #[clap(long, value_delimiter(','))]
+    add_rustc: Vec<String>,
+
+    /// add a C toolchain, with the syntax "toolchain_name:flavor:path/to/cc[:flags[:path/to/ar]]"
+    ///
+    /// toolchain_name here is an arbitrary id that will be used to uniquely identify
+    /// the compiler as a toolchain, for the purposes of --toolchains and --pairs.
+    /// flavor is which builtin toolchain it should act like (gcc, clang, or zigcc;
+    /// msvc can't be customized), flags are space-separated, and the archiver defaults to "ar".
+    /// A leading drive letter (`C:\...`) is kept as part of the compiler path.
+    /// e.g. `--add-c-toolchain "gcc12:gcc:/usr/bin/gcc-12:-mno-sse"`
+    #[clap(long, value_parser = parse_c_toolchain)]
+    add_c_toolchain: Vec<CustomCToolchain>,
+
+    /// add a toolchain implemented by an external program, with the syntax "toolchain_name:path/to/plugin"
+    ///
+    /// toolchain_name here is an arbitrary id that will be used to uniquely identify
+    /// the plugin as a toolchain, for the purposes of --toolchains and --pairs.
+    /// abi-cafe talks to the plugin with JSON over stdin/stdout, see the docs for details.
+    #[clap(long, value_delimiter(','))]
+    add_plugin_toolchain: Vec<String>,
+
     /// add a rustc_codegen_backend, with the syntax "toolchain_name:path/to/backend"
     ///
     /// toolchain_name here is an arbitrary id that will be used to uniquely identify
@@ -157,14 +215,93 @@ struct Cli {
     #[clap(long)]
     disable_builtin_rules: bool,
 
+    /// add this many randomly generated tests to the test suite
+    ///
+    /// Each test is a random kdl-script program (types and function signatures),
+    /// named "fuzzN" where N is the seed it was generated from. So if "fuzz17" fails,
+    /// you can reproduce it with `--fuzz-types 1 --fuzz-seed 17`.
+    #[clap(long, default_value_t = 0)]
+    fuzz_types: u64,
+
+    /// the seed for the first test of --fuzz-types (subsequent tests increment it)
+    #[clap(long, default_value_t = 0)]
+    fuzz_seed: u64,
+
+    /// how to run the tests (bin, dylib)
+    ///
+    /// "bin" links each test into its own executable and runs it.
+    ///
+    /// "dylib" links each test into a dylib, which is loaded and run by a
+    /// long-lived worker process that forks for each test (so a crashing
+    /// test still can't take down abi-cafe, but tests don't pay for spawning
+    /// a process).
+    #[clap(long, default_value_t = RunMode::Bin)]
+    run_mode: RunMode,
+
+    /// internal: run the test dylib at this path, reporting results to the harness channel (see --run-mode)
+    #[clap(long, hide = true)]
+    dylib_worker: Option<Utf8PathBuf>,
+
+    /// internal: run the test dylibs sent over stdin, one after another (see --run-mode)
+    #[clap(long, hide = true)]
+    dylib_server: bool,
+
     /// deprecated, does nothing (we always procgen now)
     #[clap(long, hide = true)]
     procgen_tests: bool,
 }
 
+/// Parse an `--add-c-toolchain` spec
+fn parse_c_toolchain(spec: &str) -> Result<CustomCToolchain, String> {
+    let syntax = "must be 'impl_name:flavor:path/to/cc[:flags[:path/to/ar]]'";
+    let mut parts = spec.splitn(3, ':');
+    let (Some(id), Some(flavor), Some(spec_rest)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(format!("invalid syntax, {syntax}"));
+    };
+    if id.is_empty() {
+        return Err(format!("missing toolchain name, {syntax}"));
+    }
+    match flavor {
+        TOOLCHAIN_GCC | TOOLCHAIN_CLANG | TOOLCHAIN_ZIGCC => {}
+        TOOLCHAIN_MSVC => {
+            return Err("msvc can't be used as the flavor of a custom C toolchain".to_owned())
+        }
+        _ => {
+            return Err(format!(
+                "unknown C compiler flavor {flavor:?} (expected gcc, clang, or zigcc)"
+            ))
+        }
+    }
+    // Don't mistake the colon of a windows drive letter for a separator
+    let drive_len = match spec_rest.as_bytes() {
+        [letter, b':', b'\\' | b'/', ..] if letter.is_ascii_alphabetic() => 2,
+        _ => 0,
+    };
+    let (compiler, rest) = match spec_rest[drive_len..].split_once(':') {
+        Some((path, rest)) => (&spec_rest[..drive_len + path.len()], Some(rest)),
+        None => (spec_rest, None),
+    };
+    if compiler.is_empty() {
+        return Err(format!("missing compiler path, {syntax}"));
+    }
+    let (flags, archiver) = match rest {
+        Some(rest) => rest.split_once(':').unwrap_or((rest, "ar")),
+        None => ("", "ar"),
+    };
+    Ok(CustomCToolchain {
+        id: id.to_owned(),
+        flavor: flavor.to_owned(),
+        compiler: compiler.to_owned(),
+        flags: flags.split_whitespace().map(String::from).collect(),
+        archiver: archiver.to_owned(),
+    })
+}
+
 pub fn make_app() -> Config {
     let Cli {
         tests,
+        tags,
         conventions,
         reprs,
         toolchains,
@@ -172,12 +309,22 @@ pub fn make_app() -> Config {
         gen_vals,
         write_vals,
         minimize_vals,
+        minimize_types,
+        export_repro,
         output_format,
+        run_mode,
+        dylib_worker,
+        dylib_server,
+        add_rustc,
+        add_c_toolchain,
+        add_plugin_toolchain,
         add_rustc_codegen_backend,
         add_tests,
         rules,
         disable_builtin_tests,
         disable_builtin_rules,
+        fuzz_types,
+        fuzz_seed,
         debug,
         // unimplemented
         select_vals: _,
@@ -187,6 +334,7 @@ pub fn make_app() -> Config {
     } = Cli::parse();
 
     let run_tests = tests;
+    let run_tags = tags;
     let run_toolchains = toolchains;
     let run_conventions = conventions;
     let run_reprs = reprs;
@@ -219,6 +367,57 @@ pub fn make_app() -> Config {
         run_pairs = pairs.into_iter().collect();
     }
 
+    let rustcs: Vec<(String, String)> = add_rustc
+        .iter()
+        .map(|pair| {
+            pair.split_once(':').expect(
+                "invalid syntax, must be 'impl_name:+toolchain' or 'impl_name:path/to/rustc'",
+            )
+        })
+        .map(|(a, b)| (String::from(a), String::from(b)))
+        .collect();
+
+    for (name, _spec) in &rustcs {
+        if !run_pairs.iter().any(|(a, b)| a == name || b == name) {
+            warn!(
+                "Rustc `{name}` is not tested.
+Hint: Try using `--pairs {name}_calls_rustc` or `--pairs rustc_calls_{name}`.
+"
+            );
+        }
+    }
+
+    let c_toolchains = add_c_toolchain;
+
+    for CustomCToolchain { id: name, .. } in &c_toolchains {
+        if !run_pairs.iter().any(|(a, b)| a == name || b == name) {
+            warn!(
+                "C toolchain `{name}` is not tested.
+Hint: Try using `--pairs {name}_calls_cc` or `--pairs cc_calls_{name}`.
+"
+            );
+        }
+    }
+
+    let plugin_toolchains: Vec<(String, String)> = add_plugin_toolchain
+        .iter()
+        .map(|pair| {
+            pair.split_once(':')
+                .expect("invalid syntax, must be 'impl_name:path/to/plugin'")
+        })
+        .map(|(a, b)| (String::from(a), String::from(b)))
+        .collect();
+
+    for (name, _path) in &plugin_toolchains {
+        if !run_pairs.iter().any(|(a, b)| a == name || b == name) {
+            warn!(
+                "Plugin toolchain `{name}` is not tested.
+Hint: Try using `--pairs {name}_calls_cc` or `--pairs cc_calls_{name}`.
+"
+            );
+        }
+    }
+
     let rustc_codegen_backends: Vec<(String, String)> = add_rustc_codegen_backend
         .iter()
         .map(|pair| {
@@ -277,19 +476,30 @@ Hint: Try using `--pairs {name}_calls_rustc` or `--pairs rustc_calls_{name}`.
     };
     Config {
         output_format,
+        run_mode,
         run_conventions,
         run_reprs,
         run_toolchains,
         run_tests,
+        run_tags,
         run_pairs,
+        rustcs,
+        c_toolchains,
+        plugin_toolchains,
         rustc_codegen_backends,
         run_values,
         run_writers,
         run_selections,
         minimizing_write_impl,
+        minimize_types,
+        export_repro,
         disable_builtin_tests,
         disable_builtin_rules,
+        fuzz_types,
+        fuzz_seed,
         paths,
         debug,
+        dylib_worker,
+        dylib_server,
     }
 }
@@ -43,6 +43,10 @@ pub enum GenerateError {
         block2: String,
         block2_val_count: usize,
     },
+    #[error("{context} has an unknown calling convention \"{convention}\"")]
+    UnknownConvention { context: String, convention: String },
+    #[error("plugin toolchain {plugin} failed to generate source\n{message}")]
+    Plugin { plugin: String, message: String },
     #[error("failed to read and parse test {test}")]
     ReadTest {
         test: TestId,
@@ -68,6 +72,14 @@ pub enum BuildError {
     RustCompile(std::process::Output),
     #[error("c compile error\n{0}")]
     CCompile(#[from] cc::Error),
+    #[error("zig compile error \n{} \n{}",
+        String::from_utf8_lossy(&.0.stdout),
+        String::from_utf8_lossy(&.0.stderr))]
+    ZigCompile(std::process::Output),
+    #[error("plugin compile error \n{} \n{}",
+        String::from_utf8_lossy(&.0.stdout),
+        String::from_utf8_lossy(&.0.stderr))]
+    PluginCompile(std::process::Output),
 }
 
 #[allow(clippy::enum_variant_names)]
@@ -120,6 +132,42 @@ pub enum CheckFailure {
         caller: String,
         callee: String,
     },
+    #[error(
+        "    func {func_name} should have unwound into the caller
+        callee unwound: {callee_unwound}
+        caller caught it: {caller_caught}"
+    )]
+    UnwindMismatch {
+        func_idx: usize,
+        func_name: String,
+        callee_unwound: bool,
+        caller_caught: bool,
+    },
+    #[error(
+        "    func {func_name} should have aborted (it unwinds, but its convention can't)
+        callee unwound: {callee_unwound}
+        caller caught it: {caller_caught}"
+    )]
+    AbortMismatch {
+        func_idx: usize,
+        func_name: String,
+        callee_unwound: bool,
+        caller_caught: bool,
+    },
+    #[error(
+        "    func {func_name}'s {side} failed an assert
+      the value was {val_path}: {val_ty_name}"
+    )]
+    AssertFailed {
+        func_idx: usize,
+        val_idx: usize,
+        func_name: String,
+        side: String,
+        val_path: String,
+        val_ty_name: String,
+    },
+    #[error("    func {func_name} never ran (a previous assert aborted the process)")]
+    NotRun { func_idx: usize, func_name: String },
 }
 
 #[derive(Debug, thiserror::Error, Diagnostic)]
@@ -141,6 +189,10 @@ pub enum RunError {
         bin: camino::Utf8PathBuf,
         e: std::io::Error,
     },
+    #[error("failed to open harness channel {channel:?}\n  {e}")]
+    ChannelError { channel: String, e: std::io::Error },
+    #[error("dylib worker failed\n  {0}")]
+    WorkerError(std::io::Error),
     #[error("test impl didn't call set_func before calling write_val")]
     MissingSetFunc,
     #[error("test impl called write_val on func {func} val {val} twice")]
@@ -160,6 +212,14 @@ pub enum RunError {
         callee_val_idx: usize,
         callee_func: String,
     },
+    #[error("test impl aborted while fn {func} was unwinding: {status}")]
+    UnwindAborted {
+        status: std::process::ExitStatus,
+        func_idx: usize,
+        func: String,
+    },
+    #[error("fn {func} unwound all the way out of the caller without being caught")]
+    UnwindEscaped { func_idx: usize, func: String },
     #[error("test impl sent invalid messages to harness (executed some kind of UB?)")]
     InvalidMessages {
         caller_funcs: TestBuffer,
@@ -167,6 +227,21 @@ pub enum RunError {
     },
 }
 
+#[derive(Debug, thiserror::Error, Diagnostic)]
+pub enum ExportError {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Unsupported(#[from] UnsupportedError),
+    #[error("io error\n{0}")]
+    Io(#[from] std::io::Error),
+    #[error("io error\n{0}")]
+    Fmt(#[from] std::fmt::Error),
+    #[error("{0} has nothing to export (it didn't generate sources)")]
+    NoSource(String),
+    #[error("not exporting to {0}, it already exists and wasn't made by --export-repro")]
+    WontOverwrite(camino::Utf8PathBuf),
+}
+
 fn fmt_bytes(bytes: &[u8]) -> String {
     bytes
         .iter()
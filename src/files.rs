@@ -76,6 +76,11 @@ pub fn get_file(path: impl AsRef<Utf8Path>) -> String {
     load_file(file)
 }
 
+/// Like [`get_file`][] but for paths that come from users (like a test's imports)
+pub fn find_file(path: impl AsRef<Utf8Path>) -> Option<String> {
+    INCLUDES.get_file(path.as_ref()).map(load_file)
+}
+
 pub fn load_file(file: &File) -> String {
     let Some(string) = file.contents_utf8() else {
         unreachable!("embedded file wasn't utf8: {}", file.path().display());
@@ -60,7 +60,6 @@ impl TestHarness {
         Ok(real_lib_name)
     }
 
-    #[allow(dead_code)]
     pub async fn link_dylib(
         &self,
         key: &TestKey,
@@ -73,7 +72,14 @@ impl TestHarness {
             .expect("failed to acquire concurrency limit semaphore");
         let dynamic_lib_name = self.dynamic_lib_name(key);
         info!("linking     {dynamic_lib_name}");
-        build_harness_dylib(&self.toolchains, &self.paths, build, &dynamic_lib_name)
+        let link_args = self.dynamic_side_link_args(key, true)?;
+        build_harness_dylib(
+            &self.toolchains,
+            &self.paths,
+            build,
+            &dynamic_lib_name,
+            &link_args,
+        )
     }
 
     pub async fn link_bin(
@@ -93,11 +99,75 @@ impl TestHarness {
         } else {
             self.paths.freestanding_bin_main_file()
         };
-        build_harness_main(&self.toolchains, &self.paths, build, &bin_name, &bin_main)
+        let link_args = self.dynamic_side_link_args(key, false)?;
+        build_harness_main(
+            &self.toolchains,
+            &self.paths,
+            build,
+            &bin_name,
+            &bin_main,
+            &link_args,
+        )
+    }
+
+    /// Whether this side was built by a different rustc than the one we link with.
+    ///
+    /// Those sides are built as cdylibs (see `RustcToolchain::hide_std_from`).
+    pub fn is_dynamic_side(&self, key: &TestKey, call_side: CallSide) -> bool {
+        let linker_version = self.toolchains.toolchains[TOOLCHAIN_RUSTC].version();
+        let toolchain = self.toolchain_by_test_key(key, call_side);
+        toolchain.lang() == "rust" && toolchain.version() != linker_version
+    }
+
+    pub fn has_dynamic_sides(&self, key: &TestKey) -> bool {
+        self.is_dynamic_side(key, CallSide::Caller) || self.is_dynamic_side(key, CallSide::Callee)
+    }
+
+    /// Whether we know how to link sides that were built as cdylibs.
+    ///
+    /// Those rely on the dynamic linker finding the harness's symbols (and in the
+    /// dylib run mode, the callee's) at runtime, which dlls can't do at all, and
+    /// which we don't tell ld64 how to do.
+    pub fn supports_dynamic_sides(&self) -> bool {
+        let target = &self.toolchains.platform_info.target;
+        !target.contains("windows") && !target.contains("apple")
+    }
+
+    /// Extra arguments for linking a test with sides that were built as cdylibs.
+    fn dynamic_side_link_args(&self, key: &TestKey, dylib: bool) -> Result<Vec<String>, LinkError> {
+        let mut args = vec![];
+        if !self.has_dynamic_sides(key) {
+            return Ok(args);
+        }
+        // The sides sit right next to the test
+        args.push("-Clink-arg=-Wl,-rpath,$ORIGIN".to_owned());
+        if dylib
+            && self.is_dynamic_side(key, CallSide::Caller)
+            && !self.is_dynamic_side(key, CallSide::Callee)
+        {
+            // The caller looks up the callee's functions when it's loaded, but a cdylib
+            // only exports its own rust items. Executables export whatever the libs they
+            // link against need, so the bin run mode doesn't need this.
+            let test = self.test(&key.test);
+            let mut script = String::from("{\n  global:\n");
+            for func in key.options.active_funcs(&test.types) {
+                let func = test.types.realize_func(func);
+                script.push_str(&format!("    {};\n", func.name));
+            }
+            script.push_str("};\n");
+            let script_path = self
+                .paths
+                .out_dir
+                .join(format!("{}.map", self.base_id(key, None, "_")));
+            std::fs::write(&script_path, script)?;
+            args.push(format!("-Clink-arg=-Wl,--version-script={script_path}"));
+        }
+        Ok(args)
     }
 
     fn static_lib_name(&self, key: &TestKey, call_side: CallSide) -> String {
-        self.base_id(key, Some(call_side), "_")
+        // rustc turns `-` into `_` when deriving crate names (and so lib names) from paths
+        self.base_id(key, Some(call_side), "_").replace('-', "_")
     }
 
     fn dynamic_lib_name(&self, key: &TestKey) -> String {
@@ -136,6 +206,7 @@ fn build_harness_dylib(
     paths: &Paths,
     build: &BuildOutput,
     dynamic_lib_name: &str,
+    link_args: &[String],
 ) -> Result<LinkOutput, LinkError> {
     let target = &toolchains.platform_info.target;
     let rustc = &toolchains.rustc_command;
@@ -163,6 +234,7 @@ fn build_harness_dylib(
     if toolchains.debug {
         cmd.arg("-g");
     }
+    cmd.args(link_args);
 
     debug!("running: {:?}", cmd);
     let out = cmd.output()?;
@@ -181,6 +253,7 @@ fn build_harness_main(
     build: &BuildOutput,
     bin_name: &str,
     bin_main: &Utf8Path,
+    link_args: &[String],
 ) -> Result<LinkOutput, LinkError> {
     let target = &toolchains.platform_info.target;
     let rustc = &toolchains.rustc_command;
@@ -207,6 +280,7 @@ fn build_harness_main(
     if toolchains.debug {
         cmd.arg("-g");
     }
+    cmd.args(link_args);
 
     debug!("running: {:?}", cmd);
     let out = cmd.output()?;
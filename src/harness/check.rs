@@ -14,6 +14,7 @@ impl TestHarness {
         RunOutput {
             caller_funcs,
             callee_funcs,
+            failed_assert,
         }: &RunOutput,
     ) -> CheckOutput {
         let test = self
@@ -31,16 +32,53 @@ impl TestHarness {
         let mut results: Vec<SubtestDetails> = Vec::new();
 
         // `Run` already checks that this length is congruent with all the inputs/outputs Vecs
-        let expected_funcs = key.options.functions.active_funcs(&test.types);
+        let expected_funcs = key.options.active_funcs(&test.types);
 
         // Layer 1 is the funcs/subtests. Because we have already checked
         // that they agree on their lengths, we can zip them together
         // to walk through their views of each subtest's execution.
+        let mut aborted = false;
         'funcs: for func_idx in expected_funcs {
+            let func = test.types.realize_func(func_idx);
+            let func_name = func.name.to_string();
+            let TestRulesPattern { check, reason, .. } =
+                self.get_subtest_rules(key, func_idx, &func_name);
+
+            // Tests that assert their own values only tell us about the first failure
+            // (and stop running there), and noop tests don't tell us anything at all.
+            if let WriteImpl::Assert | WriteImpl::Noop = options.val_writer {
+                let failed = failed_assert.as_ref().filter(|a| a.func_idx == func_idx);
+                let result = match failed {
+                    _ if aborted => Err(CheckFailure::NotRun {
+                        func_idx,
+                        func_name: func_name.clone(),
+                    }),
+                    Some(failed) => Err(assert_error(&test, failed)),
+                    None => Ok(()),
+                };
+                results.push(SubtestDetails {
+                    func_name,
+                    result,
+                    minimized: None,
+                    minimized_kdl: None,
+                    exported_repro: None,
+                    expect: check,
+                    reason,
+                });
+                aborted |= failed.is_some();
+                continue;
+            }
+
             let caller_func = caller_funcs.funcs.get(func_idx).unwrap_or(&empty_func);
             let callee_func = callee_funcs.funcs.get(func_idx).unwrap_or(&empty_func);
             let mut expected_vals = vec![];
-            for arg in test.vals.at_func(func_idx) {
+            // A function that unwinds never produces its outputs
+            let num_args = if func.unwinds() {
+                func.inputs.len()
+            } else {
+                func.inputs.len() + func.outputs.len()
+            };
+            for arg in test.vals.at_func(func_idx).take(num_args) {
                 for val in arg {
                     if val.should_write_val(options) {
                         expected_vals.push(val);
@@ -54,8 +92,13 @@ impl TestHarness {
                 let callee_val = callee_func.vals.get(val_idx).unwrap_or(&empty_val);
                 if let Err(e) = self.check_val(&test, expected_val, caller_val, callee_val) {
                     results.push(SubtestDetails {
+                        func_name,
                         result: Err(e),
                         minimized: None,
+                        minimized_kdl: None,
+                        exported_repro: None,
+                        expect: check,
+                        reason,
                     });
                     // FIXME: now that each value is absolutely indexed,
                     // we should be able to check all the values independently
@@ -65,10 +108,37 @@ impl TestHarness {
                 }
             }
 
-            // If we got this far then the test passes
+            let result = if options.aborts(func) {
+                // The unwind should have killed the process before the caller could catch it
+                if callee_func.unwound && !caller_func.unwound {
+                    Ok(())
+                } else {
+                    Err(CheckFailure::AbortMismatch {
+                        func_idx,
+                        func_name: func_name.clone(),
+                        callee_unwound: callee_func.unwound,
+                        caller_caught: caller_func.unwound,
+                    })
+                }
+            } else if func.unwinds() && !(callee_func.unwound && caller_func.unwound) {
+                Err(CheckFailure::UnwindMismatch {
+                    func_idx,
+                    func_name: func_name.clone(),
+                    callee_unwound: callee_func.unwound,
+                    caller_caught: caller_func.unwound,
+                })
+            } else {
+                // If we got this far then the test passes
+                Ok(())
+            };
             results.push(SubtestDetails {
-                result: Ok(()),
+                func_name,
+                result,
                 minimized: None,
+                minimized_kdl: None,
+                exported_repro: None,
+                expect: check,
+                reason,
             });
         }
 
@@ -84,7 +154,11 @@ impl TestHarness {
             .collect::<Vec<_>>();
         let max_name_len = names.iter().fold(0, |max, name| max.max(name.len()));
         let num_passed = results.iter().filter(|t| t.result.is_ok()).count();
-        let all_passed = num_passed == results.len();
+        // Subtests with their own rules are graded by those, so a test "passes"
+        // as long as none of its subtests had an unexpected result.
+        let all_passed = results
+            .iter()
+            .all(|t| t.conclusion() != TestConclusion::Failed);
 
         if !all_passed {
             for (subtest_name, subtest) in names.iter().zip(&results) {
@@ -92,6 +166,12 @@ impl TestHarness {
                     Ok(()) => {
                         info!("Test {subtest_name:width$} passed", width = max_name_len);
                     }
+                    Err(_) if subtest.conclusion() != TestConclusion::Failed => {
+                        info!(
+                            "Test {subtest_name:width$} failed (expected)",
+                            width = max_name_len
+                        );
+                    }
                     Err(e) => {
                         let red = console::Style::new().red();
                         let message = format!(
@@ -156,6 +236,30 @@ impl TestHarness {
                 let callee = enum_variant_name(enum_ty, callee_tag);
                 return Err(tag_error(types, &expected_val, expected, caller, callee));
             }
+
+            // The variants matched, but also check the raw discriminants that follow the tag,
+            // in case the two sides disagree on the enum's size or what the values are.
+            let caller_raw = caller_val.bytes.get(4..).unwrap_or_default();
+            let callee_raw = callee_val.bytes.get(4..).unwrap_or_default();
+            let discriminant = enum_ty.variants[expected_tag].val;
+            let expected = enum_discriminant_bytes(discriminant, caller_raw.len());
+            if caller_raw != callee_raw || caller_raw != expected {
+                let func = expected_val.func();
+                let arg = expected_val.arg();
+                return Err(CheckFailure::ValMismatch {
+                    func_idx: expected_val.func_idx,
+                    arg_idx: expected_val.arg_idx,
+                    val_idx: expected_val.val_idx,
+                    func_name: func.func_name.to_string(),
+                    arg_name: arg.arg_name.to_string(),
+                    arg_ty_name: types.format_ty(arg.ty),
+                    val_path: expected_val.path.to_string(),
+                    val_ty_name: types.format_ty(expected_val.ty),
+                    expected,
+                    caller: caller_raw.to_vec(),
+                    callee: callee_raw.to_vec(),
+                });
+            }
         } else if let Ty::Primitive(PrimitiveTy::Bool) = types.realize_ty(expected_val.ty) {
             let expected_tag = expected_val.generate_idx(2);
             let caller_tag = load_tag(caller_val);
@@ -172,9 +276,15 @@ impl TestHarness {
             let func = expected_val.func();
             let arg = expected_val.arg();
             let mut expected = vec![0; caller_val.bytes.len().max(callee_val.bytes.len())];
-            expected_val.fill_bytes(&mut expected);
-            // FIXME: this doesn't do the right thing for enums
-            // <https://github.com/Gankra/abi-cafe/issues/34>
+            let bit_int = match types.realize_ty(expected_val.ty) {
+                Ty::Primitive(prim) => prim.bit_int(),
+                _ => None,
+            };
+            if let Some((bits, signed)) = bit_int {
+                expected_val.fill_bit_int_bytes(&mut expected, bits, signed);
+            } else {
+                expected_val.fill_bytes(&mut expected);
+            }
             return Err(CheckFailure::ValMismatch {
                 func_idx: expected_val.func_idx,
                 arg_idx: expected_val.arg_idx,
@@ -200,6 +310,16 @@ fn load_tag(val: &ValBuffer) -> Option<usize> {
     Some(u32::from_ne_bytes(bytes) as usize)
 }
 
+/// The bytes an enum with this discriminant should have, if it's `len` bytes big
+fn enum_discriminant_bytes(val: i64, len: usize) -> Vec<u8> {
+    let mut bytes = (val as i128).to_le_bytes().to_vec();
+    bytes.resize(len, if val < 0 { 0xFF } else { 0 });
+    if cfg!(target_endian = "big") {
+        bytes.reverse();
+    }
+    bytes
+}
+
 fn tagged_variant_name(tagged_ty: &kdl_script::types::TaggedTy, tag: Option<usize>) -> String {
     let Some(tag) = tag else {
         return "<tag never recorded?>".to_owned();
@@ -271,3 +391,26 @@ fn tag_error(
         callee,
     }
 }
+
+fn assert_error(test: &TestWithVals, failed: &FailedAssert) -> CheckFailure {
+    let types = &test.types;
+    let func_name = types.realize_func(failed.func_idx).name.to_string();
+    // Find the value the test was asserting on
+    let val = test
+        .vals
+        .at_func(failed.func_idx)
+        .flatten()
+        .find(|val| val.absolute_val_idx == failed.val_idx);
+    let (val_path, val_ty_name) = match val {
+        Some(val) => (val.path.to_string(), types.format_ty(val.ty)),
+        None => ("<unknown value>".to_owned(), "<unknown type>".to_owned()),
+    };
+    CheckFailure::AssertFailed {
+        func_idx: failed.func_idx,
+        val_idx: failed.val_idx,
+        func_name,
+        side: failed.side.to_string(),
+        val_path,
+        val_ty_name,
+    }
+}
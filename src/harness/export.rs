@@ -0,0 +1,322 @@
+//! Exporting minimized failures as standalone cargo projects (`--export-repro`).
+//!
+//! The exported project contains the generated caller/callee sources, a build.rs
+//! that compiles them with the same commands abi-cafe used, and a main.rs that
+//! calls into the caller. Nothing in it depends on abi-cafe, so it can be handed
+//! to whoever maintains the compiler that's misbehaving.
+
+use std::fmt::Write;
+use std::process::Command;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::info;
+
+use crate::error::*;
+use crate::harness::report::*;
+use crate::harness::test::*;
+use crate::toolchains::{ReproCommand, ReproOutput};
+use crate::*;
+
+impl TestHarness {
+    /// Write a standalone project reproducing the failure in `results` to a subdirectory of `dir`.
+    ///
+    /// Returns the path of the project.
+    pub fn export_repro(
+        &self,
+        dir: &Utf8Path,
+        results: &TestRunResults,
+    ) -> Result<Utf8PathBuf, ExportError> {
+        let key = &results.key;
+        if key.options.val_writer == WriteImpl::HarnessCallback {
+            return Err(UnsupportedError::Other(
+                "the harness value writer can't be exported, use --minimize-vals".to_owned(),
+            ))?;
+        }
+        let Some(Ok(source)) = &results.source else {
+            return Err(ExportError::NoSource(self.full_test_name(key)));
+        };
+
+        let project_dir = dir.join(self.base_id(key, None, "_"));
+        if !is_replaceable(&project_dir) {
+            return Err(ExportError::WontOverwrite(project_dir));
+        }
+        files::clear_and_create_dir(&project_dir);
+        std::fs::create_dir_all(project_dir.join("src"))?;
+        info!("exporting   {project_dir}");
+
+        let mut sides = vec![];
+        for (call_side, src) in [
+            (CallSide::Caller, &source.caller_src),
+            (CallSide::Callee, &source.callee_src),
+        ] {
+            let toolchain = self.toolchain_by_test_key(key, call_side);
+            let name = call_side.name();
+            let command = toolchain.repro_command(name)?;
+            let src_name = match src.extension() {
+                Some(ext) => format!("{name}.{ext}"),
+                None => name.to_owned(),
+            };
+            std::fs::copy(src, project_dir.join(&src_name))?;
+            sides.push(ReproSide {
+                call_side,
+                toolchain: key.toolchain_id(call_side).to_owned(),
+                src_name,
+                version: tool_version(&command.program, command.version_arg),
+                command,
+            });
+        }
+
+        let package = r#"[package]
+name = "abi-cafe-repro"
+version = "0.1.0"
+edition = "2021"
+publish = false
+
+# Not part of any enclosing workspace
+[workspace]
+"#;
+        std::fs::write(project_dir.join("Cargo.toml"), package)?;
+        std::fs::write(project_dir.join("build.rs"), build_script(&sides)?)?;
+        std::fs::write(
+            project_dir.join("src").join("main.rs"),
+            files::get_file("harness/main.rs"),
+        )?;
+        std::fs::write(project_dir.join("README.md"), self.readme(results, &sides)?)?;
+
+        Ok(project_dir)
+    }
+
+    fn readme(&self, results: &TestRunResults, sides: &[ReproSide]) -> Result<String, ExportError> {
+        let key = &results.key;
+        let options = &key.options;
+        let mut f = String::new();
+        writeln!(f, "{README_MARKER}")?;
+        writeln!(f, "# {}", self.full_test_name(key))?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "An ABI mismatch between {} (caller) and {} (callee), found by abi-cafe.",
+            key.caller, key.callee
+        )?;
+        writeln!(
+            f,
+            "Run `cargo run` to build both sides with the commands below and call between them."
+        )?;
+        writeln!(
+            f,
+            "build.rs runs these commands as-is (not through the `cc` crate), \
+             so the compilers they name need to be on your PATH."
+        )?;
+        writeln!(f)?;
+        writeln!(f, "* test: {}", key.test)?;
+        writeln!(f, "* calling convention: {}", options.convention)?;
+        writeln!(f, "* repr: {}", options.repr)?;
+        writeln!(f, "* values: {}", options.val_generator)?;
+        writeln!(f, "* writer: {}", options.val_writer)?;
+
+        for side in sides {
+            writeln!(f)?;
+            writeln!(f, "## {} ({})", side.call_side, side.toolchain)?;
+            writeln!(f)?;
+            writeln!(f, "```text")?;
+            writeln!(f, "{}", side.command_line())?;
+            writeln!(f)?;
+            writeln!(f, "$ {} {}", side.command.program, side.command.version_arg)?;
+            writeln!(f, "{}", side.version.trim_end())?;
+            writeln!(f, "```")?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "## expected")?;
+        writeln!(f)?;
+        let expected = match options.val_writer {
+            WriteImpl::Print => {
+                "The program prints every value the caller and callee see. For each \
+                 value the caller and callee should print the same bytes."
+            }
+            WriteImpl::Assert => {
+                "The program exits successfully. Each side checks the values it \
+                 receives and exits with status 100 + the index of the first \
+                 value that doesn't match."
+            }
+            WriteImpl::Noop | WriteImpl::HarnessCallback => "The program exits successfully.",
+        };
+        writeln!(f, "{expected}")?;
+
+        writeln!(f)?;
+        writeln!(f, "## observed")?;
+        writeln!(f)?;
+        writeln!(f, "```text")?;
+        if let Some(Err(e)) = &results.run {
+            writeln!(f, "{e}")?;
+        }
+        if let Some(check) = &results.check {
+            for e in check
+                .subtest_checks
+                .iter()
+                .filter_map(|sub| sub.result.as_ref().err())
+            {
+                writeln!(f, "{e}")?;
+            }
+        }
+        writeln!(f, "```")?;
+        if let Some(log) = &results.run_log {
+            for (name, output) in [("stdout", &log.stdout), ("stderr", &log.stderr)] {
+                if output.trim().is_empty() {
+                    continue;
+                }
+                writeln!(f)?;
+                writeln!(f, "{name}:")?;
+                writeln!(f)?;
+                writeln!(f, "```text")?;
+                writeln!(f, "{}", output.trim_end())?;
+                writeln!(f, "```")?;
+            }
+        }
+        Ok(f)
+    }
+}
+
+struct ReproSide {
+    call_side: CallSide,
+    toolchain: ToolchainId,
+    src_name: String,
+    command: ReproCommand,
+    version: String,
+}
+
+impl ReproSide {
+    fn output_name(&self) -> String {
+        let name = self.call_side.name();
+        match self.command.output {
+            ReproOutput::StaticLib => format!("lib{name}.a"),
+            ReproOutput::DynamicLib => {
+                use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+                format!("{DLL_PREFIX}{name}{DLL_SUFFIX}")
+            }
+            ReproOutput::Object => format!("{name}.o"),
+        }
+    }
+
+    /// The command as a human would type it
+    fn command_line(&self) -> String {
+        let mut line = self.command.program.clone();
+        for arg in &self.command.args {
+            line.push(' ');
+            line.push_str(arg);
+        }
+        match self.command.emit_flag {
+            Some(flag) => line.push_str(&format!(" {flag}{}", self.output_name())),
+            None => line.push_str(&format!(" -o {}", self.output_name())),
+        }
+        line.push_str(&format!(" {}", self.src_name));
+        line
+    }
+}
+
+/// The first line of an exported README, so we know which directories are ours to replace
+const README_MARKER: &str = "<!-- exported by abi-cafe --export-repro -->";
+
+/// Whether we can (re)export to this directory without destroying something of the user's
+fn is_replaceable(project_dir: &Utf8Path) -> bool {
+    let Ok(mut entries) = std::fs::read_dir(project_dir) else {
+        // Doesn't exist (or isn't a directory, which create_dir will complain about)
+        return true;
+    };
+    if entries.next().is_none() {
+        return true;
+    }
+    std::fs::read_to_string(project_dir.join("README.md"))
+        .is_ok_and(|readme| readme.starts_with(README_MARKER))
+}
+
+/// Get the version of a compiler, for the README.
+fn tool_version(program: &str, version_arg: &str) -> String {
+    match Command::new(program).arg(version_arg).output() {
+        Ok(out) => {
+            let mut version = String::from_utf8_lossy(&out.stdout).into_owned();
+            version.push_str(&String::from_utf8_lossy(&out.stderr));
+            version
+        }
+        Err(e) => format!("<couldn't run {program}: {e}>"),
+    }
+}
+
+fn build_script(sides: &[ReproSide]) -> Result<String, std::fmt::Error> {
+    let mut f = String::new();
+    writeln!(f, "use std::path::PathBuf;")?;
+    writeln!(f, "use std::process::Command;")?;
+    writeln!(f)?;
+    writeln!(
+        f,
+        "fn compile(program: &str, args: &[&str], src: &str, output: &str, emit_flag: Option<&str>) -> PathBuf {{"
+    )?;
+    writeln!(
+        f,
+        "    let out_dir = PathBuf::from(std::env::var(\"OUT_DIR\").unwrap());"
+    )?;
+    writeln!(f, "    let output = out_dir.join(output);")?;
+    writeln!(f, "    let mut cmd = Command::new(program);")?;
+    writeln!(f, "    cmd.args(args);")?;
+    writeln!(f, "    match emit_flag {{")?;
+    writeln!(
+        f,
+        "        Some(flag) => cmd.arg(format!(\"{{flag}}{{}}\", output.display())),"
+    )?;
+    writeln!(f, "        None => cmd.arg(\"-o\").arg(&output),")?;
+    writeln!(f, "    }};")?;
+    writeln!(f, "    let status = cmd")?;
+    writeln!(f, "        .arg(src)")?;
+    writeln!(f, "        .status()")?;
+    writeln!(
+        f,
+        "        .unwrap_or_else(|e| panic!(\"failed to run {{program}}: {{e}}\"));"
+    )?;
+    writeln!(
+        f,
+        "    assert!(status.success(), \"failed to compile {{src}}\");"
+    )?;
+    writeln!(f, "    println!(\"cargo:rerun-if-changed={{src}}\");")?;
+    writeln!(f, "    output")?;
+    writeln!(f, "}}")?;
+    writeln!(f)?;
+    writeln!(f, "fn main() {{")?;
+    for side in sides {
+        let name = side.call_side.name();
+        writeln!(f, "    let {name} = compile(")?;
+        writeln!(f, "        {:?},", side.command.program)?;
+        writeln!(f, "        &{:?},", side.command.args)?;
+        writeln!(f, "        {:?},", side.src_name)?;
+        writeln!(f, "        {:?},", side.output_name())?;
+        writeln!(f, "        {:?},", side.command.emit_flag)?;
+        writeln!(f, "    );")?;
+        match side.command.output {
+            ReproOutput::StaticLib => {
+                writeln!(
+                    f,
+                    "    println!(\"cargo:rustc-link-search=native={{}}\", {name}.parent().unwrap().display());"
+                )?;
+                writeln!(f, "    println!(\"cargo:rustc-link-lib=static={name}\");")?;
+            }
+            ReproOutput::DynamicLib => {
+                writeln!(
+                    f,
+                    "    println!(\"cargo:rustc-link-search=native={{}}\", {name}.parent().unwrap().display());"
+                )?;
+                writeln!(f, "    println!(\"cargo:rustc-link-lib=dylib={name}\");")?;
+                writeln!(
+                    f,
+                    "    println!(\"cargo:rustc-link-arg=-Wl,-rpath,{{}}\", {name}.parent().unwrap().display());"
+                )?;
+            }
+            ReproOutput::Object => {
+                writeln!(
+                    f,
+                    "    println!(\"cargo:rustc-link-arg={{}}\", {name}.display());"
+                )?;
+            }
+        }
+    }
+    writeln!(f, "}}")?;
+    Ok(f)
+}
@@ -0,0 +1,833 @@
+//! Type-level minimization of failing tests (`--minimize-types`).
+//!
+//! Narrowing a failure to one function/arg/value with a [`FunctionSelector`][]
+//! still leaves the types as big as they were in the original test. This
+//! takes the failing function and repeatedly shrinks it (dropping arguments,
+//! dropping fields, flattening nested structs, shortening arrays...),
+//! re-running the test each time to see if the failure still reproduces.
+//! Once no more reductions reproduce the failure, the remaining program is
+//! the smallest kdl-script program we could find that has the same problem.
+//!
+//! This is just greedy delta-debugging: it tries the reductions in order of
+//! how much they cut away, and restarts from the first one that works.
+
+use std::fmt::Write;
+use std::sync::Arc;
+
+use camino::Utf8PathBuf;
+use kdl_script::parse::{
+    Attr, AttrAligned, AttrConvention, AttrPacked, AttrPassthrough, AttrRepr, AttrUnwind, Repr,
+};
+use kdl_script::types::{FuncIdx, PrimitiveTy, Ty, TyIdx};
+use kdl_script::TypedProgram;
+use tracing::{info, warn};
+
+use crate::harness::test::*;
+use crate::*;
+
+/// The most times we'll rebuild and rerun a test while minimizing one failure.
+const MAX_MINIMIZE_ATTEMPTS: usize = 100;
+
+/// An owned, structural version of a type that's easy to mutate.
+///
+/// Nominal types are inlined into their uses, so that each use can
+/// be reduced independently (they're given unique names when rendered).
+#[derive(Debug, Clone, PartialEq)]
+enum MinTy {
+    Prim(PrimitiveTy),
+    Empty,
+    Array(Box<MinTy>, u64),
+    Ref(Box<MinTy>),
+    Slice(Box<MinTy>),
+    NonZero(PrimitiveTy),
+    Option(Box<MinTy>),
+    Struct {
+        name: String,
+        attrs: Vec<String>,
+        fields: Vec<MinField>,
+    },
+    Union {
+        name: String,
+        attrs: Vec<String>,
+        fields: Vec<MinField>,
+    },
+    Enum {
+        name: String,
+        attrs: Vec<String>,
+        variants: Vec<String>,
+    },
+    Tagged {
+        name: String,
+        attrs: Vec<String>,
+        variants: Vec<MinVariant>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct MinField {
+    name: String,
+    ty: MinTy,
+    /// Bitfield width (only kept for struct fields)
+    bits: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct MinVariant {
+    name: String,
+    fields: Option<Vec<MinField>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct MinFunc {
+    name: String,
+    attrs: Vec<String>,
+    inputs: Vec<MinField>,
+    outputs: Vec<MinField>,
+}
+
+/// The parts of a failure that have to survive minimization, so that shrinking
+/// can't drift onto some other failure that it happens to run into along the way.
+#[derive(Debug, Clone)]
+pub struct FailureSignature {
+    func_idx: FuncIdx,
+    func_name: String,
+    kind: FailureKind,
+    /// The name of the value that was wrong, without the path to it
+    /// (shrinking renames and flattens the values around it).
+    val_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FailureKind {
+    Val,
+    Tag,
+    /// An assert only says a value was wrong, not how
+    Assert,
+    Unwind,
+    Abort,
+    NotRun,
+}
+
+impl FailureSignature {
+    pub fn new(failure: &CheckFailure) -> Self {
+        let (func_idx, func_name, kind, val_path) = match failure {
+            CheckFailure::ValMismatch {
+                func_idx,
+                func_name,
+                val_path,
+                ..
+            } => (func_idx, func_name, FailureKind::Val, Some(val_path)),
+            CheckFailure::TagMismatch {
+                func_idx,
+                func_name,
+                val_path,
+                ..
+            } => (func_idx, func_name, FailureKind::Tag, Some(val_path)),
+            CheckFailure::AssertFailed {
+                func_idx,
+                func_name,
+                val_path,
+                ..
+            } => (func_idx, func_name, FailureKind::Assert, Some(val_path)),
+            CheckFailure::UnwindMismatch {
+                func_idx,
+                func_name,
+                ..
+            } => (func_idx, func_name, FailureKind::Unwind, None),
+            CheckFailure::AbortMismatch {
+                func_idx,
+                func_name,
+                ..
+            } => (func_idx, func_name, FailureKind::Abort, None),
+            CheckFailure::NotRun {
+                func_idx,
+                func_name,
+            } => (func_idx, func_name, FailureKind::NotRun, None),
+        };
+        FailureSignature {
+            func_idx: *func_idx,
+            func_name: func_name.clone(),
+            kind,
+            val_name: val_path.map(|path| val_name(path).to_owned()),
+        }
+    }
+
+    /// Whether a failure of a minimized program is still the one we started with.
+    fn matches(&self, failure: &CheckFailure) -> bool {
+        let other = FailureSignature::new(failure);
+        let same_kind = match (self.kind, other.kind) {
+            (FailureKind::Assert, FailureKind::Val | FailureKind::Tag) => true,
+            (kind, other_kind) => kind == other_kind,
+        };
+        same_kind && self.func_name == other.func_name && self.val_name == other.val_name
+    }
+}
+
+/// The last part of a value's path (`x` in `arg0.pos[2].x`), with any indexing stripped.
+fn val_name(val_path: &str) -> &str {
+    let leaf = val_path.rsplit('.').next().unwrap_or(val_path);
+    leaf.split('[').next().unwrap_or(leaf)
+}
+
+impl TestHarness {
+    /// Try to shrink the types of a failing function while preserving the failure.
+    ///
+    /// On success, returns the path to the minimized kdl-script program.
+    pub async fn minimize_types(
+        &self,
+        key: &TestKey,
+        original: &FailureSignature,
+    ) -> Result<Utf8PathBuf, MinimizeError> {
+        let test = self.test(&key.test);
+        let mut func = MinFunc::from_program(&test.types, original.func_idx)?;
+
+        // First make sure the failure reproduces when the function is all alone
+        let mut best = self
+            .try_minimized(key, original, &func)
+            .await
+            .ok_or(MinimizeError::DoesNotReproduce)?;
+
+        let mut attempts = 1;
+        'minimize: while attempts < MAX_MINIMIZE_ATTEMPTS {
+            for candidate in func.reductions() {
+                if attempts >= MAX_MINIMIZE_ATTEMPTS {
+                    break 'minimize;
+                }
+                attempts += 1;
+                if let Some(output) = self.try_minimized(key, original, &candidate).await {
+                    best = output;
+                    func = candidate;
+                    continue 'minimize;
+                }
+            }
+            // Nothing reproduced, we're done!
+            break;
+        }
+        info!("minimized {} in {attempts} attempts", key.test);
+
+        // Save the program next to the sources it generated
+        let (test_name, src, output) = best;
+        let kdl_path = output
+            .caller_src
+            .parent()
+            .map(|dir| dir.join(format!("{test_name}.kdl")))
+            .unwrap_or_else(|| Utf8PathBuf::from(format!("{test_name}.kdl")));
+        std::fs::write(&kdl_path, src)?;
+        Ok(kdl_path)
+    }
+
+    /// Run this candidate program, returning its sources if it still fails the same way.
+    async fn try_minimized(
+        &self,
+        key: &TestKey,
+        original: &FailureSignature,
+        func: &MinFunc,
+    ) -> Option<(TestId, String, GenerateOutput)> {
+        let test_name = self.fresh_minimized_test_name(&key.test);
+        let src = func.render();
+        let types = match kdl_script::Compiler::new().compile_string(&test_name, src.clone()) {
+            Ok(types) => types,
+            Err(e) => {
+                // This is a bug in the minimizer, but not a fatal one
+                warn!("minimizer produced an invalid program: {e}\n{src}");
+                return None;
+            }
+        };
+        let test = match Test::new(test_name.clone(), types) {
+            Ok(test) => test,
+            Err(e) => {
+                warn!("minimizer produced an invalid program: {e}\n{src}");
+                return None;
+            }
+        };
+        // Only registered for as long as it takes to run it
+        self.add_test(Arc::new(test));
+
+        let mut test_key = key.clone();
+        test_key.test = test_name.clone();
+        test_key.options.functions = FunctionSelector::All;
+        test_key.options.val_writer = WriteImpl::HarnessCallback;
+        let rules = TestRules {
+            run: TestRunMode::Check,
+            check: TestCheckMode::Pass(TestRunMode::Check),
+            reason: None,
+        };
+        let results = self.do_test(test_key, rules).await;
+        self.remove_test(&test_name);
+        let reproduced = results.check.as_ref().is_some_and(|check| {
+            check
+                .subtest_checks
+                .iter()
+                .any(|t| t.result.as_ref().is_err_and(|e| original.matches(e)))
+        });
+        if !reproduced {
+            return None;
+        }
+        let output = results.source?.ok()?;
+        Some((test_name, src, output))
+    }
+}
+
+/// An error that occurs while trying to minimize types.
+#[derive(Debug, thiserror::Error)]
+pub enum MinimizeError {
+    #[error("the failure doesn't reproduce when the function is tested alone")]
+    DoesNotReproduce,
+    #[error("can't minimize types involving {0}")]
+    Unsupported(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl MinFunc {
+    fn from_program(types: &TypedProgram, func_idx: FuncIdx) -> Result<Self, MinimizeError> {
+        let func = types.realize_func(func_idx);
+        let args = |args: &[kdl_script::types::Arg]| {
+            args.iter()
+                .map(|arg| {
+                    Ok(MinField {
+                        name: field_name(&arg.name),
+                        ty: MinTy::from_program(types, arg.ty)?,
+                        bits: None,
+                    })
+                })
+                .collect::<Result<Vec<_>, MinimizeError>>()
+        };
+        Ok(MinFunc {
+            name: func.name.to_string(),
+            attrs: func.attrs.iter().map(render_attr).collect(),
+            inputs: args(&func.inputs)?,
+            outputs: args(&func.outputs)?,
+        })
+    }
+
+    /// Every version of this function that's one reduction smaller, biggest cuts first.
+    fn reductions(&self) -> Vec<MinFunc> {
+        let mut out = vec![];
+        let arg_lists = |func: &MinFunc| [func.inputs.clone(), func.outputs.clone()];
+        let with_args = |is_input: bool, args: Vec<MinField>| {
+            let mut func = self.clone();
+            if is_input {
+                func.inputs = args;
+            } else {
+                func.outputs = args;
+            }
+            func
+        };
+
+        for (list_idx, args) in arg_lists(self).into_iter().enumerate() {
+            let is_input = list_idx == 0;
+            // Drop arguments
+            for idx in 0..args.len() {
+                let mut new_args = args.clone();
+                new_args.remove(idx);
+                out.push(with_args(is_input, new_args));
+            }
+            // Replace struct inputs with their fields (only inputs, to not make multiple returns)
+            if is_input {
+                for (idx, arg) in args.iter().enumerate() {
+                    if let MinTy::Struct { fields, .. } = &arg.ty {
+                        let mut new_args = args.clone();
+                        new_args.splice(idx..=idx, fields.iter().cloned());
+                        out.push(with_args(is_input, new_args));
+                    }
+                }
+            }
+        }
+        // Drop attributes
+        for idx in 0..self.attrs.len() {
+            let mut func = self.clone();
+            func.attrs.remove(idx);
+            out.push(func);
+        }
+        // Reduce the types of arguments
+        for (list_idx, args) in arg_lists(self).into_iter().enumerate() {
+            for (idx, arg) in args.iter().enumerate() {
+                for ty in arg.ty.reductions() {
+                    let mut new_args = args.clone();
+                    new_args[idx].ty = ty;
+                    out.push(with_args(list_idx == 0, new_args));
+                }
+            }
+        }
+        out
+    }
+
+    fn render(&self) -> String {
+        let mut renderer = Renderer::default();
+        let arg_list = |renderer: &mut Renderer, args: &[MinField]| {
+            unique_names(args)
+                .into_iter()
+                .zip(args)
+                .map(|(name, arg)| (name, renderer.ty_name(&arg.ty)))
+                .collect::<Vec<_>>()
+        };
+        let inputs = arg_list(&mut renderer, &self.inputs);
+        let outputs = arg_list(&mut renderer, &self.outputs);
+
+        let mut out = String::new();
+        for (_name, decl) in &renderer.decls {
+            writeln!(out, "{decl}").unwrap();
+        }
+        for attr in &self.attrs {
+            writeln!(out, "{attr}").unwrap();
+        }
+        writeln!(out, r#"fn "{}" {{"#, self.name).unwrap();
+        for (block, args) in [("inputs", inputs), ("outputs", outputs)] {
+            writeln!(out, "    {block} {{").unwrap();
+            for (name, ty) in args {
+                writeln!(out, r#"        {name} "{ty}""#).unwrap();
+            }
+            writeln!(out, "    }}").unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+impl MinTy {
+    fn from_program(types: &TypedProgram, ty: TyIdx) -> Result<Self, MinimizeError> {
+        let fields = |fields: &[kdl_script::types::FieldTy]| {
+            fields
+                .iter()
+                .map(|field| {
+                    Ok(MinField {
+                        name: field_name(&field.ident),
+                        ty: MinTy::from_program(types, field.ty)?,
+                        bits: field.bits,
+                    })
+                })
+                .collect::<Result<Vec<_>, MinimizeError>>()
+        };
+        let min_ty = match types.realize_ty(ty) {
+            Ty::Primitive(prim) => MinTy::Prim(*prim),
+            Ty::Empty => MinTy::Empty,
+            Ty::Array(array_ty) => MinTy::Array(
+                Box::new(MinTy::from_program(types, array_ty.elem_ty)?),
+                array_ty.len,
+            ),
+            Ty::Ref(ref_ty) => MinTy::Ref(Box::new(MinTy::from_program(types, ref_ty.pointee_ty)?)),
+            Ty::Slice(slice_ty) => {
+                MinTy::Slice(Box::new(MinTy::from_program(types, slice_ty.elem_ty)?))
+            }
+            Ty::NonZero(nonzero_ty) => match types.realize_ty(nonzero_ty.int_ty) {
+                Ty::Primitive(prim) => MinTy::NonZero(*prim),
+                _ => unreachable!("NonZero of a non-primitive"),
+            },
+            Ty::Option(option_ty) => {
+                MinTy::Option(Box::new(MinTy::from_program(types, option_ty.some_ty)?))
+            }
+            Ty::Struct(struct_ty) => MinTy::Struct {
+                name: struct_ty.name.to_string(),
+                attrs: struct_ty.attrs.iter().map(render_attr).collect(),
+                fields: fields(&struct_ty.fields)?,
+            },
+            Ty::Union(union_ty) => MinTy::Union {
+                name: union_ty.name.to_string(),
+                attrs: union_ty.attrs.iter().map(render_attr).collect(),
+                fields: fields(&union_ty.fields)?,
+            },
+            Ty::Enum(enum_ty) => MinTy::Enum {
+                name: enum_ty.name.to_string(),
+                attrs: enum_ty.attrs.iter().map(render_attr).collect(),
+                variants: enum_ty
+                    .variants
+                    .iter()
+                    .map(|v| format!("{} {}", v.name, v.val))
+                    .collect(),
+            },
+            Ty::Tagged(tagged_ty) => MinTy::Tagged {
+                name: tagged_ty.name.to_string(),
+                attrs: tagged_ty.attrs.iter().map(render_attr).collect(),
+                variants: tagged_ty
+                    .variants
+                    .iter()
+                    .map(|v| {
+                        Ok(MinVariant {
+                            name: v.name.to_string(),
+                            fields: v.fields.as_deref().map(fields).transpose()?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, MinimizeError>>()?,
+            },
+            Ty::Alias(alias_ty) => {
+                if !alias_ty.attrs.is_empty() {
+                    return Err(MinimizeError::Unsupported(
+                        "aliases with attributes".to_owned(),
+                    ));
+                }
+                // Aliases are transparent, so we can just use the real type
+                MinTy::from_program(types, alias_ty.real)?
+            }
+            Ty::Pun(_) => {
+                // Each side of the test may see a completely different type here,
+                // so we can't pick one to shrink.
+                return Err(MinimizeError::Unsupported("puns".to_owned()));
+            }
+        };
+        Ok(min_ty)
+    }
+
+    /// Every version of this type that's one reduction smaller, biggest cuts first.
+    fn reductions(&self) -> Vec<MinTy> {
+        let mut out = vec![];
+        match self {
+            MinTy::Prim(_) | MinTy::Empty => {}
+            MinTy::Array(elem, len) => {
+                // Replace the array with its element, or shorten it
+                out.push((**elem).clone());
+                if *len > 1 {
+                    out.push(MinTy::Array(elem.clone(), len / 2));
+                    if len / 2 != len - 1 {
+                        out.push(MinTy::Array(elem.clone(), len - 1));
+                    }
+                }
+                for elem in elem.reductions() {
+                    out.push(MinTy::Array(Box::new(elem), *len));
+                }
+            }
+            MinTy::Ref(pointee) => {
+                for pointee in pointee.reductions() {
+                    out.push(MinTy::Ref(Box::new(pointee)));
+                }
+            }
+            MinTy::Slice(elem) => {
+                // Replace the slice with its element
+                out.push((**elem).clone());
+                for elem in elem.reductions() {
+                    out.push(MinTy::Slice(Box::new(elem)));
+                }
+            }
+            MinTy::NonZero(prim) => {
+                out.push(MinTy::Prim(*prim));
+            }
+            MinTy::Option(payload) => {
+                // Replace the option with its payload, but only keep payloads with a niche
+                out.push((**payload).clone());
+                for payload in payload.reductions() {
+                    if matches!(payload, MinTy::Ref(_) | MinTy::NonZero(_)) {
+                        out.push(MinTy::Option(Box::new(payload)));
+                    }
+                }
+            }
+            MinTy::Struct {
+                name,
+                attrs,
+                fields,
+            } => {
+                let rebuild = |attrs: Vec<String>, fields: Vec<MinField>| MinTy::Struct {
+                    name: name.clone(),
+                    attrs,
+                    fields,
+                };
+                // A struct with one field can just be that field
+                // (unless it has attributes, which might be the whole point)
+                if let [field] = &fields[..] {
+                    if attrs.is_empty() {
+                        out.push(field.ty.clone());
+                    }
+                }
+                for fields in field_list_reductions(fields, true) {
+                    out.push(rebuild(attrs.clone(), fields));
+                }
+                for attrs in drop_each(attrs) {
+                    out.push(rebuild(attrs, fields.clone()));
+                }
+            }
+            MinTy::Union {
+                name,
+                attrs,
+                fields,
+            } => {
+                let rebuild = |attrs: Vec<String>, fields: Vec<MinField>| MinTy::Union {
+                    name: name.clone(),
+                    attrs,
+                    fields,
+                };
+                for fields in field_list_reductions(fields, false) {
+                    out.push(rebuild(attrs.clone(), fields));
+                }
+                for attrs in drop_each(attrs) {
+                    out.push(rebuild(attrs, fields.clone()));
+                }
+            }
+            MinTy::Enum {
+                name,
+                attrs,
+                variants,
+            } => {
+                let rebuild = |attrs: Vec<String>, variants: Vec<String>| MinTy::Enum {
+                    name: name.clone(),
+                    attrs,
+                    variants,
+                };
+                if variants.len() > 1 {
+                    for variants in drop_each(variants) {
+                        out.push(rebuild(attrs.clone(), variants));
+                    }
+                }
+                for attrs in drop_each(attrs) {
+                    out.push(rebuild(attrs, variants.clone()));
+                }
+            }
+            MinTy::Tagged {
+                name,
+                attrs,
+                variants,
+            } => {
+                let rebuild = |attrs: Vec<String>, variants: Vec<MinVariant>| MinTy::Tagged {
+                    name: name.clone(),
+                    attrs,
+                    variants,
+                };
+                if variants.len() > 1 {
+                    for variants in drop_each(variants) {
+                        out.push(rebuild(attrs.clone(), variants));
+                    }
+                }
+                for (idx, variant) in variants.iter().enumerate() {
+                    let Some(fields) = &variant.fields else {
+                        continue;
+                    };
+                    let mut new_variants = variants.clone();
+                    new_variants[idx].fields = None;
+                    out.push(rebuild(attrs.clone(), new_variants));
+                    for fields in field_list_reductions(fields, true) {
+                        let mut new_variants = variants.clone();
+                        new_variants[idx].fields = Some(fields);
+                        out.push(rebuild(attrs.clone(), new_variants));
+                    }
+                }
+                for attrs in drop_each(attrs) {
+                    out.push(rebuild(attrs, variants.clone()));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Reductions of a list of fields (dropping them, flattening them, reducing their types).
+///
+/// At least one field is always kept, as empty aggregates are their own can of worms.
+fn field_list_reductions(fields: &[MinField], allow_flatten: bool) -> Vec<Vec<MinField>> {
+    let mut out = vec![];
+    if fields.len() > 1 {
+        out.extend(drop_each(fields));
+    }
+    if allow_flatten {
+        for (idx, field) in fields.iter().enumerate() {
+            if let MinTy::Struct {
+                fields: inner_fields,
+                attrs,
+                ..
+            } = &field.ty
+            {
+                if !attrs.is_empty() {
+                    continue;
+                }
+                let mut new_fields = fields.to_vec();
+                new_fields.splice(idx..=idx, inner_fields.iter().cloned());
+                out.push(new_fields);
+            }
+        }
+    }
+    for (idx, field) in fields.iter().enumerate() {
+        // Make bitfields normal fields
+        if field.bits.is_some() {
+            let mut new_fields = fields.to_vec();
+            new_fields[idx].bits = None;
+            out.push(new_fields);
+            continue;
+        }
+        for ty in field.ty.reductions() {
+            let mut new_fields = fields.to_vec();
+            new_fields[idx].ty = ty;
+            out.push(new_fields);
+        }
+    }
+    out
+}
+
+/// Every version of this list with one element removed.
+fn drop_each<T: Clone>(list: &[T]) -> Vec<Vec<T>> {
+    (0..list.len())
+        .map(|idx| {
+            let mut new_list = list.to_vec();
+            new_list.remove(idx);
+            new_list
+        })
+        .collect()
+}
+
+/// Turns [`MinTy`][]s back into kdl-script source.
+#[derive(Default)]
+struct Renderer {
+    /// (name, source) of all the type declarations, in dependency order
+    decls: Vec<(String, String)>,
+}
+
+impl Renderer {
+    /// Get the kdl-script name for this type, declaring it if necessary
+    fn ty_name(&mut self, ty: &MinTy) -> String {
+        match ty {
+            MinTy::Prim(name) => name.to_string(),
+            MinTy::Empty => "()".to_owned(),
+            MinTy::Array(elem, len) => format!("[{}; {len}]", self.ty_name(elem)),
+            MinTy::Ref(pointee) => format!("&{}", self.ty_name(pointee)),
+            MinTy::Slice(elem) => format!("&[{}]", self.ty_name(elem)),
+            MinTy::NonZero(prim) => format!("NonZero<{prim}>"),
+            MinTy::Option(payload) => format!("Option<{}>", self.ty_name(payload)),
+            MinTy::Struct {
+                name,
+                attrs,
+                fields,
+            } => {
+                let fields = self.field_list(fields, true);
+                self.declare(name, |name| {
+                    format!("{}struct \"{name}\" {{\n{fields}}}\n", attr_lines(attrs))
+                })
+            }
+            MinTy::Union {
+                name,
+                attrs,
+                fields,
+            } => {
+                let fields = self.field_list(fields, false);
+                self.declare(name, |name| {
+                    format!("{}union \"{name}\" {{\n{fields}}}\n", attr_lines(attrs))
+                })
+            }
+            MinTy::Enum {
+                name,
+                attrs,
+                variants,
+            } => {
+                let mut variant_list = String::new();
+                for variant in variants {
+                    writeln!(variant_list, "    {variant}").unwrap();
+                }
+                self.declare(name, |name| {
+                    format!(
+                        "{}enum \"{name}\" {{\n{variant_list}}}\n",
+                        attr_lines(attrs)
+                    )
+                })
+            }
+            MinTy::Tagged {
+                name,
+                attrs,
+                variants,
+            } => {
+                let mut variant_list = String::new();
+                for variant in variants {
+                    if let Some(fields) = &variant.fields {
+                        let fields = self.field_list(fields, false);
+                        writeln!(variant_list, "    {} {{", variant.name).unwrap();
+                        for line in fields.lines() {
+                            writeln!(variant_list, "    {line}").unwrap();
+                        }
+                        writeln!(variant_list, "    }}").unwrap();
+                    } else {
+                        writeln!(variant_list, "    {}", variant.name).unwrap();
+                    }
+                }
+                self.declare(name, |name| {
+                    format!(
+                        "{}tagged \"{name}\" {{\n{variant_list}}}\n",
+                        attr_lines(attrs)
+                    )
+                })
+            }
+        }
+    }
+
+    /// Render fields, where flattening may have moved bitfields somewhere they aren't allowed
+    fn field_list(&mut self, fields: &[MinField], allow_bits: bool) -> String {
+        let mut out = String::new();
+        for (name, field) in unique_names(fields).into_iter().zip(fields) {
+            let ty = self.ty_name(&field.ty);
+            match field.bits {
+                Some(bits) if allow_bits => writeln!(out, r#"    {name} "{ty}:{bits}""#).unwrap(),
+                _ => writeln!(out, r#"    {name} "{ty}""#).unwrap(),
+            }
+        }
+        out
+    }
+
+    /// Declare a nominal type, reusing an identical existing declaration if possible.
+    ///
+    /// Reductions can make two uses of the same type differ, so if the name
+    /// is already taken by something else we give this one a fresh name.
+    fn declare(&mut self, name: &str, render: impl Fn(&str) -> String) -> String {
+        for suffix in 0.. {
+            let candidate = if suffix == 0 {
+                name.to_owned()
+            } else {
+                format!("{name}{suffix}")
+            };
+            let decl = render(&candidate);
+            match self
+                .decls
+                .iter()
+                .find(|(existing, _)| *existing == candidate)
+            {
+                Some((_, existing_decl)) if *existing_decl == decl => return candidate,
+                Some(_) => continue,
+                None => {
+                    self.decls.push((candidate.clone(), decl));
+                    return candidate;
+                }
+            }
+        }
+        unreachable!()
+    }
+}
+
+/// Flattening can bring together fields with the same name, so rename any duplicates.
+fn unique_names(fields: &[MinField]) -> Vec<String> {
+    let mut names = Vec::<String>::new();
+    for (idx, field) in fields.iter().enumerate() {
+        let name = if field.name != "_" && names.contains(&field.name) {
+            format!("{}_{idx}", field.name)
+        } else {
+            field.name.clone()
+        };
+        names.push(name);
+    }
+    names
+}
+
+fn attr_lines(attrs: &[String]) -> String {
+    attrs.iter().map(|attr| format!("{attr}\n")).collect()
+}
+
+fn field_name(ident: &kdl_script::parse::Ident) -> String {
+    if ident.was_blank {
+        "_".to_owned()
+    } else {
+        ident.to_string()
+    }
+}
+
+fn render_attr(attr: &Attr) -> String {
+    match attr {
+        Attr::Packed(AttrPacked {}) => "@packed".to_owned(),
+        Attr::Align(AttrAligned { align }) => format!("@align {}", align.val),
+        Attr::Repr(AttrRepr { reprs }) => {
+            let mut out = "@repr".to_owned();
+            for repr in reprs {
+                let repr = match repr {
+                    Repr::Primitive(prim) => prim.to_string(),
+                    Repr::Lang(lang) => lang.to_string(),
+                    Repr::Transparent => "transparent".to_owned(),
+                };
+                write!(out, r#" "{repr}""#).unwrap();
+            }
+            out
+        }
+        Attr::Passthrough(AttrPassthrough(attr)) => format!("@ {:?}", &**attr),
+        Attr::Unwind(AttrUnwind {}) => "@unwind".to_owned(),
+        Attr::Convention(AttrConvention(convention)) => format!("@convention {:?}", &**convention),
+    }
+}
@@ -8,6 +8,7 @@
 
 use crate::*;
 use camino::Utf8PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use test::*;
 use tokio::sync::{OnceCell, Semaphore};
@@ -15,22 +16,28 @@ use tracing::warn;
 
 mod build;
 mod check;
+mod export;
 mod generate;
+mod minimize;
 mod read;
 pub mod report;
 mod run;
 pub mod test;
 pub mod vals;
 
+pub use minimize::FailureSignature;
 pub use read::{find_test_rules, find_tests, spawn_read_test};
-pub use run::TestBuffer;
+#[cfg(unix)]
+pub use run::run_dylib_server;
+pub use run::{run_dylib_worker, TestBuffer};
 
 pub type Memoized<K, V> = Mutex<SortedMap<K, Arc<OnceCell<V>>>>;
 
 pub struct TestHarness {
     paths: Paths,
+    run_mode: RunMode,
     pub toolchains: Toolchains,
-    tests: SortedMap<TestId, Arc<Test>>,
+    tests: Mutex<SortedMap<TestId, Arc<Test>>>,
     test_rules: Vec<ExpectFile>,
     tests_with_vals: Memoized<(TestId, ValueGeneratorKind), Arc<TestWithVals>>,
     tests_with_toolchain:
@@ -38,6 +45,11 @@ pub struct TestHarness {
     generated_sources: Memoized<Utf8PathBuf, ()>,
     built_static_libs: Memoized<String, String>,
     concurrency_limiter: tokio::sync::Semaphore,
+    /// Workers for running tests in dylib mode
+    #[cfg(unix)]
+    dylib_workers: run::DylibWorkers,
+    /// Counter for naming the tests produced by the minimizer
+    minimized_tests: AtomicUsize,
 }
 
 impl TestHarness {
@@ -49,7 +61,8 @@ impl TestHarness {
         let toolchains = toolchains::create_toolchains(cfg);
         Self {
             paths: cfg.paths.clone(),
-            tests,
+            run_mode: cfg.run_mode,
+            tests: Mutex::new(tests),
             test_rules,
             toolchains,
             tests_with_vals: Default::default(),
@@ -57,6 +70,9 @@ impl TestHarness {
             generated_sources: Default::default(),
             built_static_libs: Default::default(),
             concurrency_limiter: Semaphore::new(128),
+            #[cfg(unix)]
+            dylib_workers: Default::default(),
+            minimized_tests: AtomicUsize::new(0),
         }
     }
 
@@ -70,10 +86,31 @@ impl TestHarness {
     }
 
     pub fn all_tests(&self) -> Vec<Arc<Test>> {
-        self.tests.values().cloned().collect()
+        self.tests.lock().unwrap().values().cloned().collect()
     }
     pub fn test(&self, test: &TestId) -> Arc<Test> {
-        self.tests[test].clone()
+        self.tests.lock().unwrap()[test].clone()
+    }
+    /// Add a test that was made after the harness was created (i.e. by the minimizer)
+    pub fn add_test(&self, test: Arc<Test>) {
+        self.tests.lock().unwrap().insert(test.name.clone(), test);
+    }
+    /// Forget a test added with [`Self::add_test`][], along with everything derived from it
+    pub fn remove_test(&self, test: &TestId) {
+        self.tests.lock().unwrap().remove(test);
+        self.tests_with_vals
+            .lock()
+            .unwrap()
+            .retain(|(test_id, _), _| test_id != test);
+        self.tests_with_toolchain
+            .lock()
+            .unwrap()
+            .retain(|(test_id, _, _), _| test_id != test);
+    }
+    /// Get a unique name for a minimized version of a test
+    fn fresh_minimized_test_name(&self, test: &TestId) -> TestId {
+        let idx = self.minimized_tests.fetch_add(1, Ordering::Relaxed);
+        format!("{test}_min{idx}")
     }
     pub async fn test_with_vals(
         &self,
@@ -126,6 +163,55 @@ impl TestHarness {
         rt.spawn(async move { harness.do_test(test_key, rules).await })
     }
 
+    /// Why the test's own metadata says this combination isn't worth running, if it does.
+    pub fn pruned_reason(&self, test_key: &TestKey) -> Option<String> {
+        for call_side in [CallSide::Caller, CallSide::Callee] {
+            let toolchain_id = test_key.toolchain_id(call_side);
+            if !self.toolchains.toolchains.contains_key(toolchain_id) {
+                return Some(format!("toolchain {toolchain_id} isn't available"));
+            }
+        }
+        let test = self.test(&test_key.test);
+        let options = &test_key.options;
+        if !test.has_convention(options.convention) {
+            let conventions = test.meta.conventions.iter().map(|c| c.to_string());
+            return Some(format!(
+                "test only applies to conventions {}",
+                conventions.collect::<Vec<_>>().join(", ")
+            ));
+        }
+        if !test.has_repr(options.repr) {
+            let reprs = test.meta.reprs.iter().map(|r| r.to_string());
+            return Some(format!(
+                "test only applies to reprs {}",
+                reprs.collect::<Vec<_>>().join(", ")
+            ));
+        }
+        for &prim in &test.meta.requires {
+            for call_side in [CallSide::Caller, CallSide::Callee] {
+                let toolchain = self.toolchain_by_test_key(test_key, call_side);
+                if let Err(e) = toolchain.supports_primitive(prim) {
+                    return Some(format!("test requires {prim}: {e}"));
+                }
+            }
+        }
+        if self.has_dynamic_sides(test_key) {
+            if !self.supports_dynamic_sides() {
+                return Some("this target can't load a second version of rust's std".to_owned());
+            }
+            let unwinds = options.active_funcs(&test.types).into_iter().any(|func| {
+                let func = test.types.realize_func(func);
+                func.unwinds() && !options.aborts(func)
+            });
+            if unwinds {
+                return Some(
+                    "a panic can't be caught by a different copy of rust's std".to_owned(),
+                );
+            }
+        }
+        None
+    }
+
     /// Generate, Compile, Link, Load, and Run this test.
     #[tracing::instrument(name = "test", skip_all, fields(id = self.base_id(&test_key, None, "::")))]
     pub async fn do_test(&self, test_key: TestKey, test_rules: TestRules) -> TestRunResults {
@@ -171,7 +257,10 @@ impl TestHarness {
         }
 
         res.ran_to = Link;
-        res.link = Some(self.link_bin(&res.key, build).await);
+        res.link = Some(match self.run_mode {
+            RunMode::Bin => self.link_bin(&res.key, build).await,
+            RunMode::Dylib => self.link_dylib(&res.key, build).await,
+        });
         let link = match res.link.as_ref().unwrap() {
             Ok(v) => v,
             Err(e) => {
@@ -184,7 +273,12 @@ impl TestHarness {
         }
 
         res.ran_to = Run;
-        res.run = Some(self.run_bin_test(&res.key, link).await);
+        let (run_log, run) = match self.run_mode {
+            RunMode::Bin => self.run_bin_test(&res.key, link).await,
+            RunMode::Dylib => self.run_dylib_test(&res.key, link).await,
+        };
+        res.run_log = run_log;
+        res.run = Some(run);
         let run = match res.run.as_ref().unwrap() {
             Ok(v) => v,
             Err(e) => {
@@ -229,7 +323,7 @@ impl TestHarness {
         output.push_str(separator);
         output.push_str(&format!("repr_{repr}"));
         if let FunctionSelector::One { idx, args } = functions {
-            let test = self.tests[test].clone();
+            let test = self.test(test);
             let func = test.types.realize_func(*idx);
             output.push_str(separator);
             output.push_str(&func.name);
@@ -1,3 +1,4 @@
+mod fuzz;
 mod procgen;
 
 use std::{
@@ -17,6 +18,10 @@ use crate::*;
 pub enum TestFile {
     Kdl(Pathish),
     KdlProcgen(Pathish),
+    /// A randomly generated program (see `--fuzz-types`)
+    Fuzz {
+        seed: u64,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -30,6 +35,15 @@ impl Pathish {
             Pathish::Runtime(path) | Pathish::Static(path) => path.as_str(),
         }
     }
+    /// How to load the files this one imports (from the same place this one came from)
+    fn import_loader(&self) -> fn(&str) -> std::io::Result<String> {
+        match self {
+            Pathish::Runtime(_) => |path| read_runtime_file_to_string(Utf8Path::new(path)),
+            Pathish::Static(_) => {
+                |path| crate::files::find_file(path).ok_or(std::io::ErrorKind::NotFound.into())
+            }
+        }
+    }
 }
 
 pub fn find_test_rules(cfg: &Config) -> Result<Vec<ExpectFile>, GenerateError> {
@@ -62,9 +76,20 @@ pub fn find_tests(cfg: &Config) -> Result<SortedMap<TestId, TestFile>, GenerateE
     let mut tests = find_tests_runtime(cfg.paths.runtime_test_input_dir.as_deref())?;
     let mut more_tests = find_tests_static(cfg.disable_builtin_tests)?;
     tests.append(&mut more_tests);
+    let mut fuzz_tests = find_tests_fuzz(cfg.fuzz_types, cfg.fuzz_seed);
+    tests.append(&mut fuzz_tests);
     Ok(tests)
 }
 
+pub fn find_tests_fuzz(count: u64, first_seed: u64) -> SortedMap<TestId, TestFile> {
+    (0..count)
+        .map(|idx| {
+            let seed = first_seed.wrapping_add(idx);
+            (fuzz::fuzz_test_name(seed), TestFile::Fuzz { seed })
+        })
+        .collect()
+}
+
 pub fn find_tests_runtime(
     start_dir: Option<&Utf8Path>,
 ) -> Result<SortedMap<TestId, TestFile>, GenerateError> {
@@ -85,6 +110,9 @@ pub fn find_tests_runtime(
 
             let path = entry.path();
             let test_file = Utf8PathBuf::from_path_buf(path).expect("non-utf8 test path");
+            if is_library(&test_file) {
+                continue;
+            }
             let Some((name, test)) = classify_test(&test_file, true) else {
                 warn!("test isn't a known test format: {}", test_file);
                 continue;
@@ -116,6 +144,9 @@ pub fn find_tests_static(
                 let path = file.path();
                 let test_file =
                     Utf8PathBuf::from_path_buf(path.to_owned()).expect("non-utf8 test path");
+                if is_library(&test_file) {
+                    continue;
+                }
                 let Some((name, test)) = classify_test(&test_file, false) else {
                     warn!("test isn't a known test format: {}", test_file);
                     continue;
@@ -146,23 +177,26 @@ async fn read_test(test: TestId, test_file: TestFile) -> Result<Arc<Test>, Gener
 }
 
 async fn read_test_inner(test: &TestId, test_file: TestFile) -> Result<Arc<Test>, GenerateError> {
-    let (test_file, input) = match test_file {
+    let mut compiler = kdl_script::Compiler::new();
+    let (src_name, input) = match test_file {
         TestFile::KdlProcgen(test_file) => {
             let ty_def = read_file_to_string(&test_file)?;
             let input = procgen::procgen_test_for_ty_string(test, Some(&ty_def));
-            (test_file, input)
+            compiler = compiler.with_import_loader(test_file.import_loader());
+            (test_file.as_str().to_owned(), input)
         }
         TestFile::Kdl(test_file) => {
             let input = read_file_to_string(&test_file)?;
-            (test_file, input)
+            compiler = compiler.with_import_loader(test_file.import_loader());
+            (test_file.as_str().to_owned(), input)
+        }
+        TestFile::Fuzz { seed } => {
+            let input = fuzz::fuzz_test_for_seed(seed);
+            (format!("{test}.kdl"), input)
         }
     };
-    let mut compiler = kdl_script::Compiler::new();
-    let types = compiler.compile_string(test_file.as_str(), input)?;
-    Ok(Arc::new(Test {
-        name: test.to_owned(),
-        types,
-    }))
+    let types = compiler.compile_string(&src_name, input)?;
+    Ok(Arc::new(Test::new(test.to_owned(), types)?))
 }
 
 fn read_file_to_string(pathish: &Pathish) -> std::io::Result<String> {
@@ -172,6 +206,12 @@ fn read_file_to_string(pathish: &Pathish) -> std::io::Result<String> {
     }
 }
 
+/// Whether this is a library of types for tests to `import`, instead of a test itself
+fn is_library(test_file: &Utf8Path) -> bool {
+    let file_name = test_file.file_name().expect("test file had no name!?");
+    file_name.ends_with(".lib.kdl")
+}
+
 #[allow(clippy::manual_map)]
 fn classify_test(test_file: &Utf8Path, is_runtime: bool) -> Option<(String, TestFile)> {
     let file_name = test_file.file_name().expect("test file had no name!?");
@@ -0,0 +1,227 @@
+//! Randomly generated kdl-script programs (`--fuzz-types`).
+//!
+//! Where procgen takes a hand-written type and stamps out a fixed set of
+//! signatures for it, this synthesizes both the types *and* the signatures
+//! from a seed. The same seed always produces the same program, so the seed
+//! is used as the test's name to make any failures reproducible.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_core::SeedableRng;
+
+type RngImpl = rand_pcg::Pcg64;
+
+/// Primitives we're willing to put in a fuzzed program.
+///
+/// This intentionally avoids the more exotic primitives (i128, f16, ...)
+/// that many toolchains don't support, as those would just get the
+/// whole program skipped.
+const PRIMITIVES: &[&str] = &[
+    "i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64", "f32", "f64", "bool", "ptr",
+];
+
+/// Get the name of the fuzzed test for a given seed
+pub fn fuzz_test_name(seed: u64) -> String {
+    format!("fuzz{seed}")
+}
+
+/// Generate a random kdl-script program from the given seed
+pub fn fuzz_test_for_seed(seed: u64) -> String {
+    let mut test_body = String::new();
+    let mut fuzzer = TypeFuzzer {
+        rng: RngImpl::seed_from_u64(seed),
+        nominals: vec![],
+    };
+    fuzzer
+        .fuzz_program(&mut test_body)
+        .expect("failed to format fuzzed program!?");
+    test_body
+}
+
+struct TypeFuzzer {
+    rng: RngImpl,
+    /// Names of all the nominal types declared so far
+    nominals: Vec<String>,
+}
+
+impl TypeFuzzer {
+    fn fuzz_program(&mut self, out: &mut dyn std::fmt::Write) -> std::fmt::Result {
+        // Types can only refer to types declared before them, so that
+        // we never produce an infinitely sized type.
+        let num_tys = self.rng.gen_range(1..=5);
+        for ty_idx in 0..num_tys {
+            self.fuzz_tydecl(out, ty_idx)?;
+        }
+
+        let num_funcs = self.rng.gen_range(4..=10);
+        for func_idx in 0..num_funcs {
+            self.fuzz_func(out, func_idx)?;
+        }
+        Ok(())
+    }
+
+    fn fuzz_tydecl(&mut self, out: &mut dyn std::fmt::Write, ty_idx: usize) -> std::fmt::Result {
+        // Weighted to prefer the kinds of types every backend supports
+        let kind = self.rng.gen_range(0..10);
+        let name = match kind {
+            0..=5 => {
+                let name = format!("Struct{ty_idx}");
+                // Attributes get the whole program skipped by backends that don't
+                // support them, so keep them rare.
+                let packed = match self.rng.gen_range(0..20) {
+                    0..=1 => {
+                        let align = [4, 8, 16, 32].choose(&mut self.rng).unwrap();
+                        writeln!(out, "@align {align}")?;
+                        false
+                    }
+                    2 => {
+                        writeln!(out, "@packed")?;
+                        true
+                    }
+                    _ => false,
+                };
+                writeln!(out, r#"struct "{name}" {{"#)?;
+                let num_fields = self.rng.gen_range(1..=6);
+                for field_idx in 0..num_fields {
+                    // Rust doesn't allow (possibly) overaligned types in packed ones,
+                    // so packed structs are made of primitives
+                    let field_ty = if packed {
+                        self.fuzz_prim_ty()
+                    } else {
+                        self.fuzz_ty()
+                    };
+                    writeln!(out, r#"    f{field_idx} "{field_ty}""#)?;
+                }
+                writeln!(out, "}}")?;
+                name
+            }
+            6..=7 => {
+                let name = format!("Union{ty_idx}");
+                writeln!(out, r#"union "{name}" {{"#)?;
+                let num_fields = self.rng.gen_range(2..=4);
+                for field_idx in 0..num_fields {
+                    let field_ty = self.fuzz_ty();
+                    writeln!(out, r#"    f{field_idx} "{field_ty}""#)?;
+                }
+                writeln!(out, "}}")?;
+                name
+            }
+            8 => {
+                let name = format!("Enum{ty_idx}");
+                writeln!(out, r#"enum "{name}" {{"#)?;
+                let num_variants = self.rng.gen_range(1..=5);
+                for variant_idx in 0..num_variants {
+                    writeln!(out, "    V{variant_idx}")?;
+                }
+                writeln!(out, "}}")?;
+                name
+            }
+            _ => {
+                let name = format!("Tagged{ty_idx}");
+                writeln!(out, r#"tagged "{name}" {{"#)?;
+                let num_variants = self.rng.gen_range(1..=4);
+                for variant_idx in 0..num_variants {
+                    let num_fields = self.rng.gen_range(0..=3);
+                    if num_fields == 0 {
+                        writeln!(out, "    V{variant_idx}")?;
+                        continue;
+                    }
+                    writeln!(out, "    V{variant_idx} {{")?;
+                    for field_idx in 0..num_fields {
+                        let field_ty = self.fuzz_ty();
+                        writeln!(out, r#"        f{field_idx} "{field_ty}""#)?;
+                    }
+                    writeln!(out, "    }}")?;
+                }
+                writeln!(out, "}}")?;
+                name
+            }
+        };
+        writeln!(out)?;
+        self.nominals.push(name);
+        Ok(())
+    }
+
+    fn fuzz_func(&mut self, out: &mut dyn std::fmt::Write, func_idx: usize) -> std::fmt::Result {
+        writeln!(out, r#"fn "func{func_idx}" {{"#)?;
+        writeln!(out, "    inputs {{")?;
+        let num_inputs = self.rng.gen_range(0..=8);
+        for _ in 0..num_inputs {
+            // Occasionally pass things by-reference
+            if self.rng.gen_ratio(1, 8) {
+                let arg_ty = self.fuzz_ty();
+                writeln!(out, r#"        _ "&{arg_ty}""#)?;
+            } else {
+                let arg_ty = self.fuzz_value_ty();
+                writeln!(out, r#"        _ "{arg_ty}""#)?;
+            }
+        }
+        writeln!(out, "    }}")?;
+        writeln!(out, "    outputs {{")?;
+        if self.rng.gen_ratio(1, 2) {
+            let arg_ty = self.fuzz_value_ty();
+            writeln!(out, r#"        _ "{arg_ty}""#)?;
+        }
+        writeln!(out, "    }}")?;
+        writeln!(out, "}}")?;
+        writeln!(out)?;
+        Ok(())
+    }
+
+    /// Pick a type to pass by-value
+    ///
+    /// Arrays can't be passed by-value in C, so they only appear in
+    /// fields or behind references.
+    fn fuzz_value_ty(&mut self) -> String {
+        if !self.nominals.is_empty() && self.rng.gen_ratio(2, 5) {
+            self.nominals.choose(&mut self.rng).unwrap().clone()
+        } else {
+            PRIMITIVES.choose(&mut self.rng).unwrap().to_string()
+        }
+    }
+
+    /// Pick a type to use for a field (or to put behind a reference)
+    fn fuzz_ty(&mut self) -> String {
+        let base = self.fuzz_value_ty();
+        self.maybe_array(base)
+    }
+
+    /// Like [`Self::fuzz_ty`][] but only (arrays of) primitives
+    fn fuzz_prim_ty(&mut self) -> String {
+        let base = PRIMITIVES.choose(&mut self.rng).unwrap().to_string();
+        self.maybe_array(base)
+    }
+
+    fn maybe_array(&mut self, base: String) -> String {
+        if self.rng.gen_ratio(1, 6) {
+            let len = self.rng.gen_range(1..=4);
+            format!("[{base}; {len}]")
+        } else {
+            base
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::fuzz_test_for_seed;
+
+    #[test]
+    fn fuzz_seed_is_reproducible() {
+        for seed in 0..64 {
+            let src = fuzz_test_for_seed(seed);
+            assert_eq!(
+                src,
+                fuzz_test_for_seed(seed),
+                "seed {seed} isn't deterministic"
+            );
+            if let Err(e) = kdl_script::Compiler::new().compile_string("fuzz.kdl", src.clone()) {
+                panic!("seed {seed} doesn't compile: {e:?}\n{src}");
+            }
+        }
+        // Make sure the new attributes actually come up
+        let all = (0..64).map(fuzz_test_for_seed).collect::<String>();
+        assert!(all.contains("@packed"));
+        assert!(all.contains("@align"));
+    }
+}
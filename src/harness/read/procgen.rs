@@ -15,9 +15,9 @@ fn procgen_test_for_ty_impl(
     // Apply the type's definitions first
     let has_refs = if let Some(ty_def) = ty_def {
         writeln!(out, "{}", ty_def)?;
-        // To avoid outparam nonsense, avoid testing outputs of the type
-        // if any part of its definition involves a reference.
-        // (Yes this is a blunt check but it's fine enough.)
+        // The callee can't produce references for the caller (they'd dangle),
+        // so avoid testing outputs of the type if any part of its definition
+        // involves a reference. (Yes this is a blunt check but it's fine enough.)
         ty_def.contains('&')
     } else {
         false
@@ -29,6 +29,11 @@ fn procgen_test_for_ty_impl(
     if !has_refs {
         add_func(out, "val_out", &[], &[ty])?;
         add_func(out, "val_in_out", &[ty], &[ty])?;
+        // Outparams, and extra outputs that get lowered to outparams
+        add_func(out, "ref_out", &[], &[&ty_ref])?;
+        add_func(out, "val_in_ref_out", &[ty], &[&ty_ref])?;
+        add_func(out, "val_out_2", &[], &[ty, ty])?;
+        add_func(out, "ref_out_val_out", &[], &[&ty_ref, ty])?;
     }
 
     // Stress out the calling convention and try lots of different
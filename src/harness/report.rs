@@ -8,6 +8,7 @@ use serde_json::json;
 use crate::error::*;
 use crate::harness::test::*;
 use crate::*;
+use kdl_script::types::FuncIdx;
 
 /// These are the builtin test-expectations, edit these if there are new rules!
 impl TestHarness {
@@ -20,23 +21,22 @@ impl TestHarness {
         let mut result = TestRules {
             run: Check,
             check: Pass(Check),
+            reason: None,
         };
 
-        for expect_file in &self.test_rules {
-            for (target_cfg, rules) in &expect_file.target {
-                if !self.cfg_enabled(target_cfg) {
-                    continue;
-                }
-                for (pattern, rules) in rules {
-                    if pattern.matches(key) {
-                        if let Some(run) = rules.run {
-                            result.run = run;
-                        }
-                        if let Some(check) = rules.check {
-                            result.check = check;
-                        }
-                    }
-                }
+        for (pattern, rules) in self.matching_rules(key) {
+            // Rules that select a specific function only apply to subtests
+            if pattern.func.is_some() {
+                continue;
+            }
+            if let Some(run) = rules.run {
+                result.run = run;
+            }
+            if let Some(check) = rules.check {
+                result.check = check;
+            }
+            if let Some(reason) = &rules.reason {
+                result.reason = Some(reason.clone());
             }
         }
 
@@ -51,6 +51,57 @@ impl TestHarness {
         result
     }
 
+    /// Get the rules for a specific function (subtest) of a test.
+    ///
+    /// Only rules that explicitly select a function with `func_<name>` (or `func_<idx>`)
+    /// are considered, as everything else was already applied to the whole test by
+    /// [`TestHarness::get_test_rules`][].
+    pub fn get_subtest_rules(
+        &self,
+        key: &TestKey,
+        func_idx: FuncIdx,
+        func_name: &str,
+    ) -> TestRulesPattern {
+        let mut result = TestRulesPattern {
+            run: None,
+            check: None,
+            reason: None,
+        };
+        for (pattern, rules) in self.matching_rules(key) {
+            let Some(func) = &pattern.func else {
+                continue;
+            };
+            let matches = if let Ok(idx) = func.parse::<FuncIdx>() {
+                idx == func_idx
+            } else {
+                glob_matches(func, func_name)
+            };
+            if !matches {
+                continue;
+            }
+            // Subtests only exist in the check phase, so `run` means nothing here
+            if let Some(check) = rules.check {
+                result.check = Some(check);
+            }
+            if let Some(reason) = &rules.reason {
+                result.reason = Some(reason.clone());
+            }
+        }
+        result
+    }
+
+    fn matching_rules<'a>(
+        &'a self,
+        key: &'a TestKey,
+    ) -> impl Iterator<Item = (&'a TestKeyPattern, &'a TestRulesPattern)> + 'a {
+        self.test_rules
+            .iter()
+            .flat_map(|expect_file| &expect_file.target)
+            .filter(|(target_cfg, _)| self.cfg_enabled(target_cfg))
+            .flat_map(|(_, rules)| rules)
+            .filter(|(pattern, _)| pattern.matches(key))
+    }
+
     fn cfg_enabled(&self, target_cfg: &str) -> bool {
         if target_cfg == "*" {
             true
@@ -123,6 +174,16 @@ pub struct RunOutput {
     pub caller_funcs: TestBuffer,
     #[serde(skip)]
     pub callee_funcs: TestBuffer,
+    /// With `WriteImpl::Assert` the test checks its own values, and only reports the first failure
+    pub failed_assert: Option<FailedAssert>,
+}
+
+/// An assert that failed in a test using `WriteImpl::Assert`
+#[derive(Debug, Serialize)]
+pub struct FailedAssert {
+    pub side: CallSide,
+    pub func_idx: usize,
+    pub val_idx: usize,
 }
 
 pub fn report_test(results: TestRunResults) -> TestReport {
@@ -144,8 +205,18 @@ pub fn report_test(results: TestRunResults) -> TestReport {
             TestCheckMode::Busted(must_fail) => success_at_step(&results, must_fail, false),
             TestCheckMode::Random(_) => Some(true),
         };
+        // Subtests with their own rules were already graded by `check_test`,
+        // so a passing check means all the *remaining* subtests passed.
+        // If there's nothing remaining, then the whole test is effectively busted.
+        let all_subtests_busted = results.check.as_ref().is_some_and(|check| {
+            !check.subtest_checks.is_empty()
+                && check
+                    .subtest_checks
+                    .iter()
+                    .all(|subtest| subtest.conclusion() == TestConclusion::Busted)
+        });
         if passed.unwrap_or(false) {
-            if matches!(results.rules.check, TestCheckMode::Busted(_)) {
+            if matches!(results.rules.check, TestCheckMode::Busted(_)) || all_subtests_busted {
                 TestConclusion::Busted
             } else {
                 TestConclusion::Passed
@@ -168,13 +239,16 @@ pub fn report_test(results: TestRunResults) -> TestReport {
         } else {
             Some(TestCheckMode::Busted(results.rules.run))
         },
+        reason: None,
     };
     TestReport {
         key: results.key.clone(),
-        rules: results.rules,
+        rules: results.rules.clone(),
         conclusion,
         could_be,
         results,
+        minimized: None,
+        exported_repro: None,
     }
 }
 
@@ -194,6 +268,8 @@ fn success_at_step(results: &TestRunResults, step: &TestRunMode, wants_pass: boo
 #[derive(Debug, Serialize)]
 pub struct FullReport {
     pub summary: TestSummary,
+    /// The versions of the toolchains that were tested (when they know them)
+    pub toolchain_versions: SortedMap<ToolchainId, String>,
     pub possible_rules: Option<ExpectFile>,
     pub tests: Vec<TestReport>,
 }
@@ -205,6 +281,10 @@ pub struct TestReport {
     pub results: TestRunResults,
     pub conclusion: TestConclusion,
     pub could_be: TestRulesPattern,
+    /// A single-function program that was confirmed to reproduce a run failure
+    pub minimized: Option<GenerateOutput>,
+    /// Standalone project reproducing this failure (`--export-repro`)
+    pub exported_repro: Option<Utf8PathBuf>,
 }
 
 #[derive(Debug, Serialize)]
@@ -230,11 +310,14 @@ pub struct TestKeyPattern {
     pub caller: Option<ToolchainId>,
     pub callee: Option<ToolchainId>,
     pub toolchain: Option<ToolchainId>,
+    /// Restricts the rule to the subtests for functions with this name (or index)
+    pub func: Option<String>,
     pub options: TestOptionsPattern,
 }
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TestOptionsPattern {
     pub convention: Option<CallingConvention>,
+    pub val_writer: Option<WriteImpl>,
     pub val_generator: Option<ValueGeneratorKind>,
     pub repr: Option<LangRepr>,
 }
@@ -254,32 +337,34 @@ impl TestKeyPattern {
             caller,
             callee,
             toolchain,
+            func: _,
             options:
                 TestOptionsPattern {
                     convention,
+                    val_writer,
                     val_generator,
                     repr,
                 },
         } = self;
 
         if let Some(test) = test {
-            if test != &key.test {
+            if !glob_matches(test, &key.test) {
                 return false;
             }
         }
 
         if let Some(caller) = caller {
-            if caller != &key.caller {
+            if !glob_matches(caller, &key.caller) {
                 return false;
             }
         }
         if let Some(callee) = callee {
-            if callee != &key.callee {
+            if !glob_matches(callee, &key.callee) {
                 return false;
             }
         }
         if let Some(toolchain) = toolchain {
-            if toolchain != &key.caller && toolchain != &key.callee {
+            if !glob_matches(toolchain, &key.caller) && !glob_matches(toolchain, &key.callee) {
                 return false;
             }
         }
@@ -289,6 +374,11 @@ impl TestKeyPattern {
                 return false;
             }
         }
+        if let Some(val_writer) = val_writer {
+            if val_writer != &key.options.val_writer {
+                return false;
+            }
+        }
         if let Some(val_generator) = val_generator {
             if val_generator != &key.options.val_generator {
                 return false;
@@ -316,9 +406,11 @@ impl std::str::FromStr for TestKeyPattern {
             caller: None,
             callee: None,
             toolchain: None,
+            func: None,
             options: TestOptionsPattern {
                 convention: None,
                 repr: None,
+                val_writer: None,
                 val_generator: None,
             },
         };
@@ -329,6 +421,13 @@ impl std::str::FromStr for TestKeyPattern {
         key.test = (!test.is_empty()).then(|| test.to_string());
 
         for part in rest {
+            // func (first, because function names can contain anything,
+            // including the pair suffixes below)
+            if let Some(func) = part.strip_prefix("func_") {
+                key.func = Some(func.to_owned());
+                continue;
+            }
+
             // pairs
             if let Some((caller, callee)) = part.split_once("_calls_") {
                 key.caller = Some(caller.to_owned());
@@ -359,6 +458,12 @@ impl std::str::FromStr for TestKeyPattern {
                 key.options.convention = Some(conv.parse()?);
                 continue;
             }
+
+            // writer
+            if let Ok(val_writer) = part.parse() {
+                key.options.val_writer = Some(val_writer);
+                continue;
+            }
             // generator
             if let Ok(val_generator) = part.parse() {
                 key.options.val_generator = Some(val_generator);
@@ -377,9 +482,11 @@ impl std::fmt::Display for TestKeyPattern {
             caller,
             callee,
             toolchain,
+            func,
             options:
                 TestOptionsPattern {
                     convention,
+                    val_writer,
                     val_generator,
                     repr,
                 },
@@ -397,6 +504,10 @@ impl std::fmt::Display for TestKeyPattern {
             output.push_str(separator);
             output.push_str(&format!("repr_{repr}"));
         }
+        if let Some(func) = func {
+            output.push_str(separator);
+            output.push_str(&format!("func_{func}"));
+        }
         if let Some(toolchain) = toolchain {
             output.push_str(separator);
             output.push_str(&format!("{toolchain}_toolchain"));
@@ -422,6 +533,10 @@ impl std::fmt::Display for TestKeyPattern {
                 // Noting
             }
         }
+        if let Some(val_writer) = val_writer {
+            output.push_str(separator);
+            output.push_str(&val_writer.to_string());
+        }
         if let Some(val_generator) = val_generator {
             output.push_str(separator);
             output.push_str(&val_generator.to_string());
@@ -448,11 +563,14 @@ impl Serialize for TestKeyPattern {
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TestRules {
     pub run: TestRunMode,
     #[serde(flatten)]
     pub check: TestCheckMode,
+    /// Why the test has these rules (usually a link to an issue)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -460,6 +578,9 @@ pub struct TestRulesPattern {
     pub run: Option<TestRunMode>,
     #[serde(flatten)]
     pub check: Option<TestCheckMode>,
+    /// A free-form explanation for the rule, reported alongside the results
+    #[serde(default, alias = "issue", skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
 /// How far the test should be executed
 ///
@@ -509,6 +630,8 @@ pub struct TestRunResults {
     pub build: Option<Result<BuildOutput, BuildError>>,
     pub link: Option<Result<LinkOutput, LinkError>>,
     pub run: Option<Result<RunOutput, RunError>>,
+    /// What the test program printed while running
+    pub run_log: Option<RunLog>,
     pub check: Option<CheckOutput>,
 }
 
@@ -522,6 +645,7 @@ impl TestRunResults {
             build: None,
             link: None,
             run: None,
+            run_log: None,
             check: None,
         }
     }
@@ -544,6 +668,15 @@ pub struct LinkOutput {
     pub test_bin: Utf8PathBuf,
 }
 
+/// The stdout/stderr of a test program.
+///
+/// The harness talks to the test over its own channel, so these are purely informational.
+#[derive(Debug, Serialize)]
+pub struct RunLog {
+    pub stdout: String,
+    pub stderr: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct CheckOutput {
     pub all_passed: bool,
@@ -553,8 +686,37 @@ pub struct CheckOutput {
 
 #[derive(Debug, Serialize)]
 pub struct SubtestDetails {
+    pub func_name: String,
     pub result: Result<(), CheckFailure>,
     pub minimized: Option<GenerateOutput>,
+    /// The smallest kdl-script program found to reproduce this failure (`--minimize-types`)
+    pub minimized_kdl: Option<Utf8PathBuf>,
+    /// Standalone project reproducing this failure (`--export-repro`)
+    pub exported_repro: Option<Utf8PathBuf>,
+    /// Expectation from a `func_<name>`/`func_<idx>` rule, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect: Option<TestCheckMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl SubtestDetails {
+    /// Grade the result of this subtest against its rules.
+    ///
+    /// Subtests only exist in the check phase, so any `fail`/`busted`
+    /// expectation is taken to mean "expected to fail checking".
+    pub fn conclusion(&self) -> TestConclusion {
+        use TestConclusion::*;
+        match (self.expect, self.result.is_ok()) {
+            (None | Some(TestCheckMode::Pass(_)), true) => Passed,
+            (None | Some(TestCheckMode::Pass(_)), false) => Failed,
+            (Some(TestCheckMode::Fail(_)), true) => Failed,
+            (Some(TestCheckMode::Fail(_)), false) => Passed,
+            (Some(TestCheckMode::Busted(_)), true) => Failed,
+            (Some(TestCheckMode::Busted(_)), false) => Busted,
+            (Some(TestCheckMode::Random(_)), _) => Passed,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Serialize, PartialEq, Eq, PartialOrd, Ord)]
@@ -574,6 +736,17 @@ impl FullReport {
     ) -> Result<(), std::io::Error> {
         use TestCheckMode::*;
         use TestConclusion::*;
+        if !self.toolchain_versions.is_empty() {
+            writeln!(f, "Toolchains:")?;
+            let max_id_len = self
+                .toolchain_versions
+                .keys()
+                .fold(0, |max, id| max.max(id.len()));
+            for (id, version) in &self.toolchain_versions {
+                writeln!(f, "  {id:max_id_len$} {version}")?;
+            }
+            writeln!(f)?;
+        }
         writeln!(f, "Final Results:")?;
 
         let red = Style::new().red();
@@ -616,6 +789,27 @@ impl FullReport {
                         write!(f, "{}", red.apply_to(" to "))?;
                         writeln!(f, "{}", red.apply_to(msg))?;
                         writeln!(f, "  {}", red.apply_to(err))?;
+                        if let Some(log) = &test.results.run_log {
+                            for (name, output) in [("stdout", &log.stdout), ("stderr", &log.stderr)]
+                            {
+                                if output.trim().is_empty() {
+                                    continue;
+                                }
+                                writeln!(f, "  {}", blue.apply_to(format!("{name}:")))?;
+                                for line in output.lines() {
+                                    writeln!(f, "    {line}")?;
+                                }
+                            }
+                        }
+                        if let Some(minimized) = &test.minimized {
+                            writeln!(f, "  {}", blue.apply_to("minimized to:"))?;
+                            writeln!(f, "    caller: {}", blue.apply_to(&minimized.caller_src))?;
+                            writeln!(f, "    callee: {}", blue.apply_to(&minimized.callee_src))?;
+                        }
+                        if let Some(exported) = &test.exported_repro {
+                            writeln!(f, "  {}", blue.apply_to("exported to:"))?;
+                            writeln!(f, "    {}", blue.apply_to(exported))?;
+                        }
                     }
                 }
                 (Failed, Random(_)) => {
@@ -631,7 +825,10 @@ impl FullReport {
                 )?,
 
                 (TestConclusion::Busted, _) | (Passed, TestCheckMode::Busted(_)) => {
-                    write!(f, "{}", blue.apply_to("busted (known failure, ignored)"))?
+                    write!(f, "{}", blue.apply_to("busted (known failure, ignored)"))?;
+                    if let Some(reason) = &test.rules.reason {
+                        write!(f, " {}", blue.apply_to(format!("- {reason}")))?;
+                    }
                 }
             }
 
@@ -646,12 +843,23 @@ impl FullReport {
             };
             let sub_results = &check_result.subtest_checks;
             let num_passed = sub_results.iter().filter(|t| t.result.is_ok()).count();
+            let num_busted = sub_results
+                .iter()
+                .filter(|t| t.conclusion() == TestConclusion::Busted)
+                .count();
 
-            writeln!(f, " ({num_passed:>3}/{:<3} passed)", sub_results.len())?;
+            write!(f, " ({num_passed:>3}/{:<3} passed", sub_results.len())?;
+            if num_busted > 0 {
+                write!(f, ", {}", blue.apply_to(format!("{num_busted} busted")))?;
+            }
+            writeln!(f, ")")?;
             // If all the subtests pass, don't bother with a breakdown.
             if check_result.all_passed {
                 continue;
             }
+            if let Some(description) = &harness.test(&test.key.test).meta.description {
+                writeln!(f, "  {}", blue.apply_to(description))?;
+            }
 
             let max_name_len = check_result
                 .subtest_names
@@ -660,16 +868,37 @@ impl FullReport {
             for (subtest_name, subtest) in check_result.subtest_names.iter().zip(sub_results.iter())
             {
                 write!(f, "  {:width$} ", subtest_name, width = max_name_len)?;
-                if let Err(e) = &subtest.result {
+                let conclusion = subtest.conclusion();
+                if conclusion == TestConclusion::Busted {
+                    write!(f, "{}", blue.apply_to("busted (known failure, ignored)"))?;
+                    if let Some(reason) = &subtest.reason {
+                        write!(f, " {}", blue.apply_to(format!("- {reason}")))?;
+                    }
+                    writeln!(f)?;
+                } else if conclusion == TestConclusion::Passed {
+                    writeln!(f)?;
+                } else if let Err(e) = &subtest.result {
                     writeln!(f, "{}", red.apply_to("failed!"))?;
                     if let Some(minimized) = &subtest.minimized {
                         writeln!(f, "    {}", blue.apply_to("minimized to:"))?;
                         writeln!(f, "      caller: {}", blue.apply_to(&minimized.caller_src))?;
                         writeln!(f, "      callee: {}", blue.apply_to(&minimized.callee_src))?;
                     }
+                    if let Some(minimized_kdl) = &subtest.minimized_kdl {
+                        writeln!(f, "    {}", blue.apply_to("types minimized to:"))?;
+                        writeln!(f, "      {}", blue.apply_to(minimized_kdl))?;
+                    }
+                    if let Some(exported) = &subtest.exported_repro {
+                        writeln!(f, "    {}", blue.apply_to("exported to:"))?;
+                        writeln!(f, "      {}", blue.apply_to(exported))?;
+                    }
                     writeln!(f, "{}", red.apply_to(e))?;
                 } else {
-                    writeln!(f)?;
+                    writeln!(
+                        f,
+                        "{}",
+                        green.apply_to("fixed (subtest was busted, congrats!)")
+                    )?;
                 }
             }
             writeln!(f)?;
@@ -783,6 +1012,39 @@ impl FullReport {
     }
 }
 
+/// Match a string against a simple glob pattern.
+///
+/// `*` matches any number of characters and `?` matches exactly one.
+fn glob_matches(pattern: &str, input: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let input = input.chars().collect::<Vec<_>>();
+    let (mut p, mut i) = (0, 0);
+    // The last `*` we saw, and where in the input we resumed after it
+    let mut backtrack = None;
+    while i < input.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, i));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == input[i] => {
+                p += 1;
+                i += 1;
+            }
+            _ => {
+                // Mismatch, let the last `*` eat one more character
+                let Some((star_p, star_i)) = backtrack else {
+                    return false;
+                };
+                backtrack = Some((star_p, star_i + 1));
+                p = star_p + 1;
+                i = star_i + 1;
+            }
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
 fn format_err<T, E: std::fmt::Display>(maybe_res: &Option<Result<T, E>>) -> String {
     let Some(res) = maybe_res else {
         return String::new();
@@ -792,3 +1054,54 @@ fn format_err<T, E: std::fmt::Display>(maybe_res: &Option<Result<T, E>>) -> Stri
     };
     format!("{res}")
 }
+
+#[cfg(test)]
+mod test {
+    use super::{glob_matches, TestKeyPattern};
+
+    #[test]
+    fn glob_matching() {
+        // Empty patterns only match empty inputs
+        assert!(glob_matches("", ""));
+        assert!(!glob_matches("", "i8"));
+        assert!(glob_matches("*", ""));
+
+        // Trailing `*`
+        assert!(glob_matches("opaque*", "opaque"));
+        assert!(glob_matches("opaque*", "opaque_example"));
+        assert!(!glob_matches("opaque*", "opaqu"));
+
+        // `?` at the end needs exactly one more character
+        assert!(glob_matches("i?", "i8"));
+        assert!(!glob_matches("i?", "i"));
+        assert!(!glob_matches("i?", "i16"));
+        assert!(glob_matches("i??", "i16"));
+
+        // Backtracking through a `*`
+        assert!(glob_matches("*_calls_*", "rustc_calls_cc"));
+        assert!(glob_matches("a*b*c", "aXbYbZc"));
+
+        // No match
+        assert!(!glob_matches("u*", "i32"));
+        assert!(!glob_matches("*_calls_cc", "cc_calls_rustc"));
+        assert!(!glob_matches("simple", "simple2"));
+    }
+
+    #[test]
+    fn func_patterns() {
+        // Function names that look like other parts of a key are still function names
+        for func in ["x_calls_y", "foo_callee", "foo_caller", "bar_toolchain"] {
+            let pattern: TestKeyPattern = format!("mytest::func_{func}").parse().unwrap();
+            assert_eq!(pattern.func.as_deref(), Some(func));
+            assert_eq!(pattern.caller, None);
+            assert_eq!(pattern.callee, None);
+            assert_eq!(pattern.toolchain, None);
+        }
+
+        // ...and everything else still parses alongside them
+        let pattern: TestKeyPattern = "mytest::rustc_calls_cc::func_a_callee".parse().unwrap();
+        assert_eq!(pattern.func.as_deref(), Some("a_callee"));
+        assert_eq!(pattern.caller.as_deref(), Some("rustc"));
+        assert_eq!(pattern.callee.as_deref(), Some("cc"));
+    }
+}
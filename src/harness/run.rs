@@ -1,7 +1,14 @@
 //! The runtime actual types and functions that are injected into
 //! compiled tests.
 
-use serde::Deserialize;
+#[cfg(unix)]
+mod worker;
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process::Stdio;
+
+use camino::Utf8Path;
 use serde::Serialize;
 use tracing::info;
 
@@ -9,25 +16,61 @@ use crate::error::*;
 use crate::harness::report::*;
 use crate::*;
 
+#[cfg(unix)]
+pub use worker::{run_dylib_server, DylibWorkers};
+
 impl TestHarness {
-    #[allow(dead_code)]
+    #[cfg(unix)]
+    pub async fn run_dylib_test(
+        &self,
+        key: &TestKey,
+        linked_test: &LinkOutput,
+    ) -> (Option<RunLog>, Result<RunOutput, RunError>) {
+        let test = self.test(&key.test);
+        info!(
+            "running     {}",
+            linked_test.test_bin.file_name().unwrap_or_default()
+        );
+        // Load the dylib in one of our worker processes (see DylibWorkers),
+        // so that a crashing test can't take down the whole harness.
+        match self.dylib_workers.run(&linked_test.test_bin) {
+            Ok((output, messages)) => read_test_output(&test, &key.options, output, &messages),
+            Err(e) => {
+                let bin = linked_test.test_bin.clone();
+                (None, Err(RunError::ExecError { bin, e }))
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
     pub async fn run_dylib_test(
         &self,
-        _key: &TestKey,
+        key: &TestKey,
         linked_test: &LinkOutput,
-    ) -> Result<RunOutput, RunError> {
-        let output = run_dylib_test(linked_test)?;
-        Ok(output)
+    ) -> (Option<RunLog>, Result<RunOutput, RunError>) {
+        let test = self.test(&key.test);
+        // Without fork there's no cheap way to keep a worker around that a crashing test
+        // can't take down, so each test gets a fresh one (just us, in --dylib-worker mode).
+        let worker = match std::env::current_exe() {
+            Ok(worker) => worker,
+            Err(e) => {
+                let bin = linked_test.test_bin.clone();
+                return (None, Err(RunError::ExecError { bin, e }));
+            }
+        };
+        let mut cmd = Command::new(worker);
+        cmd.arg("--dylib-worker").arg(&linked_test.test_bin);
+        run_harness_process(test, linked_test, &key.options, cmd)
     }
 
     pub async fn run_bin_test(
         &self,
         key: &TestKey,
         linked_test: &LinkOutput,
-    ) -> Result<RunOutput, RunError> {
+    ) -> (Option<RunLog>, Result<RunOutput, RunError>) {
         let test = self.test(&key.test);
-        let output = run_bin_test(test, linked_test)?;
-        Ok(output)
+        let cmd = Command::new(&linked_test.test_bin);
+        run_harness_process(test, linked_test, &key.options, cmd)
     }
 }
 
@@ -43,6 +86,9 @@ pub struct TestBuffer {
 #[derive(Debug, Serialize, Default)]
 pub struct FuncBuffer {
     pub vals: Vec<ValBuffer>,
+    /// For the callee this means it started unwinding,
+    /// for the caller it means it caught the unwind.
+    pub unwound: bool,
 }
 
 #[derive(Debug, Serialize, Default)]
@@ -72,36 +118,157 @@ impl TestBuffer {
     }
 }
 
-// The signatures of the interface from our perspective.
-// From the test's perspective the WriteBuffers are totally opaque.
-pub type SetFuncCallback = unsafe extern "C" fn(&mut TestBuffer, u32) -> ();
-pub type WriteValCallback = unsafe extern "C" fn(&mut TestBuffer, u32, *const u8, u32) -> ();
-pub type TestInit =
-    unsafe extern "C" fn(SetFuncCallback, WriteValCallback, &mut TestBuffer, &mut TestBuffer) -> ();
+/// The env var that tells a test process where to report its execution to.
+///
+/// On unix this is the number of an fd the process inherits (the write end of a pipe),
+/// elsewhere it's the path of a file to create.
+///
+/// Keeping this separate from stdout means anything the test prints
+/// (or any message a runtime decides to emit) can't corrupt the results.
+pub const HARNESS_CHANNEL_ENV: &str = "ABI_CAFE_HARNESS_CHANNEL";
+
+/// The messages a test process reports its execution with.
+///
+/// In bin mode these are written by harness_main.rs, in dylib mode by [`run_dylib_worker`][].
+/// Each message is a tag byte followed by its fields, with integers in little-endian:
+///
+/// * `0` Func: side (`u8`), func idx (`u32`)
+/// * `1` Val: side (`u8`), val idx (`u32`), len (`u32`), `len` bytes
+/// * `2` Unwind: side (`u8`)
+/// * `3` Escaped
+/// * `4` Done
+///
+/// where the side is `0` for the caller and `1` for the callee.
+#[derive(Debug, PartialEq)]
+enum HarnessMessage {
+    Func {
+        id: HarnessSide,
+        func: u32,
+    },
+    Val {
+        id: HarnessSide,
+        val: u32,
+        bytes: Vec<u8>,
+    },
+    Unwind {
+        id: HarnessSide,
+    },
+    Escaped,
+    Done,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum HarnessSide {
+    Caller,
+    Callee,
+}
+
+impl HarnessMessage {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = vec![];
+        match self {
+            HarnessMessage::Func { id, func } => {
+                out.push(0);
+                out.push(id.encode());
+                out.extend_from_slice(&func.to_le_bytes());
+            }
+            HarnessMessage::Val { id, val, bytes } => {
+                out.push(1);
+                out.push(id.encode());
+                out.extend_from_slice(&val.to_le_bytes());
+                out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                out.extend_from_slice(bytes);
+            }
+            HarnessMessage::Unwind { id } => {
+                out.push(2);
+                out.push(id.encode());
+            }
+            HarnessMessage::Escaped => out.push(3),
+            HarnessMessage::Done => out.push(4),
+        }
+        out
+    }
+
+    /// Decode one message from the front of `input`, advancing it past the message.
+    ///
+    /// Returns None if the input is malformed or truncated (i.e. the test crashed mid-write).
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let message = match take_u8(input)? {
+            0 => HarnessMessage::Func {
+                id: HarnessSide::decode(take_u8(input)?)?,
+                func: take_u32(input)?,
+            },
+            1 => {
+                let id = HarnessSide::decode(take_u8(input)?)?;
+                let val = take_u32(input)?;
+                let len = take_u32(input)? as usize;
+                let bytes = take_bytes(input, len)?.to_vec();
+                HarnessMessage::Val { id, val, bytes }
+            }
+            2 => HarnessMessage::Unwind {
+                id: HarnessSide::decode(take_u8(input)?)?,
+            },
+            3 => HarnessMessage::Escaped,
+            4 => HarnessMessage::Done,
+            _ => return None,
+        };
+        Some(message)
+    }
+}
+
+impl HarnessSide {
+    fn encode(self) -> u8 {
+        match self {
+            HarnessSide::Caller => 0,
+            HarnessSide::Callee => 1,
+        }
+    }
+    fn decode(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(HarnessSide::Caller),
+            1 => Some(HarnessSide::Callee),
+            _ => None,
+        }
+    }
+}
+
+fn take_bytes<'a>(input: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if input.len() < len {
+        return None;
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Some(bytes)
+}
+
+fn take_u8(input: &mut &[u8]) -> Option<u8> {
+    take_bytes(input, 1).map(|bytes| bytes[0])
+}
+
+fn take_u32(input: &mut &[u8]) -> Option<u32> {
+    take_bytes(input, 4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn side_vals<'a>(
+    id: HarnessSide,
+    caller_vals: &'a mut TestBuffer,
+    callee_vals: &'a mut TestBuffer,
+) -> &'a mut TestBuffer {
+    match id {
+        HarnessSide::Caller => caller_vals,
+        HarnessSide::Callee => callee_vals,
+    }
+}
 
-pub unsafe extern "C" fn set_func(test: &mut TestBuffer, func: u32) {
+fn set_func(test: &mut TestBuffer, func: u32) {
     let idx = func as usize;
     // If things aren't in-order, add empty entries to make the index exist
     let new_len = test.funcs.len().max(idx + 1);
-    test.funcs
-        .resize_with(new_len, || FuncBuffer { vals: vec![] });
+    test.funcs.resize_with(new_len, FuncBuffer::default);
     test.cur_func = Some(idx);
 }
 
-pub unsafe extern "C" fn write_val(
-    test: &mut TestBuffer,
-    val_idx: u32,
-    input: *const u8,
-    size: u32,
-) {
-    write_val_inner(
-        test,
-        val_idx,
-        std::slice::from_raw_parts(input, size as usize),
-    )
-}
-
-fn write_val_inner(test: &mut TestBuffer, val_idx: u32, data: &[u8]) {
+fn write_val(test: &mut TestBuffer, val_idx: u32, data: &[u8]) {
     // Get the current function
     let Some(func_idx) = test.cur_func else {
         test.had_missing_set_func = true;
@@ -127,122 +294,350 @@ fn write_val_inner(test: &mut TestBuffer, val_idx: u32, data: &[u8]) {
     val.bytes = data.to_vec();
 }
 
+fn mark_unwind(test: &mut TestBuffer) {
+    let Some(func_idx) = test.cur_func else {
+        test.had_missing_set_func = true;
+        return;
+    };
+    test.funcs
+        .get_mut(func_idx)
+        .expect("harness corrupted its own func idx!?")
+        .unwound = true;
+}
+
 /// Run the test!
 ///
 /// See the README for a high-level description of this design.
-fn run_dylib_test(test_dylib: &LinkOutput) -> Result<RunOutput, RunError> {
-    // Initialize all the buffers the tests will write to
-    let mut caller_vals = TestBuffer::new();
-    let mut callee_vals = TestBuffer::new();
+fn run_harness_process(
+    test: Arc<Test>,
+    test_bin: &LinkOutput,
+    options: &TestOptions,
+    mut cmd: Command,
+) -> (Option<RunLog>, Result<RunOutput, RunError>) {
+    info!(
+        "running     {}",
+        test_bin.test_bin.file_name().unwrap_or_default()
+    );
+    let channel = match HarnessChannel::new(test_bin, &mut cmd) {
+        Ok(channel) => channel,
+        Err(e) => return (None, Err(e)),
+    };
+    debug!("running: {:?}", cmd);
+    let child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    // The test has its end of the channel now, so drop ours (which cmd holds onto)
+    drop(cmd);
+    let messages = channel.read_in_background();
+    let output = match child.and_then(|child| child.wait_with_output()) {
+        Ok(output) => output,
+        Err(e) => {
+            let bin = test_bin.test_bin.clone();
+            return (None, Err(RunError::ExecError { bin, e }));
+        }
+    };
+    // The test (and so the only other end of the channel) is gone, so this won't block
+    let messages = messages.join().unwrap_or_default();
+    read_test_output(&test, options, output, &messages)
+}
 
-    unsafe {
-        info!(
-            "running     {}",
-            test_dylib.test_bin.file_name().unwrap_or_default()
-        );
-        // Load the dylib of the test, and get its test_start symbol
-        debug!("loading     {}", &test_dylib.test_bin);
-        let lib = libloading::Library::new(&test_dylib.test_bin)?;
-        let do_test: libloading::Symbol<TestInit> = lib.get(b"test_start")?;
-        debug!("calling harness dynamic function");
-        // Actually run the test!
-        do_test(set_func, write_val, &mut caller_vals, &mut callee_vals);
+/// Work out how a test went from its exit status, its output, and what it reported
+/// over the harness channel.
+fn read_test_output(
+    test: &Test,
+    options: &TestOptions,
+    output: std::process::Output,
+    messages: &[u8],
+) -> (Option<RunLog>, Result<RunOutput, RunError>) {
+    let log = RunLog {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    };
+    let status = output.status;
+    let result = match options.val_writer {
+        WriteImpl::HarnessCallback => {
+            read_harness_messages(test, options, status, decode_harness_messages(messages))
+        }
+        WriteImpl::Print => {
+            // The printed values are a readable version of the harness messages,
+            // but the program just exits when it's done.
+            let mut messages = parse_printed_messages(&log.stdout);
+            if status.success() {
+                messages.push(HarnessMessage::Done);
+            }
+            read_harness_messages(test, options, status, messages)
+        }
+        WriteImpl::Assert | WriteImpl::Noop => {
+            if let (false, Some(failed_assert)) =
+                (status.success(), parse_failed_assert(&log.stderr))
+            {
+                Ok(RunOutput {
+                    caller_funcs: TestBuffer::new(),
+                    callee_funcs: TestBuffer::new(),
+                    failed_assert: Some(failed_assert),
+                })
+            } else if !status.success() && expects_abort(test, options) {
+                // These modes don't report unwinding, so aborting is all we can check
+                Ok(RunOutput {
+                    caller_funcs: TestBuffer::new(),
+                    callee_funcs: TestBuffer::new(),
+                    failed_assert: None,
+                })
+            } else {
+                let mut messages = vec![];
+                if status.success() {
+                    messages.push(HarnessMessage::Done);
+                }
+                read_harness_messages(test, options, status, messages)
+            }
+        }
+    };
+    (Some(log), result)
+}
 
-        // Finalize the buffers (clear all the pending values).
-        caller_vals.finish_tests()?;
-        callee_vals.finish_tests()?;
+/// The harness' end of the channel a test process reports its execution to
+enum HarnessChannel {
+    /// A pipe, which the messages are streamed through as the test runs
+    #[cfg(unix)]
+    Pipe(std::io::PipeReader),
+    /// A file, which is read once the test exits
+    #[cfg(not(unix))]
+    File(camino::Utf8PathBuf),
+}
+
+impl HarnessChannel {
+    /// Make a new channel, and set up `cmd` to report to it
+    #[cfg(unix)]
+    fn new(_test_bin: &LinkOutput, cmd: &mut Command) -> Result<Self, RunError> {
+        use std::os::fd::{AsRawFd, OwnedFd};
+        use std::os::unix::process::CommandExt;
+
+        let (reader, writer) = std::io::pipe().map_err(|e| RunError::ChannelError {
+            channel: "pipe".to_owned(),
+            e,
+        })?;
+        // The fd is close-on-exec (like every fd std makes), so that none of the other tests
+        // we're running can hold it open. Only this one gets to inherit it.
+        let writer = OwnedFd::from(writer);
+        let fd = writer.as_raw_fd();
+        cmd.env(HARNESS_CHANNEL_ENV, fd.to_string());
+        unsafe {
+            cmd.pre_exec(move || {
+                // Keep our end open until the test is spawned (dropping cmd closes it)
+                let _ = &writer;
+                if libc::fcntl(fd, libc::F_SETFD, 0) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(HarnessChannel::Pipe(reader))
     }
 
-    Ok(RunOutput {
-        caller_funcs: caller_vals,
-        callee_funcs: callee_vals,
-    })
+    /// Make a new channel, and set up `cmd` to report to it
+    #[cfg(not(unix))]
+    fn new(test_bin: &LinkOutput, cmd: &mut Command) -> Result<Self, RunError> {
+        let channel = camino::Utf8PathBuf::from(format!("{}.harness", test_bin.test_bin));
+        // Don't let a previous run's messages get mistaken for this one's
+        let _ = std::fs::remove_file(&channel);
+        cmd.env(HARNESS_CHANNEL_ENV, &channel);
+        Ok(HarnessChannel::File(channel))
+    }
+
+    /// Collect everything the test reports (which will be ready once the test exits)
+    fn read_in_background(self) -> std::thread::JoinHandle<Vec<u8>> {
+        std::thread::spawn(move || {
+            let mut messages = vec![];
+            match self {
+                #[cfg(unix)]
+                HarnessChannel::Pipe(mut reader) => {
+                    // If the test crashes this is just cut short
+                    let _ = reader.read_to_end(&mut messages);
+                }
+                // If the test never got around to creating the channel, it just reported nothing
+                #[cfg(not(unix))]
+                HarnessChannel::File(path) => {
+                    let _ = File::open(path).and_then(|mut file| file.read_to_end(&mut messages));
+                }
+            }
+            messages
+        })
+    }
 }
 
-/// Run the test!
+/// Open the test process' end of the channel (see [`HARNESS_CHANNEL_ENV`][])
+fn open_harness_channel() -> Result<File, RunError> {
+    let channel = std::env::var(HARNESS_CHANNEL_ENV).unwrap_or_default();
+    #[cfg(unix)]
+    let file = {
+        use std::os::fd::FromRawFd;
+        let fd = channel
+            .parse::<std::os::fd::RawFd>()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+        // Safety: the harness gave us this fd to own
+        fd.map(|fd| unsafe { File::from_raw_fd(fd) })
+    };
+    #[cfg(not(unix))]
+    let file = File::create(&channel);
+    file.map_err(|e| RunError::ChannelError { channel, e })
+}
+
+/// Decode the contents of the harness channel.
 ///
-/// See the README for a high-level description of this design.
-fn run_bin_test(test: Arc<Test>, test_bin: &LinkOutput) -> Result<RunOutput, RunError> {
-    #[derive(Deserialize, Serialize)]
-    #[serde(rename_all = "kebab-case")]
-    #[serde(tag = "info")]
-    enum HarnessJsonMessage {
-        Func {
-            id: HarnessSide,
-            func: u32,
-        },
-        Val {
-            id: HarnessSide,
-            val: u32,
-            bytes: Vec<u8>,
-        },
-        Done,
-    }
-
-    #[derive(Deserialize, Serialize)]
-    #[serde(rename_all = "kebab-case")]
-    enum HarnessSide {
-        Caller,
-        Callee,
+/// If the test crashed in the middle of a message, everything before that is still returned.
+fn decode_harness_messages(mut input: &[u8]) -> Vec<HarnessMessage> {
+    let mut messages = vec![];
+    while !input.is_empty() {
+        let Some(message) = HarnessMessage::decode(&mut input) else {
+            break;
+        };
+        messages.push(message);
+    }
+    messages
+}
+
+/// Parse the output of a test using `WriteImpl::Print`, which looks like:
+///
+/// ```text
+/// caller func 0 (my_func)
+/// caller val 0 (arg0.x) = 1 [01 00 00 00]
+/// caller unwound
+/// ```
+///
+/// The typed value before the bytes is just for humans reading the output, only the
+/// bytes are compared. Any lines that don't look like this are ignored.
+fn parse_printed_messages(stdout: &str) -> Vec<HarnessMessage> {
+    stdout.lines().filter_map(parse_printed_message).collect()
+}
+
+fn parse_printed_message(line: &str) -> Option<HarnessMessage> {
+    let (side, rest) = line.split_once(' ')?;
+    let id = match side {
+        "caller" => HarnessSide::Caller,
+        "callee" => HarnessSide::Callee,
+        _ => return None,
+    };
+    if rest == "unwound" {
+        return Some(HarnessMessage::Unwind { id });
+    }
+    if let Some(rest) = rest.strip_prefix("func ") {
+        let (func, _name) = rest.split_once(' ')?;
+        let func = func.parse().ok()?;
+        return Some(HarnessMessage::Func { id, func });
+    }
+    if let Some(rest) = rest.strip_prefix("val ") {
+        let (val, rest) = rest.split_once(' ')?;
+        let val = val.parse().ok()?;
+        let (_path_and_typed, bytes) = rest.strip_suffix(']')?.rsplit_once('[')?;
+        let bytes = bytes
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        return Some(HarnessMessage::Val { id, val, bytes });
     }
+    None
+}
+
+/// Find the report of a test using `WriteImpl::Assert` failing an assert, which looks like:
+///
+/// ```text
+/// abi-cafe assert failed: callee func 0 val 3 (arg0.x)
+/// ```
+fn parse_failed_assert(stderr: &str) -> Option<FailedAssert> {
+    stderr.lines().find_map(|line| {
+        let rest = line.strip_prefix("abi-cafe assert failed: ")?;
+        let mut parts = rest.split_whitespace();
+        let side = match parts.next()? {
+            "caller" => CallSide::Caller,
+            "callee" => CallSide::Callee,
+            _ => return None,
+        };
+        let ("func", func_idx, "val", val_idx) =
+            (parts.next()?, parts.next()?, parts.next()?, parts.next()?)
+        else {
+            return None;
+        };
+        Some(FailedAssert {
+            side,
+            func_idx: func_idx.parse().ok()?,
+            val_idx: val_idx.parse().ok()?,
+        })
+    })
+}
 
+/// Interpret the messages a test process reported.
+fn read_harness_messages(
+    test: &Test,
+    options: &TestOptions,
+    status: std::process::ExitStatus,
+    messages: Vec<HarnessMessage>,
+) -> Result<RunOutput, RunError> {
     // Initialize all the buffers the tests will write to
     let mut caller_vals = TestBuffer::new();
     let mut callee_vals = TestBuffer::new();
     let mut finished_clean = false;
+    let mut escaped = false;
 
-    unsafe {
-        info!(
-            "running     {}",
-            test_bin.test_bin.file_name().unwrap_or_default()
-        );
-        // Load the dylib of the test, and get its test_start symbol
-
-        debug!("loading     {}", &test_bin.test_bin);
-        let mut cmd = Command::new(&test_bin.test_bin);
-        let output = cmd.output().map_err(|e| RunError::ExecError {
-            bin: test_bin.test_bin.clone(),
-            e,
-        })?;
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        for line in stdout.lines() {
-            let Ok(message): Result<HarnessJsonMessage, _> = serde_json::from_str(line) else {
-                finished_clean = false;
-                continue;
-            };
-            match message {
-                HarnessJsonMessage::Func { id, func } => {
-                    let buf = match id {
-                        HarnessSide::Caller => &mut caller_vals,
-                        HarnessSide::Callee => &mut callee_vals,
-                    };
-                    set_func(buf, func)
-                }
-                HarnessJsonMessage::Val { id, val, bytes } => {
-                    let buf = match id {
-                        HarnessSide::Caller => &mut caller_vals,
-                        HarnessSide::Callee => &mut callee_vals,
-                    };
-                    write_val_inner(buf, val, &bytes)
-                }
-                HarnessJsonMessage::Done => {
-                    finished_clean = true;
-                }
+    for message in messages {
+        match message {
+            HarnessMessage::Func { id, func } => {
+                set_func(side_vals(id, &mut caller_vals, &mut callee_vals), func)
+            }
+            HarnessMessage::Val { id, val, bytes } => write_val(
+                side_vals(id, &mut caller_vals, &mut callee_vals),
+                val,
+                &bytes,
+            ),
+            HarnessMessage::Unwind { id } => {
+                mark_unwind(side_vals(id, &mut caller_vals, &mut callee_vals))
+            }
+            HarnessMessage::Escaped => {
+                escaped = true;
+            }
+            HarnessMessage::Done => {
+                finished_clean = true;
             }
         }
-        if !output.status.success() {
-            let (caller_func_idx, caller_val_idx, caller_func) = best_vals(&test, &caller_vals);
-            let (callee_func_idx, callee_val_idx, callee_func) = best_vals(&test, &callee_vals);
-            return Err(RunError::BadExit {
-                status: output.status,
-                caller_func_idx,
-                caller_val_idx,
-                caller_func,
-                callee_func_idx,
-                callee_val_idx,
-                callee_func,
+    }
+    if !status.success() {
+        if let Some(func_idx) = unwinding_func(&callee_vals) {
+            if options.aborts(test.types.realize_func(func_idx)) {
+                // Just as specified, let check make sure the caller didn't see the unwind
+                caller_vals.finish_tests()?;
+                callee_vals.finish_tests()?;
+                return Ok(RunOutput {
+                    caller_funcs: caller_vals,
+                    callee_funcs: callee_vals,
+                    failed_assert: None,
+                });
+            }
+            return Err(RunError::UnwindAborted {
+                status,
+                func_idx,
+                func: test.types.realize_func(func_idx).name.to_string(),
             });
         }
+        let (caller_func_idx, caller_val_idx, caller_func) = best_vals(test, &caller_vals);
+        let (callee_func_idx, callee_val_idx, callee_func) = best_vals(test, &callee_vals);
+        return Err(RunError::BadExit {
+            status,
+            caller_func_idx,
+            caller_val_idx,
+            caller_func,
+            callee_func_idx,
+            callee_val_idx,
+            callee_func,
+        });
+    }
+
+    if escaped {
+        let func_idx = unwinding_func(&callee_vals).unwrap_or(0);
+        return Err(RunError::UnwindEscaped {
+            func_idx,
+            func: test.types.realize_func(func_idx).name.to_string(),
+        });
     }
 
     if !finished_clean {
@@ -258,9 +653,99 @@ fn run_bin_test(test: Arc<Test>, test_bin: &LinkOutput) -> Result<RunOutput, Run
     Ok(RunOutput {
         caller_funcs: caller_vals,
         callee_funcs: callee_vals,
+        failed_assert: None,
     })
 }
 
+/// The buffers the dylib worker hands to the test.
+///
+/// From the test's perspective these are totally opaque.
+pub struct WorkerBuffer<'a> {
+    side: HarnessSide,
+    channel: &'a File,
+}
+
+// The signatures of the interface from the worker's perspective.
+type SetFuncCallback = unsafe extern "C" fn(&mut WorkerBuffer, u32) -> ();
+type WriteValCallback = unsafe extern "C" fn(&mut WorkerBuffer, u32, *const u8, u32) -> ();
+type MarkUnwindCallback = unsafe extern "C" fn(&mut WorkerBuffer) -> ();
+/// Returns whether an unwind escaped the caller
+type TestInit = unsafe extern "C" fn(
+    SetFuncCallback,
+    WriteValCallback,
+    MarkUnwindCallback,
+    &mut WorkerBuffer,
+    &mut WorkerBuffer,
+) -> bool;
+
+unsafe extern "C" fn worker_set_func(buf: &mut WorkerBuffer, func: u32) {
+    report_to_harness(buf.channel, HarnessMessage::Func { id: buf.side, func });
+}
+
+unsafe extern "C" fn worker_write_val(
+    buf: &mut WorkerBuffer,
+    val: u32,
+    input: *const u8,
+    size: u32,
+) {
+    let bytes = std::slice::from_raw_parts(input, size as usize).to_vec();
+    let message = HarnessMessage::Val {
+        id: buf.side,
+        val,
+        bytes,
+    };
+    report_to_harness(buf.channel, message);
+}
+
+unsafe extern "C" fn worker_mark_unwind(buf: &mut WorkerBuffer) {
+    report_to_harness(buf.channel, HarnessMessage::Unwind { id: buf.side });
+}
+
+/// Report a message immediately, so that we know how far we got if the test crashes
+fn report_to_harness(mut channel: &File, message: HarnessMessage) {
+    channel
+        .write_all(&message.encode())
+        .expect("failed to write to harness channel");
+}
+
+/// Entry point of `--dylib-worker`: load a test dylib and run it,
+/// reporting the results in the same format as the harness main of a test binary.
+pub fn run_dylib_worker(test_dylib: &Utf8Path) -> Result<(), RunError> {
+    let channel = open_harness_channel()?;
+    run_dylib(test_dylib, &channel)
+}
+
+/// Load a test dylib and run it, reporting the results to `channel`
+fn run_dylib(test_dylib: &Utf8Path, channel: &File) -> Result<(), RunError> {
+    let mut caller_vals = WorkerBuffer {
+        side: HarnessSide::Caller,
+        channel,
+    };
+    let mut callee_vals = WorkerBuffer {
+        side: HarnessSide::Callee,
+        channel,
+    };
+    unsafe {
+        // Load the dylib of the test, and get its test_start symbol
+        let lib = libloading::Library::new(test_dylib)?;
+        let test_start: libloading::Symbol<TestInit> = lib.get(b"test_start")?;
+        // Actually run the test!
+        let escaped = test_start(
+            worker_set_func,
+            worker_write_val,
+            worker_mark_unwind,
+            &mut caller_vals,
+            &mut callee_vals,
+        );
+        if escaped {
+            report_to_harness(channel, HarnessMessage::Escaped);
+        } else {
+            report_to_harness(channel, HarnessMessage::Done);
+        }
+    }
+    Ok(())
+}
+
 fn best_vals(test: &Test, vals: &TestBuffer) -> (usize, usize, String) {
     let default_funcs = FuncBuffer::default();
     let func_idx = vals.cur_func.unwrap_or(0);
@@ -270,3 +755,197 @@ fn best_vals(test: &Test, vals: &TestBuffer) -> (usize, usize, String) {
 
     (func_idx, val_idx, func_name)
 }
+
+/// Whether this version of the test only runs a function that should abort the process
+fn expects_abort(test: &Test, options: &TestOptions) -> bool {
+    options
+        .active_funcs(&test.types)
+        .into_iter()
+        .any(|func| options.aborts(test.types.realize_func(func)))
+}
+
+/// If the callee was in the middle of unwinding, get the function it was unwinding out of
+fn unwinding_func(vals: &TestBuffer) -> Option<usize> {
+    let func_idx = vals.cur_func?;
+    vals.funcs[func_idx].unwound.then_some(func_idx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn all_messages() -> Vec<HarnessMessage> {
+        vec![
+            HarnessMessage::Func {
+                id: HarnessSide::Caller,
+                func: 3,
+            },
+            HarnessMessage::Val {
+                id: HarnessSide::Caller,
+                val: 0,
+                bytes: vec![1, 2, 3, 4],
+            },
+            HarnessMessage::Val {
+                id: HarnessSide::Callee,
+                val: 0x0102_0304,
+                bytes: vec![],
+            },
+            HarnessMessage::Unwind {
+                id: HarnessSide::Callee,
+            },
+            HarnessMessage::Escaped,
+            HarnessMessage::Done,
+        ]
+    }
+
+    fn encode_all(messages: &[HarnessMessage]) -> Vec<u8> {
+        messages.iter().flat_map(HarnessMessage::encode).collect()
+    }
+
+    #[test]
+    fn message_round_trip() {
+        for message in all_messages() {
+            let encoded = message.encode();
+            let mut input = &encoded[..];
+            assert_eq!(HarnessMessage::decode(&mut input), Some(message));
+            assert!(input.is_empty());
+        }
+        let messages = all_messages();
+        assert_eq!(decode_harness_messages(&encode_all(&messages)), messages);
+        assert_eq!(decode_harness_messages(&[]), vec![]);
+    }
+
+    #[test]
+    fn message_wire_format() {
+        let message = HarnessMessage::Val {
+            id: HarnessSide::Callee,
+            val: 2,
+            bytes: vec![0xAA, 0xBB],
+        };
+        assert_eq!(message.encode(), [1, 1, 2, 0, 0, 0, 2, 0, 0, 0, 0xAA, 0xBB]);
+    }
+
+    #[test]
+    fn truncated_messages() {
+        // Crashing mid-write only loses the message that was being written
+        let messages = all_messages();
+        let encoded = encode_all(&messages);
+        let mut boundaries = vec![0];
+        for message in &messages {
+            boundaries.push(boundaries.last().unwrap() + message.encode().len());
+        }
+        for len in 0..encoded.len() {
+            let complete = boundaries.iter().filter(|&&end| end <= len).count() - 1;
+            assert_eq!(
+                decode_harness_messages(&encoded[..len]),
+                messages[..complete],
+                "cut off after {len} bytes"
+            );
+        }
+        // A val that claims more bytes than there are
+        let mut input = &[1, 0, 0, 0, 0, 0, 8, 0, 0, 0, 1, 2][..];
+        assert_eq!(HarnessMessage::decode(&mut input), None);
+    }
+
+    #[test]
+    fn garbage_messages() {
+        // Unknown tags and sides stop decoding, but keep what came before
+        for garbage in [&[9][..], &[0, 2, 0, 0, 0, 0], &[2, 0xFF], &[0xFF; 16]] {
+            let mut input = garbage;
+            assert_eq!(HarnessMessage::decode(&mut input), None, "{garbage:?}");
+
+            let mut encoded = HarnessMessage::Done.encode();
+            encoded.extend_from_slice(garbage);
+            encoded.extend(HarnessMessage::Escaped.encode());
+            assert_eq!(
+                decode_harness_messages(&encoded),
+                vec![HarnessMessage::Done],
+                "{garbage:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn printed_messages() {
+        let stdout = "\
+hello from the test
+caller func 0 (my_func)
+caller val 0 (arg0.x) = 1 [01 00 00 00]
+callee func 1 (other)
+callee val 12 (arg1[0].y) = [1, 2] [01 02]
+callee val 1 (empty) = {} []
+callee unwound
+caller func x (not a number)
+callee val 0 (no bytes) = 1
+callee val 0 (bad bytes) = 1 [zz]
+someone func 0 (not a side)
+";
+        assert_eq!(
+            parse_printed_messages(stdout),
+            vec![
+                HarnessMessage::Func {
+                    id: HarnessSide::Caller,
+                    func: 0
+                },
+                HarnessMessage::Val {
+                    id: HarnessSide::Caller,
+                    val: 0,
+                    bytes: vec![1, 0, 0, 0]
+                },
+                HarnessMessage::Func {
+                    id: HarnessSide::Callee,
+                    func: 1
+                },
+                HarnessMessage::Val {
+                    id: HarnessSide::Callee,
+                    val: 12,
+                    bytes: vec![1, 2]
+                },
+                HarnessMessage::Val {
+                    id: HarnessSide::Callee,
+                    val: 1,
+                    bytes: vec![]
+                },
+                HarnessMessage::Unwind {
+                    id: HarnessSide::Callee
+                },
+            ]
+        );
+        assert_eq!(parse_printed_messages(""), vec![]);
+    }
+
+    #[test]
+    fn failed_asserts() {
+        let stderr = "\
+thread 'main' panicked somewhere
+abi-cafe assert failed: callee func 2 val 3 (arg0.x)
+abi-cafe assert failed: caller func 0 val 0 (the second one doesn't count)
+";
+        assert!(matches!(
+            parse_failed_assert(stderr),
+            Some(FailedAssert {
+                side: CallSide::Callee,
+                func_idx: 2,
+                val_idx: 3
+            })
+        ));
+        assert!(matches!(
+            parse_failed_assert("abi-cafe assert failed: caller func 1 val 0"),
+            Some(FailedAssert {
+                side: CallSide::Caller,
+                func_idx: 1,
+                val_idx: 0
+            })
+        ));
+        for stderr in [
+            "",
+            "assert failed: callee func 2 val 3",
+            "abi-cafe assert failed: nobody func 2 val 3",
+            "abi-cafe assert failed: callee fn 2 val 3",
+            "abi-cafe assert failed: callee func 2 val",
+            "abi-cafe assert failed: callee func two val 3",
+        ] {
+            assert!(parse_failed_assert(stderr).is_none(), "{stderr}");
+        }
+    }
+}
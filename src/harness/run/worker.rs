@@ -0,0 +1,199 @@
+//! Long-lived worker processes for running test dylibs.
+//!
+//! Spawning a process for every test costs more than running most tests, so in dylib mode
+//! the harness keeps `--dylib-server` workers around and sends them dylibs to run. A worker
+//! forks itself to run each one, so a test crashing (or scribbling over memory) still only
+//! takes down its own process, and the worker moves on to the next test.
+//!
+//! The harness sends a worker the path of a dylib, and gets back how the test exited, its
+//! stdout, its stderr, and everything it reported over its harness channel. The exit status
+//! is a raw `i32` wait status, and everything else is a `u32` length followed by that many
+//! bytes (all little-endian).
+
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Output, Stdio};
+use std::sync::Mutex;
+
+use camino::Utf8Path;
+
+use super::run_dylib;
+use crate::error::RunError;
+
+/// The workers that are waiting for a test to run
+#[derive(Default)]
+pub struct DylibWorkers {
+    idle: Mutex<Vec<DylibWorker>>,
+}
+
+struct DylibWorker {
+    process: Child,
+    requests: ChildStdin,
+    responses: BufReader<ChildStdout>,
+}
+
+impl DylibWorkers {
+    /// Run a test dylib, getting back how it exited, its output, and the messages it reported
+    pub fn run(&self, test_dylib: &Utf8Path) -> std::io::Result<(Output, Vec<u8>)> {
+        let worker = self.idle.lock().unwrap().pop();
+        let mut worker = match worker {
+            Some(worker) => worker,
+            None => DylibWorker::spawn()?,
+        };
+        let result = match worker.run(test_dylib) {
+            Ok(result) => result,
+            Err(_) => {
+                // Tests crash in their own process, so the worker itself must have died
+                // (or been killed). Replace it and try again.
+                worker = DylibWorker::spawn()?;
+                worker.run(test_dylib)?
+            }
+        };
+        self.idle.lock().unwrap().push(worker);
+        Ok(result)
+    }
+}
+
+impl DylibWorker {
+    fn spawn() -> std::io::Result<Self> {
+        let mut process = Command::new(std::env::current_exe()?)
+            .arg("--dylib-server")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let requests = process.stdin.take().unwrap();
+        let responses = BufReader::new(process.stdout.take().unwrap());
+        Ok(DylibWorker {
+            process,
+            requests,
+            responses,
+        })
+    }
+
+    fn run(&mut self, test_dylib: &Utf8Path) -> std::io::Result<(Output, Vec<u8>)> {
+        write_blob(&mut self.requests, test_dylib.as_str().as_bytes())?;
+        let mut status = [0; 4];
+        self.responses.read_exact(&mut status)?;
+        let status = ExitStatus::from_raw(i32::from_le_bytes(status));
+        let stdout = read_blob(&mut self.responses)?;
+        let stderr = read_blob(&mut self.responses)?;
+        let messages = read_blob(&mut self.responses)?;
+        let output = Output {
+            status,
+            stdout,
+            stderr,
+        };
+        Ok((output, messages))
+    }
+}
+
+impl Drop for DylibWorker {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// Entry point of `--dylib-server`: run the test dylibs the harness sends us,
+/// until it hangs up.
+pub fn run_dylib_server() -> Result<(), RunError> {
+    let mut requests = std::io::stdin().lock();
+    // Our own copy of stdout, so that none of the state of std's Stdout gets forked into a test
+    let mut responses = std::io::stdout()
+        .as_fd()
+        .try_clone_to_owned()
+        .map(File::from)
+        .map_err(RunError::WorkerError)?;
+    loop {
+        let test_dylib = match read_blob(&mut requests) {
+            Ok(test_dylib) => String::from_utf8_lossy(&test_dylib).into_owned(),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(RunError::WorkerError(e)),
+        };
+        let (output, messages) =
+            run_forked(Utf8Path::new(&test_dylib)).map_err(RunError::WorkerError)?;
+        let mut response = output.status.into_raw().to_le_bytes().to_vec();
+        for blob in [output.stdout, output.stderr, messages] {
+            write_blob(&mut response, &blob).unwrap();
+        }
+        responses
+            .write_all(&response)
+            .map_err(RunError::WorkerError)?;
+    }
+}
+
+/// Run a test dylib in a fork of this process, returning how it exited,
+/// its output, and the messages it reported.
+fn run_forked(test_dylib: &Utf8Path) -> std::io::Result<(Output, Vec<u8>)> {
+    let (stdout_reader, stdout_writer) = std::io::pipe()?;
+    let (stderr_reader, stderr_writer) = std::io::pipe()?;
+    let (mut channel_reader, channel_writer) = std::io::pipe()?;
+    let stdin = File::open("/dev/null")?;
+    // Safety: nothing else is running in this process (the threads from reading
+    // the last test's output have all been joined), so the fork gets a consistent copy of it.
+    let pid = unsafe { libc::fork() };
+    if pid == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if pid == 0 {
+        // We're the fork, so become the test
+        unsafe {
+            libc::dup2(stdin.as_raw_fd(), 0);
+            libc::dup2(stdout_writer.as_raw_fd(), 1);
+            libc::dup2(stderr_writer.as_raw_fd(), 2);
+        }
+        let channel = File::from(OwnedFd::from(channel_writer));
+        let code = match std::panic::catch_unwind(|| run_dylib(test_dylib, &channel)) {
+            Ok(Ok(())) => 0,
+            Ok(Err(e)) => {
+                eprintln!("Error: {e:?}");
+                1
+            }
+            Err(_) => 101,
+        };
+        let _ = std::io::stdout().flush();
+        // Exit right here (never back into the server loop), but still let C's stdio
+        // flush whatever the test printed with it.
+        unsafe { libc::exit(code) }
+    }
+    // Only the fork gets to hold the write ends, so that we see them close when it exits
+    drop((stdin, stdout_writer, stderr_writer, channel_writer));
+    let stdout = std::thread::spawn(move || read_to_end(stdout_reader));
+    let stderr = std::thread::spawn(move || read_to_end(stderr_reader));
+    let mut messages = vec![];
+    // If the test crashes this is just cut short
+    let _ = channel_reader.read_to_end(&mut messages);
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    let mut status = 0;
+    if unsafe { libc::waitpid(pid, &mut status, 0) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let output = Output {
+        status: ExitStatus::from_raw(status),
+        stdout,
+        stderr,
+    };
+    Ok((output, messages))
+}
+
+fn read_to_end(mut input: impl Read) -> Vec<u8> {
+    let mut output = vec![];
+    let _ = input.read_to_end(&mut output);
+    output
+}
+
+fn write_blob(output: &mut impl Write, blob: &[u8]) -> std::io::Result<()> {
+    output.write_all(&(blob.len() as u32).to_le_bytes())?;
+    output.write_all(blob)
+}
+
+fn read_blob(input: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    input.read_exact(&mut len)?;
+    let mut blob = vec![0; u32::from_le_bytes(len) as usize];
+    input.read_exact(&mut blob)?;
+    Ok(blob)
+}
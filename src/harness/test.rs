@@ -2,7 +2,11 @@ use std::sync::Arc;
 
 use crate::harness::vals::{ValueGeneratorKind, ValueTree};
 use crate::toolchains::*;
-use kdl_script::{parse::LangRepr, types::FuncIdx, DefinitionGraph, PunEnv, TypedProgram};
+use kdl_script::{
+    parse::LangRepr,
+    types::{Func, FuncIdx, PrimitiveTy},
+    DefinitionGraph, PunEnv, TypedProgram,
+};
 use serde::Serialize;
 
 use crate::{error::GenerateError, CliParseError};
@@ -40,6 +44,23 @@ pub struct Test {
     pub name: String,
     /// Parsed and Typechecked kdl-script program
     pub types: Arc<TypedProgram>,
+    /// What the test says about itself
+    pub meta: TestMeta,
+}
+
+/// Metadata from a test's top-level nodes (`description`, `tags`, ...)
+#[derive(Debug, Clone, Default)]
+pub struct TestMeta {
+    /// What the test is for
+    pub description: Option<String>,
+    /// Groups the test belongs to (see `--tags`)
+    pub tags: Vec<String>,
+    /// The only conventions the test is meaningful for (empty means all of them)
+    pub conventions: Vec<CallingConvention>,
+    /// The only reprs the test is meaningful for (empty means all of them)
+    pub reprs: Vec<LangRepr>,
+    /// Primitives that both toolchains must support for the test to be worth running
+    pub requires: Vec<PrimitiveTy>,
 }
 
 #[derive(Debug, Clone)]
@@ -65,6 +86,64 @@ pub struct TestOptions {
     pub val_generator: ValueGeneratorKind,
     pub repr: LangRepr,
 }
+impl TestOptions {
+    /// The calling convention a function should use: its own `@convention` if it
+    /// has one, and otherwise the one this version of the test is being run with.
+    pub fn func_convention(&self, func: &Func) -> CallingConvention {
+        func.convention()
+            .map(|convention| {
+                convention
+                    .parse()
+                    .expect("@convention wasn't validated when the test was read")
+            })
+            .unwrap_or(self.convention)
+    }
+
+    /// Whether calling a function should abort the whole process,
+    /// because it unwinds but its convention doesn't allow unwinding.
+    pub fn aborts(&self, func: &Func) -> bool {
+        func.unwinds() && !self.func_convention(func).allows_unwinding()
+    }
+
+    /// The functions this version of the test calls.
+    ///
+    /// A function that aborts would take every other function down with it,
+    /// so it's only called when it's selected on its own.
+    pub fn active_funcs(&self, types: &TypedProgram) -> Vec<FuncIdx> {
+        match &self.functions {
+            FunctionSelector::All => types
+                .all_funcs()
+                .filter(|&func| !self.aborts(types.realize_func(func)))
+                .collect(),
+            FunctionSelector::One { idx, args: _ } => vec![*idx],
+        }
+    }
+
+    /// Split out the functions that abort into versions of the test that run just them
+    /// (see [`TestOptions::active_funcs`][]).
+    pub fn split_aborts(self, types: &TypedProgram) -> Vec<TestOptions> {
+        if self.functions != FunctionSelector::All {
+            return vec![self];
+        }
+        let aborts = types
+            .all_funcs()
+            .filter(|&func| self.aborts(types.realize_func(func)))
+            .map(|idx| TestOptions {
+                functions: FunctionSelector::One {
+                    idx,
+                    args: ArgSelector::All,
+                },
+                ..self.clone()
+            })
+            .collect::<Vec<_>>();
+        let mut split = vec![];
+        if aborts.is_empty() || !self.active_funcs(types).is_empty() {
+            split.push(self);
+        }
+        split.extend(aborts);
+        split
+    }
+}
 impl FunctionSelector {
     pub fn should_write_arg(&self, func_idx: usize, arg_idx: usize) -> bool {
         match &self {
@@ -105,13 +184,6 @@ impl FunctionSelector {
             }
         }
     }
-
-    pub fn active_funcs(&self, types: &TypedProgram) -> Vec<FuncIdx> {
-        match self {
-            FunctionSelector::All => types.all_funcs().collect(),
-            FunctionSelector::One { idx, args: _ } => vec![*idx],
-        }
-    }
 }
 
 #[derive(Clone, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
@@ -132,7 +204,8 @@ pub enum ValSelector {
     One { idx: usize },
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CallSide {
     Caller,
     Callee,
@@ -202,7 +275,7 @@ impl std::ops::Deref for TestImpl {
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum WriteImpl {
     HarnessCallback,
     Assert,
@@ -235,8 +308,49 @@ impl std::fmt::Display for WriteImpl {
 }
 
 impl Test {
-    pub fn has_convention(&self, _convention: CallingConvention) -> bool {
-        true
+    /// Wrap up a typechecked program, validating the parts kdl-script doesn't understand
+    pub fn new(name: TestId, types: Arc<TypedProgram>) -> Result<Self, GenerateError> {
+        let parse_convention = |context: String, convention: &str| {
+            convention
+                .parse::<CallingConvention>()
+                .map_err(|_| GenerateError::UnknownConvention {
+                    context,
+                    convention: convention.to_owned(),
+                })
+        };
+        for func in types.all_funcs() {
+            let func = types.realize_func(func);
+            if let Some(convention) = func.convention() {
+                parse_convention(format!("function {}", func.name), convention)?;
+            }
+        }
+
+        let meta = types.meta();
+        let meta = TestMeta {
+            description: meta.description.as_ref().map(|desc| desc.to_string()),
+            tags: meta.tags.iter().map(|tag| tag.to_string()).collect(),
+            conventions: meta
+                .conventions
+                .iter()
+                .map(|convention| parse_convention(format!("test {name}"), convention))
+                .collect::<Result<_, _>>()?,
+            reprs: meta.reprs.clone(),
+            requires: types.required_primitives().to_vec(),
+        };
+        Ok(Self { name, types, meta })
+    }
+
+    pub fn has_convention(&self, convention: CallingConvention) -> bool {
+        self.meta.conventions.is_empty() || self.meta.conventions.contains(&convention)
+    }
+
+    pub fn has_repr(&self, repr: LangRepr) -> bool {
+        self.meta.reprs.is_empty() || self.meta.reprs.contains(&repr)
+    }
+
+    /// Whether the test has any of these tags (or there are no tags to look for)
+    pub fn has_any_tag(&self, tags: &[String]) -> bool {
+        tags.is_empty() || self.meta.tags.iter().any(|tag| tags.contains(tag))
     }
 
     pub async fn with_vals(
@@ -288,6 +402,10 @@ pub enum CallingConvention {
     Cdecl,
     /// The platorm's default OS convention (usually C, but Windows is Weird).
     System,
+    /// C, but unwinding across the boundary is allowed
+    CUnwind,
+    /// System, but unwinding across the boundary is allowed
+    SystemUnwind,
 
     // These conventions are specific ones
     /// x64 windows C convention
@@ -315,6 +433,8 @@ impl CallingConvention {
             CallingConvention::Rust => "rust",
             CallingConvention::Cdecl => "cdecl",
             CallingConvention::System => "system",
+            CallingConvention::CUnwind => "c-unwind",
+            CallingConvention::SystemUnwind => "system-unwind",
             CallingConvention::Win64 => "win64",
             CallingConvention::Sysv64 => "sysv64",
             CallingConvention::Aapcs => "aapcs",
@@ -323,6 +443,15 @@ impl CallingConvention {
             CallingConvention::Vectorcall => "vectorcall",
         }
     }
+
+    /// Whether unwinding out of a function with this convention is defined behaviour
+    /// (if not, it must abort the process).
+    pub fn allows_unwinding(&self) -> bool {
+        matches!(
+            self,
+            CallingConvention::Rust | CallingConvention::CUnwind | CallingConvention::SystemUnwind
+        )
+    }
 }
 
 impl std::fmt::Display for CallingConvention {
@@ -340,6 +469,8 @@ impl std::str::FromStr for CallingConvention {
             "rust" => CallingConvention::Rust,
             "cdecl" => CallingConvention::Cdecl,
             "system" => CallingConvention::System,
+            "c-unwind" => CallingConvention::CUnwind,
+            "system-unwind" => CallingConvention::SystemUnwind,
             "win64" => CallingConvention::Win64,
             "sysv64" => CallingConvention::Sysv64,
             "aapcs" => CallingConvention::Aapcs,
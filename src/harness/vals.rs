@@ -61,8 +61,22 @@ pub struct Value {
 
 #[derive(Debug, Clone)]
 pub enum ValueGenerator {
-    Graffiti { idx: u64 },
-    Random { seed: u64 },
+    Graffiti {
+        idx: u64,
+    },
+    Random {
+        seed: u64,
+    },
+    /// A value that only has `bits` bits, sign or zero extended to the size of its type
+    Bitfield {
+        inner: Box<ValueGenerator>,
+        bits: u32,
+        signed: bool,
+    },
+    /// A value that can't be all zeroes
+    NonZero {
+        inner: Box<ValueGenerator>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -318,13 +332,44 @@ impl ValueGeneratorBuilder {
                 let new_path = format!("{path}.*");
                 self.build_values(types, ty.pointee_ty, vals, new_path)?;
             }
+            Ty::NonZero(_) => {
+                let mut val = self.next(ty_idx, path);
+                val.val = ValueGenerator::NonZero {
+                    inner: Box::new(val.val),
+                };
+                vals.push(val);
+            }
 
             // Struct and array are just all of their fields combined
             Ty::Struct(ty) => {
                 for field in &ty.fields {
                     let field_name = &field.ident;
                     let new_path = format!("{path}.{field_name}");
-                    self.build_values(types, field.ty, vals, new_path)?;
+                    if field.bits == Some(0) {
+                        // Zero-width bitfields are just padding
+                        continue;
+                    }
+                    if let Some(bits) = field.bits {
+                        // Bitfields are always integers, but can't hold all of their values
+                        let signed = matches!(
+                            types.realize_ty(field.ty),
+                            Ty::Primitive(
+                                PrimitiveTy::I8
+                                    | PrimitiveTy::I16
+                                    | PrimitiveTy::I32
+                                    | PrimitiveTy::I64
+                            )
+                        );
+                        let mut val = self.next(field.ty, new_path);
+                        val.val = ValueGenerator::Bitfield {
+                            inner: Box::new(val.val),
+                            bits,
+                            signed,
+                        };
+                        vals.push(val);
+                    } else {
+                        self.build_values(types, field.ty, vals, new_path)?;
+                    }
                 }
             }
             Ty::Array(ty) => {
@@ -368,6 +413,27 @@ impl ValueGeneratorBuilder {
                 }
             }
 
+            // Option and Slice have an implicit "tag" for whether it's Some / the length
+            Ty::Option(ty) => {
+                let tag_generator = self.next(ty_idx, path.clone());
+                let is_some = tag_generator.generate_bool();
+                vals.push(tag_generator);
+
+                if is_some {
+                    self.build_values(types, ty.some_ty, vals, path)?;
+                }
+            }
+            Ty::Slice(ty) => {
+                let tag_generator = self.next(ty_idx, path.clone());
+                let len = tag_generator.generate_idx(MAX_SLICE_LEN + 1);
+                vals.push(tag_generator);
+
+                for idx in 0..len {
+                    let new_path = format!("{path}[{idx}]");
+                    self.build_values(types, ty.elem_ty, vals, new_path)?;
+                }
+            }
+
             // Pun ty is similar to a union, but for integrity we want to enforce that all paths
             // produce the same number of values
             Ty::Pun(ty) => {
@@ -429,9 +495,54 @@ impl ValueGenerator {
                 let mut rng = RngImpl::seed_from_u64(*seed);
                 rng.fill_bytes(output);
             }
+            ValueGenerator::Bitfield {
+                inner,
+                bits,
+                signed,
+            } => inner.fill_bit_int_bytes(output, *bits, *signed),
+            ValueGenerator::NonZero { inner } => {
+                inner.fill_bytes(output);
+                if output.iter().all(|&byte| byte == 0) {
+                    output[0] = 1;
+                }
+            }
+        }
+    }
+
+    /// If this is the value of a bitfield, get its width and whether it's signed
+    pub fn bitfield(&self) -> Option<(u32, bool)> {
+        match self {
+            ValueGenerator::Bitfield { bits, signed, .. } => Some((*bits, *signed)),
+            _ => None,
+        }
+    }
+
+    /// Fill `output` with an `iN`/`uN` value, sign or zero extended (little-endian)
+    /// to the whole buffer. Only the low `bits` bits are random.
+    pub fn fill_bit_int_bytes(&self, output: &mut [u8], bits: u32, signed: bool) {
+        self.fill_bytes(output);
+        let bits = bits as usize;
+        let sign = signed && bits > 0 && (output[(bits - 1) / 8] >> ((bits - 1) % 8)) & 1 == 1;
+        let ext = if sign { 0xFF } else { 0x00 };
+        for (byte_idx, byte) in output.iter_mut().enumerate() {
+            let used_bits = bits.saturating_sub(byte_idx * 8);
+            if used_bits < 8 {
+                let mask = (1u8 << used_bits) - 1;
+                *byte = (*byte & mask) | (ext & !mask);
+            }
         }
     }
 
+    /// Get the bytes of an `iN`/`uN` value, in its canonical form.
+    ///
+    /// Unused bits of `_BitInt(N)` are unspecified, so the value is always reported
+    /// sign or zero extended to the next multiple of 64 bits.
+    pub fn generate_bit_int(&self, bits: u32, signed: bool) -> Vec<u8> {
+        let mut buf = vec![0; bit_int_canonical_size(bits)];
+        self.fill_bit_int_bytes(&mut buf, bits, signed);
+        buf
+    }
+
     pub fn select_val<'a, T>(&self, options: &'a [T]) -> Option<&'a T> {
         let idx = self.generate_idx(options.len());
         options.get(idx)
@@ -451,6 +562,9 @@ impl ValueGenerator {
                 RngImpl::seed_from_u64(*idx)
             }
             ValueGenerator::Random { seed } => RngImpl::seed_from_u64(*seed),
+            ValueGenerator::Bitfield { inner, .. } | ValueGenerator::NonZero { inner } => {
+                return inner.generate_idx(len)
+            }
         };
         rng.gen_range(0..len)
     }
@@ -499,3 +613,11 @@ impl ValueGenerator {
         self.generate_u128() as i128
     }
 }
+
+/// The longest slice we'll generate (the length of each is picked like a tag)
+pub const MAX_SLICE_LEN: usize = 4;
+
+/// The size in bytes an `iN`/`uN` value is reported as (see [`ValueGenerator::generate_bit_int`][])
+pub fn bit_int_canonical_size(bits: u32) -> usize {
+    bits.div_ceil(64) as usize * 8
+}
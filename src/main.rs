@@ -20,7 +20,7 @@ use std::error::Error;
 use std::process::Command;
 use std::sync::Arc;
 use tokio::sync::OnceCell;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 pub type SortedMap<K, V> = std::collections::BTreeMap<K, V>;
 
@@ -54,23 +54,68 @@ impl std::str::FromStr for OutputFormat {
     }
 }
 
+/// How to run the linked test programs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunMode {
+    /// Link each test into its own executable and run that
+    Bin,
+    /// Link each test into a dylib and load it into a worker process
+    Dylib,
+}
+impl std::fmt::Display for RunMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            RunMode::Bin => "bin",
+            RunMode::Dylib => "dylib",
+        };
+        string.fmt(f)
+    }
+}
+impl std::str::FromStr for RunMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let val = match s {
+            "bin" => RunMode::Bin,
+            "dylib" => RunMode::Dylib,
+            _ => return Err(format!("unknown run mode: {s}")),
+        };
+        Ok(val)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub output_format: OutputFormat,
+    pub run_mode: RunMode,
     pub run_conventions: Vec<CallingConvention>,
     pub run_reprs: Vec<LangRepr>,
     pub run_toolchains: Vec<String>,
     pub run_pairs: Vec<(String, String)>,
     pub run_tests: Vec<String>,
+    pub run_tags: Vec<String>,
     pub run_values: Vec<ValueGeneratorKind>,
     pub run_writers: Vec<WriteImpl>,
     pub run_selections: Vec<FunctionSelector>,
     pub minimizing_write_impl: WriteImpl,
+    pub minimize_types: bool,
+    pub export_repro: Option<camino::Utf8PathBuf>,
+    /// Extra rustcs to test, as (toolchain id, rustup toolchain or path)
+    pub rustcs: Vec<(String, String)>,
+    pub c_toolchains: Vec<CustomCToolchain>,
+    /// Plugin toolchains, as (toolchain id, path to plugin)
+    pub plugin_toolchains: Vec<(String, String)>,
     pub rustc_codegen_backends: Vec<(String, String)>,
     pub disable_builtin_tests: bool,
     pub disable_builtin_rules: bool,
+    pub fuzz_types: u64,
+    pub fuzz_seed: u64,
     pub paths: Paths,
     pub debug: bool,
+    /// If set, we're just a worker process that should run this one test dylib
+    pub dylib_worker: Option<camino::Utf8PathBuf>,
+    /// If set, we're just a worker process that runs the test dylibs it's sent
+    pub dylib_server: bool,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -80,6 +125,15 @@ pub struct TestsFailed {}
 fn main() -> Result<(), Box<dyn Error>> {
     let cfg = cli::make_app();
     debug!("parsed cli!");
+    if let Some(dylib) = &cfg.dylib_worker {
+        harness::run_dylib_worker(dylib)?;
+        return Ok(());
+    }
+    #[cfg(unix)]
+    if cfg.dylib_server {
+        harness::run_dylib_server()?;
+        return Ok(());
+    }
     cfg.paths.init_dirs()?;
 
     let rt = tokio::runtime::Runtime::new().expect("failed to init tokio runtime");
@@ -125,10 +179,10 @@ fn main() -> Result<(), Box<dyn Error>> {
         if !cfg.run_tests.is_empty() && !cfg.run_tests.contains(&test.name) {
             continue;
         }
+        if !test.has_any_tag(&cfg.run_tags) {
+            continue;
+        }
         for &convention in &cfg.run_conventions {
-            if !test.has_convention(convention) {
-                continue;
-            }
             for (caller_id, callee_id) in &cfg.run_pairs {
                 if !cfg.run_toolchains.is_empty()
                     && !cfg.run_toolchains.iter().any(|x| x == caller_id)
@@ -140,23 +194,40 @@ fn main() -> Result<(), Box<dyn Error>> {
                     for &val_generator in &cfg.run_values {
                         for &val_writer in &cfg.run_writers {
                             for functions in &cfg.run_selections {
-                                // Run the test!
-                                let test_key = TestKey {
-                                    test: test.name.to_owned(),
-                                    caller: caller_id.to_owned(),
-                                    callee: callee_id.to_owned(),
-                                    options: TestOptions {
-                                        convention,
-                                        repr,
-                                        val_writer,
-                                        val_generator,
-                                        functions: functions.clone(),
-                                    },
+                                let options = TestOptions {
+                                    convention,
+                                    repr,
+                                    val_writer,
+                                    val_generator,
+                                    functions: functions.clone(),
                                 };
-                                let rules = harness.get_test_rules(&test_key);
-                                let task = harness.clone().spawn_test(&rt, rules, test_key.clone());
+                                // Functions that abort need a process to themselves
+                                for options in options.split_aborts(&test.types) {
+                                    // Run the test!
+                                    let test_key = TestKey {
+                                        test: test.name.to_owned(),
+                                        caller: caller_id.to_owned(),
+                                        callee: callee_id.to_owned(),
+                                        options,
+                                    };
+                                    let mut rules = harness.get_test_rules(&test_key);
+                                    if let Some(reason) = harness.pruned_reason(&test_key) {
+                                        info!(
+                                            "skipping {}: {reason}",
+                                            harness.full_test_name(&test_key)
+                                        );
+                                        rules.run = TestRunMode::Skip;
+                                        let mut results = TestRunResults::new(test_key, rules);
+                                        results.source =
+                                            Some(Err(UnsupportedError::Other(reason).into()));
+                                        tasks.push(TestTask::Pruned(Box::new(results)));
+                                        continue;
+                                    }
+                                    let task =
+                                        harness.clone().spawn_test(&rt, rules, test_key.clone());
 
-                                tasks.push(task);
+                                    tasks.push(TestTask::Spawned(task));
+                                }
                             }
                         }
                     }
@@ -167,10 +238,11 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Join on all the tasks, and compute their results
     let reports = tasks
         .into_iter()
-        .map(|task| {
-            let results = rt.block_on(task).expect("failed to join task");
-            report_test(results)
+        .map(|task| match task {
+            TestTask::Spawned(task) => rt.block_on(task).expect("failed to join task"),
+            TestTask::Pruned(results) => *results,
         })
+        .map(report_test)
         .collect::<Vec<_>>();
 
     // Compute the final report
@@ -193,8 +265,15 @@ fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// A test in the matrix, which is either running or was skipped without running it
+/// (kept together so that the results come out in matrix order).
+enum TestTask {
+    Spawned(tokio::task::JoinHandle<TestRunResults>),
+    Pruned(Box<TestRunResults>),
+}
+
 fn compute_final_report(
-    _cfg: &Config,
+    cfg: &Config,
     harness: &Arc<TestHarness>,
     reports: Vec<TestReport>,
 ) -> FullReport {
@@ -216,7 +295,39 @@ fn compute_final_report(
             Failed => {
                 num_failed += 1;
                 let pattern = harness.base_id(&report.key, None, "::");
-                if let Ok(pattern) = pattern.parse() {
+                let Ok(pattern) = pattern.parse::<TestKeyPattern>() else {
+                    continue;
+                };
+                // If only some of the subtests failed, suggest rules for just those
+                // so that we don't lose coverage of the rest of the test.
+                let partial_check = report.results.check.as_ref().filter(|check| {
+                    check
+                        .subtest_checks
+                        .iter()
+                        .any(|subtest| subtest.conclusion() != Failed)
+                });
+                if let Some(check) = partial_check {
+                    for subtest in &check.subtest_checks {
+                        if subtest.conclusion() != Failed {
+                            continue;
+                        }
+                        let mut pattern = pattern.clone();
+                        pattern.func = Some(subtest.func_name.clone());
+                        let check = if subtest.result.is_ok() {
+                            TestCheckMode::Pass(TestRunMode::Check)
+                        } else {
+                            TestCheckMode::Busted(TestRunMode::Check)
+                        };
+                        expects.insert(
+                            pattern,
+                            TestRulesPattern {
+                                run: None,
+                                check: Some(check),
+                                reason: None,
+                            },
+                        );
+                    }
+                } else {
                     expects.insert(pattern, report.could_be.clone());
                 }
             }
@@ -234,7 +345,18 @@ fn compute_final_report(
         })
     };
 
+    let toolchain_versions = cfg
+        .run_pairs
+        .iter()
+        .flat_map(|(caller, callee)| [caller, callee])
+        .filter_map(|id| {
+            let version = harness.toolchains.toolchains.get(id)?.version()?;
+            Some((id.clone(), version.to_owned()))
+        })
+        .collect();
+
     FullReport {
+        toolchain_versions,
         summary: TestSummary {
             num_tests,
             num_passed,
@@ -259,9 +381,10 @@ fn generate_minimized_failures(
         let Some(check) = report.results.check.as_ref() else {
             continue;
         };
-        // FIXME: certainly classes of run failure could also be minimized,
-        // because we have information indicating there was an error in a specific func!
         for (subtest_idx, subtest) in check.subtest_checks.iter().enumerate() {
+            if subtest.conclusion() != TestConclusion::Failed {
+                continue;
+            }
             let Err(failure) = &subtest.result else {
                 continue;
             };
@@ -285,13 +408,27 @@ fn generate_minimized_failures(
                         vals: ValSelector::One { idx: val_idx },
                     },
                 },
+                CheckFailure::UnwindMismatch { func_idx, .. }
+                | CheckFailure::AbortMismatch { func_idx, .. }
+                | CheckFailure::AssertFailed { func_idx, .. } => FunctionSelector::One {
+                    idx: func_idx,
+                    args: ArgSelector::All,
+                },
+                // Nothing ran, so there's nothing to minimize
+                CheckFailure::NotRun { .. } => continue,
             };
 
             let mut test_key = report.key.clone();
             test_key.options.functions = functions;
             test_key.options.val_writer = cfg.minimizing_write_impl;
-            let mut rules = report.rules;
-            rules.run = TestRunMode::Generate;
+            let mut rules = report.rules.clone();
+            // If we can, check that the minimized program actually reproduces the failure
+            // (noop programs can't be checked, so just generate those).
+            rules.run = if test_key.options.val_writer == WriteImpl::Noop {
+                TestRunMode::Generate
+            } else {
+                TestRunMode::Check
+            };
 
             let task = harness.clone().spawn_test(rt, rules, test_key);
             tasks.push((test_idx, subtest_idx, task));
@@ -300,12 +437,165 @@ fn generate_minimized_failures(
 
     for (test_idx, subtest_idx, task) in tasks {
         let results = rt.block_on(task).expect("failed to join task");
-        reports.tests[test_idx]
+        let reproduced = match (results.rules.run, &results.check) {
+            (TestRunMode::Check, Some(check)) => {
+                check.subtest_checks.iter().any(|sub| sub.result.is_err())
+            }
+            (TestRunMode::Check, None) => false,
+            _ => true,
+        };
+        if !reproduced {
+            info!(
+                "{} didn't reproduce the failure",
+                harness.full_test_name(&results.key)
+            );
+            continue;
+        }
+        let subtest = &mut reports.tests[test_idx]
             .results
             .check
             .as_mut()
             .unwrap()
-            .subtest_checks[subtest_idx]
-            .minimized = results.source.and_then(|r| r.ok());
+            .subtest_checks[subtest_idx];
+        subtest.exported_repro = export_repro(cfg, harness, &results);
+        subtest.minimized = results.source.and_then(|r| r.ok());
+    }
+
+    generate_minimized_crashes(cfg, harness, rt, reports);
+
+    if cfg.minimize_types {
+        generate_minimized_types(harness, rt, reports);
+    }
+}
+
+/// Regenerate programs that crashed with just the function they crashed in,
+/// and rerun them to confirm that function alone is enough to crash.
+fn generate_minimized_crashes(
+    cfg: &Config,
+    harness: &Arc<TestHarness>,
+    rt: &tokio::runtime::Runtime,
+    reports: &mut FullReport,
+) {
+    let mut tasks = vec![];
+    for (test_idx, report) in reports.tests.iter().enumerate() {
+        if report.conclusion != TestConclusion::Failed {
+            continue;
+        }
+        let func_idxs = match report.results.run {
+            Some(Err(RunError::BadExit {
+                caller_func_idx,
+                callee_func_idx,
+                ..
+            })) => {
+                // The two sides usually agree, but if they don't, either could be the culprit
+                let mut func_idxs = vec![caller_func_idx];
+                if callee_func_idx != caller_func_idx {
+                    func_idxs.push(callee_func_idx);
+                }
+                func_idxs
+            }
+            Some(Err(
+                RunError::UnwindAborted { func_idx, .. } | RunError::UnwindEscaped { func_idx, .. },
+            )) => vec![func_idx],
+            _ => continue,
+        };
+        for func_idx in func_idxs {
+            let mut test_key = report.key.clone();
+            test_key.options.functions = FunctionSelector::One {
+                idx: func_idx,
+                args: ArgSelector::All,
+            };
+            test_key.options.val_writer = cfg.minimizing_write_impl;
+            let mut rules = report.rules.clone();
+            rules.run = TestRunMode::Run;
+
+            let task = harness.clone().spawn_test(rt, rules, test_key);
+            tasks.push((test_idx, task));
+        }
+    }
+
+    for (test_idx, task) in tasks {
+        let results = rt.block_on(task).expect("failed to join task");
+        let report = &mut reports.tests[test_idx];
+        if report.minimized.is_some() {
+            continue;
+        }
+        if let Some(Err(
+            RunError::BadExit { .. }
+            | RunError::UnwindAborted { .. }
+            | RunError::UnwindEscaped { .. },
+        )) = results.run
+        {
+            report.exported_repro = export_repro(cfg, harness, &results);
+            report.minimized = results.source.and_then(|r| r.ok());
+        } else {
+            info!(
+                "{} didn't crash on its own",
+                harness.full_test_name(&results.key)
+            );
+        }
+    }
+}
+
+/// Export a minimized failure if `--export-repro` asked for it.
+fn export_repro(
+    cfg: &Config,
+    harness: &TestHarness,
+    results: &TestRunResults,
+) -> Option<camino::Utf8PathBuf> {
+    let dir = cfg.export_repro.as_ref()?;
+    match harness.export_repro(dir, results) {
+        Ok(path) => Some(path),
+        Err(e) => {
+            warn!(
+                "couldn't export {}: {e}",
+                harness.full_test_name(&results.key)
+            );
+            None
+        }
+    }
+}
+
+fn generate_minimized_types(
+    harness: &Arc<TestHarness>,
+    rt: &tokio::runtime::Runtime,
+    reports: &mut FullReport,
+) {
+    info!("minimizing types of failures...");
+    let mut tasks = vec![];
+    for (test_idx, report) in reports.tests.iter().enumerate() {
+        let Some(check) = report.results.check.as_ref() else {
+            continue;
+        };
+        for (subtest_idx, subtest) in check.subtest_checks.iter().enumerate() {
+            if subtest.conclusion() != TestConclusion::Failed {
+                continue;
+            }
+            let Err(failure) = &subtest.result else {
+                continue;
+            };
+            let original = FailureSignature::new(failure);
+            let harness = harness.clone();
+            let key = report.key.clone();
+            let task = rt.spawn(async move { harness.minimize_types(&key, &original).await });
+            tasks.push((test_idx, subtest_idx, task));
+        }
+    }
+
+    for (test_idx, subtest_idx, task) in tasks {
+        let result = rt.block_on(task).expect("failed to join task");
+        let report = &mut reports.tests[test_idx];
+        match result {
+            Ok(kdl_path) => {
+                report.results.check.as_mut().unwrap().subtest_checks[subtest_idx].minimized_kdl =
+                    Some(kdl_path);
+            }
+            Err(e) => {
+                warn!(
+                    "couldn't minimize types of {}: {e}",
+                    harness.full_test_name(&report.key)
+                );
+            }
+        }
     }
 }
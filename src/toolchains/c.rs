@@ -24,11 +24,27 @@ pub struct CcToolchain {
     cc_flavor: CCFlavor,
     target: String,
     platform: Platform,
-    mode: &'static str,
+    /// How to invoke the compiler
+    driver: CcDriver,
+    /// User-provided flags to pass to the compiler (on top of our own)
+    flags: Vec<String>,
+    /// The output of `-dumpversion`, if the compiler could be run
+    version: Option<String>,
     debug: bool,
 }
 
-#[derive(PartialEq)]
+enum CcDriver {
+    /// Let the cc crate find and invoke the "system" compiler
+    CcCrate,
+    /// Invoke this compiler ourselves (after these leading args), and archive with `archiver`
+    Command {
+        program: String,
+        args: Vec<String>,
+        archiver: String,
+    },
+}
+
+#[derive(PartialEq, Clone, Copy)]
 enum CCFlavor {
     Clang,
     Gcc,
@@ -36,6 +52,35 @@ enum CCFlavor {
     Zigcc,
 }
 
+impl CCFlavor {
+    fn from_name(name: &str) -> Self {
+        match name {
+            TOOLCHAIN_GCC => CCFlavor::Gcc,
+            TOOLCHAIN_CLANG => CCFlavor::Clang,
+            TOOLCHAIN_MSVC => CCFlavor::Msvc,
+            TOOLCHAIN_ZIGCC => CCFlavor::Zigcc,
+            name => {
+                panic!("Unknown C compiler flavor {name:?} (expected gcc, clang, msvc, or zigcc)")
+            }
+        }
+    }
+
+    /// How to invoke the compiler of this flavor on your PATH
+    fn default_driver(self) -> CcDriver {
+        let (program, args, archiver): (&str, &[&str], &str) = match self {
+            CCFlavor::Gcc => ("gcc", &[], "ar"),
+            CCFlavor::Clang => ("clang", &[], "ar"),
+            CCFlavor::Zigcc => ("zig", &["cc"], "ar"),
+            CCFlavor::Msvc => ("cl", &[], "lib"),
+        };
+        CcDriver::Command {
+            program: program.to_owned(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            archiver: archiver.to_owned(),
+        }
+    }
+}
+
 #[derive(PartialEq)]
 enum Platform {
     Windows,
@@ -56,7 +101,7 @@ impl std::ops::Deref for TestState {
 }
 impl TestState {
     fn new(inner: TestImpl) -> Self {
-        let desired_funcs = inner.options.functions.active_funcs(&inner.types);
+        let desired_funcs = inner.options.active_funcs(&inner.types);
         Self {
             inner,
             desired_funcs,
@@ -79,20 +124,20 @@ impl Toolchain for CcToolchain {
         })
     }
 
+    fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+    fn supports_primitive(&self, prim: PrimitiveTy) -> Result<(), GenerateError> {
+        self.primitive_tyname(&prim).map(|_| ())
+    }
+
     fn compile_callee(
         &self,
         src_path: &Utf8Path,
         out_dir: &Utf8Path,
         lib_name: &str,
     ) -> Result<String, BuildError> {
-        match self.mode {
-            "cc" => self.compile_cc(src_path, out_dir, lib_name),
-            "gcc" => self.compile_gcc(src_path, out_dir, lib_name),
-            "clang" => self.compile_clang(src_path, out_dir, lib_name),
-            "msvc" => self.compile_msvc(src_path, out_dir, lib_name),
-            "zigcc" => self.compile_zigcc(src_path, out_dir, lib_name),
-            _ => unimplemented!("unknown c compiler"),
-        }
+        self.compile(src_path, out_dir, lib_name)
     }
 
     fn compile_caller(
@@ -101,14 +146,60 @@ impl Toolchain for CcToolchain {
         out_dir: &Utf8Path,
         lib_name: &str,
     ) -> Result<String, BuildError> {
-        match self.mode {
-            "cc" => self.compile_cc(src_path, out_dir, lib_name),
-            "gcc" => self.compile_gcc(src_path, out_dir, lib_name),
-            "clang" => self.compile_clang(src_path, out_dir, lib_name),
-            "msvc" => self.compile_msvc(src_path, out_dir, lib_name),
-            "zigcc" => self.compile_zigcc(src_path, out_dir, lib_name),
-            _ => unimplemented!("unknown c compiler"),
+        self.compile(src_path, out_dir, lib_name)
+    }
+
+    fn repro_command(&self, _lib_name: &str) -> Result<ReproCommand, UnsupportedError> {
+        let (program, mut args) = match &self.driver {
+            CcDriver::CcCrate => {
+                // Ask the cc crate what it would have done
+                let mut build = cc::Build::new();
+                for flag in self.flags() {
+                    build.flag(flag);
+                }
+                let compiler = build
+                    .opt_level(0)
+                    .debug(self.debug)
+                    .cargo_metadata(false)
+                    .cargo_debug(false)
+                    .cargo_warnings(false)
+                    .cargo_output(false)
+                    .target(&self.target)
+                    .try_get_compiler()
+                    .map_err(|e| UnsupportedError::Other(e.to_string()))?;
+                let mut args = compiler
+                    .args()
+                    .iter()
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect::<Vec<_>>();
+                args.push("-c".to_owned());
+                return Ok(ReproCommand {
+                    program: compiler.path().to_string_lossy().into_owned(),
+                    args,
+                    output: ReproOutput::Object,
+                    emit_flag: None,
+                    version_arg: "--version",
+                });
+            }
+            CcDriver::Command { .. } if self.cc_flavor == CCFlavor::Msvc => {
+                return Err(UnsupportedError::Other(
+                    "can't export reproductions for msvc".to_owned(),
+                ))
+            }
+            CcDriver::Command { program, args, .. } => (program.clone(), args.clone()),
+        };
+        args.extend(self.flags().into_iter().map(String::from));
+        if self.debug {
+            args.push("-g".to_owned());
         }
+        args.extend(["-ffunction-sections", "-fdata-sections", "-fPIC", "-c"].map(String::from));
+        Ok(ReproCommand {
+            program,
+            args,
+            output: ReproOutput::Object,
+            emit_flag: None,
+            version_arg: "--version",
+        })
     }
 
     fn generate_callee(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError> {
@@ -157,6 +248,7 @@ impl CcToolchain {
         writeln!(f, "{{")?;
         f.add_indent(1);
         let function = state.types.realize_func(func);
+        self.check_unwind(function)?;
 
         // Report we're starting a function
         self.write_set_function(f, state, CALLER_VALS, func)?;
@@ -171,13 +263,15 @@ impl CcToolchain {
         }
 
         // Call the function
-        self.call_function(f, state, function)?;
+        let outputs = lower_outputs(&state.types, function)?;
+        self.call_function(f, state, function, &outputs)?;
 
         // Report all the outputs
-        for arg in &function.outputs {
+        for output in &outputs {
             let arg_vals: ArgValuesIter = func_vals.next_arg();
+            let arg = output.arg;
 
-            self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLER_VALS)?;
+            self.write_var(f, state, &arg.name, output.val_ty, arg_vals, CALLER_VALS)?;
         }
 
         f.sub_indent(1);
@@ -190,25 +284,33 @@ impl CcToolchain {
         f: &mut Fivemat,
         state: &TestState,
         function: &Func,
+        outputs: &[LoweredOutput],
     ) -> Result<(), GenerateError> {
         let func_name = &function.name;
 
-        // make sure the outputs aren't weird
-        self.check_returns(state, function)?;
-        if let Some(arg) = function.outputs.first() {
-            let (pre, post) = &state.tynames[&arg.ty];
-            write!(f, "{pre}{}{post} = ", arg.name)?;
+        // Make space for the outparams to be written to
+        for output in outputs.iter().filter(|output| output.outparam) {
+            let (pre, post) = &state.tynames[&output.val_ty];
+            writeln!(f, "{pre}{}{post};", output.arg.name)?;
+        }
+        if let Some(output) = outputs.iter().find(|output| !output.outparam) {
+            let (pre, post) = &state.tynames[&output.val_ty];
+            write!(f, "{pre}{}{post} = ", output.arg.name)?;
         }
 
         // Call the function
         write!(f, "{func_name}(")?;
-        let inputs = function.inputs.iter();
+        let inputs = function.inputs.iter().map(|arg| arg.name.to_string());
+        let outparams = outputs
+            .iter()
+            .filter(|output| output.outparam)
+            .map(|output| format!("&{}", output.arg.name));
 
-        for (arg_idx, arg) in inputs.enumerate() {
+        for (arg_idx, arg) in inputs.chain(outparams).enumerate() {
             if arg_idx > 0 {
                 write!(f, ", ")?;
             }
-            write!(f, "{}", arg.name)?;
+            write!(f, "{arg}")?;
         }
         writeln!(f, ");")?;
         writeln!(f)?;
@@ -239,6 +341,7 @@ impl CcToolchain {
         func: FuncIdx,
     ) -> Result<(), GenerateError> {
         let function = state.types.realize_func(func);
+        self.check_unwind(function)?;
         self.generate_signature(f, state, func)?;
         writeln!(f, " {{")?;
         f.add_indent(1);
@@ -255,16 +358,21 @@ impl CcToolchain {
         }
 
         // Create outputs and report them
-        for arg in &function.outputs {
+        let outputs = lower_outputs(&state.types, function)?;
+        for output in &outputs {
             let arg_vals = func_vals.next_arg();
-            self.init_var(f, state, &arg.name, arg.ty, arg_vals.clone())?;
-            self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLEE_VALS)?;
+            let arg = output.arg;
+            self.init_var(f, state, &arg.name, output.val_ty, arg_vals.clone())?;
+            self.write_var(f, state, &arg.name, output.val_ty, arg_vals, CALLEE_VALS)?;
         }
 
-        // Return the outputs
-        self.check_returns(state, function)?;
-        if let Some(arg) = function.outputs.first() {
-            writeln!(f, "return {};", arg.name)?;
+        // Return the outputs (memcpy so arrays work too)
+        for output in outputs.iter().filter(|output| output.outparam) {
+            let name = &output.arg.name;
+            writeln!(f, "memcpy({name}_out, &{name}, sizeof({name}));")?;
+        }
+        if let Some(output) = outputs.iter().find(|output| !output.outparam) {
+            writeln!(f, "return {};", output.arg.name)?;
         }
         f.sub_indent(1);
         writeln!(f, "}}")?;
@@ -273,12 +381,19 @@ impl CcToolchain {
 }
 
 impl CcToolchain {
-    pub fn new(system_info: &Config, target: &str, mode: &'static str) -> Self {
-        let cc_flavor = match mode {
-            TOOLCHAIN_GCC => CCFlavor::Gcc,
-            TOOLCHAIN_CLANG => CCFlavor::Clang,
-            TOOLCHAIN_MSVC => CCFlavor::Msvc,
-            TOOLCHAIN_ZIGCC => CCFlavor::Zigcc,
+    /// C has no way to unwind or catch an unwind, so it can't be on either side of `@unwind`
+    fn check_unwind(&self, function: &Func) -> Result<(), GenerateError> {
+        if function.unwinds() {
+            return Err(UnsupportedError::Other(format!(
+                "c can't unwind out of or catch {}",
+                function.name
+            )))?;
+        }
+        Ok(())
+    }
+
+    pub fn new(system_info: &Config, target: &str, mode: &str) -> Self {
+        let (cc_flavor, driver) = match mode {
             TOOLCHAIN_CC => {
                 let compiler = cc::Build::new()
                     .cargo_metadata(false)
@@ -286,7 +401,7 @@ impl CcToolchain {
                     .cargo_warnings(false)
                     .cargo_output(false)
                     .get_compiler();
-                if compiler.is_like_msvc() {
+                let cc_flavor = if compiler.is_like_msvc() {
                     CCFlavor::Msvc
                 } else if compiler.is_like_gnu() {
                     CCFlavor::Gcc
@@ -294,33 +409,110 @@ impl CcToolchain {
                     CCFlavor::Clang
                 } else {
                     panic!("Unknown compiler flavour for CC");
-                }
+                };
+                (cc_flavor, CcDriver::CcCrate)
+            }
+            mode => {
+                let cc_flavor = CCFlavor::from_name(mode);
+                (cc_flavor, cc_flavor.default_driver())
             }
-            mode => panic!("Unknown CcToolchain mode {mode:?}"),
         };
+        Self::with_driver(system_info, target, cc_flavor, driver, vec![])
+    }
 
+    /// Create a toolchain from an `--add-c-toolchain`
+    pub fn custom(system_info: &Config, target: &str, custom: &CustomCToolchain) -> Self {
+        let cc_flavor = CCFlavor::from_name(&custom.flavor);
+        let args = if cc_flavor == CCFlavor::Zigcc {
+            vec!["cc".to_owned()]
+        } else {
+            vec![]
+        };
+        let driver = CcDriver::Command {
+            program: custom.compiler.clone(),
+            args,
+            archiver: custom.archiver.clone(),
+        };
+        Self::with_driver(system_info, target, cc_flavor, driver, custom.flags.clone())
+    }
+
+    fn with_driver(
+        system_info: &Config,
+        target: &str,
+        cc_flavor: CCFlavor,
+        driver: CcDriver,
+        flags: Vec<String>,
+    ) -> Self {
         let platform = if target.contains("windows") {
             Platform::Windows
         } else {
             Platform::Unixy
         };
+        // Compilers are optional, so don't complain if it's missing until we try to use it
+        let version = match &driver {
+            _ if cc_flavor == CCFlavor::Msvc => None,
+            CcDriver::CcCrate => cc::Build::new()
+                .cargo_metadata(false)
+                .cargo_debug(false)
+                .cargo_warnings(false)
+                .cargo_output(false)
+                .try_get_compiler()
+                .ok()
+                .and_then(|compiler| dump_version(Command::new(compiler.path()))),
+            CcDriver::Command { program, args, .. } => {
+                let mut cmd = Command::new(program);
+                cmd.args(args);
+                dump_version(cmd)
+            }
+        };
 
         Self {
             cc_flavor,
             target: target.to_owned(),
             platform,
-            mode,
+            driver,
+            flags,
+            version,
             debug: system_info.debug,
         }
     }
 
-    fn extra_flags(&self) -> &[&str] {
-        match self.cc_flavor {
+    /// The major version of the compiler, if we know it
+    fn major_version(&self) -> Option<u32> {
+        let version = self.version.as_deref()?;
+        version.split('.').next()?.parse().ok()
+    }
+
+    /// All the flags we want to pass to the compiler
+    fn flags(&self) -> Vec<&str> {
+        let platform_flags: &[&str] = match self.cc_flavor {
             CCFlavor::Gcc if cfg!(target_arch = "arm") => &["-mfp16-format=ieee"],
             CCFlavor::Clang if cfg!(all(target_arch = "powerpc64", target_endian = "little")) => {
                 &["-mfloat128"]
             }
             _ => &[],
+        };
+        let mut flags = platform_flags.to_vec();
+        flags.extend(self.flags.iter().map(|flag| &**flag));
+        flags
+    }
+
+    fn compile(
+        &self,
+        src_path: &Utf8Path,
+        out_dir: &Utf8Path,
+        lib_name: &str,
+    ) -> Result<String, BuildError> {
+        match &self.driver {
+            CcDriver::CcCrate => self.compile_cc(src_path, out_dir, lib_name),
+            CcDriver::Command { .. } if self.cc_flavor == CCFlavor::Msvc => {
+                self.compile_msvc(src_path, out_dir, lib_name)
+            }
+            CcDriver::Command {
+                program,
+                args,
+                archiver,
+            } => self.compile_command(program, args, archiver, src_path, out_dir, lib_name),
         }
     }
 
@@ -331,7 +523,7 @@ impl CcToolchain {
         lib_name: &str,
     ) -> Result<String, BuildError> {
         let mut build = cc::Build::new();
-        for flag in self.extra_flags() {
+        for flag in self.flags() {
             build.flag(flag);
         }
         build
@@ -349,49 +541,20 @@ impl CcToolchain {
         Ok(String::from(lib_name))
     }
 
-    fn compile_clang(
-        &self,
-        src_path: &Utf8Path,
-        out_dir: &Utf8Path,
-        lib_name: &str,
-    ) -> Result<String, BuildError> {
-        let obj_path = out_dir.join(format!("{lib_name}.o"));
-        let lib_path = out_dir.join(format!("lib{lib_name}.a"));
-        let mut cmd = Command::new("clang");
-        for flag in self.extra_flags() {
-            cmd.arg(flag);
-        }
-        if self.debug {
-            cmd.arg("-g");
-        }
-        cmd.arg("-ffunction-sections")
-            .arg("-fdata-sections")
-            .arg("-fPIC")
-            .arg("-o")
-            .arg(&obj_path)
-            .arg("-c")
-            .arg(src_path)
-            .status()?;
-        Command::new("ar")
-            .arg("cq")
-            .arg(&lib_path)
-            .arg(&obj_path)
-            .status()?;
-        Command::new("ar").arg("s").arg(&lib_path).status()?;
-        Ok(String::from(lib_name))
-    }
-
-    fn compile_zigcc(
+    fn compile_command(
         &self,
+        program: &str,
+        args: &[String],
+        archiver: &str,
         src_path: &Utf8Path,
         out_dir: &Utf8Path,
         lib_name: &str,
     ) -> Result<String, BuildError> {
         let obj_path = out_dir.join(format!("{lib_name}.o"));
         let lib_path = out_dir.join(format!("lib{lib_name}.a"));
-        let mut cmd = Command::new("zig");
-        cmd.arg("cc");
-        for flag in self.extra_flags() {
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        for flag in self.flags() {
             cmd.arg(flag);
         }
         if self.debug {
@@ -405,44 +568,12 @@ impl CcToolchain {
             .arg("-c")
             .arg(src_path)
             .status()?;
-        Command::new("ar")
+        Command::new(archiver)
             .arg("cq")
             .arg(&lib_path)
             .arg(&obj_path)
             .status()?;
-        Command::new("ar").arg("s").arg(&lib_path).status()?;
-        Ok(String::from(lib_name))
-    }
-
-    fn compile_gcc(
-        &self,
-        src_path: &Utf8Path,
-        out_dir: &Utf8Path,
-        lib_name: &str,
-    ) -> Result<String, BuildError> {
-        let obj_path = out_dir.join(format!("{lib_name}.o"));
-        let lib_path = out_dir.join(format!("lib{lib_name}.a"));
-        let mut cmd = Command::new("gcc");
-        for flag in self.extra_flags() {
-            cmd.arg(flag);
-        }
-        if self.debug {
-            cmd.arg("-g");
-        }
-        cmd.arg("-ffunction-sections")
-            .arg("-fdata-sections")
-            .arg("-fPIC")
-            .arg("-o")
-            .arg(&obj_path)
-            .arg("-c")
-            .arg(src_path)
-            .status()?;
-        Command::new("ar")
-            .arg("cq")
-            .arg(&lib_path)
-            .arg(&obj_path)
-            .status()?;
-        Command::new("ar").arg("s").arg(&lib_path).status()?;
+        Command::new(archiver).arg("s").arg(&lib_path).status()?;
         Ok(String::from(lib_name))
     }
 
@@ -454,22 +585,13 @@ impl CcToolchain {
     ) -> Result<String, BuildError> {
         unimplemented!()
     }
+}
 
-    fn check_returns(&self, state: &TestState, function: &Func) -> Result<(), GenerateError> {
-        let has_outparams = function
-            .outputs
-            .iter()
-            .any(|arg| state.types.ty_contains_ref(arg.ty));
-        if has_outparams {
-            return Err(UnsupportedError::Other(
-                "outparams (outputs containing references) aren't supported".to_owned(),
-            ))?;
-        }
-        if function.outputs.len() > 1 {
-            return Err(UnsupportedError::Other(
-                "multiple returns (should this be a struct?)".to_owned(),
-            ))?;
-        }
-        Ok(())
-    }
+/// Ask a gcc-like compiler what version it is
+fn dump_version(mut cmd: Command) -> Option<String> {
+    cmd.arg("-dumpversion")
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_owned())
 }
@@ -1,6 +1,8 @@
 use super::*;
 use kdl_script::parse::Attr;
-use kdl_script::types::{AliasTy, ArrayTy, FuncIdx, PrimitiveTy, RefTy, Ty, TyIdx};
+use kdl_script::types::{
+    AliasTy, ArrayTy, FuncIdx, NonZeroTy, OptionTy, PrimitiveTy, RefTy, SliceTy, Ty, TyIdx,
+};
 use std::fmt::Write;
 
 impl CcToolchain {
@@ -47,6 +49,118 @@ impl CcToolchain {
         Ok(())
     }
 
+    /// The name of a primitive, or why we can't use it
+    pub fn primitive_tyname(&self, prim: &PrimitiveTy) -> Result<String, GenerateError> {
+        let bit_int_name;
+        let name = match prim {
+            PrimitiveTy::I8 => "int8_t ",
+            PrimitiveTy::I16 => "int16_t ",
+            PrimitiveTy::I32 => "int32_t ",
+            PrimitiveTy::I64 => "int64_t ",
+            PrimitiveTy::I128 => "__int128_t ",
+            PrimitiveTy::U8 => "uint8_t ",
+            PrimitiveTy::U16 => "uint16_t ",
+            PrimitiveTy::U32 => "uint32_t ",
+            PrimitiveTy::U64 => "uint64_t ",
+            PrimitiveTy::U128 => "__uint128_t ",
+            PrimitiveTy::F32 => "float ",
+            PrimitiveTy::F64 => "double ",
+            PrimitiveTy::C32 | PrimitiveTy::C64 if self.cc_flavor == CCFlavor::Msvc => Err(
+                UnsupportedError::Other("MSVC doesn't support _Complex".to_owned()),
+            )?,
+            PrimitiveTy::C32 => "float _Complex ",
+            PrimitiveTy::C64 => "double _Complex ",
+            PrimitiveTy::Bool => "bool ",
+            PrimitiveTy::Ptr => "void *",
+            PrimitiveTy::BitInt(bits) | PrimitiveTy::UBitInt(bits) => {
+                let signed = matches!(prim, PrimitiveTy::BitInt(_));
+                self.check_bit_int(*bits, signed)?;
+                bit_int_name = bit_int_tyname(*bits, signed);
+                &bit_int_name
+            }
+            PrimitiveTy::F16 => match &self.cc_flavor {
+                CCFlavor::Gcc
+                    if cfg!(any(
+                        target_arch = "x86",
+                        target_arch = "x86_64",
+                        target_arch = "arm",
+                        target_arch = "aarch64",
+                        target_arch = "riscv32",
+                        target_arch = "riscv64",
+                    )) =>
+                {
+                    "_Float16 "
+                }
+                CCFlavor::Gcc => Err(UnsupportedError::Other(
+                    "GCC isn't known to support f16 on this target".to_owned(),
+                ))?,
+                CCFlavor::Clang | CCFlavor::Zigcc
+                    if cfg!(any(
+                        all(target_arch = "x86", target_feature = "sse2"),
+                        target_arch = "x86_64",
+                        target_arch = "arm",
+                        target_arch = "aarch64",
+                        target_arch = "riscv32",
+                        target_arch = "riscv64",
+                    )) =>
+                {
+                    "_Float16 "
+                }
+                CCFlavor::Clang | CCFlavor::Zigcc => Err(UnsupportedError::Other(
+                    "Clang isn't known to support f16 on this target".to_owned(),
+                ))?,
+                CCFlavor::Msvc => Err(UnsupportedError::Other(
+                    "MSVC doesn't support f16".to_owned(),
+                ))?,
+            },
+            PrimitiveTy::F128 => {
+                match &self.cc_flavor {
+                    CCFlavor::Gcc
+                        if cfg!(any(
+                            target_arch = "x86",
+                            target_arch = "x86_64",
+                            target_arch = "aarch64",
+                            target_arch = "riscv32",
+                            target_arch = "riscv64",
+                            target_arch = "loongarch64",
+                            // GCC PowerPC support requires the VSX feature, which is only
+                            // enabled by default on powerpc64le. Rust doesn't currently support
+                            // `cfg(target_feature = "vsx").
+                            all(target_arch = "powerpc64", target_endian = "little"),
+                        )) && !cfg!(target_vendor = "apple") =>
+                    {
+                        "_Float128 "
+                    }
+                    CCFlavor::Gcc => Err(UnsupportedError::Other(
+                        "GCC isn't known to support f128 on this target".to_owned(),
+                    ))?,
+                    CCFlavor::Clang | CCFlavor::Zigcc
+                        if cfg!(any(
+                            target_arch = "x86",
+                            target_arch = "x86_64",
+                            target_arch = "aarch64",
+                            target_arch = "riscv32",
+                            target_arch = "riscv64",
+                            // Clang PowerPC support requires the VSX feature, which is only
+                            // enabled by default on powerpc64le. Rust doesn't currently support
+                            // `cfg(target_feature = "vsx").
+                            all(target_arch = "powerpc64", target_endian = "little"),
+                        )) && !cfg!(any(target_env = "msvc", target_vendor = "apple",)) =>
+                    {
+                        "__float128 "
+                    }
+                    CCFlavor::Clang | CCFlavor::Zigcc => Err(UnsupportedError::Other(
+                        "Clang isn't known to support f128 on this target".to_owned(),
+                    ))?,
+                    CCFlavor::Msvc => Err(UnsupportedError::Other(
+                        "MSVC doesn't support f128".to_owned(),
+                    ))?,
+                }
+            }
+        };
+        Ok(name.to_owned())
+    }
+
     pub fn intern_tyname(&self, state: &mut TestState, ty: TyIdx) -> Result<(), GenerateError> {
         // Don't double-intern
         if state.tynames.contains_key(&ty) {
@@ -56,129 +170,22 @@ impl CcToolchain {
         let (prefix, suffix) = match state.types.realize_ty(ty) {
             // Structural types that don't need definitions but we should
             // intern the name of
-            Ty::Primitive(prim) => {
-                let name = match prim {
-                    PrimitiveTy::I8 => "int8_t ",
-                    PrimitiveTy::I16 => "int16_t ",
-                    PrimitiveTy::I32 => "int32_t ",
-                    PrimitiveTy::I64 => "int64_t ",
-                    PrimitiveTy::I128 => "__int128_t ",
-                    PrimitiveTy::U8 => "uint8_t ",
-                    PrimitiveTy::U16 => "uint16_t ",
-                    PrimitiveTy::U32 => "uint32_t ",
-                    PrimitiveTy::U64 => "uint64_t ",
-                    PrimitiveTy::U128 => "__uint128_t ",
-                    PrimitiveTy::F32 => "float ",
-                    PrimitiveTy::F64 => "double ",
-                    PrimitiveTy::Bool => "bool ",
-                    PrimitiveTy::Ptr => "void *",
-                    PrimitiveTy::I256 => {
-                        Err(UnsupportedError::Other("c doesn't have i256?".to_owned()))?
-                    }
-                    PrimitiveTy::U256 => {
-                        Err(UnsupportedError::Other("c doesn't have u256?".to_owned()))?
-                    }
-                    PrimitiveTy::F16 => match &self.cc_flavor {
-                        CCFlavor::Gcc
-                            if cfg!(any(
-                                target_arch = "x86",
-                                target_arch = "x86_64",
-                                target_arch = "arm",
-                                target_arch = "aarch64",
-                                target_arch = "riscv32",
-                                target_arch = "riscv64",
-                            )) =>
-                        {
-                            "_Float16 "
-                        }
-                        CCFlavor::Gcc => Err(UnsupportedError::Other(
-                            "GCC isn't known to support f16 on this target".to_owned(),
-                        ))?,
-                        CCFlavor::Clang | CCFlavor::Zigcc
-                            if cfg!(any(
-                                all(target_arch = "x86", target_feature = "sse2"),
-                                target_arch = "x86_64",
-                                target_arch = "arm",
-                                target_arch = "aarch64",
-                                target_arch = "riscv32",
-                                target_arch = "riscv64",
-                            )) =>
-                        {
-                            "_Float16 "
-                        }
-                        CCFlavor::Clang | CCFlavor::Zigcc => Err(UnsupportedError::Other(
-                            "Clang isn't known to support f16 on this target".to_owned(),
-                        ))?,
-                        CCFlavor::Msvc => Err(UnsupportedError::Other(
-                            "MSVC doesn't support f16".to_owned(),
-                        ))?,
-                    },
-                    PrimitiveTy::F128 => {
-                        match &self.cc_flavor {
-                            CCFlavor::Gcc
-                                if cfg!(any(
-                                    target_arch = "x86",
-                                    target_arch = "x86_64",
-                                    target_arch = "aarch64",
-                                    target_arch = "riscv32",
-                                    target_arch = "riscv64",
-                                    target_arch = "loongarch64",
-                                    // GCC PowerPC support requires the VSX feature, which is only
-                                    // enabled by default on powerpc64le. Rust doesn't currently support
-                                    // `cfg(target_feature = "vsx").
-                                    all(target_arch = "powerpc64", target_endian = "little"),
-                                )) && !cfg!(target_vendor = "apple") =>
-                            {
-                                "_Float128 "
-                            }
-                            CCFlavor::Gcc => Err(UnsupportedError::Other(
-                                "GCC isn't known to support f128 on this target".to_owned(),
-                            ))?,
-                            CCFlavor::Clang | CCFlavor::Zigcc
-                                if cfg!(any(
-                                    target_arch = "x86",
-                                    target_arch = "x86_64",
-                                    target_arch = "aarch64",
-                                    target_arch = "riscv32",
-                                    target_arch = "riscv64",
-                                    // Clang PowerPC support requires the VSX feature, which is only
-                                    // enabled by default on powerpc64le. Rust doesn't currently support
-                                    // `cfg(target_feature = "vsx").
-                                    all(target_arch = "powerpc64", target_endian = "little"),
-                                )) && !cfg!(any(
-                                    target_env = "msvc",
-                                    target_vendor = "apple",
-                                )) =>
-                            {
-                                "__float128 "
-                            }
-                            CCFlavor::Clang | CCFlavor::Zigcc => Err(UnsupportedError::Other(
-                                "Clang isn't known to support f128 on this target".to_owned(),
-                            ))?,
-                            CCFlavor::Msvc => Err(UnsupportedError::Other(
-                                "MSVC doesn't support f128".to_owned(),
-                            ))?,
-                        }
-                    }
-                };
-                (name.to_owned(), None)
-            }
+            Ty::Primitive(prim) => (self.primitive_tyname(prim)?, None),
             Ty::Array(ArrayTy { elem_ty, len }) => {
                 let (pre, post) = &state.tynames[elem_ty];
                 (pre.clone(), Some(format!("[{len}]{post}")))
             }
             Ty::Ref(RefTy { pointee_ty }) => {
-                let (pre, post) = &state.tynames[pointee_ty];
-                // If the last type modifier was postfix (an array dimension)
-                // Then we need to introduce a set of parens to make this pointer
-                // bind more tightly
-                let was_postfix = matches!(state.types.realize_ty(*pointee_ty), Ty::Array(_));
-                if was_postfix {
-                    (format!("{pre}(*"), Some(format!("){post}")))
-                } else {
-                    (format!("{pre}*"), Some(post.clone()))
-                }
+                let (pre, post) = self.pointer_tyname(state, *pointee_ty);
+                (pre, Some(post))
             }
+            // The niche means these are just the payload (a nullable pointer or any int)
+            Ty::NonZero(NonZeroTy { int_ty: inner_ty })
+            | Ty::Option(OptionTy { some_ty: inner_ty }) => {
+                let (pre, post) = state.tynames[inner_ty].clone();
+                (pre, Some(post))
+            }
+            Ty::Slice(SliceTy { elem_ty }) => (format!("{} ", slice_tyname(state, *elem_ty)), None),
             // Nominal types we need to emit a decl for
             Ty::Struct(struct_ty) => (format!("{} ", struct_ty.name), None),
             Ty::Union(union_ty) => (format!("{} ", union_ty.name), None),
@@ -205,6 +212,20 @@ impl CcToolchain {
         Ok(())
     }
 
+    /// The name of a pointer to this type
+    pub fn pointer_tyname(&self, state: &TestState, pointee_ty: TyIdx) -> (String, String) {
+        let (pre, post) = &state.tynames[&pointee_ty];
+        // If the last type modifier was postfix (an array dimension)
+        // Then we need to introduce a set of parens to make this pointer
+        // bind more tightly
+        let was_postfix = matches!(state.types.realize_ty(pointee_ty), Ty::Array(_));
+        if was_postfix {
+            (format!("{pre}(*"), format!("){post}"))
+        } else {
+            (format!("{pre}*"), post.clone())
+        }
+    }
+
     pub fn generate_forward_decl(
         &self,
         f: &mut Fivemat,
@@ -232,6 +253,10 @@ impl CcToolchain {
                 let ty_name = &tagged_ty.name;
                 writeln!(f, "typedef struct {ty_name} {ty_name};")?;
             }
+            Ty::Slice(SliceTy { elem_ty }) => {
+                let ty_name = slice_tyname(state, *elem_ty);
+                writeln!(f, "typedef struct {ty_name} {ty_name};")?;
+            }
             Ty::Alias(AliasTy { name, real, attrs }) => {
                 if !attrs.is_empty() {
                     return Err(UnsupportedError::Other(
@@ -251,19 +276,21 @@ impl CcToolchain {
                     | PrimitiveTy::I32
                     | PrimitiveTy::I64
                     | PrimitiveTy::I128
-                    | PrimitiveTy::I256
                     | PrimitiveTy::U8
                     | PrimitiveTy::U16
                     | PrimitiveTy::U32
                     | PrimitiveTy::U64
                     | PrimitiveTy::U128
-                    | PrimitiveTy::U256
                     | PrimitiveTy::F16
                     | PrimitiveTy::F32
                     | PrimitiveTy::F64
                     | PrimitiveTy::F128
+                    | PrimitiveTy::C32
+                    | PrimitiveTy::C64
                     | PrimitiveTy::Bool
-                    | PrimitiveTy::Ptr => {
+                    | PrimitiveTy::Ptr
+                    | PrimitiveTy::BitInt(_)
+                    | PrimitiveTy::UBitInt(_) => {
                         // Builtin
                     }
                 };
@@ -271,7 +298,7 @@ impl CcToolchain {
             Ty::Array(ArrayTy { .. }) => {
                 // Builtin
             }
-            Ty::Ref(RefTy { .. }) => {
+            Ty::Ref(RefTy { .. }) | Ty::NonZero(_) | Ty::Option(_) => {
                 // Builtin
             }
             Ty::Empty => {
@@ -302,7 +329,14 @@ impl CcToolchain {
                 for field in &struct_ty.fields {
                     let field_name = &field.ident;
                     let (pre, post) = &state.tynames[&field.ty];
-                    writeln!(f, "{pre}{field_name}{post};")?;
+                    if field.bits == Some(0) {
+                        // Zero-width bitfields aren't allowed to have a name
+                        writeln!(f, "{pre}{post} : 0;")?;
+                    } else if let Some(bits) = field.bits {
+                        writeln!(f, "{pre}{field_name}{post} : {bits};")?;
+                    } else {
+                        writeln!(f, "{pre}{field_name}{post};")?;
+                    }
                 }
                 f.sub_indent(1);
                 writeln!(f, "}} {};\n", struct_ty.name)?;
@@ -322,12 +356,21 @@ impl CcToolchain {
             }
             Ty::Enum(enum_ty) => {
                 // Emit an actual enum decl
-                self.generate_repr_attr(f, state, &enum_ty.attrs, "enum")?;
-                writeln!(f, "typedef enum {} {{", enum_ty.name)?;
+                let fixed_type = self.generate_repr_attr(f, state, &enum_ty.attrs, "enum")?;
+                match fixed_type {
+                    Some(int_ty) => writeln!(
+                        f,
+                        "typedef enum {} : {} {{",
+                        enum_ty.name,
+                        int_ty.trim_end()
+                    )?,
+                    None => writeln!(f, "typedef enum {} {{", enum_ty.name)?,
+                }
                 f.add_indent(1);
                 for variant in &enum_ty.variants {
                     let variant_name = &variant.name;
-                    writeln!(f, "{}_{variant_name},", enum_ty.name)?;
+                    let val = c_enum_discriminant(variant.val);
+                    writeln!(f, "{}_{variant_name} = {val},", enum_ty.name)?;
                 }
                 f.sub_indent(1);
                 writeln!(f, "}} {};\n", enum_ty.name)?;
@@ -378,19 +421,21 @@ impl CcToolchain {
                     | PrimitiveTy::I32
                     | PrimitiveTy::I64
                     | PrimitiveTy::I128
-                    | PrimitiveTy::I256
                     | PrimitiveTy::U8
                     | PrimitiveTy::U16
                     | PrimitiveTy::U32
                     | PrimitiveTy::U64
                     | PrimitiveTy::U128
-                    | PrimitiveTy::U256
                     | PrimitiveTy::F16
                     | PrimitiveTy::F32
                     | PrimitiveTy::F64
                     | PrimitiveTy::F128
+                    | PrimitiveTy::C32
+                    | PrimitiveTy::C64
                     | PrimitiveTy::Bool
-                    | PrimitiveTy::Ptr => {
+                    | PrimitiveTy::Ptr
+                    | PrimitiveTy::BitInt(_)
+                    | PrimitiveTy::UBitInt(_) => {
                         // Builtin
                     }
                 };
@@ -398,7 +443,18 @@ impl CcToolchain {
             Ty::Array(ArrayTy { .. }) => {
                 // Builtin
             }
-            Ty::Ref(RefTy { .. }) => {
+            Ty::Slice(SliceTy { elem_ty }) => {
+                // Emit the (pointer, length) struct everyone passes slices as
+                let ty_name = slice_tyname(state, *elem_ty);
+                let (pre, post) = self.pointer_tyname(state, *elem_ty);
+                writeln!(f, "typedef struct {ty_name} {{")?;
+                f.add_indent(1);
+                writeln!(f, "{pre}ptr{post};")?;
+                writeln!(f, "size_t len;")?;
+                f.sub_indent(1);
+                writeln!(f, "}} {ty_name};\n")?;
+            }
+            Ty::Ref(RefTy { .. }) | Ty::NonZero(_) | Ty::Option(_) => {
                 // Builtin
             }
             Ty::Empty => {
@@ -410,20 +466,34 @@ impl CcToolchain {
         Ok(())
     }
 
+    /// Emit the attributes of a type decl.
+    ///
+    /// For an enum with an integer repr, returns the fixed underlying type it should be declared with.
     pub fn generate_repr_attr(
         &self,
         f: &mut Fivemat,
         state: &TestState,
         attrs: &[Attr],
-        _ty_style: &str,
-    ) -> Result<(), GenerateError> {
-        use kdl_script::parse::{AttrAligned, AttrPacked, AttrPassthrough, AttrRepr, Repr};
-        if !attrs.is_empty() {
+        ty_style: &str,
+    ) -> Result<Option<String>, GenerateError> {
+        use kdl_script::parse::{
+            AttrAligned, AttrPacked, AttrPassthrough, AttrRepr, AttrUnwind, Repr,
+        };
+        // The only attribute we support so far is an enum's integer repr
+        let is_enum_int_repr = |attr: &Attr| match attr {
+            Attr::Repr(AttrRepr { reprs }) => {
+                ty_style == "enum" && reprs.iter().all(|repr| matches!(repr, Repr::Primitive(_)))
+            }
+            _ => false,
+        };
+        if !attrs.iter().all(is_enum_int_repr) {
             return Err(UnsupportedError::Other(
                 "c doesn't support attrs yet".to_owned(),
             ))?;
         }
 
+        let mut fixed_type = None;
+
         let mut default_lang_repr = true;
         let mut lang_repr = None;
         let mut repr_attrs = vec![];
@@ -441,6 +511,16 @@ impl CcToolchain {
                 Attr::Passthrough(AttrPassthrough(attr)) => {
                     other_attrs.push(attr);
                 }
+                Attr::Unwind(AttrUnwind {}) => {
+                    return Err(UnsupportedError::Other(
+                        "@unwind doesn't apply to types".to_owned(),
+                    ))?;
+                }
+                Attr::Convention(_) => {
+                    return Err(UnsupportedError::Other(
+                        "@convention doesn't apply to types".to_owned(),
+                    ))?;
+                }
                 Attr::Repr(AttrRepr { reprs }) => {
                     default_lang_repr = false;
                     // Any explicit repr attributes disables default C
@@ -451,6 +531,9 @@ impl CcToolchain {
                                     "unsupport repr transparent".to_owned(),
                                 ))?;
                             }
+                            Repr::Primitive(prim) if ty_style == "enum" => {
+                                fixed_type = Some(self.enum_fixed_type(*prim)?);
+                            }
                             Repr::Primitive(prim) => {
                                 return Err(UnsupportedError::Other(format!(
                                     "unsupport repr {prim:?}"
@@ -486,7 +569,29 @@ impl CcToolchain {
         for attr in other_attrs {
             writeln!(f, "{}", attr)?;
         }
-        Ok(())
+        Ok(fixed_type)
+    }
+
+    /// The fixed underlying type (`enum E : int64_t`) for an enum with an integer repr.
+    ///
+    /// This is C23, but clang has long accepted it as an extension.
+    fn enum_fixed_type(&self, prim: PrimitiveTy) -> Result<String, GenerateError> {
+        let supported = match self.cc_flavor {
+            CCFlavor::Clang | CCFlavor::Zigcc => true,
+            CCFlavor::Gcc => self.major_version().is_some_and(|major| major >= 13),
+            CCFlavor::Msvc => false,
+        };
+        if !supported {
+            return Err(UnsupportedError::Other(
+                "this C compiler doesn't support enums with fixed underlying types".to_owned(),
+            ))?;
+        }
+        if prim.int_range().is_none() {
+            return Err(UnsupportedError::Other(format!(
+                "enums can't have a {prim:?} repr"
+            )))?;
+        }
+        self.primitive_tyname(&prim)
     }
 
     pub fn generate_signature(
@@ -496,14 +601,15 @@ impl CcToolchain {
         func: FuncIdx,
     ) -> Result<(), GenerateError> {
         let function = state.types.realize_func(func);
+        let outputs = lower_outputs(&state.types, function)?;
 
-        let (pre, post) = if let Some(output) = function.outputs.first() {
-            let (pre, post) = &state.tynames[&output.ty];
+        let (pre, post) = if let Some(output) = outputs.iter().find(|output| !output.outparam) {
+            let (pre, post) = &state.tynames[&output.val_ty];
             (&**pre, &**post)
         } else {
             ("void ", "")
         };
-        let convention_decl = self.convention_decl(state.options.convention)?;
+        let convention_decl = self.convention_decl(state.options.func_convention(function))?;
         write!(f, "{pre}{}{}{post}(", convention_decl, function.name)?;
         let mut multiarg = false;
         // Add inputs
@@ -516,6 +622,16 @@ impl CcToolchain {
             let (pre, post) = &state.tynames[&arg.ty];
             write!(f, "{pre}{}{post}", arg_name)?;
         }
+        // Add outparams
+        for output in outputs.iter().filter(|output| output.outparam) {
+            if multiarg {
+                write!(f, ", ")?;
+            }
+            multiarg = true;
+            let arg_name = &output.arg.name;
+            let (pre, post) = self.pointer_tyname(state, output.val_ty);
+            write!(f, "{pre}{arg_name}_out{post}")?;
+        }
         write!(f, ")")?;
         Ok(())
     }
@@ -541,16 +657,49 @@ impl CcToolchain {
         //  * __cdecl, __clrcall, __stdcall, __fastcall, __thiscall, __vectorcall
 
         let val = match convention {
-            System | Win64 | Sysv64 | Aapcs => {
-                // Don't want to think about these yet, I think they're
-                // all properly convered by other ABIs
+            Aapcs => {
+                // Don't want to think about this one yet, I think it's
+                // properly covered by other ABIs
                 return Err(self.unsupported_convention(&convention))?;
             }
+            System | SystemUnwind => {
+                // Matches rustc's definition: stdcall on 32-bit windows, otherwise C
+                if self.platform == Windows && self.is_x86() {
+                    match self.cc_flavor {
+                        Msvc => "__stdcall ",
+                        Gcc | Clang | Zigcc => "__attribute__((stdcall)) ",
+                    }
+                } else {
+                    ""
+                }
+            }
+            Win64 => {
+                if self.is_x86_64() {
+                    match self.cc_flavor {
+                        // MSVC only targets windows, where this is already the default
+                        Msvc => "",
+                        Gcc | Clang | Zigcc => "__attribute__((ms_abi)) ",
+                    }
+                } else {
+                    return Err(self.unsupported_convention(&convention))?;
+                }
+            }
+            Sysv64 => {
+                if self.is_x86_64() {
+                    match self.cc_flavor {
+                        Msvc => return Err(self.unsupported_convention(&convention))?,
+                        Gcc | Clang | Zigcc => "__attribute__((sysv_abi)) ",
+                    }
+                } else {
+                    return Err(self.unsupported_convention(&convention))?;
+                }
+            }
             // C knows no Rust
             Rust => {
                 return Err(self.unsupported_convention(&convention))?;
             }
-            C => "",
+            // C can't unwind, but it can still be the one *declaring* these
+            C | CUnwind => "",
             Cdecl => {
                 if self.platform == Windows {
                     match self.cc_flavor {
@@ -605,7 +754,71 @@ impl CcToolchain {
         }
     }
 
+    /// `_BitInt(N)` is new in C23, so check if this compiler actually has it
+    fn check_bit_int(&self, bits: u32, signed: bool) -> Result<(), GenerateError> {
+        if signed && bits < 2 {
+            return Err(UnsupportedError::Other(
+                "c's signed _BitInt needs at least 2 bits".to_owned(),
+            ))?;
+        }
+        match self.cc_flavor {
+            CCFlavor::Clang | CCFlavor::Zigcc => Ok(()),
+            CCFlavor::Gcc if self.major_version().is_some_and(|v| v >= 14) && self.is_x86_64() => {
+                Ok(())
+            }
+            CCFlavor::Gcc => Err(UnsupportedError::Other(
+                "GCC only supports _BitInt on x86_64 (in GCC 14 and later)".to_owned(),
+            ))?,
+            CCFlavor::Msvc => Err(UnsupportedError::Other(
+                "MSVC doesn't support _BitInt".to_owned(),
+            ))?,
+        }
+    }
+
     fn unsupported_convention(&self, convention: &CallingConvention) -> UnsupportedError {
         UnsupportedError::Other(format!("unsupported convention {convention}"))
     }
+
+    fn is_x86_64(&self) -> bool {
+        self.target.starts_with("x86_64")
+    }
+
+    fn is_x86(&self) -> bool {
+        ["i386", "i586", "i686"]
+            .iter()
+            .any(|arch| self.target.starts_with(arch))
+    }
+}
+
+/// The C name of an `iN`/`uN`
+pub fn bit_int_tyname(bits: u32, signed: bool) -> String {
+    if signed {
+        format!("_BitInt({bits}) ")
+    } else {
+        format!("unsigned _BitInt({bits}) ")
+    }
+}
+
+/// The C name of the struct a `&[T]` is passed as
+pub fn slice_tyname(state: &TestState, elem_ty: TyIdx) -> String {
+    let elem_name = state
+        .types
+        .format_ty(elem_ty)
+        .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+    format!("slice_{elem_name}")
+}
+
+/// Write an enum discriminant as a C literal.
+///
+/// Values outside of `int` get an explicit suffix (C compilers will then
+/// widen the enum's underlying type), and the most negative value can't be
+/// written as a negated literal at all.
+fn c_enum_discriminant(val: i64) -> String {
+    if val == i64::MIN {
+        format!("({}LL - 1)", val + 1)
+    } else if i32::try_from(val).is_ok() {
+        val.to_string()
+    } else {
+        format!("{val}LL")
+    }
 }
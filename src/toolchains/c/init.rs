@@ -1,6 +1,9 @@
+use super::declare::bit_int_tyname;
 use super::*;
-use crate::harness::vals::{ArgValuesIter, Value};
-use kdl_script::types::{AliasTy, ArrayTy, PrimitiveTy, RefTy, Ty, TyIdx};
+use crate::harness::vals::{ArgValuesIter, Value, MAX_SLICE_LEN};
+use kdl_script::types::{
+    AliasTy, ArrayTy, NonZeroTy, OptionTy, PrimitiveTy, RefTy, SliceTy, Ty, TyIdx,
+};
 use std::fmt::Write;
 
 impl CcToolchain {
@@ -58,6 +61,25 @@ impl CcToolchain {
                         write!(f, "{val}")?
                     }
                 }
+                // Complex values are the bits of the real part followed by the imaginary part
+                PrimitiveTy::C32 => {
+                    let val = val.generate_u64();
+                    let re = val & 0xFFFF_FFFF;
+                    let im = val >> 32;
+                    write!(
+                        f,
+                        "(((union {{ uint32_t bits[2]; float _Complex value; }}){{ .bits = {{ {re:#X}u, {im:#X}u }} }}).value)"
+                    )?
+                }
+                PrimitiveTy::C64 => {
+                    let val = val.generate_u128();
+                    let re = val & 0xFFFF_FFFF_FFFF_FFFF;
+                    let im = val >> 64;
+                    write!(
+                        f,
+                        "(((union {{ uint64_t bits[2]; double _Complex value; }}){{ .bits = {{ {re:#X}ull, {im:#X}ull }} }}).value)"
+                    )?
+                }
                 PrimitiveTy::Bool => write!(f, "{}", val.generate_bool())?,
                 PrimitiveTy::Ptr => {
                     if true {
@@ -66,11 +88,11 @@ impl CcToolchain {
                         write!(f, "(void*){:#X}ul", val.generate_u32())?
                     }
                 }
-                PrimitiveTy::I256 => {
-                    Err(UnsupportedError::Other("c doesn't have i256?".to_owned()))?
-                }
-                PrimitiveTy::U256 => {
-                    Err(UnsupportedError::Other("c doesn't have u256?".to_owned()))?
+                PrimitiveTy::BitInt(bits) | PrimitiveTy::UBitInt(bits) => {
+                    let signed = matches!(prim, PrimitiveTy::BitInt(_));
+                    let tyname = bit_int_tyname(*bits, signed);
+                    write!(f, "({tyname})")?;
+                    self.init_bit_int_bits(f, val, *bits, signed)?;
                 }
                 PrimitiveTy::F16 => write!(
                     f,
@@ -94,11 +116,43 @@ impl CcToolchain {
                     write!(f, "{name}_{variant_name}")?;
                 }
             }
+            Ty::NonZero(NonZeroTy { int_ty }) => {
+                self.init_leaf_value(f, state, *int_ty, val, alias)?;
+            }
             _ => unreachable!("only primitives and enums should be passed to generate_leaf_value"),
         }
         Ok(())
     }
 
+    /// Write the bits of an `iN`/`uN` as an unsigned value, without needing C23 literal suffixes
+    pub fn init_bit_int_bits(
+        &self,
+        f: &mut Fivemat,
+        val: &Value,
+        bits: u32,
+        signed: bool,
+    ) -> Result<(), GenerateError> {
+        let bytes = val.generate_bit_int(bits, signed);
+        let mut chunks = bytes
+            .chunks(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()));
+        if bytes.len() == 8 {
+            write!(f, "{:#X}ull", chunks.next().unwrap())?;
+            return Ok(());
+        }
+        // Build it up out of 64-bit chunks in a type that has room for all of them
+        let wide_tyname = bit_int_tyname(bytes.len() as u32 * 8, false);
+        write!(f, "(")?;
+        for (chunk_idx, chunk) in chunks.enumerate() {
+            if chunk_idx > 0 {
+                write!(f, " | ")?;
+            }
+            write!(f, "(({wide_tyname}){chunk:#X}ull << {})", chunk_idx * 64)?;
+        }
+        write!(f, ")")?;
+        Ok(())
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn init_value(
         &self,
@@ -112,10 +166,51 @@ impl CcToolchain {
     ) -> Result<(), GenerateError> {
         match state.types.realize_ty(ty) {
             // Primitives and Enums are the only "real" values with actual bytes
-            Ty::Primitive(_) | Ty::Enum(_) => {
+            Ty::Primitive(_) | Ty::Enum(_) | Ty::NonZero(_) => {
                 let val = vals.next_val();
                 self.init_leaf_value(f, state, ty, &val, alias)?;
             }
+            Ty::Option(OptionTy { some_ty }) => {
+                let tag_val = vals.next_val();
+                if tag_val.generate_bool() {
+                    self.init_value(f, state, *some_ty, vals, alias, ref_temp_name, extra_decls)?;
+                } else if let Ty::Ref(_) = state.types.realize_ty(*some_ty) {
+                    write!(f, "NULL")?;
+                } else {
+                    write!(f, "0")?;
+                }
+            }
+            Ty::Slice(SliceTy { elem_ty }) => {
+                let tag_val = vals.next_val();
+                let len = tag_val.generate_idx(MAX_SLICE_LEN + 1);
+                if len == 0 {
+                    write!(f, "{{ .ptr = NULL, .len = 0 }}")?;
+                    return Ok(());
+                }
+                // The elements live in a temporary array
+                write!(f, "{{ .ptr = {ref_temp_name}, .len = {len} }}")?;
+                let mut ref_temp = String::new();
+                let mut ref_temp_f = Fivemat::new(&mut ref_temp, INDENT);
+                let (pre, post) = &state.tynames[elem_ty];
+                write!(&mut ref_temp_f, "{pre}{ref_temp_name}[{len}]{post} = {{")?;
+                for elem_idx in 0..len {
+                    if elem_idx > 0 {
+                        write!(&mut ref_temp_f, ", ")?;
+                    }
+                    let ref_temp_name = format!("{ref_temp_name}{elem_idx}_");
+                    self.init_value(
+                        &mut ref_temp_f,
+                        state,
+                        *elem_ty,
+                        vals,
+                        alias,
+                        &ref_temp_name,
+                        extra_decls,
+                    )?;
+                }
+                write!(&mut ref_temp_f, "}};")?;
+                extra_decls.push(ref_temp);
+            }
             Ty::Ref(RefTy { pointee_ty }) => {
                 // The value is a mutable reference to a temporary
                 write!(f, "&{ref_temp_name}")?;
@@ -152,7 +247,9 @@ impl CcToolchain {
             // Nominal types we need to emit a decl for
             Ty::Struct(struct_ty) => {
                 write!(f, "{{ ")?;
-                for (field_idx, field) in struct_ty.fields.iter().enumerate() {
+                // Zero-width bitfields have nothing to initialize
+                let fields = struct_ty.fields.iter().filter(|f| f.bits != Some(0));
+                for (field_idx, field) in fields.enumerate() {
                     if field_idx > 0 {
                         write!(f, ", ")?;
                     }
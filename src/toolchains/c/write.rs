@@ -1,5 +1,7 @@
+use super::declare::bit_int_tyname;
 use super::*;
-use kdl_script::types::{Ty, TyIdx};
+use crate::harness::vals::{bit_int_canonical_size, MAX_SLICE_LEN};
+use kdl_script::types::{PrimitiveTy, Ty, TyIdx};
 use std::fmt::Write;
 
 impl CcToolchain {
@@ -12,16 +14,14 @@ impl CcToolchain {
     ) -> Result<(), GenerateError> {
         // Always need includes for things like int8_t
         writeln!(f, "{}", crate::files::get_file("harness/c/test_prefix.h"))?;
-        // No extra harness gunk if not needed
-        if state.options.val_writer != WriteImpl::HarnessCallback {
-            return Ok(());
-        }
-        // Load test harness "headers"
-        writeln!(
-            f,
-            "{}",
-            crate::files::get_file("harness/c/harness_prefix.h")
-        )?;
+        // Load test harness "headers" (if needed)
+        let prefix = match state.options.val_writer {
+            WriteImpl::HarnessCallback => "harness/c/harness_prefix.h",
+            WriteImpl::Print => "harness/c/print_prefix.h",
+            WriteImpl::Assert => "harness/c/assert_prefix.h",
+            WriteImpl::Noop => return Ok(()),
+        };
+        writeln!(f, "{}", crate::files::get_file(prefix))?;
 
         writeln!(f)?;
 
@@ -89,7 +89,7 @@ impl CcToolchain {
                     writeln!(f, "}}")?;
                 }
             }
-            Ty::Primitive(_) => {
+            Ty::Primitive(_) | Ty::NonZero(_) => {
                 // Hey an actual leaf, report it (and burn a value)
                 let val = vals.next_val();
                 if val.should_write_val(&state.options) {
@@ -107,7 +107,7 @@ impl CcToolchain {
                     if tag_generator.should_write_val(&state.options) {
                         writeln!(f, "if ({enum_name}_{variant_name} == {from}) {{")?;
                         f.add_indent(1);
-                        self.write_tag_field(f, state, to, from, tag_idx, &tag_generator)?;
+                        self.write_enum_tag_field(f, state, to, from, tag_idx, &tag_generator)?;
                         f.sub_indent(1);
                         writeln!(f, "}} else {{")?;
                         f.add_indent(1);
@@ -137,8 +137,8 @@ impl CcToolchain {
                 }
             }
             Ty::Struct(struct_ty) => {
-                // recurse into each field
-                for field in &struct_ty.fields {
+                // recurse into each field (zero-width bitfields have no value)
+                for field in struct_ty.fields.iter().filter(|f| f.bits != Some(0)) {
                     let field_name = &field.ident;
                     let base = format!("{from}.{field_name}");
                     self.write_fields(f, state, to, &base, field.ty, vals)?;
@@ -224,6 +224,57 @@ impl CcToolchain {
                 let base = format!("(*{from})");
                 self.write_fields(f, state, to, &base, ref_ty.pointee_ty, vals)?
             }
+            Ty::Option(option_ty) => {
+                // The niche is the implicit "tag", only look at the payload if it's there
+                let tag_generator = vals.next_val();
+                let is_some = tag_generator.generate_bool();
+                let should_write_tag = tag_generator.should_write_val(&state.options);
+                writeln!(f, "if ({from}) {{")?;
+                f.add_indent(1);
+                if is_some {
+                    if should_write_tag {
+                        self.write_tag_field(f, state, to, from, 1, &tag_generator)?;
+                    }
+                    self.write_fields(f, state, to, from, option_ty.some_ty, vals)?;
+                } else if should_write_tag {
+                    self.write_error_tag_field(f, state, to, &tag_generator)?;
+                }
+                f.sub_indent(1);
+                writeln!(f, "}} else {{")?;
+                f.add_indent(1);
+                if should_write_tag {
+                    if is_some {
+                        self.write_error_tag_field(f, state, to, &tag_generator)?;
+                    } else {
+                        self.write_tag_field(f, state, to, from, 0, &tag_generator)?;
+                    }
+                }
+                f.sub_indent(1);
+                writeln!(f, "}}")?;
+            }
+            Ty::Slice(slice_ty) => {
+                // The length is the implicit "tag", only look at the elements if it's right
+                let tag_generator = vals.next_val();
+                let len = tag_generator.generate_idx(MAX_SLICE_LEN + 1);
+                let should_write_tag = tag_generator.should_write_val(&state.options);
+                writeln!(f, "if ({from}.len == {len}) {{")?;
+                f.add_indent(1);
+                if should_write_tag {
+                    self.write_tag_field(f, state, to, from, len, &tag_generator)?;
+                }
+                for i in 0..len {
+                    let base = format!("{from}.ptr[{i}]");
+                    self.write_fields(f, state, to, &base, slice_ty.elem_ty, vals)?;
+                }
+                f.sub_indent(1);
+                writeln!(f, "}} else {{")?;
+                f.add_indent(1);
+                if should_write_tag {
+                    self.write_error_tag_field(f, state, to, &tag_generator)?;
+                }
+                f.sub_indent(1);
+                writeln!(f, "}}")?;
+            }
             Ty::Union(union_ty) => {
                 // Process the implicit "tag" value
                 let tag_generator = vals.next_val();
@@ -250,11 +301,37 @@ impl CcToolchain {
         path: &str,
         val: &ValueRef,
     ) -> Result<(), GenerateError> {
+        // The unused bits of a _BitInt are unspecified, so report a widened copy.
+        // Bitfields don't have an address, so those get copied out too.
+        let bit_int = match state.types.realize_ty(val.ty) {
+            Ty::Primitive(prim) => prim.bit_int(),
+            _ => None,
+        };
+        let canon_tyname = if let Some((bits, signed)) = bit_int {
+            Some(bit_int_tyname(
+                bit_int_canonical_size(bits) as u32 * 8,
+                signed,
+            ))
+        } else if val.bitfield().is_some() {
+            Some(state.tynames[&val.ty].0.clone())
+        } else {
+            None
+        };
+        // Convenience for triggering test failures
+        let poisoned = path.contains("abicafepoison") && to.contains(CALLEE_VALS);
+        let path = if let Some(canon_tyname) = &canon_tyname {
+            writeln!(f, "{{")?;
+            f.add_indent(1);
+            writeln!(f, "{canon_tyname}_canon = {path};")?;
+            "_canon"
+        } else {
+            path
+        };
+
         match state.options.val_writer {
             WriteImpl::HarnessCallback => {
                 let val_idx = val.absolute_val_idx;
-                // Convenience for triggering test failures
-                let rvalue = if path.contains("abicafepoison") && to.contains(CALLEE_VALS) {
+                let rvalue = if poisoned {
                     "(uint32_t)0x12345678"
                 } else {
                     path
@@ -262,17 +339,47 @@ impl CcToolchain {
                 writeln!(f, "write_val({to}, {val_idx}, {rvalue});")?;
             }
             WriteImpl::Assert => {
-                write!(f, "assert_eq({path}, ")?;
-                self.init_leaf_value(f, state, val.ty, val, None)?;
-                writeln!(f, ");")?;
+                let side = side_name(to);
+                let func_idx = val.func_idx;
+                let val_idx = val.absolute_val_idx;
+                let val_path = &val.path;
+                writeln!(f, "{{")?;
+                f.add_indent(1);
+                if let (Some(canon_tyname), Some((bits, signed))) = (&canon_tyname, bit_int) {
+                    write!(f, "{canon_tyname}_expected = ")?;
+                    self.init_bit_int_bits(f, val, bits, signed)?;
+                } else {
+                    let (pre, post) = &state.tynames[&val.ty];
+                    write!(f, "{pre}_expected{post} = ")?;
+                    self.init_leaf_value(f, state, val.ty, val, None)?;
+                }
+                writeln!(f, ";")?;
+                writeln!(
+                    f,
+                    "check_val(\"{side}\", {func_idx}, {val_idx}, \"{val_path}\", {path}, _expected);"
+                )?;
+                f.sub_indent(1);
+                writeln!(f, "}}")?;
             }
             WriteImpl::Print => {
-                writeln!(f, "printf(\"%d\", {path});")?;
+                let side = side_name(to);
+                let val_idx = val.absolute_val_idx;
+                let val_path = &val.path;
+                let (fmt, typed) = print_format(state, val.ty, path);
+                writeln!(
+                    f,
+                    "print_val(\"{side}\", {val_idx}, \"{val_path}\", {fmt}, {typed}, {path});"
+                )?;
             }
             WriteImpl::Noop => {
                 // Noop, do nothing
             }
         }
+
+        if canon_tyname.is_some() {
+            f.sub_indent(1);
+            writeln!(f, "}}")?;
+        }
         Ok(())
     }
 
@@ -300,10 +407,10 @@ impl CcToolchain {
                 writeln!(f, "}}")?;
             }
             WriteImpl::Assert => {
-                // Noop, do nothing
+                // Noop, being in this branch is the assertion
             }
             WriteImpl::Print => {
-                // Noop, do nothing
+                self.write_print_tag(f, to, val, variant_idx as u32)?;
             }
             WriteImpl::Noop => {
                 // Noop, do nothing
@@ -312,7 +419,31 @@ impl CcToolchain {
         Ok(())
     }
 
-    #[allow(dead_code)]
+    /// Like [`Self::write_tag_field`][] but also reports the raw bytes of the enum,
+    /// so that the check can catch the two sides disagreeing on its size/discriminants.
+    pub fn write_enum_tag_field(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        to: &str,
+        path: &str,
+        variant_idx: usize,
+        val: &ValueRef,
+    ) -> Result<(), GenerateError> {
+        match state.options.val_writer {
+            WriteImpl::HarnessCallback => {
+                // Convenience for triggering test failures
+                if path.contains("abicafepoison") && to.contains(CALLEE_VALS) {
+                    return self.write_error_tag_field(f, state, to, val);
+                }
+                let val_idx = val.absolute_val_idx;
+                writeln!(f, "write_enum_val({to}, {val_idx}, {variant_idx}, {path});")?;
+            }
+            _ => self.write_tag_field(f, state, to, path, variant_idx, val)?,
+        }
+        Ok(())
+    }
+
     pub fn write_error_tag_field(
         &self,
         f: &mut Fivemat,
@@ -330,8 +461,18 @@ impl CcToolchain {
                 f.sub_indent(1);
                 writeln!(f, "}}")?;
             }
-            WriteImpl::Assert | WriteImpl::Print => {
-                writeln!(f, r#"unreachable("enum had unexpected variant!?")"#)?;
+            WriteImpl::Assert => {
+                let side = side_name(to);
+                let func_idx = val.func_idx;
+                let val_idx = val.absolute_val_idx;
+                let val_path = &val.path;
+                writeln!(
+                    f,
+                    "assert_failed(\"{side}\", {func_idx}, {val_idx}, \"{val_path}\");"
+                )?;
+            }
+            WriteImpl::Print => {
+                self.write_print_tag(f, to, val, u32::MAX)?;
             }
             WriteImpl::Noop => {
                 // Noop, do nothing
@@ -351,10 +492,83 @@ impl CcToolchain {
             WriteImpl::HarnessCallback => {
                 writeln!(f, "set_func({vals}, {idx});")?;
             }
-            WriteImpl::Print | WriteImpl::Noop | WriteImpl::Assert => {
+            WriteImpl::Print => {
+                let side = side_name(vals);
+                let func_name = &state.types.realize_func(idx).name;
+                writeln!(f, "set_func(\"{side}\", {idx}, \"{func_name}\");")?;
+            }
+            WriteImpl::Noop | WriteImpl::Assert => {
                 // Noop
             }
         }
         Ok(())
     }
+
+    /// Print the variant index of a tag (print_val needs an lvalue, so make a temporary)
+    fn write_print_tag(
+        &self,
+        f: &mut Fivemat,
+        to: &str,
+        val: &ValueRef,
+        variant_idx: u32,
+    ) -> Result<(), GenerateError> {
+        let side = side_name(to);
+        let val_idx = val.absolute_val_idx;
+        let val_path = &val.path;
+        writeln!(f, "{{")?;
+        f.add_indent(1);
+        writeln!(f, "uint32_t _temp = {variant_idx};")?;
+        writeln!(
+            f,
+            "print_val(\"{side}\", {val_idx}, \"{val_path}\", \"%\" PRIu32, _temp, _temp);"
+        )?;
+        f.sub_indent(1);
+        writeln!(f, "}}")?;
+        Ok(())
+    }
+}
+
+/// The printf format and argument the print writer uses to show a leaf value.
+///
+/// printf has no conversion for 128-bit ints, complex numbers, or big `_BitInt`s,
+/// so those just show up as `?` (their bytes are still printed).
+fn print_format(state: &TestState, ty: TyIdx, path: &str) -> (&'static str, String) {
+    let prim = match state.types.realize_ty(ty) {
+        Ty::Primitive(prim) => *prim,
+        Ty::NonZero(nonzero_ty) => match state.types.realize_ty(nonzero_ty.int_ty) {
+            Ty::Primitive(prim) => *prim,
+            _ => return ("\"%s\"", "\"?\"".to_owned()),
+        },
+        _ => return ("\"%s\"", "\"?\"".to_owned()),
+    };
+    match prim {
+        PrimitiveTy::I8 | PrimitiveTy::I16 | PrimitiveTy::I32 | PrimitiveTy::I64 => {
+            ("\"%\" PRId64", format!("(int64_t){path}"))
+        }
+        PrimitiveTy::U8 | PrimitiveTy::U16 | PrimitiveTy::U32 | PrimitiveTy::U64 => {
+            ("\"%\" PRIu64", format!("(uint64_t){path}"))
+        }
+        PrimitiveTy::BitInt(bits) if bits <= 64 => ("\"%\" PRId64", format!("(int64_t){path}")),
+        PrimitiveTy::UBitInt(bits) if bits <= 64 => ("\"%\" PRIu64", format!("(uint64_t){path}")),
+        PrimitiveTy::F16 | PrimitiveTy::F32 | PrimitiveTy::F64 | PrimitiveTy::F128 => {
+            ("\"%g\"", format!("(double){path}"))
+        }
+        PrimitiveTy::Bool => ("\"%d\"", format!("(int){path}")),
+        PrimitiveTy::Ptr => ("\"%p\"", format!("(void*){path}")),
+        PrimitiveTy::I128
+        | PrimitiveTy::U128
+        | PrimitiveTy::C32
+        | PrimitiveTy::C64
+        | PrimitiveTy::BitInt(_)
+        | PrimitiveTy::UBitInt(_) => ("\"%s\"", "\"?\"".to_owned()),
+    }
+}
+
+/// The name the print/assert writers use for the side a values buffer belongs to
+fn side_name(vals: &str) -> &'static str {
+    if vals == CALLER_VALS {
+        "caller"
+    } else {
+        "callee"
+    }
 }
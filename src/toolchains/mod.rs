@@ -5,13 +5,18 @@ use crate::harness::test::*;
 use crate::{error::*, SortedMap};
 
 use camino::{Utf8Path, Utf8PathBuf};
+use kdl_script::types::{Arg, Func, PrimitiveTy, RefTy, Ty, TyIdx, TypedProgram};
 use kdl_script::PunEnv;
 
 pub mod c;
+pub mod plugin;
 pub mod rust;
+pub mod zig;
 
 pub use c::CcToolchain;
+pub use plugin::PluginToolchain;
 pub use rust::RustcToolchain;
+pub use zig::ZigToolchain;
 
 pub const TOOLCHAIN_RUSTC: &str = "rustc";
 pub const TOOLCHAIN_CC: &str = "cc";
@@ -19,6 +24,7 @@ pub const TOOLCHAIN_GCC: &str = "gcc";
 pub const TOOLCHAIN_CLANG: &str = "clang";
 pub const TOOLCHAIN_MSVC: &str = "msvc";
 pub const TOOLCHAIN_ZIGCC: &str = "zigcc";
+pub const TOOLCHAIN_ZIG: &str = "zig";
 
 const C_TOOLCHAINS: &[&str] = &[
     TOOLCHAIN_CC,
@@ -30,10 +36,15 @@ const C_TOOLCHAINS: &[&str] = &[
 
 /// A compiler/language toolchain!
 pub trait Toolchain {
-    #[allow(dead_code)]
     fn lang(&self) -> &'static str;
     fn src_ext(&self) -> &'static str;
     fn pun_env(&self) -> Arc<PunEnv>;
+    /// The version of the compiler, if we know it (for the report).
+    fn version(&self) -> Option<&str>;
+    /// Whether this toolchain can use the given primitive at all (for pruning tests that `requires` it).
+    fn supports_primitive(&self, _prim: PrimitiveTy) -> Result<(), GenerateError> {
+        Ok(())
+    }
     fn generate_callee(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError>;
     fn generate_caller(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError>;
 
@@ -49,6 +60,96 @@ pub trait Toolchain {
         out_dir: &Utf8Path,
         lib_name: &str,
     ) -> Result<String, BuildError>;
+
+    /// How to compile a source file for a standalone reproduction (see `--export-repro`).
+    fn repro_command(&self, lib_name: &str) -> Result<ReproCommand, UnsupportedError>;
+}
+
+/// A command that compiles one side of a test, outside of abi-cafe.
+///
+/// The output path and source file get appended as `-o <output> <src>`
+/// (or `<emit_flag><output> <src>`).
+#[derive(Debug, Clone)]
+pub struct ReproCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    pub output: ReproOutput,
+    /// A flag the output path gets glued onto, for compilers that don't take `-o`
+    pub emit_flag: Option<&'static str>,
+    /// The argument that gets `program` to print its version
+    pub version_arg: &'static str,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReproOutput {
+    /// A static library (named `lib<lib_name>.a`)
+    StaticLib,
+    /// A dynamic library (named like `lib<lib_name>.so` or `<lib_name>.dll`)
+    DynamicLib,
+    /// A single object file
+    Object,
+}
+
+/// One of a function's outputs, as it's actually passed in the signature
+#[derive(Debug, Clone)]
+pub struct LoweredOutput<'a> {
+    pub arg: &'a Arg,
+    /// The type of the value produced (the pointee of a `&T` output)
+    pub val_ty: TyIdx,
+    /// Whether this is written through a pointer to storage the caller provides
+    /// (appended to the inputs), instead of being the return value.
+    pub outparam: bool,
+}
+
+/// Work out how a function's outputs get passed.
+///
+/// `&T` outputs are outparams. Only one value can actually be returned, so the
+/// first non-reference output is the return value and any others also become outparams.
+pub fn lower_outputs<'a>(
+    types: &TypedProgram,
+    func: &'a Func,
+) -> Result<Vec<LoweredOutput<'a>>, GenerateError> {
+    let mut has_return = false;
+    let mut outputs = Vec::with_capacity(func.outputs.len());
+    for arg in &func.outputs {
+        let (val_ty, outparam) = match types.realize_ty(arg.ty) {
+            Ty::Ref(RefTy { pointee_ty }) => (*pointee_ty, true),
+            _ if has_return => (arg.ty, true),
+            _ => {
+                has_return = true;
+                (arg.ty, false)
+            }
+        };
+        // The callee can only produce values that live in the caller's storage,
+        // any reference it returns would be dangling.
+        if types.ty_contains_ref(val_ty) {
+            Err(UnsupportedError::Other(format!(
+                "output {} contains a reference the callee would have to allocate",
+                arg.name
+            )))?;
+        }
+        outputs.push(LoweredOutput {
+            arg,
+            val_ty,
+            outparam,
+        });
+    }
+    Ok(outputs)
+}
+
+/// A C toolchain defined by the user (`--add-c-toolchain`)
+#[derive(Debug, Clone)]
+pub struct CustomCToolchain {
+    /// The id to refer to it by in `--pairs` and test names
+    pub id: String,
+    /// Which compiler this acts like (gcc, clang, msvc, or zigcc)
+    pub flavor: String,
+    /// The compiler executable
+    pub compiler: String,
+    /// Extra flags to pass to the compiler
+    pub flags: Vec<String>,
+    /// The archiver executable
+    pub archiver: String,
 }
 
 /// All the toolchains
@@ -82,8 +183,13 @@ pub(crate) fn create_toolchains(cfg: &crate::Config) -> Toolchains {
     std::env::set_var("TARGET", platform_info.target.clone());
     std::env::set_var("OPT_LEVEL", "0");
 
-    // Add rust toolchains
-    add_toolchain(&mut toolchains, TOOLCHAIN_RUSTC, base_rustc);
+    // Add rust toolchains (the base rustc is the one that links the harness)
+    for (name, spec) in &cfg.rustcs {
+        let command = resolve_rustc(spec);
+        let mut rustc = RustcToolchain::new(cfg, &command, None);
+        rustc.hide_std_from(&base_rustc);
+        add_toolchain(&mut toolchains, name, rustc);
+    }
     for (name, path) in &cfg.rustc_codegen_backends {
         add_toolchain(
             &mut toolchains,
@@ -91,6 +197,7 @@ pub(crate) fn create_toolchains(cfg: &crate::Config) -> Toolchains {
             RustcToolchain::new(cfg, &rustc_command, Some(path.to_owned())),
         );
     }
+    add_toolchain(&mut toolchains, TOOLCHAIN_RUSTC, base_rustc);
 
     // Add c toolchains
     for &name in C_TOOLCHAINS {
@@ -100,6 +207,30 @@ pub(crate) fn create_toolchains(cfg: &crate::Config) -> Toolchains {
             CcToolchain::new(cfg, &platform_info.target, name),
         );
     }
+    for custom in &cfg.c_toolchains {
+        add_toolchain(
+            &mut toolchains,
+            &custom.id,
+            CcToolchain::custom(cfg, &platform_info.target, custom),
+        );
+    }
+
+    // Add zig toolchains (if zig is installed, pairings with it are skipped otherwise)
+    let zig = ZigToolchain::new(cfg, &platform_info.target);
+    if zig.version().is_some() {
+        add_toolchain(&mut toolchains, TOOLCHAIN_ZIG, zig);
+    } else {
+        tracing::debug!("`zig version` failed, zig toolchain unavailable");
+    }
+
+    // Add plugin toolchains
+    for (name, path) in &cfg.plugin_toolchains {
+        add_toolchain(
+            &mut toolchains,
+            name,
+            PluginToolchain::new(name, Utf8Path::new(path), &platform_info),
+        );
+    }
 
     Toolchains {
         platform_info,
@@ -109,6 +240,32 @@ pub(crate) fn create_toolchains(cfg: &crate::Config) -> Toolchains {
     }
 }
 
+/// Find the rustc for an `--add-rustc` spec.
+///
+/// `+toolchain` is looked up with rustup (like `rustc +toolchain`), anything else is a path.
+fn resolve_rustc(spec: &str) -> Utf8PathBuf {
+    let Some(toolchain) = spec.strip_prefix('+') else {
+        return spec.into();
+    };
+    let out = std::process::Command::new("rustup")
+        .arg("which")
+        .arg("--toolchain")
+        .arg(toolchain)
+        .arg("rustc")
+        .output()
+        .expect("rustup failed to run");
+    if !out.status.success() {
+        panic!(
+            "couldn't find rustup toolchain {toolchain}\n{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+    }
+    String::from_utf8(out.stdout)
+        .expect("rustup gave a non-utf8 path")
+        .trim()
+        .into()
+}
+
 /// Register a toolchain
 fn add_toolchain<A: Toolchain + Send + Sync + 'static>(
     toolchains: &mut ToolchainMap,
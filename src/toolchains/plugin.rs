@@ -0,0 +1,704 @@
+//! Toolchains implemented by an external program (`--add-plugin-toolchain`)
+//!
+//! Every request is a single JSON object written to the plugin's stdin, and the
+//! plugin answers with a single JSON object on its stdout. See the "plugin toolchains"
+//! section of the toolchain docs for the full protocol.
+
+use std::collections::BTreeMap;
+use std::io::Write as _;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use kdl_script::parse::{Attr, AttrAligned, AttrConvention, AttrPassthrough, AttrRepr, Repr};
+use kdl_script::types::*;
+use kdl_script::{Definition, PunEnv};
+use serde::{Deserialize, Serialize};
+
+use super::*;
+use crate::harness::vals::{Value, MAX_SLICE_LEN};
+use tracing::debug;
+
+pub struct PluginToolchain {
+    /// The id of the toolchain (for error messages)
+    id: String,
+    /// The plugin executable
+    command: Utf8PathBuf,
+    /// What the plugin told us about itself
+    info: PluginInfo,
+    /// Info about the platform we're targeting
+    platform_info: PlatformInfo,
+    /// The plugin's language (leaked, because we need it for the whole run)
+    lang: &'static str,
+    /// The plugin's source extension (leaked, because we need it for the whole run)
+    src_ext: &'static str,
+}
+
+/// The response to an `info` request
+#[derive(Debug, Deserialize)]
+struct PluginInfo {
+    lang: String,
+    src_ext: String,
+    #[serde(default)]
+    version: Option<String>,
+    /// Commands that build a source file into `{out_dir}/lib{lib_name}.a`
+    compile: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "request", rename_all = "kebab-case")]
+enum PluginRequest<'a> {
+    Info,
+    Generate {
+        side: CallSide,
+        test: PluginTest<'a>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum PluginGenerateResponse {
+    Source(String),
+    Unsupported(String),
+    Error(String),
+}
+
+/// A [`TestImpl`][] with everything a plugin needs already resolved
+#[derive(Debug, Serialize)]
+struct PluginTest<'a> {
+    name: &'a str,
+    convention: String,
+    repr: String,
+    writer: String,
+    /// Every type the functions reference (with puns already resolved)
+    types: BTreeMap<TyIdx, PluginTy>,
+    /// The order to declare/define things in (see [`DefinitionGraph::definitions`][])
+    definitions: Vec<PluginDefinition>,
+    /// The functions to generate
+    funcs: Vec<PluginFunc>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+enum PluginTy {
+    Primitive {
+        name: String,
+    },
+    Struct {
+        name: String,
+        fields: Vec<PluginField>,
+        attrs: Vec<PluginAttr>,
+    },
+    Union {
+        name: String,
+        fields: Vec<PluginField>,
+        attrs: Vec<PluginAttr>,
+    },
+    Enum {
+        name: String,
+        variants: Vec<String>,
+        discriminants: Vec<i64>,
+        attrs: Vec<PluginAttr>,
+    },
+    Tagged {
+        name: String,
+        variants: Vec<PluginVariant>,
+        attrs: Vec<PluginAttr>,
+    },
+    Alias {
+        name: String,
+        real: TyIdx,
+        attrs: Vec<PluginAttr>,
+    },
+    Pun {
+        name: String,
+        real: TyIdx,
+    },
+    Array {
+        elem: TyIdx,
+        len: u64,
+    },
+    Ref {
+        pointee: TyIdx,
+    },
+    Slice {
+        elem: TyIdx,
+    },
+    NonZero {
+        int: TyIdx,
+    },
+    Option {
+        some: TyIdx,
+    },
+    Empty,
+}
+
+#[derive(Debug, Serialize)]
+struct PluginField {
+    name: String,
+    ty: TyIdx,
+    bits: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct PluginVariant {
+    name: String,
+    fields: Option<Vec<PluginField>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum PluginAttr {
+    Packed,
+    Align(i64),
+    Repr(Vec<String>),
+    Passthrough(String),
+    Unwind,
+    Convention(String),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum PluginDefinition {
+    DeclareTy(TyIdx),
+    DefineTy(TyIdx),
+    DeclareFunc(FuncIdx),
+    DefineFunc(FuncIdx),
+}
+
+#[derive(Debug, Serialize)]
+struct PluginFunc {
+    idx: FuncIdx,
+    name: String,
+    unwinds: bool,
+    convention: String,
+    inputs: Vec<PluginArg>,
+    outputs: Vec<PluginArg>,
+}
+
+#[derive(Debug, Serialize)]
+struct PluginArg {
+    name: String,
+    ty: TyIdx,
+    /// Whether any of this arg's values should be written
+    write: bool,
+    vals: Vec<PluginVal>,
+}
+
+#[derive(Debug, Serialize)]
+struct PluginVal {
+    /// The index of the value in its function (what `write_val` expects)
+    idx: usize,
+    path: String,
+    ty: TyIdx,
+    /// Whether this value should be written
+    write: bool,
+    #[serde(flatten)]
+    value: PluginValue,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum PluginValue {
+    /// The little-endian bytes of an integer, float, or pointer
+    Bytes(Vec<u8>),
+    Bool(bool),
+    /// The index of the active variant of an enum, union, or tagged union
+    Variant(usize),
+}
+
+impl PluginToolchain {
+    pub fn new(id: &str, command: &Utf8Path, platform_info: &PlatformInfo) -> Self {
+        let info: PluginInfo = call_plugin(command, &PluginRequest::Info)
+            .and_then(|response| serde_json::from_slice(&response).map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| panic!("plugin toolchain {id} failed to describe itself: {e}"));
+        // Toolchains describe themselves with static strings, so just leak these
+        let lang = Box::leak(info.lang.clone().into_boxed_str());
+        let src_ext = Box::leak(info.src_ext.clone().into_boxed_str());
+        Self {
+            id: id.to_owned(),
+            command: command.to_owned(),
+            info,
+            platform_info: platform_info.clone(),
+            lang,
+            src_ext,
+        }
+    }
+
+    fn generate(
+        &self,
+        f: &mut dyn Write,
+        test: TestImpl,
+        side: CallSide,
+    ) -> Result<(), GenerateError> {
+        let test = self.describe_test(&test)?;
+        let request = PluginRequest::Generate { side, test };
+        let response = call_plugin(&self.command, &request)
+            .and_then(|response| serde_json::from_slice(&response).map_err(|e| e.to_string()))
+            .map_err(|message| GenerateError::Plugin {
+                plugin: self.id.clone(),
+                message,
+            })?;
+        match response {
+            PluginGenerateResponse::Source(source) => {
+                write!(f, "{source}")?;
+                Ok(())
+            }
+            PluginGenerateResponse::Unsupported(reason) => Err(UnsupportedError::Other(reason))?,
+            PluginGenerateResponse::Error(message) => Err(GenerateError::Plugin {
+                plugin: self.id.clone(),
+                message,
+            }),
+        }
+    }
+
+    fn describe_test<'a>(&self, test: &'a TestImpl) -> Result<PluginTest<'a>, GenerateError> {
+        let funcs = test.options.active_funcs(&test.types);
+        let mut types = BTreeMap::new();
+        let mut plugin_funcs = vec![];
+        for &func_idx in &funcs {
+            let func = test.types.realize_func(func_idx);
+            let mut vals = test.vals.at_func(func_idx);
+            let mut args = |args: &[Arg]| -> Result<Vec<PluginArg>, GenerateError> {
+                let mut out = vec![];
+                for arg in args {
+                    self.describe_ty(test, arg.ty, &mut types)?;
+                    let arg_vals = vals.next_arg();
+                    let write = arg_vals.should_write_arg(&test.options);
+                    let vals = arg_vals
+                        .map(|val| PluginVal {
+                            idx: val.absolute_val_idx,
+                            path: val.path.clone(),
+                            ty: val.ty,
+                            write: val.should_write_val(&test.options),
+                            value: self.describe_val(test, &val),
+                        })
+                        .collect();
+                    out.push(PluginArg {
+                        name: arg.name.to_string(),
+                        ty: arg.ty,
+                        write,
+                        vals,
+                    });
+                }
+                Ok(out)
+            };
+            let inputs = args(&func.inputs)?;
+            let outputs = args(&func.outputs)?;
+            plugin_funcs.push(PluginFunc {
+                idx: func_idx,
+                name: func.name.to_string(),
+                unwinds: func.unwinds(),
+                convention: test.options.func_convention(func).to_string(),
+                inputs,
+                outputs,
+            });
+        }
+        let definitions = test
+            .defs
+            .definitions(funcs)
+            .into_iter()
+            .map(|def| match def {
+                Definition::DeclareTy(ty) => PluginDefinition::DeclareTy(ty),
+                Definition::DefineTy(ty) => PluginDefinition::DefineTy(ty),
+                Definition::DeclareFunc(func) => PluginDefinition::DeclareFunc(func),
+                Definition::DefineFunc(func) => PluginDefinition::DefineFunc(func),
+            })
+            .collect();
+
+        Ok(PluginTest {
+            name: &test.name,
+            convention: test.options.convention.to_string(),
+            repr: test.options.repr.to_string(),
+            writer: test.options.val_writer.to_string(),
+            types,
+            definitions,
+            funcs: plugin_funcs,
+        })
+    }
+
+    /// Add a type (and everything it refers to) to `types`
+    fn describe_ty(
+        &self,
+        test: &TestImpl,
+        ty_idx: TyIdx,
+        types: &mut BTreeMap<TyIdx, PluginTy>,
+    ) -> Result<(), GenerateError> {
+        if types.contains_key(&ty_idx) {
+            return Ok(());
+        }
+        let fields = |fields: &[FieldTy]| {
+            fields
+                .iter()
+                .map(|field| PluginField {
+                    name: field.ident.to_string(),
+                    ty: field.ty,
+                    bits: field.bits,
+                })
+                .collect::<Vec<_>>()
+        };
+        let (ty, children) = match test.types.realize_ty(ty_idx) {
+            Ty::Primitive(prim) => (
+                PluginTy::Primitive {
+                    name: prim.to_string(),
+                },
+                vec![],
+            ),
+            Ty::Struct(ty) => (
+                PluginTy::Struct {
+                    name: ty.name.to_string(),
+                    fields: fields(&ty.fields),
+                    attrs: describe_attrs(&ty.attrs),
+                },
+                ty.fields.iter().map(|field| field.ty).collect(),
+            ),
+            Ty::Union(ty) => (
+                PluginTy::Union {
+                    name: ty.name.to_string(),
+                    fields: fields(&ty.fields),
+                    attrs: describe_attrs(&ty.attrs),
+                },
+                ty.fields.iter().map(|field| field.ty).collect(),
+            ),
+            Ty::Enum(ty) => (
+                PluginTy::Enum {
+                    name: ty.name.to_string(),
+                    variants: ty.variants.iter().map(|v| v.name.to_string()).collect(),
+                    discriminants: ty.variants.iter().map(|v| v.val).collect(),
+                    attrs: describe_attrs(&ty.attrs),
+                },
+                vec![],
+            ),
+            Ty::Tagged(ty) => (
+                PluginTy::Tagged {
+                    name: ty.name.to_string(),
+                    variants: ty
+                        .variants
+                        .iter()
+                        .map(|v| PluginVariant {
+                            name: v.name.to_string(),
+                            fields: v.fields.as_deref().map(fields),
+                        })
+                        .collect(),
+                    attrs: describe_attrs(&ty.attrs),
+                },
+                ty.variants
+                    .iter()
+                    .flat_map(|v| v.fields.iter().flatten())
+                    .map(|field| field.ty)
+                    .collect(),
+            ),
+            Ty::Alias(ty) => (
+                PluginTy::Alias {
+                    name: ty.name.to_string(),
+                    real: ty.real,
+                    attrs: describe_attrs(&ty.attrs),
+                },
+                vec![ty.real],
+            ),
+            Ty::Pun(ty) => {
+                let real = test.types.resolve_pun(ty, &test.env)?;
+                (
+                    PluginTy::Pun {
+                        name: ty.name.to_string(),
+                        real,
+                    },
+                    vec![real],
+                )
+            }
+            Ty::Array(ty) => (
+                PluginTy::Array {
+                    elem: ty.elem_ty,
+                    len: ty.len,
+                },
+                vec![ty.elem_ty],
+            ),
+            Ty::Ref(ty) => (
+                PluginTy::Ref {
+                    pointee: ty.pointee_ty,
+                },
+                vec![ty.pointee_ty],
+            ),
+            Ty::Slice(ty) => (PluginTy::Slice { elem: ty.elem_ty }, vec![ty.elem_ty]),
+            Ty::NonZero(ty) => (PluginTy::NonZero { int: ty.int_ty }, vec![ty.int_ty]),
+            Ty::Option(ty) => (PluginTy::Option { some: ty.some_ty }, vec![ty.some_ty]),
+            Ty::Empty => (PluginTy::Empty, vec![]),
+        };
+        types.insert(ty_idx, ty);
+        for child in children {
+            self.describe_ty(test, child, types)?;
+        }
+        Ok(())
+    }
+
+    fn describe_val(&self, test: &TestImpl, val: &Value) -> PluginValue {
+        let bytes = |len: usize| {
+            let mut buf = vec![0; len];
+            val.fill_bytes(&mut buf);
+            PluginValue::Bytes(buf)
+        };
+        let mut ty = test.types.realize_ty(val.ty);
+        if let Ty::NonZero(nonzero_ty) = ty {
+            ty = test.types.realize_ty(nonzero_ty.int_ty);
+        }
+        match ty {
+            Ty::Primitive(prim) => match prim {
+                PrimitiveTy::I8 | PrimitiveTy::U8 => bytes(1),
+                PrimitiveTy::I16 | PrimitiveTy::U16 | PrimitiveTy::F16 => bytes(2),
+                PrimitiveTy::I32 | PrimitiveTy::U32 | PrimitiveTy::F32 => bytes(4),
+                PrimitiveTy::I64 | PrimitiveTy::U64 | PrimitiveTy::F64 | PrimitiveTy::C32 => {
+                    bytes(8)
+                }
+                PrimitiveTy::I128 | PrimitiveTy::U128 | PrimitiveTy::F128 | PrimitiveTy::C64 => {
+                    bytes(16)
+                }
+                PrimitiveTy::BitInt(bits) => PluginValue::Bytes(val.generate_bit_int(*bits, true)),
+                PrimitiveTy::UBitInt(bits) => {
+                    PluginValue::Bytes(val.generate_bit_int(*bits, false))
+                }
+                PrimitiveTy::Bool => PluginValue::Bool(val.generate_bool()),
+                PrimitiveTy::Ptr => {
+                    let is_32bit = self.platform_info.cfgs.contains(
+                        &cargo_platform::Cfg::from_str("target_pointer_width=\"32\"")
+                            .expect("failed to parse pointer width cfg"),
+                    );
+                    bytes(if is_32bit { 4 } else { 8 })
+                }
+            },
+            Ty::Enum(ty) => PluginValue::Variant(val.generate_idx(ty.variants.len())),
+            Ty::Union(ty) => PluginValue::Variant(val.generate_idx(ty.fields.len())),
+            Ty::Tagged(ty) => PluginValue::Variant(val.generate_idx(ty.variants.len())),
+            Ty::Option(_) => PluginValue::Variant(val.generate_idx(2)),
+            Ty::Slice(_) => PluginValue::Variant(val.generate_idx(MAX_SLICE_LEN + 1)),
+            ty => unreachable!("{ty:?} can't be a leaf value"),
+        }
+    }
+
+    fn compile(
+        &self,
+        src_path: &Utf8Path,
+        out_dir: &Utf8Path,
+        lib_name: &str,
+    ) -> Result<String, BuildError> {
+        for template in &self.info.compile {
+            let mut args = template.iter().map(|arg| {
+                arg.replace("{src}", src_path.as_str())
+                    .replace("{out_dir}", out_dir.as_str())
+                    .replace("{lib_name}", lib_name)
+            });
+            let Some(program) = args.next() else {
+                continue;
+            };
+            let mut cmd = Command::new(program);
+            cmd.args(args);
+            debug!("running: {:?}", cmd);
+            let out = cmd.output()?;
+            if !out.status.success() {
+                return Err(BuildError::PluginCompile(out));
+            }
+        }
+        Ok(String::from(lib_name))
+    }
+}
+
+fn describe_attrs(attrs: &[Attr]) -> Vec<PluginAttr> {
+    attrs
+        .iter()
+        .map(|attr| match attr {
+            Attr::Packed(_) => PluginAttr::Packed,
+            Attr::Align(AttrAligned { align }) => PluginAttr::Align(align.val),
+            Attr::Repr(AttrRepr { reprs }) => PluginAttr::Repr(
+                reprs
+                    .iter()
+                    .map(|repr| match repr {
+                        Repr::Primitive(prim) => prim.to_string(),
+                        Repr::Lang(lang) => lang.to_string(),
+                        Repr::Transparent => "transparent".to_owned(),
+                    })
+                    .collect(),
+            ),
+            Attr::Passthrough(AttrPassthrough(text)) => PluginAttr::Passthrough(text.to_string()),
+            Attr::Unwind(_) => PluginAttr::Unwind,
+            Attr::Convention(AttrConvention(convention)) => {
+                PluginAttr::Convention(convention.to_string())
+            }
+        })
+        .collect()
+}
+
+/// Send a request to the plugin, and get its raw response
+fn call_plugin(command: &Utf8Path, request: &PluginRequest) -> Result<Vec<u8>, String> {
+    let request = serde_json::to_vec(request).map_err(|e| e.to_string())?;
+    let mut child = Command::new(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to spawn {command}: {e}"))?;
+    // Write from another thread so a plugin that answers before reading everything can't deadlock us
+    let mut stdin = child.stdin.take().expect("plugin stdin wasn't piped");
+    let writer = std::thread::spawn(move || stdin.write_all(&request));
+    let out = child.wait_with_output().map_err(|e| e.to_string())?;
+    writer
+        .join()
+        .expect("plugin writer thread panicked")
+        .map_err(|e| format!("failed to send request to {command}: {e}"))?;
+    if !out.status.success() {
+        return Err(format!(
+            "{command} exited with {}\n{}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr)
+        ));
+    }
+    Ok(out.stdout)
+}
+
+impl Toolchain for PluginToolchain {
+    fn lang(&self) -> &'static str {
+        self.lang
+    }
+    fn src_ext(&self) -> &'static str {
+        self.src_ext
+    }
+    fn pun_env(&self) -> Arc<PunEnv> {
+        Arc::new(kdl_script::PunEnv {
+            lang: self.info.lang.clone(),
+        })
+    }
+    fn version(&self) -> Option<&str> {
+        self.info.version.as_deref()
+    }
+
+    fn generate_callee(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError> {
+        self.generate(f, test, CallSide::Callee)
+    }
+    fn generate_caller(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError> {
+        self.generate(f, test, CallSide::Caller)
+    }
+
+    fn compile_callee(
+        &self,
+        src_path: &Utf8Path,
+        out_dir: &Utf8Path,
+        lib_name: &str,
+    ) -> Result<String, BuildError> {
+        self.compile(src_path, out_dir, lib_name)
+    }
+    fn compile_caller(
+        &self,
+        src_path: &Utf8Path,
+        out_dir: &Utf8Path,
+        lib_name: &str,
+    ) -> Result<String, BuildError> {
+        self.compile(src_path, out_dir, lib_name)
+    }
+
+    fn repro_command(&self, _lib_name: &str) -> Result<ReproCommand, UnsupportedError> {
+        Err(UnsupportedError::Other(format!(
+            "can't export reproductions for plugin toolchain {}",
+            self.id
+        )))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use crate::harness::vals::ValueGeneratorKind;
+    use kdl_script::parse::LangRepr;
+
+    /// A plugin that can generate the callee of "hello", and fails to generate "broken"
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/toolchains/plugin/fixture.sh"
+    );
+
+    fn fixture() -> PluginToolchain {
+        let platform_info = PlatformInfo {
+            target: "x86_64-unknown-linux-gnu".to_owned(),
+            cfgs: vec![],
+        };
+        PluginToolchain::new("fixture", Utf8Path::new(FIXTURE), &platform_info)
+    }
+
+    fn test_impl(toolchain: &PluginToolchain, name: &str) -> TestImpl {
+        let src = "fn \"hello\" {\n    inputs { x \"u32\"; }\n}";
+        let types = kdl_script::Compiler::new()
+            .compile_string(&format!("{name}.kdl"), src.to_owned())
+            .unwrap();
+        let test = Arc::new(Test::new(name.to_owned(), types).unwrap());
+        let options = TestOptions {
+            convention: CallingConvention::C,
+            functions: FunctionSelector::All,
+            val_writer: WriteImpl::HarnessCallback,
+            val_generator: ValueGeneratorKind::Graffiti,
+            repr: LangRepr::C,
+        };
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let test = test.with_vals(options.val_generator).await.unwrap();
+            let test = test.with_toolchain(toolchain).await.unwrap();
+            test.with_options(options).unwrap()
+        })
+    }
+
+    #[test]
+    fn generate_and_compile() {
+        let toolchain = fixture();
+        assert_eq!(toolchain.lang(), "c");
+        assert_eq!(toolchain.src_ext(), "c");
+        assert_eq!(toolchain.version(), Some("fixture 1.0"));
+
+        let mut src = String::new();
+        toolchain
+            .generate_callee(&mut src, test_impl(&toolchain, "hello"))
+            .unwrap();
+        assert_eq!(
+            src,
+            "#include <stdint.h>\nvoid hello(uint32_t x) { (void)x; }\n"
+        );
+
+        let out_dir = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+            .unwrap()
+            .join(format!("abi-cafe-plugin-test-{}", std::process::id()));
+        std::fs::create_dir_all(&out_dir).unwrap();
+        let src_path = out_dir.join("hello_callee.c");
+        std::fs::write(&src_path, &src).unwrap();
+        let lib_name = toolchain
+            .compile_callee(&src_path, &out_dir, "hello_callee")
+            .unwrap();
+        assert_eq!(lib_name, "hello_callee");
+        assert!(out_dir.join("libhello_callee.a").exists());
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[test]
+    fn unsupported_response() {
+        let toolchain = fixture();
+        let mut src = String::new();
+        let e = toolchain
+            .generate_caller(&mut src, test_impl(&toolchain, "hello"))
+            .unwrap_err();
+        let GenerateError::Unsupported(UnsupportedError::Other(reason)) = e else {
+            panic!("expected the test to be unsupported, got {e:?}");
+        };
+        assert_eq!(reason, "only generates callees");
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn error_response() {
+        let toolchain = fixture();
+        let mut src = String::new();
+        let e = toolchain
+            .generate_callee(&mut src, test_impl(&toolchain, "broken"))
+            .unwrap_err();
+        let GenerateError::Plugin { plugin, message } = e else {
+            panic!("expected the plugin to fail, got {e:?}");
+        };
+        assert_eq!(plugin, "fixture");
+        assert_eq!(message, "asked to generate a broken test");
+        assert!(src.is_empty());
+    }
+}
@@ -0,0 +1,25 @@
+#!/bin/sh
+# A minimal plugin toolchain, for testing the plugin protocol.
+#
+# It only knows how to generate the callee of a test named "hello", which it claims
+# is unsupported from the caller side, and it fails every request for a test named "broken".
+
+request=$(cat)
+case "$request" in
+    *'"request":"info"'*)
+        printf '%s\n' '{"lang": "c", "src_ext": "c", "version": "fixture 1.0", "compile": [["cc", "-c", "{src}", "-o", "{out_dir}/{lib_name}.o"], ["ar", "rcs", "{out_dir}/lib{lib_name}.a", "{out_dir}/{lib_name}.o"]]}'
+        ;;
+    *'"test":{"name":"broken"'*)
+        printf '%s\n' '{"error": "asked to generate a broken test"}'
+        ;;
+    *'"side":"callee","test":{"name":"hello"'*)
+        printf '%s\n' '{"source": "#include <stdint.h>\nvoid hello(uint32_t x) { (void)x; }\n"}'
+        ;;
+    *'"test":{"name":"hello"'*)
+        printf '%s\n' '{"unsupported": "only generates callees"}'
+        ;;
+    *)
+        echo "unexpected request: $request" >&2
+        exit 1
+        ;;
+esac
@@ -1,5 +1,6 @@
 //! Rust(c) codegen backend backend
 
+mod bitfield;
 mod declare;
 mod init;
 mod write;
@@ -37,7 +38,7 @@ impl std::ops::Deref for TestState {
 }
 impl TestState {
     fn new(inner: TestImpl) -> Self {
-        let desired_funcs = inner.options.functions.active_funcs(&inner.types);
+        let desired_funcs = inner.options.active_funcs(&inner.types);
         Self {
             inner,
             desired_funcs,
@@ -51,7 +52,7 @@ impl TestState {
 pub struct RustcToolchain {
     /// What command should we invoke rustc from?
     command: Utf8PathBuf,
-    /// The rustc version
+    /// The rustc version (e.g. "1.80.0 (051478957 2024-07-21)")
     version: String,
     /// Is this a nightly rustc?
     is_nightly: bool,
@@ -63,6 +64,9 @@ pub struct RustcToolchain {
     codegen_backend: Option<String>,
     /// Enable debuginfo
     debug: bool,
+    /// Build cdylibs instead of static libs, so that our std stays hidden inside them
+    /// (see [`RustcToolchain::hide_std_from`][])
+    hide_std: bool,
 }
 
 #[derive(PartialEq)]
@@ -83,6 +87,12 @@ impl Toolchain for RustcToolchain {
             lang: "rust".to_string(),
         })
     }
+    fn version(&self) -> Option<&str> {
+        Some(&self.version)
+    }
+    fn supports_primitive(&self, prim: PrimitiveTy) -> Result<(), GenerateError> {
+        self.primitive_tyname(&prim).map(|_| ())
+    }
     fn compile_callee(
         &self,
         src_path: &Utf8Path,
@@ -91,7 +101,7 @@ impl Toolchain for RustcToolchain {
     ) -> Result<String, BuildError> {
         let mut cmd = Command::new(&self.command);
         cmd.arg("--crate-type")
-            .arg("staticlib")
+            .arg(self.crate_type())
             .arg("--out-dir")
             .arg(out_dir)
             .arg("--target")
@@ -124,6 +134,33 @@ impl Toolchain for RustcToolchain {
         self.compile_callee(src_path, out_dir, lib_name)
     }
 
+    fn repro_command(&self, lib_name: &str) -> Result<ReproCommand, UnsupportedError> {
+        let mut args = vec![
+            "--crate-type".to_owned(),
+            self.crate_type().to_owned(),
+            "--target".to_owned(),
+            self.platform_info.target.clone(),
+            format!("-Cmetadata={lib_name}"),
+        ];
+        if self.debug {
+            args.push("-g".to_owned());
+        }
+        if let Some(codegen_backend) = &self.codegen_backend {
+            args.push(format!("-Zcodegen-backend={codegen_backend}"));
+        }
+        Ok(ReproCommand {
+            program: self.command.to_string(),
+            args,
+            output: if self.hide_std {
+                ReproOutput::DynamicLib
+            } else {
+                ReproOutput::StaticLib
+            },
+            emit_flag: None,
+            version_arg: "--version",
+        })
+    }
+
     fn generate_callee(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError> {
         let mut f = Fivemat::new(f, INDENT);
         let mut state = TestState::new(test);
@@ -149,7 +186,8 @@ impl RustcToolchain {
         self.generate_caller_externs(f, state)?;
 
         // Generate the test function the harness will call
-        writeln!(f, "#[no_mangle]\npub extern \"C\" fn do_test() {{")?;
+        // (C-unwind so the harness can notice if an unwind was never caught)
+        writeln!(f, "#[no_mangle]\npub extern \"C-unwind\" fn do_test() {{")?;
         for &func in &state.desired_funcs {
             // Generate the individual function calls
             self.generate_caller_body(f, state, func)?;
@@ -181,14 +219,51 @@ impl RustcToolchain {
             self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLER_VALS)?;
         }
 
+        // Make space for the outparams to be written to
+        let outputs = lower_outputs(&state.types, function)?;
+        for output in outputs.iter().filter(|output| output.outparam) {
+            let tyname = &state.tynames[&output.val_ty];
+            writeln!(
+                f,
+                "let mut {} = std::mem::MaybeUninit::<{tyname}>::uninit();",
+                output.arg.name
+            )?;
+        }
+
+        if function.unwinds() {
+            // Call the function, expecting it to unwind instead of returning
+            // (or abort the process, if the convention doesn't allow unwinding)
+            write!(
+                f,
+                "let unwound = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {{ "
+            )?;
+            self.call_function(f, function, &outputs)?;
+            writeln!(f, "}})).is_err();")?;
+            writeln!(f, "if unwound {{")?;
+            f.add_indent(1);
+            self.write_unwind(f, state, CALLER_VALS)?;
+            f.sub_indent(1);
+            writeln!(f, "}}")?;
+            f.sub_indent(1);
+            writeln!(f, "}}")?;
+            return Ok(());
+        }
+
         // Call the function
-        self.call_function(f, state, function)?;
+        self.call_function(f, function, &outputs)?;
+        writeln!(f)?;
+        for output in outputs.iter().filter(|output| output.outparam) {
+            let name = &output.arg.name;
+            writeln!(f, "let {name} = {name}.assume_init();")?;
+        }
+        writeln!(f)?;
 
         // Report all the outputs
-        for arg in &function.outputs {
+        for output in &outputs {
             let arg_vals: ArgValuesIter = func_vals.next_arg();
+            let arg = output.arg;
 
-            self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLER_VALS)?;
+            self.write_var(f, state, &arg.name, output.val_ty, arg_vals, CALLER_VALS)?;
         }
 
         f.sub_indent(1);
@@ -199,29 +274,30 @@ impl RustcToolchain {
     fn call_function(
         &self,
         f: &mut Fivemat,
-        state: &TestState,
         function: &Func,
+        outputs: &[LoweredOutput],
     ) -> Result<(), GenerateError> {
         let func_name = &function.name;
 
-        // make sure the outputs aren't weird
-        self.check_returns(state, function)?;
-        if let Some(output) = function.outputs.first() {
-            write!(f, "let {} = ", output.name)?;
+        if let Some(output) = outputs.iter().find(|output| !output.outparam) {
+            write!(f, "let {} = ", output.arg.name)?;
         }
 
         // Call the function
         write!(f, "{func_name}(")?;
-        let inputs = function.inputs.iter();
+        let inputs = function.inputs.iter().map(|arg| arg.name.to_string());
+        let outparams = outputs
+            .iter()
+            .filter(|output| output.outparam)
+            .map(|output| format!("{}.as_mut_ptr()", output.arg.name));
 
-        for (arg_idx, arg) in inputs.enumerate() {
+        for (arg_idx, arg) in inputs.chain(outparams).enumerate() {
             if arg_idx > 0 {
                 write!(f, ", ")?;
             }
-            write!(f, "{}", arg.name)?;
+            write!(f, "{arg}")?;
         }
-        writeln!(f, ");")?;
-        writeln!(f)?;
+        write!(f, ");")?;
         Ok(())
     }
 }
@@ -249,7 +325,7 @@ impl RustcToolchain {
         func: FuncIdx,
     ) -> Result<(), GenerateError> {
         let function = state.types.realize_func(func);
-        let convention_decl = self.convention_decl(state.options.convention)?;
+        let convention_decl = self.convention_decl(state.options.func_convention(function))?;
         writeln!(f, "#[no_mangle]")?;
         write!(f, "pub unsafe extern \"{convention_decl}\" ")?;
         self.generate_signature(f, state, func)?;
@@ -269,17 +345,34 @@ impl RustcToolchain {
             self.write_var(f, state, arg_name, arg.ty, arg_vals, CALLEE_VALS)?;
         }
 
+        if function.unwinds() {
+            // Unwind instead of producing any outputs
+            // (if the convention doesn't allow unwinding, rust aborts here)
+            self.write_unwind(f, state, CALLEE_VALS)?;
+            writeln!(f, "panic!(\"abi-cafe: {} unwinding\");", function.name)?;
+            f.sub_indent(1);
+            writeln!(f, "}}")?;
+            f.sub_indent(1);
+            writeln!(f, "}}")?;
+            return Ok(());
+        }
+
         // Create outputs and report them
-        for arg in &function.outputs {
+        let outputs = lower_outputs(&state.types, function)?;
+        for output in &outputs {
             let arg_vals = func_vals.next_arg();
-            self.init_var(f, state, &arg.name, arg.ty, arg_vals.clone())?;
-            self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLEE_VALS)?;
+            let arg = output.arg;
+            self.init_var(f, state, &arg.name, output.val_ty, arg_vals.clone())?;
+            self.write_var(f, state, &arg.name, output.val_ty, arg_vals, CALLEE_VALS)?;
         }
 
         // Return the outputs
-        self.check_returns(state, function)?;
-        if let Some(arg) = function.outputs.first() {
-            writeln!(f, "{}", arg.name)?;
+        for output in outputs.iter().filter(|output| output.outparam) {
+            let name = &output.arg.name;
+            writeln!(f, "{name}_out.write({name});")?;
+        }
+        if let Some(output) = outputs.iter().find(|output| !output.outparam) {
+            writeln!(f, "{}", output.arg.name)?;
         }
         f.sub_indent(1);
         writeln!(f, "}}")?;
@@ -303,10 +396,8 @@ impl RustcToolchain {
             if let Some(val) = line.strip_prefix("host: ") {
                 host = Some(val.to_owned());
             }
-            if let Some(line) = line.strip_prefix("rustc ") {
-                if let Some((val, _rest)) = line.split_once(' ') {
-                    version = Some(val.to_owned())
-                }
+            if let Some(val) = line.strip_prefix("rustc ") {
+                version = Some(val.to_owned())
             }
         }
         let version = version.expect("failed to get rustc version");
@@ -343,24 +434,25 @@ impl RustcToolchain {
             platform,
             codegen_backend,
             debug: system_info.debug,
+            hide_std: false,
         }
     }
 
-    fn check_returns(&self, state: &TestState, function: &Func) -> Result<(), GenerateError> {
-        let has_outparams = function
-            .outputs
-            .iter()
-            .any(|arg| state.types.ty_contains_ref(arg.ty));
-        if has_outparams {
-            return Err(UnsupportedError::Other(
-                "outparams (outputs containing references) aren't supported".to_owned(),
-            ))?;
-        }
-        if function.outputs.len() > 1 {
-            return Err(UnsupportedError::Other(
-                "multiple returns (should this be a struct?)".to_owned(),
-            ))?;
+    /// Build cdylibs if the harness is linked by a different version of rustc.
+    ///
+    /// A static lib carries its own copy of std, and two different versions of std can't be
+    /// linked into one program (they define the same unmangled symbols, so one ends up
+    /// calling into the other). A cdylib keeps its std to itself, and only exports the
+    /// test's `extern` functions.
+    pub fn hide_std_from(&mut self, harness_rustc: &RustcToolchain) {
+        self.hide_std = self.version != harness_rustc.version;
+    }
+
+    fn crate_type(&self) -> &'static str {
+        if self.hide_std {
+            "cdylib"
+        } else {
+            "staticlib"
         }
-        Ok(())
     }
 }
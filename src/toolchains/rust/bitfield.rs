@@ -0,0 +1,471 @@
+//! Lowering C bitfields to bindgen-style storage units
+//!
+//! Rust has no bitfields, so each run of adjacent bitfields becomes a byte array
+//! (`_bitfield_N`) with a getter for each field and a `new_bitfield_N` constructor.
+//! rustc can't tell us where the bits go, so this implements the bitfield layout
+//! algorithm gcc and clang use outside of windows (SysV, AAPCS64, ...).
+
+use super::*;
+use kdl_script::parse::{Attr, AttrAligned, AttrPacked, Repr};
+use kdl_script::types::{NonZeroTy, OptionTy, StructTy, TypedProgram};
+use std::fmt::Write;
+
+/// Where a struct's bitfields ended up
+pub struct BitfieldLayout {
+    /// Each run of adjacent bitfields
+    pub units: Vec<BitfieldUnit>,
+    /// The alignment (in bytes) the types of the bitfields give the struct
+    pub align: u64,
+}
+
+/// A run of adjacent bitfields, stored in `_bitfield_{idx + 1}`
+pub struct BitfieldUnit {
+    /// The field the unit starts at
+    field_idx: usize,
+    /// Byte offset of the storage in the struct
+    start: u64,
+    /// Size of the storage in bytes
+    pub size: u64,
+    /// The bitfields with values (zero-width bitfields only pad the storage)
+    pub fields: Vec<Bitfield>,
+}
+
+pub struct Bitfield {
+    pub field_idx: usize,
+    /// Offset of the first bit in the storage (little-endian bit order)
+    pub offset: u64,
+    pub bits: u32,
+    pub signed: bool,
+}
+
+impl BitfieldLayout {
+    /// The unit this field starts (if it's the first bitfield of one)
+    pub fn unit_starting_at(&self, field_idx: usize) -> Option<(usize, &BitfieldUnit)> {
+        self.units
+            .iter()
+            .enumerate()
+            .find(|(_, unit)| unit.field_idx == field_idx)
+    }
+}
+
+impl RustcToolchain {
+    /// Lay out a struct that has bitfields in it
+    pub fn bitfield_layout(
+        &self,
+        state: &TestState,
+        struct_ty: &StructTy,
+    ) -> Result<BitfieldLayout, GenerateError> {
+        if self.platform == Platform::Windows {
+            return Err(UnsupportedError::Other(
+                "msvc's bitfield layout isn't implemented for rust".to_owned(),
+            ))?;
+        }
+        if !self.has_cfg("target_pointer_width", "64") || !self.has_cfg("target_endian", "little") {
+            return Err(UnsupportedError::Other(
+                "bitfields in rust are only implemented for 64-bit little-endian targets"
+                    .to_owned(),
+            ))?;
+        }
+        // Some ABIs (like AAPCS) also let zero-width bitfields change the struct's alignment
+        if struct_ty.fields.iter().any(|f| f.bits == Some(0))
+            && !self.has_cfg("target_arch", "x86_64")
+        {
+            return Err(UnsupportedError::Other(
+                "zero-width bitfields in rust are only implemented for x86_64".to_owned(),
+            ))?;
+        }
+        let c_layout = CLayout {
+            types: &state.types,
+            env: &state.env,
+        };
+        let (_, _, layout) = c_layout.lay_out_struct(struct_ty)?;
+        Ok(layout)
+    }
+
+    /// Emit the fields of a struct with bitfields, and the impl with their accessors
+    pub fn generate_bitfield_struct_body(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        struct_ty: &StructTy,
+        has_borrows: bool,
+    ) -> Result<(), GenerateError> {
+        let layout = self.bitfield_layout(state, struct_ty)?;
+        f.add_indent(1);
+        // The storage is just bytes, so make sure the struct still gets the bitfields' alignment
+        writeln!(f, "_bitfield_align: [u{}; 0],", layout.align * 8)?;
+        for (field_idx, field) in struct_ty.fields.iter().enumerate() {
+            if field.bits.is_none() {
+                let field_name = &field.ident;
+                let field_tyname = state
+                    .borrowed_tynames
+                    .get(&field.ty)
+                    .unwrap_or(&state.tynames[&field.ty]);
+                writeln!(f, "{field_name}: {field_tyname},")?;
+            } else if let Some((unit_idx, unit)) = layout.unit_starting_at(field_idx) {
+                writeln!(f, "_bitfield_{}: [u8; {}],", unit_idx + 1, unit.size)?;
+            }
+        }
+        f.sub_indent(1);
+        writeln!(f, "}}\n")?;
+
+        let lifetime = if has_borrows { "<'_>" } else { "" };
+        writeln!(f, "impl {}{lifetime} {{", struct_ty.name)?;
+        f.add_indent(1);
+        for (unit_idx, unit) in layout.units.iter().enumerate() {
+            let unit_name = format!("_bitfield_{}", unit_idx + 1);
+            // Getters for each field
+            for bitfield in &unit.fields {
+                let field = &struct_ty.fields[bitfield.field_idx];
+                let field_tyname = &state.tynames[&field.ty];
+                let (offset, bits) = (bitfield.offset, bitfield.bits);
+                let get = format!("bitfield_get(&self.{unit_name}, {offset}, {bits})");
+                writeln!(f, "fn {}(&self) -> {field_tyname} {{", field.ident)?;
+                if bitfield.signed {
+                    let shift = 64 - bits;
+                    writeln!(
+                        f,
+                        "    (({get} << {shift}) as i64 >> {shift}) as {field_tyname}"
+                    )?;
+                } else {
+                    writeln!(f, "    {get} as {field_tyname}")?;
+                }
+                writeln!(f, "}}")?;
+            }
+            // A constructor for the whole unit
+            let args = unit
+                .fields
+                .iter()
+                .map(|bitfield| {
+                    let field = &struct_ty.fields[bitfield.field_idx];
+                    format!("{}: {}", field.ident, state.tynames[&field.ty])
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(f, "fn new{unit_name}({args}) -> [u8; {}] {{", unit.size)?;
+            f.add_indent(1);
+            // (a unit of only zero-width bitfields is just padding)
+            let binding = if unit.fields.is_empty() {
+                "let"
+            } else {
+                "let mut"
+            };
+            writeln!(f, "{binding} unit = [0; {}];", unit.size)?;
+            for bitfield in &unit.fields {
+                let field = &struct_ty.fields[bitfield.field_idx];
+                let (offset, bits) = (bitfield.offset, bitfield.bits);
+                writeln!(
+                    f,
+                    "bitfield_set(&mut unit, {offset}, {bits}, {} as u64);",
+                    field.ident
+                )?;
+            }
+            writeln!(f, "unit")?;
+            f.sub_indent(1);
+            writeln!(f, "}}")?;
+        }
+        f.sub_indent(1);
+        writeln!(f, "}}\n")?;
+        Ok(())
+    }
+}
+
+/// The C layout of types, for working out where bitfields go
+struct CLayout<'a> {
+    types: &'a TypedProgram,
+    env: &'a PunEnv,
+}
+
+impl CLayout<'_> {
+    /// Get the (size, align) of a struct, and where its bitfields are
+    fn lay_out_struct(
+        &self,
+        struct_ty: &StructTy,
+    ) -> Result<(u64, u64, BitfieldLayout), GenerateError> {
+        let mut bit_offset = 0u64;
+        let mut align = 1;
+        let mut bitfield_align = 1;
+        let mut units = Vec::<BitfieldUnit>::new();
+        let mut in_unit = false;
+        for (field_idx, field) in struct_ty.fields.iter().enumerate() {
+            let (field_size, field_align) = self.c_layout(field.ty)?;
+            // (zero-width bitfields don't count towards the struct's alignment)
+            if field.bits != Some(0) {
+                align = align.max(field_align);
+            }
+            let Some(bits) = field.bits else {
+                in_unit = false;
+                let offset = bit_offset.div_ceil(8).next_multiple_of(field_align);
+                bit_offset = (offset + field_size) * 8;
+                continue;
+            };
+            if !in_unit {
+                units.push(BitfieldUnit {
+                    field_idx,
+                    start: bit_offset.div_ceil(8),
+                    size: 0,
+                    fields: vec![],
+                });
+                in_unit = true;
+            }
+            let unit = units.last_mut().unwrap();
+            let bits = bits as u64;
+            let type_bits = field_align * 8;
+            if bits == 0 {
+                // A zero-width bitfield just pads to the next boundary of its type
+                // (the padding goes in the unit, so anything after it lands in the right place)
+                bit_offset = bit_offset.next_multiple_of(type_bits);
+                unit.size = bit_offset.div_ceil(8) - unit.start;
+                continue;
+            }
+            bitfield_align = bitfield_align.max(field_align);
+            // A bitfield can't straddle a boundary of its type's alignment
+            if bit_offset / type_bits != (bit_offset + bits - 1) / type_bits {
+                bit_offset = bit_offset.next_multiple_of(type_bits);
+            }
+            let signed = matches!(
+                self.types.realize_ty(field.ty),
+                Ty::Primitive(
+                    PrimitiveTy::I8 | PrimitiveTy::I16 | PrimitiveTy::I32 | PrimitiveTy::I64
+                )
+            );
+            unit.fields.push(Bitfield {
+                field_idx,
+                offset: bit_offset - unit.start * 8,
+                bits: bits as u32,
+                signed,
+            });
+            bit_offset += bits;
+            unit.size = bit_offset.div_ceil(8) - unit.start;
+        }
+        for attr in &struct_ty.attrs {
+            match attr {
+                Attr::Align(AttrAligned { align: new_align }) => {
+                    align = align.max(new_align.val as u64);
+                }
+                Attr::Packed(AttrPacked {}) => {
+                    return Err(UnsupportedError::Other(
+                        "packed bitfield layout isn't implemented for rust".to_owned(),
+                    ))?;
+                }
+                _ => {}
+            }
+        }
+        let size = bit_offset.div_ceil(8).next_multiple_of(align);
+        let layout = BitfieldLayout {
+            units,
+            align: bitfield_align,
+        };
+        Ok((size, align, layout))
+    }
+
+    /// Get the (size, align) of a type in C, for the types that can come before a bitfield
+    fn c_layout(&self, ty: TyIdx) -> Result<(u64, u64), GenerateError> {
+        let layout = match self.types.realize_ty(ty) {
+            Ty::Primitive(prim) => self.prim_layout(prim)?,
+            Ty::Ref(_) => (8, 8),
+            Ty::Slice(_) => (16, 8),
+            Ty::NonZero(NonZeroTy { int_ty: inner_ty })
+            | Ty::Option(OptionTy { some_ty: inner_ty }) => self.c_layout(*inner_ty)?,
+            Ty::Array(array_ty) => {
+                let (size, align) = self.c_layout(array_ty.elem_ty)?;
+                (size * array_ty.len, align)
+            }
+            Ty::Struct(struct_ty) => {
+                let (size, align, _) = self.lay_out_struct(struct_ty)?;
+                (size, align)
+            }
+            Ty::Union(union_ty) => {
+                let mut size = 0;
+                let mut align = 1;
+                for field in &union_ty.fields {
+                    let (field_size, field_align) = self.c_layout(field.ty)?;
+                    size = size.max(field_size);
+                    align = align.max(field_align);
+                }
+                (size.next_multiple_of(align), align)
+            }
+            Ty::Enum(enum_ty) => {
+                let repr = enum_ty.attrs.iter().find_map(|attr| match attr {
+                    Attr::Repr(repr) => repr.reprs.iter().find_map(|repr| match repr {
+                        Repr::Primitive(prim) => Some(*prim),
+                        _ => None,
+                    }),
+                    _ => None,
+                });
+                match repr {
+                    Some(prim) => self.prim_layout(&prim)?,
+                    None => (4, 4),
+                }
+            }
+            Ty::Alias(alias_ty) => self.c_layout(alias_ty.real)?,
+            Ty::Pun(pun) => {
+                let real_ty = self.types.resolve_pun(pun, self.env)?;
+                self.c_layout(real_ty)?
+            }
+            Ty::Tagged(_) | Ty::Empty => {
+                return Err(UnsupportedError::Other(format!(
+                    "can't lay out bitfields next to {}",
+                    self.types.format_ty(ty)
+                )))?;
+            }
+        };
+        Ok(layout)
+    }
+
+    fn prim_layout(&self, prim: &PrimitiveTy) -> Result<(u64, u64), GenerateError> {
+        let layout = match prim {
+            PrimitiveTy::I8 | PrimitiveTy::U8 | PrimitiveTy::Bool => (1, 1),
+            PrimitiveTy::I16 | PrimitiveTy::U16 | PrimitiveTy::F16 => (2, 2),
+            PrimitiveTy::I32 | PrimitiveTy::U32 | PrimitiveTy::F32 => (4, 4),
+            PrimitiveTy::I64 | PrimitiveTy::U64 | PrimitiveTy::F64 | PrimitiveTy::Ptr => (8, 8),
+            PrimitiveTy::I128 | PrimitiveTy::U128 | PrimitiveTy::F128 => (16, 16),
+            PrimitiveTy::C32 => (8, 4),
+            PrimitiveTy::C64 => (16, 8),
+            PrimitiveTy::BitInt(_) | PrimitiveTy::UBitInt(_) => Err(UnsupportedError::Other(
+                format!("rust doesn't have _BitInt ({prim})"),
+            ))?,
+        };
+        Ok(layout)
+    }
+}
+
+/// Functions for reading and writing bits in the storage units
+pub fn write_bitfield_helpers(f: &mut Fivemat) -> Result<(), GenerateError> {
+    writeln!(
+        f,
+        r#"fn bitfield_get(unit: &[u8], offset: usize, bits: usize) -> u64 {{
+    let mut val = 0;
+    for i in 0..bits {{
+        let bit = offset + i;
+        val |= (((unit[bit / 8] >> (bit % 8)) & 1) as u64) << i;
+    }}
+    val
+}}
+fn bitfield_set(unit: &mut [u8], offset: usize, bits: usize, val: u64) {{
+    for i in 0..bits {{
+        let bit = offset + i;
+        unit[bit / 8] |= (((val >> i) & 1) as u8) << (bit % 8);
+    }}
+}}
+"#
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::CLayout;
+    use kdl_script::types::Ty;
+    use kdl_script::PunEnv;
+
+    /// (field_idx, offset, bits, signed)
+    type Fields = &'static [(usize, u64, u32, bool)];
+
+    /// Lay out the struct passed to `fn "f"`, and check it against what gcc does on x86_64
+    fn check_layout(fields: &str, size: u64, align: u64, units: &[(u64, u64, Fields)]) {
+        let src = format!("struct \"S\" {{\n{fields}\n}}\nfn \"f\" {{\ninputs {{ _ \"S\"; }}\n}}");
+        let types = kdl_script::Compiler::new()
+            .compile_string("bitfield.kdl", src)
+            .unwrap();
+        let func = types.realize_func(types.all_funcs().next().unwrap());
+        let Ty::Struct(struct_ty) = types.realize_ty(func.inputs[0].ty) else {
+            panic!("not a struct");
+        };
+        let env = PunEnv {
+            lang: "rust".to_owned(),
+        };
+        let c_layout = CLayout {
+            types: &types,
+            env: &env,
+        };
+        let (real_size, real_align, layout) = c_layout.lay_out_struct(struct_ty).unwrap();
+        assert_eq!((real_size, real_align), (size, align), "{fields}");
+        let real_units = layout
+            .units
+            .iter()
+            .map(|unit| {
+                let fields = unit
+                    .fields
+                    .iter()
+                    .map(|f| (f.field_idx, f.offset, f.bits, f.signed))
+                    .collect::<Vec<_>>();
+                (unit.start, unit.size, fields)
+            })
+            .collect::<Vec<_>>();
+        let units = units
+            .iter()
+            .map(|(start, size, fields)| (*start, *size, fields.to_vec()))
+            .collect::<Vec<_>>();
+        assert_eq!(real_units, units, "{fields}");
+    }
+
+    #[test]
+    fn straddling_a_unit() {
+        // b and d would straddle a u32/u8 boundary, so they get pushed to the next one
+        check_layout(
+            r#"a "u32:30"; b "u32:4"; c "u8:7"; d "u8:2""#,
+            8,
+            4,
+            &[(
+                0,
+                7,
+                &[
+                    (0, 0, 30, false),
+                    (1, 32, 4, false),
+                    (2, 40, 7, false),
+                    (3, 48, 2, false),
+                ],
+            )],
+        );
+    }
+
+    #[test]
+    fn zero_width_padding() {
+        // Zero-width bitfields pad to their type's boundary, even right after a normal field,
+        // but don't change the struct's alignment
+        check_layout(
+            r#"a "u8"; _ "u32:0"; b "u8:3"; _ "u16:0"; c "u8:2"; d "u8""#,
+            8,
+            1,
+            &[(1, 6, &[(2, 24, 3, false), (4, 40, 2, false)])],
+        );
+        // ...and at the end of the struct they still pad it out
+        check_layout(
+            r#"a "u8:3"; _ "u16:0""#,
+            2,
+            1,
+            &[(0, 2, &[(0, 0, 3, false)])],
+        );
+    }
+
+    #[test]
+    fn mixed_signed_widths() {
+        check_layout(
+            r#"a "i8:3"; b "u16:9"; c "i32:17"; d "i64:40""#,
+            16,
+            8,
+            &[(
+                0,
+                13,
+                &[
+                    (0, 0, 3, true),
+                    (1, 3, 9, false),
+                    (2, 12, 17, true),
+                    (3, 64, 40, true),
+                ],
+            )],
+        );
+    }
+
+    #[test]
+    fn unnamed_field_ending_a_unit() {
+        // The unnamed field is still a value, and the unit ends with it
+        check_layout(
+            r#"a "u16:5"; _ "u16:3"; b "u32""#,
+            8,
+            4,
+            &[(0, 1, &[(0, 0, 5, false), (1, 5, 3, false)])],
+        );
+    }
+}
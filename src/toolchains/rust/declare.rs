@@ -1,6 +1,10 @@
 use super::*;
-use kdl_script::parse::{Attr, AttrAligned, AttrPacked, AttrPassthrough, AttrRepr, LangRepr, Repr};
-use kdl_script::types::{AliasTy, ArrayTy, FuncIdx, PrimitiveTy, RefTy, Ty, TyIdx};
+use kdl_script::parse::{
+    Attr, AttrAligned, AttrPacked, AttrPassthrough, AttrRepr, AttrUnwind, LangRepr, Repr,
+};
+use kdl_script::types::{
+    AliasTy, ArrayTy, FuncIdx, NonZeroTy, OptionTy, PrimitiveTy, RefTy, SliceTy, Ty, TyIdx,
+};
 use std::fmt::Write;
 
 impl RustcToolchain {
@@ -9,16 +13,30 @@ impl RustcToolchain {
         f: &mut Fivemat,
         state: &TestState,
     ) -> Result<(), GenerateError> {
-        let convention_decl = self.convention_decl(state.options.convention)?;
-        writeln!(f, "extern \"{convention_decl}\" {{",)?;
-        f.add_indent(1);
+        // Functions can have their own conventions, so group them into one block per convention
+        let mut blocks = Vec::<(CallingConvention, Vec<FuncIdx>)>::new();
         for &func in &state.desired_funcs {
-            self.generate_signature(f, state, func)?;
-            writeln!(f, ";")?;
+            let convention = state
+                .options
+                .func_convention(state.types.realize_func(func));
+            if let Some((_, funcs)) = blocks.iter_mut().find(|(c, _)| *c == convention) {
+                funcs.push(func);
+            } else {
+                blocks.push((convention, vec![func]));
+            }
+        }
+        for (convention, funcs) in blocks {
+            let convention_decl = self.convention_decl(convention)?;
+            writeln!(f, "extern \"{convention_decl}\" {{",)?;
+            f.add_indent(1);
+            for func in funcs {
+                self.generate_signature(f, state, func)?;
+                writeln!(f, ";")?;
+            }
+            f.sub_indent(1);
+            writeln!(f, "}}")?;
+            writeln!(f)?;
         }
-        f.sub_indent(1);
-        writeln!(f, "}}")?;
-        writeln!(f)?;
         Ok(())
     }
 
@@ -52,6 +70,51 @@ impl RustcToolchain {
         Ok(())
     }
 
+    /// The name of a primitive, or why we can't use it
+    pub fn primitive_tyname(&self, prim: &PrimitiveTy) -> Result<String, GenerateError> {
+        let name = match prim {
+            PrimitiveTy::I8 => "i8",
+            PrimitiveTy::I16 => "i16",
+            PrimitiveTy::I32 => "i32",
+            PrimitiveTy::I64 => "i64",
+            PrimitiveTy::I128 => "i128",
+            PrimitiveTy::U8 => "u8",
+            PrimitiveTy::U16 => "u16",
+            PrimitiveTy::U32 => "u32",
+            PrimitiveTy::U64 => "u64",
+            PrimitiveTy::U128 => "u128",
+            PrimitiveTy::F32 => "f32",
+            PrimitiveTy::F64 => "f64",
+            // Declared by the harness prefix, like num-complex's `Complex<T>`
+            PrimitiveTy::C32 => "Complex32",
+            PrimitiveTy::C64 => "Complex64",
+            PrimitiveTy::Bool => "bool",
+            PrimitiveTy::Ptr => "*mut ()",
+            PrimitiveTy::BitInt(_) | PrimitiveTy::UBitInt(_) => Err(UnsupportedError::Other(
+                format!("rust doesn't have _BitInt ({prim})"),
+            ))?,
+            PrimitiveTy::F16 => {
+                if self.is_nightly {
+                    "f16"
+                } else {
+                    return Err(UnsupportedError::Other(
+                        "f16 is an unstable rust feature, requires nightly".to_owned(),
+                    ))?;
+                }
+            }
+            PrimitiveTy::F128 => {
+                if self.is_nightly {
+                    "f128"
+                } else {
+                    return Err(UnsupportedError::Other(
+                        "f128 is an unstable rust feature, requires nightly".to_owned(),
+                    ))?;
+                }
+            }
+        };
+        Ok(name.to_owned())
+    }
+
     pub fn intern_tyname(&self, state: &mut TestState, ty: TyIdx) -> Result<(), GenerateError> {
         // Don't double-intern
         if state.tynames.contains_key(&ty) {
@@ -62,49 +125,7 @@ impl RustcToolchain {
         let (tyname, borrowed_tyname) = match state.types.realize_ty(ty) {
             // Structural types that don't need definitions but we should
             // intern the name of
-            Ty::Primitive(prim) => {
-                let name = match prim {
-                    PrimitiveTy::I8 => "i8",
-                    PrimitiveTy::I16 => "i16",
-                    PrimitiveTy::I32 => "i32",
-                    PrimitiveTy::I64 => "i64",
-                    PrimitiveTy::I128 => "i128",
-                    PrimitiveTy::U8 => "u8",
-                    PrimitiveTy::U16 => "u16",
-                    PrimitiveTy::U32 => "u32",
-                    PrimitiveTy::U64 => "u64",
-                    PrimitiveTy::U128 => "u128",
-                    PrimitiveTy::F32 => "f32",
-                    PrimitiveTy::F64 => "f64",
-                    PrimitiveTy::Bool => "bool",
-                    PrimitiveTy::Ptr => "*mut ()",
-                    PrimitiveTy::I256 => {
-                        Err(UnsupportedError::Other("rust doesn't have i256".to_owned()))?
-                    }
-                    PrimitiveTy::U256 => {
-                        Err(UnsupportedError::Other("rust doesn't have u256".to_owned()))?
-                    }
-                    PrimitiveTy::F16 => {
-                        if self.is_nightly {
-                            "f16"
-                        } else {
-                            return Err(UnsupportedError::Other(
-                                "f16 is an unstable rust feature, requires nightly".to_owned(),
-                            ))?;
-                        }
-                    }
-                    PrimitiveTy::F128 => {
-                        if self.is_nightly {
-                            "f128"
-                        } else {
-                            return Err(UnsupportedError::Other(
-                                "f128 is an unstable rust feature, requires nightly".to_owned(),
-                            ))?;
-                        }
-                    }
-                };
-                (name.to_owned(), None)
-            }
+            Ty::Primitive(prim) => (self.primitive_tyname(prim)?, None),
             Ty::Array(ArrayTy { elem_ty, len }) => {
                 let elem_tyname = &state.tynames[elem_ty];
                 let borrowed_tyname = state
@@ -124,6 +145,29 @@ impl RustcToolchain {
                     Some(format!("&'a mut {borrowed_pointee_tyname}")),
                 )
             }
+            // Declared by the harness prefix
+            Ty::Slice(SliceTy { elem_ty }) => {
+                let elem_tyname = &state.tynames[elem_ty];
+                let borrowed_elem_tyname =
+                    state.borrowed_tynames.get(elem_ty).unwrap_or(elem_tyname);
+                (
+                    format!("Slice<{elem_tyname}>"),
+                    Some(format!("Slice<'a, {borrowed_elem_tyname}>")),
+                )
+            }
+            Ty::NonZero(NonZeroTy { int_ty }) => {
+                (format!("NonZero<{}>", state.tynames[int_ty]), None)
+            }
+            Ty::Option(OptionTy { some_ty }) => {
+                let borrowed_tyname = state
+                    .borrowed_tynames
+                    .get(some_ty)
+                    .map(|some_tyname| format!("Option<{some_tyname}>"));
+                (
+                    format!("Option<{}>", state.tynames[some_ty]),
+                    borrowed_tyname,
+                )
+            }
             Ty::Empty => ("()".to_owned(), None),
             // Nominal types we need to emit a decl for
             Ty::Struct(struct_ty) => {
@@ -182,17 +226,21 @@ impl RustcToolchain {
                     writeln!(f, "#[derive(Copy, Clone)]")?;
                     writeln!(f, "struct {} {{", struct_ty.name)?;
                 }
-                f.add_indent(1);
-                for field in &struct_ty.fields {
-                    let field_name = &field.ident;
-                    let field_tyname = state
-                        .borrowed_tynames
-                        .get(&field.ty)
-                        .unwrap_or(&state.tynames[&field.ty]);
-                    writeln!(f, "{field_name}: {field_tyname},")?;
+                if struct_ty.fields.iter().any(|field| field.bits.is_some()) {
+                    self.generate_bitfield_struct_body(f, state, struct_ty, has_borrows)?;
+                } else {
+                    f.add_indent(1);
+                    for field in &struct_ty.fields {
+                        let field_name = &field.ident;
+                        let field_tyname = state
+                            .borrowed_tynames
+                            .get(&field.ty)
+                            .unwrap_or(&state.tynames[&field.ty]);
+                        writeln!(f, "{field_name}: {field_tyname},")?;
+                    }
+                    f.sub_indent(1);
+                    writeln!(f, "}}\n")?;
                 }
-                f.sub_indent(1);
-                writeln!(f, "}}\n")?;
             }
             Ty::Union(union_ty) => {
                 // Emit an actual union decl
@@ -223,7 +271,8 @@ impl RustcToolchain {
                 f.add_indent(1);
                 for variant in &enum_ty.variants {
                     let variant_name = &variant.name;
-                    writeln!(f, "{variant_name},")?;
+                    let val = variant.val;
+                    writeln!(f, "{variant_name} = {val},")?;
                 }
                 f.sub_indent(1);
                 writeln!(f, "}}\n")?;
@@ -285,19 +334,21 @@ impl RustcToolchain {
                     | PrimitiveTy::I32
                     | PrimitiveTy::I64
                     | PrimitiveTy::I128
-                    | PrimitiveTy::I256
                     | PrimitiveTy::U8
                     | PrimitiveTy::U16
                     | PrimitiveTy::U32
                     | PrimitiveTy::U64
                     | PrimitiveTy::U128
-                    | PrimitiveTy::U256
                     | PrimitiveTy::F16
                     | PrimitiveTy::F32
                     | PrimitiveTy::F64
                     | PrimitiveTy::F128
+                    | PrimitiveTy::C32
+                    | PrimitiveTy::C64
                     | PrimitiveTy::Bool
-                    | PrimitiveTy::Ptr => {
+                    | PrimitiveTy::Ptr
+                    | PrimitiveTy::BitInt(_)
+                    | PrimitiveTy::UBitInt(_) => {
                         // Builtin
                     }
                 };
@@ -305,7 +356,7 @@ impl RustcToolchain {
             Ty::Array(ArrayTy { .. }) => {
                 // Builtin
             }
-            Ty::Ref(RefTy { .. }) => {
+            Ty::Ref(RefTy { .. }) | Ty::Slice(_) | Ty::NonZero(_) | Ty::Option(_) => {
                 // Builtin
             }
             Ty::Empty => {
@@ -337,6 +388,16 @@ impl RustcToolchain {
                 Attr::Passthrough(AttrPassthrough(attr)) => {
                     other_attrs.push(attr.to_string());
                 }
+                Attr::Unwind(AttrUnwind {}) => {
+                    return Err(UnsupportedError::Other(
+                        "@unwind doesn't apply to types".to_owned(),
+                    ))?;
+                }
+                Attr::Convention(_) => {
+                    return Err(UnsupportedError::Other(
+                        "@convention doesn't apply to types".to_owned(),
+                    ))?;
+                }
                 Attr::Repr(AttrRepr { reprs }) => {
                     default_lang_repr = false;
                     // Any explicit repr attributes disables default C
@@ -353,14 +414,16 @@ impl RustcToolchain {
                                 PrimitiveTy::U32 => "u32",
                                 PrimitiveTy::U64 => "u64",
                                 PrimitiveTy::U128 => "u128",
-                                PrimitiveTy::I256
-                                | PrimitiveTy::U256
-                                | PrimitiveTy::F16
+                                PrimitiveTy::F16
                                 | PrimitiveTy::F32
                                 | PrimitiveTy::F64
                                 | PrimitiveTy::F128
+                                | PrimitiveTy::C32
+                                | PrimitiveTy::C64
                                 | PrimitiveTy::Bool
-                                | PrimitiveTy::Ptr => {
+                                | PrimitiveTy::Ptr
+                                | PrimitiveTy::BitInt(_)
+                                | PrimitiveTy::UBitInt(_) => {
                                     return Err(UnsupportedError::Other(format!(
                                         "unsupport repr({prim:?})"
                                     )))?;
@@ -415,7 +478,7 @@ impl RustcToolchain {
         func: FuncIdx,
     ) -> Result<(), GenerateError> {
         let function = state.types.realize_func(func);
-        self.check_returns(state, function)?;
+        let outputs = lower_outputs(&state.types, function)?;
 
         write!(f, "fn {}(", function.name)?;
         let mut multiarg = false;
@@ -429,9 +492,19 @@ impl RustcToolchain {
             let arg_ty = &state.tynames[&arg.ty];
             write!(f, "{}: {}", arg_name, arg_ty)?;
         }
+        // Add outparams
+        for output in outputs.iter().filter(|output| output.outparam) {
+            if multiarg {
+                write!(f, ", ")?;
+            }
+            multiarg = true;
+            let arg_name = &output.arg.name;
+            let arg_ty = &state.tynames[&output.val_ty];
+            write!(f, "{arg_name}_out: *mut {arg_ty}")?;
+        }
         // Add normal returns
-        if let Some(arg) = function.outputs.first() {
-            let arg_ty = &state.tynames[&arg.ty];
+        if let Some(output) = outputs.iter().find(|output| !output.outparam) {
+            let arg_ty = &state.tynames[&output.val_ty];
             write!(f, ") -> {arg_ty}")?;
         } else {
             write!(f, ")")?;
@@ -457,9 +530,29 @@ impl RustcToolchain {
             CallingConvention::C => "C",
             CallingConvention::Rust => "Rust",
             CallingConvention::System => "system",
-            CallingConvention::Win64 => "win64",
-            CallingConvention::Sysv64 => "sysv64",
-            CallingConvention::Aapcs => "aapcs",
+            CallingConvention::CUnwind => "C-unwind",
+            CallingConvention::SystemUnwind => "system-unwind",
+            CallingConvention::Win64 => {
+                if self.has_target_arch("x86_64") {
+                    "win64"
+                } else {
+                    return Err(self.unsupported_convention(&convention))?;
+                }
+            }
+            CallingConvention::Sysv64 => {
+                if self.has_target_arch("x86_64") {
+                    "sysv64"
+                } else {
+                    return Err(self.unsupported_convention(&convention))?;
+                }
+            }
+            CallingConvention::Aapcs => {
+                if self.has_target_arch("arm") {
+                    "aapcs"
+                } else {
+                    return Err(self.unsupported_convention(&convention))?;
+                }
+            }
             CallingConvention::Cdecl => {
                 if self.platform == Windows {
                     "cdecl"
@@ -501,4 +594,15 @@ impl RustcToolchain {
     fn unsupported_convention(&self, convention: &CallingConvention) -> UnsupportedError {
         UnsupportedError::Other(format!("unsupported convention {convention}"))
     }
+
+    fn has_target_arch(&self, arch: &str) -> bool {
+        self.has_cfg("target_arch", arch)
+    }
+
+    pub fn has_cfg(&self, key: &str, val: &str) -> bool {
+        self.platform_info
+            .cfgs
+            .iter()
+            .any(|cfg| matches!(cfg, cargo_platform::Cfg::KeyPair(k, v) if k == key && v == val))
+    }
 }
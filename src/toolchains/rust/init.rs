@@ -1,6 +1,8 @@
 use super::*;
 use crate::harness::vals::*;
-use kdl_script::types::{AliasTy, ArrayTy, PrimitiveTy, RefTy, Ty, TyIdx};
+use kdl_script::types::{
+    AliasTy, ArrayTy, NonZeroTy, OptionTy, PrimitiveTy, RefTy, SliceTy, Ty, TyIdx,
+};
 use std::fmt::Write;
 
 impl RustcToolchain {
@@ -28,6 +30,22 @@ impl RustcToolchain {
 
                 PrimitiveTy::F32 => write!(f, "f32::from_bits({})", val.generate_u32())?,
                 PrimitiveTy::F64 => write!(f, "f64::from_bits({})", val.generate_u64())?,
+                PrimitiveTy::C32 => {
+                    let val = val.generate_u64();
+                    let (re, im) = (val as u32, (val >> 32) as u32);
+                    write!(
+                        f,
+                        "Complex32 {{ re: f32::from_bits({re}), im: f32::from_bits({im}) }}"
+                    )?
+                }
+                PrimitiveTy::C64 => {
+                    let val = val.generate_u128();
+                    let (re, im) = (val as u64, (val >> 64) as u64);
+                    write!(
+                        f,
+                        "Complex64 {{ re: f64::from_bits({re}), im: f64::from_bits({im}) }}"
+                    )?
+                }
                 PrimitiveTy::Bool => write!(f, "{}", val.generate_bool())?,
                 PrimitiveTy::Ptr => {
                     if true {
@@ -36,12 +54,9 @@ impl RustcToolchain {
                         write!(f, "{:#X}u32 as *mut ()", val.generate_u32())?
                     }
                 }
-                PrimitiveTy::I256 => {
-                    Err(UnsupportedError::Other("rust doesn't have i256".to_owned()))?
-                }
-                PrimitiveTy::U256 => {
-                    Err(UnsupportedError::Other("rust doesn't have u256".to_owned()))?
-                }
+                PrimitiveTy::BitInt(_) | PrimitiveTy::UBitInt(_) => Err(UnsupportedError::Other(
+                    format!("rust doesn't have _BitInt ({prim})"),
+                ))?,
                 PrimitiveTy::F16 => write!(f, "f16::from_bits({})", val.generate_u16())?,
                 PrimitiveTy::F128 => write!(f, "f128::from_bits({})", val.generate_u128())?,
             },
@@ -52,6 +67,11 @@ impl RustcToolchain {
                     write!(f, "{name}::{variant_name}")?;
                 }
             }
+            Ty::NonZero(NonZeroTy { int_ty }) => {
+                write!(f, "NonZero::new(")?;
+                self.init_leaf_value(f, state, *int_ty, val, alias)?;
+                write!(f, ").unwrap()")?;
+            }
             _ => unreachable!("only primitives and enums should be passed to generate_leaf_value"),
         }
         Ok(())
@@ -70,10 +90,50 @@ impl RustcToolchain {
     ) -> Result<(), GenerateError> {
         match state.types.realize_ty(ty) {
             // Primitives and Enums are the only "real" values with actual bytes
-            Ty::Primitive(_) | Ty::Enum(_) => {
+            Ty::Primitive(_) | Ty::Enum(_) | Ty::NonZero(_) => {
                 let val = vals.next_val();
                 self.init_leaf_value(f, state, ty, &val, alias)?;
             }
+            Ty::Option(OptionTy { some_ty }) => {
+                let tag_val = vals.next_val();
+                if tag_val.generate_bool() {
+                    write!(f, "Some(")?;
+                    self.init_value(f, state, *some_ty, vals, alias, ref_temp_name, extra_decls)?;
+                    write!(f, ")")?;
+                } else {
+                    write!(f, "None")?;
+                }
+            }
+            Ty::Slice(SliceTy { elem_ty }) => {
+                let tag_val = vals.next_val();
+                let len = tag_val.generate_idx(MAX_SLICE_LEN + 1);
+                // The value borrows a temporary array
+                write!(f, "Slice::new(&{ref_temp_name})")?;
+                let mut ref_temp = String::new();
+                let mut ref_temp_f = Fivemat::new(&mut ref_temp, INDENT);
+                let elem_tyname = &state.tynames[elem_ty];
+                write!(
+                    &mut ref_temp_f,
+                    "let {ref_temp_name}: [{elem_tyname}; {len}] = ["
+                )?;
+                for elem_idx in 0..len {
+                    if elem_idx > 0 {
+                        write!(&mut ref_temp_f, ", ")?;
+                    }
+                    let ref_temp_name = format!("{ref_temp_name}{elem_idx}_");
+                    self.init_value(
+                        &mut ref_temp_f,
+                        state,
+                        *elem_ty,
+                        vals,
+                        alias,
+                        &ref_temp_name,
+                        extra_decls,
+                    )?;
+                }
+                write!(&mut ref_temp_f, "];")?;
+                extra_decls.push(ref_temp);
+            }
             Ty::Empty => {
                 write!(f, "()")?;
             }
@@ -109,6 +169,44 @@ impl RustcToolchain {
                 write!(f, "]")?;
             }
             // Nominal types we need to emit a decl for
+            Ty::Struct(struct_ty) if struct_ty.fields.iter().any(|f| f.bits.is_some()) => {
+                // Bitfields are built up a storage unit at a time
+                let name = alias.unwrap_or(&struct_ty.name);
+                let layout = self.bitfield_layout(state, struct_ty)?;
+                write!(f, "{name} {{ _bitfield_align: []")?;
+                for (field_idx, field) in struct_ty.fields.iter().enumerate() {
+                    if field.bits.is_none() {
+                        let field_name = &field.ident;
+                        write!(f, ", {field_name}: ")?;
+                        let ref_temp_name = format!("{ref_temp_name}{field_name}_");
+                        self.init_value(
+                            f,
+                            state,
+                            field.ty,
+                            vals,
+                            alias,
+                            &ref_temp_name,
+                            extra_decls,
+                        )?;
+                    } else if let Some((unit_idx, unit)) = layout.unit_starting_at(field_idx) {
+                        write!(
+                            f,
+                            ", _bitfield_{0}: {name}::new_bitfield_{0}(",
+                            unit_idx + 1
+                        )?;
+                        for (bitfield_idx, bitfield) in unit.fields.iter().enumerate() {
+                            if bitfield_idx > 0 {
+                                write!(f, ", ")?;
+                            }
+                            let field = &struct_ty.fields[bitfield.field_idx];
+                            let val = vals.next_val();
+                            self.init_leaf_value(f, state, field.ty, &val, alias)?;
+                        }
+                        write!(f, ")")?;
+                    }
+                }
+                write!(f, " }}")?;
+            }
             Ty::Struct(struct_ty) => {
                 let name = alias.unwrap_or(&struct_ty.name);
                 write!(f, "{name} {{ ")?;
@@ -1,4 +1,6 @@
 use super::*;
+use crate::harness::vals::MAX_SLICE_LEN;
+use kdl_script::parse::Attr;
 use kdl_script::types::{Ty, TyIdx};
 use std::fmt::Write;
 
@@ -10,21 +12,35 @@ impl RustcToolchain {
         f: &mut Fivemat,
         state: &TestState,
     ) -> Result<(), GenerateError> {
-        // No extra harness gunk if not needed
-        if state.options.val_writer != WriteImpl::HarnessCallback {
-            return Ok(());
-        }
-        if state.options.convention == CallingConvention::Vectorcall {
+        let uses_vectorcall = state.desired_funcs.iter().any(|&func| {
+            let convention = state
+                .options
+                .func_convention(state.types.realize_func(func));
+            convention == CallingConvention::Vectorcall
+        });
+        if uses_vectorcall {
             writeln!(f, "#![feature(abi_vectorcall)]")?;
         }
         let mut has_f16 = false;
         let mut has_f128 = false;
+        let mut has_c32 = false;
+        let mut has_c64 = false;
+        let mut has_bitfields = false;
+        let mut has_slices = false;
+        let mut has_nonzero = false;
         for def in state.defs.definitions(state.desired_funcs.iter().copied()) {
             match def {
                 kdl_script::Definition::DeclareTy(ty) | kdl_script::Definition::DefineTy(ty) => {
                     match state.types.realize_ty(ty) {
                         Ty::Primitive(PrimitiveTy::F16) => has_f16 = true,
                         Ty::Primitive(PrimitiveTy::F128) => has_f128 = true,
+                        Ty::Primitive(PrimitiveTy::C32) => has_c32 = true,
+                        Ty::Primitive(PrimitiveTy::C64) => has_c64 = true,
+                        Ty::Struct(struct_ty) => {
+                            has_bitfields |= struct_ty.fields.iter().any(|f| f.bits.is_some())
+                        }
+                        Ty::Slice(_) => has_slices = true,
+                        Ty::NonZero(_) => has_nonzero = true,
                         _ => {}
                     }
                 }
@@ -37,12 +53,36 @@ impl RustcToolchain {
         if has_f128 {
             writeln!(f, "#![feature(f128)]")?;
         }
-        // Load test harness "headers"
-        writeln!(
-            f,
-            "{}",
-            crate::files::get_file("harness/rust/harness_prefix.rs")
-        )?;
+        // Rust has no complex numbers, so use the two-field struct everyone assumes is equivalent
+        for (has_complex, name, float) in
+            [(has_c32, "Complex32", "f32"), (has_c64, "Complex64", "f64")]
+        {
+            if has_complex {
+                writeln!(f, "#[repr(C)]")?;
+                writeln!(f, "#[derive(Copy, Clone, Debug)]")?;
+                writeln!(
+                    f,
+                    "pub struct {name} {{ pub re: {float}, pub im: {float} }}"
+                )?;
+            }
+        }
+        if has_bitfields {
+            bitfield::write_bitfield_helpers(f)?;
+        }
+        if has_nonzero {
+            writeln!(f, "use std::num::NonZero;")?;
+        }
+        if has_slices {
+            writeln!(f, "{}", crate::files::get_file("harness/rust/slice.rs"))?;
+        }
+        // Load test harness "headers" (if needed)
+        let prefix = match state.options.val_writer {
+            WriteImpl::HarnessCallback => "harness/rust/harness_prefix.rs",
+            WriteImpl::Print => "harness/rust/print_prefix.rs",
+            WriteImpl::Assert => "harness/rust/assert_prefix.rs",
+            WriteImpl::Noop => return Ok(()),
+        };
+        writeln!(f, "{}", crate::files::get_file(prefix))?;
         writeln!(f)?;
 
         Ok(())
@@ -109,7 +149,7 @@ impl RustcToolchain {
                     writeln!(f, "}}")?;
                 }
             }
-            Ty::Primitive(_) => {
+            Ty::Primitive(_) | Ty::NonZero(_) => {
                 // Hey an actual leaf, report it (and burn a value)
                 let val = vals.next_val();
                 if val.should_write_val(&state.options) {
@@ -127,7 +167,7 @@ impl RustcToolchain {
                     if tag_generator.should_write_val(&state.options) {
                         writeln!(f, "if let {enum_name}::{variant_name} = &{from} {{")?;
                         f.add_indent(1);
-                        self.write_tag_field(f, state, to, from, tag_idx, &tag_generator)?;
+                        self.write_enum_tag_field(f, state, to, from, tag_idx, &tag_generator)?;
                         f.sub_indent(1);
                         writeln!(f, "}} else {{")?;
                         f.add_indent(1);
@@ -157,10 +197,22 @@ impl RustcToolchain {
                 }
             }
             Ty::Struct(struct_ty) => {
-                // recurse into each field
-                for field in &struct_ty.fields {
+                // Fields of packed structs can't be borrowed, so copy them out first
+                let packed = struct_ty
+                    .attrs
+                    .iter()
+                    .any(|attr| matches!(attr, Attr::Packed(_)));
+                // recurse into each field (zero-width bitfields have no value)
+                for field in struct_ty.fields.iter().filter(|f| f.bits != Some(0)) {
                     let field_name = &field.ident;
-                    let base = format!("{from}.{field_name}");
+                    // Bitfields are read with their getter
+                    let base = if field.bits.is_some() {
+                        format!("{from}.{field_name}()")
+                    } else if packed {
+                        format!("{{ {from}.{field_name} }}")
+                    } else {
+                        format!("{from}.{field_name}")
+                    };
                     self.write_fields(f, state, to, &base, field.ty, vals)?;
                 }
             }
@@ -239,6 +291,64 @@ impl RustcToolchain {
                 let base = format!("(*{from})");
                 self.write_fields(f, state, to, &base, ref_ty.pointee_ty, vals)?
             }
+            Ty::Option(option_ty) => {
+                // The niche is the implicit "tag", only look at the payload if it's there
+                let tag_generator = vals.next_val();
+                let is_some = tag_generator.generate_bool();
+                let should_write_tag = tag_generator.should_write_val(&state.options);
+                writeln!(f, "if {from}.is_some() {{")?;
+                f.add_indent(1);
+                if is_some {
+                    if should_write_tag {
+                        self.write_tag_field(f, state, to, from, 1, &tag_generator)?;
+                    }
+                    // Go straight to the pointee of an Option<&mut T>, it can't be moved out
+                    let (base, payload_ty) = match state.types.realize_ty(option_ty.some_ty) {
+                        Ty::Ref(ref_ty) => {
+                            (format!("(**{from}.as_ref().unwrap())"), ref_ty.pointee_ty)
+                        }
+                        _ => (format!("{from}.unwrap()"), option_ty.some_ty),
+                    };
+                    self.write_fields(f, state, to, &base, payload_ty, vals)?;
+                } else if should_write_tag {
+                    self.write_error_tag_field(f, state, to, &tag_generator)?;
+                }
+                f.sub_indent(1);
+                writeln!(f, "}} else {{")?;
+                f.add_indent(1);
+                if should_write_tag {
+                    if is_some {
+                        self.write_error_tag_field(f, state, to, &tag_generator)?;
+                    } else {
+                        self.write_tag_field(f, state, to, from, 0, &tag_generator)?;
+                    }
+                }
+                f.sub_indent(1);
+                writeln!(f, "}}")?;
+            }
+            Ty::Slice(slice_ty) => {
+                // The length is the implicit "tag", only look at the elements if it's right
+                let tag_generator = vals.next_val();
+                let len = tag_generator.generate_idx(MAX_SLICE_LEN + 1);
+                let should_write_tag = tag_generator.should_write_val(&state.options);
+                writeln!(f, "if {from}.as_slice().len() == {len} {{")?;
+                f.add_indent(1);
+                if should_write_tag {
+                    self.write_tag_field(f, state, to, from, len, &tag_generator)?;
+                }
+                for i in 0..len {
+                    let base = format!("{from}.as_slice()[{i}]");
+                    self.write_fields(f, state, to, &base, slice_ty.elem_ty, vals)?;
+                }
+                f.sub_indent(1);
+                writeln!(f, "}} else {{")?;
+                f.add_indent(1);
+                if should_write_tag {
+                    self.write_error_tag_field(f, state, to, &tag_generator)?;
+                }
+                f.sub_indent(1);
+                writeln!(f, "}}")?;
+            }
             Ty::Union(union_ty) => {
                 // Process the implicit "tag" value
                 let tag_generator = vals.next_val();
@@ -277,12 +387,25 @@ impl RustcToolchain {
                 writeln!(f, "write_val({to}, {val_idx}, &{rvalue});")?;
             }
             WriteImpl::Assert => {
-                write!(f, "assert_eq!({path}, ")?;
+                let side = side_name(to);
+                let func_idx = val.func_idx;
+                let val_idx = val.absolute_val_idx;
+                let val_path = &val.path;
+                write!(
+                    f,
+                    "check_val(\"{side}\", {func_idx}, {val_idx}, \"{val_path}\", &{path}, &("
+                )?;
                 self.init_leaf_value(f, state, val.ty, val, None)?;
-                writeln!(f, ");")?;
+                writeln!(f, "));")?;
             }
             WriteImpl::Print => {
-                writeln!(f, "println!(\"{{:?}}\", {path});")?;
+                let side = side_name(to);
+                let val_idx = val.absolute_val_idx;
+                let val_path = &val.path;
+                writeln!(
+                    f,
+                    "print_val(\"{side}\", {val_idx}, \"{val_path}\", &{path});"
+                )?;
             }
             WriteImpl::Noop => {
                 // Noop, do nothing
@@ -310,10 +433,16 @@ impl RustcToolchain {
                 writeln!(f, "write_val({to}, {val_idx}, &{}u32);", variant_idx)?;
             }
             WriteImpl::Assert => {
-                // Noop, do nothing
+                // Noop, being in this branch is the assertion
             }
             WriteImpl::Print => {
-                // Noop, do nothing
+                let side = side_name(to);
+                let val_idx = val.absolute_val_idx;
+                let val_path = &val.path;
+                writeln!(
+                    f,
+                    "print_val(\"{side}\", {val_idx}, \"{val_path}\", &{variant_idx}u32);"
+                )?;
             }
             WriteImpl::Noop => {
                 // Noop, do nothing
@@ -322,6 +451,34 @@ impl RustcToolchain {
         Ok(())
     }
 
+    /// Like [`Self::write_tag_field`][] but also reports the raw bytes of the enum,
+    /// so that the check can catch the two sides disagreeing on its size/discriminants.
+    pub fn write_enum_tag_field(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        to: &str,
+        path: &str,
+        variant_idx: usize,
+        val: &ValueRef,
+    ) -> Result<(), GenerateError> {
+        match state.options.val_writer {
+            WriteImpl::HarnessCallback => {
+                // Convenience for triggering test failures
+                if path.contains("abicafepoison") && to.contains(CALLEE_VALS) {
+                    return self.write_error_tag_field(f, state, to, val);
+                }
+                let val_idx = val.absolute_val_idx;
+                writeln!(
+                    f,
+                    "write_enum_val({to}, {val_idx}, {variant_idx}u32, &{path});"
+                )?;
+            }
+            _ => self.write_tag_field(f, state, to, path, variant_idx, val)?,
+        }
+        Ok(())
+    }
+
     pub fn write_error_tag_field(
         &self,
         f: &mut Fivemat,
@@ -334,8 +491,25 @@ impl RustcToolchain {
                 let val_idx = val.absolute_val_idx;
                 writeln!(f, "write_val({to}, {val_idx}, &{}u32);", u32::MAX)?;
             }
-            WriteImpl::Assert | WriteImpl::Print => {
-                writeln!(f, r#"unreachable!("enum had unexpected variant!?")"#)?;
+            WriteImpl::Assert => {
+                let side = side_name(to);
+                let func_idx = val.func_idx;
+                let val_idx = val.absolute_val_idx;
+                let val_path = &val.path;
+                writeln!(
+                    f,
+                    "assert_failed(\"{side}\", {func_idx}, {val_idx}, \"{val_path}\");"
+                )?;
+            }
+            WriteImpl::Print => {
+                let side = side_name(to);
+                let val_idx = val.absolute_val_idx;
+                let val_path = &val.path;
+                writeln!(
+                    f,
+                    "print_val(\"{side}\", {val_idx}, \"{val_path}\", &{}u32);",
+                    u32::MAX
+                )?;
             }
             WriteImpl::Noop => {
                 // Noop, do nothing
@@ -355,10 +529,45 @@ impl RustcToolchain {
             WriteImpl::HarnessCallback => {
                 writeln!(f, "set_func({vals}, {idx});")?;
             }
-            WriteImpl::Print | WriteImpl::Noop | WriteImpl::Assert => {
+            WriteImpl::Print => {
+                let side = side_name(vals);
+                let func_name = &state.types.realize_func(idx).name;
+                writeln!(f, "set_func(\"{side}\", {idx}, \"{func_name}\");")?;
+            }
+            WriteImpl::Noop | WriteImpl::Assert => {
+                // Noop
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_unwind(
+        &self,
+        f: &mut dyn Write,
+        state: &TestState,
+        vals: &str,
+    ) -> Result<(), GenerateError> {
+        match state.options.val_writer {
+            WriteImpl::HarnessCallback => {
+                writeln!(f, "mark_unwind({vals});")?;
+            }
+            WriteImpl::Print => {
+                let side = side_name(vals);
+                writeln!(f, "mark_unwind(\"{side}\");")?;
+            }
+            WriteImpl::Noop | WriteImpl::Assert => {
                 // Noop
             }
         }
         Ok(())
     }
 }
+
+/// The name the print/assert writers use for the side a values buffer belongs to
+fn side_name(vals: &str) -> &'static str {
+    if vals == CALLER_VALS {
+        "caller"
+    } else {
+        "callee"
+    }
+}
@@ -0,0 +1,443 @@
+//! Zig codegen backend
+//!
+//! Unlike zigcc (which is just zig's bundled clang), this generates actual Zig source,
+//! so `extern struct`/`extern union` layout and `callconv` are what get tested.
+//! The generated code uses the post-0.14 `std.builtin.CallingConvention` syntax.
+
+mod declare;
+mod init;
+mod write;
+
+use camino::Utf8Path;
+use kdl_script::types::*;
+use kdl_script::PunEnv;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::process::Command;
+use std::sync::Arc;
+
+use super::super::*;
+use super::*;
+use crate::fivemat::Fivemat;
+use crate::harness::vals::ArgValuesIter;
+
+const CALLER_VALS: &str = "CALLER_VALS";
+const CALLEE_VALS: &str = "CALLEE_VALS";
+const INDENT: &str = "    ";
+
+pub struct ZigToolchain {
+    target: String,
+    platform: Platform,
+    /// The output of `zig version`, if zig could be run
+    version: Option<String>,
+    debug: bool,
+}
+
+#[derive(PartialEq)]
+enum Platform {
+    Windows,
+    Unixy,
+}
+
+pub struct TestState {
+    pub inner: TestImpl,
+    // interning state
+    pub desired_funcs: Vec<FuncIdx>,
+    pub tynames: HashMap<TyIdx, String>,
+}
+impl std::ops::Deref for TestState {
+    type Target = TestImpl;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+impl TestState {
+    fn new(inner: TestImpl) -> Self {
+        let desired_funcs = inner.options.active_funcs(&inner.types);
+        Self {
+            inner,
+            desired_funcs,
+            tynames: Default::default(),
+        }
+    }
+}
+
+impl Toolchain for ZigToolchain {
+    fn lang(&self) -> &'static str {
+        "zig"
+    }
+    fn src_ext(&self) -> &'static str {
+        "zig"
+    }
+
+    fn pun_env(&self) -> Arc<PunEnv> {
+        Arc::new(kdl_script::PunEnv {
+            lang: "zig".to_string(),
+        })
+    }
+
+    fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+    fn supports_primitive(&self, prim: PrimitiveTy) -> Result<(), GenerateError> {
+        self.primitive_tyname(&prim).map(|_| ())
+    }
+
+    fn compile_callee(
+        &self,
+        src_path: &Utf8Path,
+        out_dir: &Utf8Path,
+        lib_name: &str,
+    ) -> Result<String, BuildError> {
+        self.compile(src_path, out_dir, lib_name)
+    }
+
+    fn compile_caller(
+        &self,
+        src_path: &Utf8Path,
+        out_dir: &Utf8Path,
+        lib_name: &str,
+    ) -> Result<String, BuildError> {
+        self.compile(src_path, out_dir, lib_name)
+    }
+
+    fn repro_command(&self, _lib_name: &str) -> Result<ReproCommand, UnsupportedError> {
+        let mut args = ["build-lib", "-static", "-fPIC", "-O", "Debug"]
+            .map(String::from)
+            .to_vec();
+        if !self.debug {
+            args.push("-fstrip".to_owned());
+        }
+        Ok(ReproCommand {
+            program: "zig".to_owned(),
+            args,
+            output: ReproOutput::StaticLib,
+            emit_flag: Some("-femit-bin="),
+            version_arg: "version",
+        })
+    }
+
+    fn generate_callee(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError> {
+        let mut f = Fivemat::new(f, INDENT);
+        let mut state = TestState::new(test);
+        self.generate_callee_impl(&mut f, &mut state)
+    }
+
+    fn generate_caller(&self, f: &mut dyn Write, test: TestImpl) -> Result<(), GenerateError> {
+        let mut f = Fivemat::new(f, INDENT);
+        let mut state = TestState::new(test);
+        self.generate_caller_impl(&mut f, &mut state)
+    }
+}
+
+impl ZigToolchain {
+    fn generate_caller_impl(
+        &self,
+        f: &mut Fivemat,
+        state: &mut TestState,
+    ) -> Result<(), GenerateError> {
+        // Generate type decls and gather up functions
+        self.generate_definitions(f, state)?;
+        // Generate decls of the functions we want to call
+        self.generate_caller_externs(f, state)?;
+
+        // Generate the test function the harness will call
+        writeln!(f, "export fn do_test() void {{")?;
+        f.add_indent(1);
+        for &func in &state.desired_funcs {
+            // Generate the individual function calls
+            self.generate_caller_body(f, state, func)?;
+        }
+        f.sub_indent(1);
+        writeln!(f, "}}")?;
+
+        Ok(())
+    }
+
+    fn generate_caller_body(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        func: FuncIdx,
+    ) -> Result<(), GenerateError> {
+        writeln!(f, "{{")?;
+        f.add_indent(1);
+        let function = state.types.realize_func(func);
+        self.check_unwind(function)?;
+
+        // Report we're starting a function
+        self.write_set_function(f, state, CALLER_VALS, func)?;
+
+        // Create vars for all the inputs
+        let mut func_vals = state.vals.at_func(func);
+        for arg in &function.inputs {
+            let arg_vals: ArgValuesIter = func_vals.next_arg();
+            // Create and report the input
+            self.init_var(f, state, &arg.name, arg.ty, arg_vals.clone())?;
+            self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLER_VALS)?;
+        }
+
+        // Call the function
+        let outputs = lower_outputs(&state.types, function)?;
+        self.call_function(f, state, function, &outputs)?;
+
+        // Report all the outputs
+        for output in &outputs {
+            let arg_vals: ArgValuesIter = func_vals.next_arg();
+            let arg = output.arg;
+
+            self.write_var(f, state, &arg.name, output.val_ty, arg_vals, CALLER_VALS)?;
+            // zig rejects unused locals, and minimized tests may not write this
+            writeln!(f, "_ = &{};", ident(&arg.name))?;
+        }
+
+        f.sub_indent(1);
+        writeln!(f, "}}")?;
+        Ok(())
+    }
+
+    fn call_function(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        function: &Func,
+        outputs: &[LoweredOutput],
+    ) -> Result<(), GenerateError> {
+        let func_name = ident(&function.name);
+
+        // Make space for the outparams to be written to
+        for output in outputs.iter().filter(|output| output.outparam) {
+            let tyname = &state.tynames[&output.val_ty];
+            writeln!(f, "var {}: {tyname} = undefined;", ident(&output.arg.name))?;
+        }
+        if let Some(output) = outputs.iter().find(|output| !output.outparam) {
+            let tyname = &state.tynames[&output.val_ty];
+            write!(f, "const {}: {tyname} = ", ident(&output.arg.name))?;
+        }
+
+        // Call the function
+        write!(f, "{func_name}(")?;
+        let inputs = function.inputs.iter().map(|arg| ident(&arg.name));
+        let outparams = outputs
+            .iter()
+            .filter(|output| output.outparam)
+            .map(|output| format!("&{}", ident(&output.arg.name)));
+
+        for (arg_idx, arg) in inputs.chain(outparams).enumerate() {
+            if arg_idx > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{arg}")?;
+        }
+        writeln!(f, ");")?;
+        writeln!(f)?;
+        Ok(())
+    }
+}
+
+impl ZigToolchain {
+    fn generate_callee_impl(
+        &self,
+        f: &mut Fivemat,
+        state: &mut TestState,
+    ) -> Result<(), GenerateError> {
+        // Generate type decls and gather up functions
+        self.generate_definitions(f, state)?;
+
+        for &func in &state.desired_funcs {
+            // Generate the individual function definitions
+            self.generate_callee_body(f, state, func)?;
+        }
+        Ok(())
+    }
+
+    fn generate_callee_body(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        func: FuncIdx,
+    ) -> Result<(), GenerateError> {
+        let function = state.types.realize_func(func);
+        self.check_unwind(function)?;
+        write!(f, "export ")?;
+        self.generate_signature(f, state, func)?;
+        writeln!(f, " {{")?;
+        f.add_indent(1);
+
+        // zig rejects unused parameters, and minimized tests may not write them
+        for arg in &function.inputs {
+            writeln!(f, "_ = &{};", ident(&arg.name))?;
+        }
+
+        // Report we're starting a function
+        self.write_set_function(f, state, CALLEE_VALS, func)?;
+
+        // Report the inputs
+        let mut func_vals = state.vals.at_func(func);
+        for arg in &function.inputs {
+            let arg_vals = func_vals.next_arg();
+            self.write_var(f, state, &arg.name, arg.ty, arg_vals, CALLEE_VALS)?;
+        }
+
+        // Create outputs and report them
+        let outputs = lower_outputs(&state.types, function)?;
+        for output in &outputs {
+            let arg_vals = func_vals.next_arg();
+            let arg = output.arg;
+            self.init_var(f, state, &arg.name, output.val_ty, arg_vals.clone())?;
+            self.write_var(f, state, &arg.name, output.val_ty, arg_vals, CALLEE_VALS)?;
+        }
+
+        // Return the outputs
+        for output in outputs.iter().filter(|output| output.outparam) {
+            let name = &output.arg.name;
+            writeln!(f, "{name}_out.* = {};", ident(name))?;
+        }
+        if let Some(output) = outputs.iter().find(|output| !output.outparam) {
+            writeln!(f, "return {};", ident(&output.arg.name))?;
+        }
+        f.sub_indent(1);
+        writeln!(f, "}}")?;
+        writeln!(f)?;
+        Ok(())
+    }
+}
+
+impl ZigToolchain {
+    /// Zig has no unwinding, so it can't be on either side of `@unwind`
+    fn check_unwind(&self, function: &Func) -> Result<(), GenerateError> {
+        if function.unwinds() {
+            return Err(UnsupportedError::Other(format!(
+                "zig can't unwind out of or catch {}",
+                function.name
+            )))?;
+        }
+        Ok(())
+    }
+
+    pub fn new(system_info: &Config, target: &str) -> Self {
+        let platform = if target.contains("windows") {
+            Platform::Windows
+        } else {
+            Platform::Unixy
+        };
+        // zig is optional, create_toolchains only registers us if this works
+        let version = Command::new("zig")
+            .arg("version")
+            .output()
+            .ok()
+            .filter(|out| out.status.success())
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_owned());
+
+        Self {
+            target: target.to_owned(),
+            platform,
+            version,
+            debug: system_info.debug,
+        }
+    }
+
+    fn compile(
+        &self,
+        src_path: &Utf8Path,
+        out_dir: &Utf8Path,
+        lib_name: &str,
+    ) -> Result<String, BuildError> {
+        let lib_path = out_dir.join(format!("lib{lib_name}.a"));
+        let mut cmd = Command::new("zig");
+        cmd.arg("build-lib")
+            .arg("-static")
+            .arg("-fPIC")
+            .arg("-O")
+            .arg("Debug");
+        if !self.debug {
+            cmd.arg("-fstrip");
+        }
+        cmd.arg(format!("-femit-bin={lib_path}"))
+            .arg(src_path)
+            // zig drops its cache next to the cwd by default
+            .arg("--cache-dir")
+            .arg(out_dir.join("zig-cache"));
+        debug!("running: {:?}", cmd);
+        let out = cmd.output()?;
+        if !out.status.success() {
+            return Err(BuildError::ZigCompile(out));
+        }
+        Ok(String::from(lib_name))
+    }
+}
+
+/// Quote an identifier if it would otherwise be a zig keyword or primitive type
+fn ident(name: &str) -> String {
+    const RESERVED: &[&str] = &[
+        "addrspace",
+        "align",
+        "allowzero",
+        "and",
+        "anyframe",
+        "anyopaque",
+        "anytype",
+        "asm",
+        "bool",
+        "break",
+        "callconv",
+        "catch",
+        "comptime",
+        "const",
+        "continue",
+        "defer",
+        "else",
+        "enum",
+        "errdefer",
+        "error",
+        "export",
+        "extern",
+        "false",
+        "fn",
+        "for",
+        "if",
+        "inline",
+        "isize",
+        "linksection",
+        "noalias",
+        "noinline",
+        "noreturn",
+        "nosuspend",
+        "null",
+        "opaque",
+        "or",
+        "orelse",
+        "packed",
+        "pub",
+        "resume",
+        "return",
+        "std",
+        "struct",
+        "suspend",
+        "switch",
+        "test",
+        "threadlocal",
+        "true",
+        "try",
+        "type",
+        "undefined",
+        "union",
+        "unreachable",
+        "usingnamespace",
+        "usize",
+        "var",
+        "void",
+        "volatile",
+        "while",
+    ];
+    const FLOATS: &[&str] = &["f16", "f32", "f64", "f80", "f128"];
+    // Any iN or uN is an integer type in zig
+    let is_int_ty = name.len() > 1
+        && matches!(name.as_bytes()[0], b'i' | b'u')
+        && name[1..].bytes().all(|b| b.is_ascii_digit());
+    if RESERVED.contains(&name) || FLOATS.contains(&name) || is_int_ty {
+        format!("@\"{name}\"")
+    } else {
+        name.to_owned()
+    }
+}
@@ -0,0 +1,484 @@
+use super::*;
+use kdl_script::parse::Attr;
+use kdl_script::types::{AliasTy, ArrayTy, FuncIdx, PrimitiveTy, RefTy, Ty, TyIdx};
+use std::fmt::Write;
+
+/// How a struct/union/enum should be laid out, as computed from its attributes
+struct Layout {
+    /// Alignment to force on the type (`@align`)
+    align: Option<i64>,
+    /// Whether every field should be underaligned (`@packed`)
+    packed: bool,
+    /// The backing integer of an enum (`@repr(u8)`)
+    tag_ty: Option<&'static str>,
+}
+
+impl ZigToolchain {
+    pub fn generate_caller_externs(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+    ) -> Result<(), GenerateError> {
+        for &func in &state.desired_funcs {
+            write!(f, "extern ")?;
+            self.generate_signature(f, state, func)?;
+            writeln!(f, ";")?;
+        }
+        writeln!(f)?;
+        Ok(())
+    }
+
+    pub fn generate_definitions(
+        &self,
+        f: &mut Fivemat,
+        state: &mut TestState,
+    ) -> Result<(), GenerateError> {
+        self.write_harness_prefix(f, state)?;
+
+        for def in state.defs.definitions(state.desired_funcs.iter().copied()) {
+            match def {
+                kdl_script::Definition::DeclareTy(ty) => {
+                    // zig declarations are order-independent, so there's nothing to
+                    // forward-declare, but we still need the name
+                    debug!("declare ty {}", state.types.format_ty(ty));
+                    self.intern_tyname(state, ty)?;
+                }
+                kdl_script::Definition::DefineTy(ty) => {
+                    debug!("define ty {}", state.types.format_ty(ty));
+                    self.generate_tydef(f, state, ty)?;
+                }
+                kdl_script::Definition::DefineFunc(_) => {
+                    // we'd buffer these up to generate them all at the end,
+                    // but we've already got them buffered, so... do nothing.
+                }
+                kdl_script::Definition::DeclareFunc(_) => {
+                    // nothing to do, executable kdl-script isn't real and can't hurt us
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The name of a primitive, or why we can't use it
+    pub fn primitive_tyname(&self, prim: &PrimitiveTy) -> Result<String, GenerateError> {
+        let name = match prim {
+            PrimitiveTy::I8 => "i8",
+            PrimitiveTy::I16 => "i16",
+            PrimitiveTy::I32 => "i32",
+            PrimitiveTy::I64 => "i64",
+            PrimitiveTy::I128 => "i128",
+            PrimitiveTy::U8 => "u8",
+            PrimitiveTy::U16 => "u16",
+            PrimitiveTy::U32 => "u32",
+            PrimitiveTy::U64 => "u64",
+            PrimitiveTy::U128 => "u128",
+            PrimitiveTy::F16 => "f16",
+            PrimitiveTy::F32 => "f32",
+            PrimitiveTy::F64 => "f64",
+            PrimitiveTy::F128 => "f128",
+            PrimitiveTy::Bool => "bool",
+            PrimitiveTy::Ptr => "?*anyopaque",
+            PrimitiveTy::BitInt(_) | PrimitiveTy::UBitInt(_) => Err(UnsupportedError::Other(
+                format!("zig's {prim} isn't guaranteed to have _BitInt's ABI"),
+            ))?,
+            PrimitiveTy::C32 | PrimitiveTy::C64 => Err(UnsupportedError::Other(
+                "zig has no _Complex type".to_owned(),
+            ))?,
+        };
+        Ok(name.to_owned())
+    }
+
+    pub fn intern_tyname(&self, state: &mut TestState, ty: TyIdx) -> Result<(), GenerateError> {
+        // Don't double-intern
+        if state.tynames.contains_key(&ty) {
+            return Ok(());
+        }
+
+        let tyname = match state.types.realize_ty(ty) {
+            // Structural types that don't need definitions but we should
+            // intern the name of
+            Ty::Primitive(prim) => self.primitive_tyname(prim)?,
+            Ty::Array(ArrayTy { elem_ty, len }) => {
+                let elem_tyname = &state.tynames[elem_ty];
+                format!("[{len}]{elem_tyname}")
+            }
+            Ty::Ref(RefTy { pointee_ty }) => {
+                let pointee_tyname = &state.tynames[pointee_ty];
+                format!("*{pointee_tyname}")
+            }
+            // Nominal types we need to emit a decl for
+            Ty::Struct(struct_ty) => ident(&struct_ty.name),
+            Ty::Union(union_ty) => ident(&union_ty.name),
+            Ty::Enum(enum_ty) => ident(&enum_ty.name),
+            Ty::Tagged(tagged_ty) => ident(&tagged_ty.name),
+            Ty::Alias(alias_ty) => ident(&alias_ty.name),
+            // Puns should be evaporated
+            Ty::Pun(pun) => {
+                let real_ty = state.types.resolve_pun(pun, &state.env)?;
+                state.tynames[&real_ty].clone()
+            }
+            Ty::Slice(_) | Ty::NonZero(_) | Ty::Option(_) => {
+                return Err(UnsupportedError::Other(
+                    "zig support for slices and niche optimized types isn't implemented".to_owned(),
+                ))?
+            }
+            Ty::Empty => {
+                return Err(UnsupportedError::Other(
+                    "zig has no C ABI for empty tuples".to_owned(),
+                ))?
+            }
+        };
+
+        state.tynames.insert(ty, tyname);
+        Ok(())
+    }
+
+    pub fn generate_tydef(
+        &self,
+        f: &mut Fivemat,
+        state: &mut TestState,
+        ty: TyIdx,
+    ) -> Result<(), GenerateError> {
+        // Make sure our own name is interned
+        self.intern_tyname(state, ty)?;
+
+        match state.types.realize_ty(ty) {
+            // Nominal types we need to emit a decl for
+            Ty::Struct(struct_ty) => {
+                if struct_ty.fields.iter().any(|field| field.bits.is_some()) {
+                    return Err(UnsupportedError::Other(
+                        "zig has no C bitfields (only packed structs)".to_owned(),
+                    ))?;
+                }
+                let layout = self.layout(state, &struct_ty.attrs, "struct")?;
+                writeln!(f, "const {} = extern struct {{", ident(&struct_ty.name))?;
+                f.add_indent(1);
+                self.generate_fields(f, state, &struct_ty.fields, &layout)?;
+                f.sub_indent(1);
+                writeln!(f, "}};\n")?;
+            }
+            Ty::Union(union_ty) => {
+                let layout = self.layout(state, &union_ty.attrs, "union")?;
+                writeln!(f, "const {} = extern union {{", ident(&union_ty.name))?;
+                f.add_indent(1);
+                self.generate_fields(f, state, &union_ty.fields, &layout)?;
+                f.sub_indent(1);
+                writeln!(f, "}};\n")?;
+            }
+            Ty::Enum(enum_ty) => {
+                let layout = self.layout(state, &enum_ty.attrs, "enum")?;
+                if layout.packed || layout.align.is_some() {
+                    return Err(UnsupportedError::Other(
+                        "zig enums can't be packed or aligned".to_owned(),
+                    ))?;
+                }
+                if layout.tag_ty.is_none() {
+                    if let Some(variant) = enum_ty
+                        .variants
+                        .iter()
+                        .find(|v| i32::try_from(v.val).is_err())
+                    {
+                        return Err(UnsupportedError::Other(format!(
+                            "zig's enum(c_int) can't hold the discriminant {}",
+                            variant.val
+                        )))?;
+                    }
+                }
+                let tag_ty = layout.tag_ty.unwrap_or("c_int");
+                writeln!(f, "const {} = enum({tag_ty}) {{", ident(&enum_ty.name))?;
+                f.add_indent(1);
+                for variant in &enum_ty.variants {
+                    writeln!(f, "{} = {},", ident(&variant.name), variant.val)?;
+                }
+                f.sub_indent(1);
+                writeln!(f, "}};\n")?;
+            }
+            Ty::Tagged(_) => {
+                // union(enum) exists but has no defined layout to test against
+                return Err(UnsupportedError::Other(
+                    "zig has no C ABI for tagged unions".to_owned(),
+                ))?;
+            }
+            Ty::Alias(AliasTy { name, real, attrs }) => {
+                if !attrs.is_empty() {
+                    return Err(UnsupportedError::Other(
+                        "don't yet know how to apply attrs to aliases".to_string(),
+                    ))?;
+                }
+                let real_tyname = &state.tynames[real];
+                writeln!(f, "const {} = {real_tyname};\n", ident(name))?;
+            }
+            Ty::Pun(..) => {
+                // Puns should be evaporated by the type name interner
+            }
+            Ty::Primitive(_) | Ty::Array(_) | Ty::Ref(_) => {
+                // Builtin
+            }
+            Ty::Slice(_) | Ty::NonZero(_) | Ty::Option(_) => {
+                // Rejected by the type name interner
+            }
+            Ty::Empty => {
+                return Err(UnsupportedError::Other(
+                    "zig has no C ABI for empty tuples".to_owned(),
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn generate_fields(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        fields: &[FieldTy],
+        layout: &Layout,
+    ) -> Result<(), GenerateError> {
+        if fields.is_empty() && layout.align.is_some() {
+            return Err(UnsupportedError::Other(
+                "zig can only align a type through its fields".to_owned(),
+            ))?;
+        }
+        for (field_idx, field) in fields.iter().enumerate() {
+            let field_name = ident(&field.ident);
+            let field_tyname = &state.tynames[&field.ty];
+            // extern types are as aligned as their most aligned field, so
+            // @packed underaligns every field, and @align overaligns the first
+            let align = match layout.align {
+                _ if layout.packed => " align(1)".to_owned(),
+                Some(align) if field_idx == 0 => format!(" align({align})"),
+                _ => String::new(),
+            };
+            writeln!(f, "{field_name}: {field_tyname}{align},")?;
+        }
+        Ok(())
+    }
+
+    fn layout(
+        &self,
+        state: &TestState,
+        attrs: &[Attr],
+        ty_style: &str,
+    ) -> Result<Layout, GenerateError> {
+        use kdl_script::parse::{
+            AttrAligned, AttrPacked, AttrPassthrough, AttrRepr, AttrUnwind, Repr,
+        };
+        let mut layout = Layout {
+            align: None,
+            packed: false,
+            tag_ty: None,
+        };
+        let mut default_lang_repr = true;
+        let mut lang_repr = None;
+        for attr in attrs {
+            match attr {
+                Attr::Align(AttrAligned { align }) => {
+                    layout.align = Some(align.val);
+                }
+                Attr::Packed(AttrPacked {}) => {
+                    layout.packed = true;
+                }
+                Attr::Passthrough(AttrPassthrough(_)) => {
+                    return Err(UnsupportedError::Other(
+                        "zig has no attributes to pass through".to_owned(),
+                    ))?;
+                }
+                Attr::Unwind(AttrUnwind {}) => {
+                    return Err(UnsupportedError::Other(
+                        "@unwind doesn't apply to types".to_owned(),
+                    ))?;
+                }
+                Attr::Convention(_) => {
+                    return Err(UnsupportedError::Other(
+                        "@convention doesn't apply to types".to_owned(),
+                    ))?;
+                }
+                Attr::Repr(AttrRepr { reprs }) => {
+                    default_lang_repr = false;
+                    // Any explicit repr attributes disables default C
+                    for repr in reprs {
+                        match repr {
+                            Repr::Primitive(prim) if ty_style == "enum" => {
+                                let tag_ty = match prim {
+                                    PrimitiveTy::I8 => "i8",
+                                    PrimitiveTy::I16 => "i16",
+                                    PrimitiveTy::I32 => "i32",
+                                    PrimitiveTy::I64 => "i64",
+                                    PrimitiveTy::U8 => "u8",
+                                    PrimitiveTy::U16 => "u16",
+                                    PrimitiveTy::U32 => "u32",
+                                    PrimitiveTy::U64 => "u64",
+                                    _ => {
+                                        return Err(UnsupportedError::Other(format!(
+                                            "unsupport repr({prim:?})"
+                                        )))?;
+                                    }
+                                };
+                                layout.tag_ty = Some(tag_ty);
+                            }
+                            Repr::Primitive(prim) => {
+                                return Err(UnsupportedError::Other(format!(
+                                    "unsupport repr({prim:?}) on a {ty_style}"
+                                )))?;
+                            }
+                            Repr::Transparent => {
+                                return Err(UnsupportedError::Other(
+                                    "unsupport repr transparent".to_owned(),
+                                ))?;
+                            }
+                            Repr::Lang(repr) => {
+                                if let Some(old_repr) = lang_repr {
+                                    return Err(UnsupportedError::Other(format!(
+                                        "multiple lang reprs on one type ({old_repr}, {repr})"
+                                    )))?;
+                                }
+                                lang_repr = Some(*repr);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if layout.packed && layout.align.is_some() {
+            return Err(UnsupportedError::Other(
+                "can't be both @packed and @align".to_owned(),
+            ))?;
+        }
+        if default_lang_repr && lang_repr.is_none() {
+            lang_repr = Some(state.options.repr);
+        }
+        match lang_repr {
+            Some(LangRepr::Rust) => Err(UnsupportedError::Other(
+                "zig doesn't support repr rust".to_owned(),
+            ))?,
+            Some(LangRepr::C) | None => Ok(layout),
+        }
+    }
+
+    pub fn generate_signature(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        func: FuncIdx,
+    ) -> Result<(), GenerateError> {
+        let function = state.types.realize_func(func);
+        let outputs = lower_outputs(&state.types, function)?;
+        let convention_decl = self.convention_decl(state.options.func_convention(function))?;
+        write!(f, "fn {}(", ident(&function.name))?;
+        let mut multiarg = false;
+        // Add inputs
+        for arg in &function.inputs {
+            if multiarg {
+                write!(f, ", ")?;
+            }
+            multiarg = true;
+            self.check_by_value(state, arg.ty)?;
+            let arg_name = ident(&arg.name);
+            let tyname = &state.tynames[&arg.ty];
+            write!(f, "{arg_name}: {tyname}")?;
+        }
+        // Add outparams
+        for output in outputs.iter().filter(|output| output.outparam) {
+            if multiarg {
+                write!(f, ", ")?;
+            }
+            multiarg = true;
+            let tyname = &state.tynames[&output.val_ty];
+            write!(f, "{}_out: *{tyname}", output.arg.name)?;
+        }
+        write!(f, ") callconv({convention_decl}) ")?;
+        if let Some(output) = outputs.iter().find(|output| !output.outparam) {
+            self.check_by_value(state, output.val_ty)?;
+            write!(f, "{}", state.tynames[&output.val_ty])?;
+        } else {
+            write!(f, "void")?;
+        }
+        Ok(())
+    }
+
+    /// C can't pass arrays by value (they decay to pointers), and zig refuses to pretend otherwise
+    fn check_by_value(&self, state: &TestState, ty: TyIdx) -> Result<(), GenerateError> {
+        match state.types.realize_ty(ty) {
+            Ty::Array(_) => Err(UnsupportedError::Other(
+                "zig can't pass arrays by value with a C calling convention".to_owned(),
+            ))?,
+            Ty::Alias(alias_ty) => self.check_by_value(state, alias_ty.real),
+            Ty::Pun(pun) => {
+                let real_ty = state.types.resolve_pun(pun, &state.env)?;
+                self.check_by_value(state, real_ty)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn convention_decl(
+        &self,
+        convention: CallingConvention,
+    ) -> Result<&'static str, GenerateError> {
+        use CallingConvention::*;
+        use Platform::*;
+
+        let val = match convention {
+            Aapcs => {
+                // Matching the C backend, this is covered by other ABIs
+                return Err(self.unsupported_convention(&convention))?;
+            }
+            System | SystemUnwind => {
+                // Matches rustc's definition: stdcall on 32-bit windows, otherwise C
+                if self.platform == Windows && self.is_x86() {
+                    ".{ .x86_stdcall = .{} }"
+                } else {
+                    ".c"
+                }
+            }
+            Win64 => {
+                if self.is_x86_64() {
+                    ".{ .x86_64_win = .{} }"
+                } else {
+                    return Err(self.unsupported_convention(&convention))?;
+                }
+            }
+            Sysv64 => {
+                if self.is_x86_64() {
+                    ".{ .x86_64_sysv = .{} }"
+                } else {
+                    return Err(self.unsupported_convention(&convention))?;
+                }
+            }
+            // Zig knows no Rust
+            Rust => {
+                return Err(self.unsupported_convention(&convention))?;
+            }
+            // Zig can't unwind, but it can still be the one *declaring* these
+            C | CUnwind => ".c",
+            Cdecl if self.platform == Windows && self.is_x86() => ".{ .x86_win = .{} }",
+            // These are all just C when they're ignored on 64-bit windows
+            Cdecl | Stdcall | Fastcall if self.platform == Windows && !self.is_x86() => ".c",
+            Stdcall if self.platform == Windows => ".{ .x86_stdcall = .{} }",
+            Fastcall if self.platform == Windows => ".{ .x86_fastcall = .{} }",
+            Vectorcall if self.platform == Windows && self.is_x86() => ".{ .x86_vectorcall = .{} }",
+            Vectorcall if self.platform == Windows && self.is_x86_64() => {
+                ".{ .x86_64_vectorcall = .{} }"
+            }
+            Cdecl | Stdcall | Fastcall | Vectorcall => {
+                return Err(self.unsupported_convention(&convention))?;
+            }
+        };
+
+        Ok(val)
+    }
+
+    fn unsupported_convention(&self, convention: &CallingConvention) -> UnsupportedError {
+        UnsupportedError::Other(format!("unsupported convention {convention}"))
+    }
+
+    fn is_x86_64(&self) -> bool {
+        self.target.starts_with("x86_64")
+    }
+
+    fn is_x86(&self) -> bool {
+        ["i386", "i586", "i686"]
+            .iter()
+            .any(|arch| self.target.starts_with(arch))
+    }
+}
@@ -0,0 +1,192 @@
+use super::*;
+use crate::harness::vals::{ArgValuesIter, Value};
+use kdl_script::types::{AliasTy, ArrayTy, PrimitiveTy, RefTy, Ty, TyIdx};
+use std::fmt::Write;
+
+impl ZigToolchain {
+    /// Initializers are all written with a known result type, so they can lean on
+    /// zig's result location inference (`.{ ... }`, `.variant`, `@bitCast(...)`).
+    pub fn init_leaf_value(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        ty: TyIdx,
+        val: &Value,
+    ) -> Result<(), GenerateError> {
+        match state.types.realize_ty(ty) {
+            // Primitives are the only "real" values with actual bytes that advance val_idx
+            Ty::Primitive(prim) => match prim {
+                PrimitiveTy::I8 => write!(f, "{}", val.generate_i8())?,
+                PrimitiveTy::I16 => write!(f, "{}", val.generate_i16())?,
+                PrimitiveTy::I32 => write!(f, "{}", val.generate_i32())?,
+                PrimitiveTy::I64 => write!(f, "{}", val.generate_i64())?,
+                PrimitiveTy::I128 => write!(f, "{}", val.generate_i128())?,
+                PrimitiveTy::U8 => write!(f, "{}", val.generate_u8())?,
+                PrimitiveTy::U16 => write!(f, "{}", val.generate_u16())?,
+                PrimitiveTy::U32 => write!(f, "{}", val.generate_u32())?,
+                PrimitiveTy::U64 => write!(f, "{}", val.generate_u64())?,
+                PrimitiveTy::U128 => write!(f, "{}", val.generate_u128())?,
+                // Floats are written as their exact bits, so every value is representable
+                PrimitiveTy::F16 => write!(f, "@bitCast(@as(u16, {:#X}))", val.generate_u16())?,
+                PrimitiveTy::F32 => write!(f, "@bitCast(@as(u32, {:#X}))", val.generate_u32())?,
+                PrimitiveTy::F64 => write!(f, "@bitCast(@as(u64, {:#X}))", val.generate_u64())?,
+                PrimitiveTy::F128 => write!(f, "@bitCast(@as(u128, {:#X}))", val.generate_u128())?,
+                PrimitiveTy::Bool => write!(f, "{}", val.generate_bool())?,
+                PrimitiveTy::Ptr => write!(
+                    f,
+                    "@ptrFromInt(@as(usize, @truncate(@as(u64, {:#X}))))",
+                    val.generate_u64()
+                )?,
+                PrimitiveTy::BitInt(_) | PrimitiveTy::UBitInt(_) => Err(UnsupportedError::Other(
+                    format!("zig's {prim} isn't guaranteed to have _BitInt's ABI"),
+                ))?,
+                PrimitiveTy::C32 | PrimitiveTy::C64 => Err(UnsupportedError::Other(
+                    "zig has no _Complex type".to_owned(),
+                ))?,
+            },
+            Ty::Enum(enum_ty) => {
+                if let Some(variant) = val.select_val(&enum_ty.variants) {
+                    write!(f, ".{}", ident(&variant.name))?;
+                }
+            }
+            _ => unreachable!("only primitives and enums should be passed to generate_leaf_value"),
+        }
+        Ok(())
+    }
+
+    pub fn init_value(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        ty: TyIdx,
+        vals: &mut ArgValuesIter,
+        ref_temp_name: &str,
+        extra_decls: &mut Vec<String>,
+    ) -> Result<(), GenerateError> {
+        match state.types.realize_ty(ty) {
+            // Primitives and Enums are the only "real" values with actual bytes
+            Ty::Primitive(_) | Ty::Enum(_) => {
+                let val = vals.next_val();
+                self.init_leaf_value(f, state, ty, &val)?;
+            }
+            Ty::Ref(RefTy { pointee_ty }) => {
+                // The value is a mutable reference to a temporary
+                write!(f, "&{ref_temp_name}")?;
+
+                // Now do the rest of the recursion on constructing the temporary
+                let mut ref_temp = String::new();
+                let mut ref_temp_f = Fivemat::new(&mut ref_temp, INDENT);
+                let pointee_tyname = &state.tynames[pointee_ty];
+                write!(&mut ref_temp_f, "var {ref_temp_name}: {pointee_tyname} = ")?;
+                let ref_temp_name = format!("{ref_temp_name}_");
+                self.init_value(
+                    &mut ref_temp_f,
+                    state,
+                    *pointee_ty,
+                    vals,
+                    &ref_temp_name,
+                    extra_decls,
+                )?;
+                write!(&mut ref_temp_f, ";")?;
+                extra_decls.push(ref_temp);
+            }
+            Ty::Array(ArrayTy { elem_ty, len }) => {
+                write!(f, ".{{ ")?;
+                for arr_idx in 0..*len {
+                    if arr_idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    let ref_temp_name = format!("{ref_temp_name}{arr_idx}_");
+                    self.init_value(f, state, *elem_ty, vals, &ref_temp_name, extra_decls)?;
+                }
+                write!(f, " }}")?;
+            }
+            // Nominal types we need to emit a decl for
+            Ty::Struct(struct_ty) => {
+                write!(f, ".{{ ")?;
+                for (field_idx, field) in struct_ty.fields.iter().enumerate() {
+                    if field_idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, ".{} = ", ident(&field.ident))?;
+                    let ref_temp_name = format!("{ref_temp_name}{}_", field.ident);
+                    self.init_value(f, state, field.ty, vals, &ref_temp_name, extra_decls)?;
+                }
+                write!(f, " }}")?;
+            }
+            Ty::Union(union_ty) => {
+                write!(f, ".{{ ")?;
+                let tag_val = vals.next_val();
+                if let Some(field) = tag_val.select_val(&union_ty.fields) {
+                    write!(f, ".{} = ", ident(&field.ident))?;
+                    let ref_temp_name = format!("{ref_temp_name}{}_", field.ident);
+                    self.init_value(f, state, field.ty, vals, &ref_temp_name, extra_decls)?;
+                }
+                write!(f, " }}")?;
+            }
+            Ty::Tagged(_) => {
+                return Err(UnsupportedError::Other(
+                    "zig has no C ABI for tagged unions".to_owned(),
+                ))?;
+            }
+            Ty::Alias(AliasTy { real, .. }) => {
+                self.init_value(f, state, *real, vals, ref_temp_name, extra_decls)?;
+            }
+
+            // Puns should be evaporated
+            Ty::Pun(pun) => {
+                let real_ty = state.types.resolve_pun(pun, &state.env)?;
+                self.init_value(f, state, real_ty, vals, ref_temp_name, extra_decls)?;
+            }
+
+            Ty::Slice(_) | Ty::NonZero(_) | Ty::Option(_) => {
+                return Err(UnsupportedError::Other(
+                    "zig support for slices and niche optimized types isn't implemented".to_owned(),
+                ))?
+            }
+            Ty::Empty => {
+                return Err(UnsupportedError::Other(
+                    "zig has no C ABI for empty tuples".to_owned(),
+                ))?
+            }
+        };
+
+        Ok(())
+    }
+
+    pub fn init_var(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        var_name: &str,
+        var_ty: TyIdx,
+        mut vals: ArgValuesIter,
+    ) -> Result<(), GenerateError> {
+        // Generate the input
+        let mut real_var_decl = String::new();
+        let mut real_var_decl_f = Fivemat::new(&mut real_var_decl, INDENT);
+        let mut extra_decls = Vec::new();
+        let tyname = &state.tynames[&var_ty];
+        write!(
+            &mut real_var_decl_f,
+            "const {}: {tyname} = ",
+            ident(var_name)
+        )?;
+        let ref_temp_name = format!("{var_name}_");
+        self.init_value(
+            &mut real_var_decl_f,
+            state,
+            var_ty,
+            &mut vals,
+            &ref_temp_name,
+            &mut extra_decls,
+        )?;
+        writeln!(&mut real_var_decl, ";")?;
+
+        for decl in extra_decls {
+            writeln!(f, "{}", decl)?;
+        }
+        writeln!(f, "{}", real_var_decl)?;
+        Ok(())
+    }
+}
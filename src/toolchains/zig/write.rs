@@ -0,0 +1,372 @@
+use super::*;
+use kdl_script::types::{Ty, TyIdx};
+use std::fmt::Write;
+
+impl ZigToolchain {
+    /// Every test should start by loading in the harness' "header"
+    pub fn write_harness_prefix(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+    ) -> Result<(), GenerateError> {
+        // Always need std for the writers
+        writeln!(
+            f,
+            "{}",
+            crate::files::get_file("harness/zig/test_prefix.zig")
+        )?;
+        // Load test harness "headers" (if needed)
+        let prefix = match state.options.val_writer {
+            WriteImpl::HarnessCallback => "harness/zig/harness_prefix.zig",
+            WriteImpl::Print => "harness/zig/print_prefix.zig",
+            WriteImpl::Assert => "harness/zig/assert_prefix.zig",
+            WriteImpl::Noop => return Ok(()),
+        };
+        writeln!(f, "{}", crate::files::get_file(prefix))?;
+
+        writeln!(f)?;
+
+        Ok(())
+    }
+
+    /// Emit the WRITE calls and FINISHED_VAL for this value.
+    /// This will WRITE every leaf subfield of the type.
+    /// `to` is the BUFFER to use, `from` is the variable name of the value.
+    pub fn write_var(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        var_name: &str,
+        var_ty: TyIdx,
+        mut vals: ArgValuesIter,
+        to: &str,
+    ) -> Result<(), GenerateError> {
+        // If we're generating a minimized test, skip this
+        if !vals.should_write_arg(&state.options) {
+            return Ok(());
+        }
+        // If noop, don't bother doing anything
+        if let WriteImpl::Noop = state.options.val_writer {
+            return Ok(());
+        };
+        self.write_fields(f, state, to, &ident(var_name), var_ty, &mut vals)?;
+
+        Ok(())
+    }
+
+    /// Recursive subroutine of write_var, which builds up rvalue paths.
+    /// Actual WRITE calls are done by write_leaf_field.
+    pub fn write_fields(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        to: &str,
+        from: &str,
+        var_ty: TyIdx,
+        vals: &mut ArgValuesIter,
+    ) -> Result<(), GenerateError> {
+        match state.types.realize_ty(var_ty) {
+            Ty::Primitive(PrimitiveTy::Bool) => {
+                // bool is basically an enum with variants "false" (0) and "true" (1)
+                let tag_generator = vals.next_val();
+                let cond = tag_generator.generate_bool();
+                if tag_generator.should_write_val(&state.options) {
+                    writeln!(f, "if ({from}) {{")?;
+                    f.add_indent(1);
+                    if cond {
+                        self.write_tag_field(f, state, to, from, 1, &tag_generator)?;
+                    } else {
+                        self.write_error_tag_field(f, state, to, &tag_generator)?;
+                    }
+                    f.sub_indent(1);
+                    writeln!(f, "}} else {{")?;
+                    f.add_indent(1);
+                    if !cond {
+                        self.write_tag_field(f, state, to, from, 0, &tag_generator)?;
+                    } else {
+                        self.write_error_tag_field(f, state, to, &tag_generator)?;
+                    }
+                    f.sub_indent(1);
+                    writeln!(f, "}}")?;
+                }
+            }
+            Ty::Primitive(_) => {
+                // Hey an actual leaf, report it (and burn a value)
+                let val = vals.next_val();
+                if val.should_write_val(&state.options) {
+                    self.write_leaf_field(f, state, to, from, &val)?;
+                }
+            }
+            Ty::Enum(enum_ty) => {
+                // enums are leaves but we care about their semantic value (variant case)
+                // so don't just pass the raw bytes, treat this like a tag we match on
+                let tag_generator = vals.next_val();
+                let tag_idx = tag_generator.generate_idx(enum_ty.variants.len());
+                if let Some(variant) = enum_ty.variants.get(tag_idx) {
+                    let enum_name = ident(&enum_ty.name);
+                    let variant_name = ident(&variant.name);
+                    if tag_generator.should_write_val(&state.options) {
+                        writeln!(f, "if ({from} == {enum_name}.{variant_name}) {{")?;
+                        f.add_indent(1);
+                        self.write_enum_tag_field(f, state, to, from, tag_idx, &tag_generator)?;
+                        f.sub_indent(1);
+                        writeln!(f, "}} else {{")?;
+                        f.add_indent(1);
+                        self.write_error_tag_field(f, state, to, &tag_generator)?;
+                        f.sub_indent(1);
+                        writeln!(f, "}}")?;
+                    }
+                }
+            }
+            Ty::Slice(_) | Ty::NonZero(_) | Ty::Option(_) => {
+                return Err(UnsupportedError::Other(
+                    "zig support for slices and niche optimized types isn't implemented".to_owned(),
+                ))?
+            }
+            Ty::Empty => {
+                // nothing worth producing
+            }
+            Ty::Alias(alias_ty) => {
+                // keep going but with the type changed
+                self.write_fields(f, state, to, from, alias_ty.real, vals)?;
+            }
+            Ty::Pun(pun) => {
+                // keep going but with the type changed
+                let real_ty = state.types.resolve_pun(pun, &state.env)?;
+                self.write_fields(f, state, to, from, real_ty, vals)?
+            }
+            Ty::Array(array_ty) => {
+                // recurse into each array index
+                for i in 0..array_ty.len {
+                    let base = format!("{from}[{i}]");
+                    self.write_fields(f, state, to, &base, array_ty.elem_ty, vals)?;
+                }
+            }
+            Ty::Struct(struct_ty) => {
+                // recurse into each field
+                for field in &struct_ty.fields {
+                    let base = format!("{from}.{}", ident(&field.ident));
+                    self.write_fields(f, state, to, &base, field.ty, vals)?;
+                }
+            }
+            Ty::Tagged(_) => {
+                return Err(UnsupportedError::Other(
+                    "zig has no C ABI for tagged unions".to_owned(),
+                ))?;
+            }
+            Ty::Ref(ref_ty) => {
+                // Add a deref, and recurse into the pointee
+                let base = format!("{from}.*");
+                self.write_fields(f, state, to, &base, ref_ty.pointee_ty, vals)?
+            }
+            Ty::Union(union_ty) => {
+                // Process the implicit "tag" value
+                let tag_generator = vals.next_val();
+                let tag_idx = tag_generator.generate_idx(union_ty.fields.len());
+                if tag_generator.should_write_val(&state.options) {
+                    self.write_tag_field(f, state, to, from, tag_idx, &tag_generator)?;
+                }
+                if let Some(field) = union_ty.fields.get(tag_idx) {
+                    let base = format!("{from}.{}", ident(&field.ident));
+                    self.write_fields(f, state, to, &base, field.ty, vals)?;
+                }
+            }
+        };
+        Ok(())
+    }
+
+    /// WRITE an actual indivisible value (primitive or c-like enum)
+    pub fn write_leaf_field(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        to: &str,
+        path: &str,
+        val: &ValueRef,
+    ) -> Result<(), GenerateError> {
+        match state.options.val_writer {
+            WriteImpl::HarnessCallback => {
+                let val_idx = val.absolute_val_idx;
+                // Convenience for triggering test failures
+                let rvalue = if path.contains("abicafepoison") && to.contains(CALLEE_VALS) {
+                    "@as(u32, 0x12345678)"
+                } else {
+                    path
+                };
+                writeln!(f, "write_val({to}, {val_idx}, {rvalue});")?;
+            }
+            WriteImpl::Assert => {
+                let side = side_name(to);
+                let func_idx = val.func_idx;
+                let val_idx = val.absolute_val_idx;
+                let val_path = &val.path;
+                let tyname = &state.tynames[&val.ty];
+                writeln!(f, "{{")?;
+                f.add_indent(1);
+                write!(f, "const _expected: {tyname} = ")?;
+                self.init_leaf_value(f, state, val.ty, val)?;
+                writeln!(f, ";")?;
+                writeln!(
+                    f,
+                    "check_val(\"{side}\", {func_idx}, {val_idx}, \"{val_path}\", {path}, _expected);"
+                )?;
+                f.sub_indent(1);
+                writeln!(f, "}}")?;
+            }
+            WriteImpl::Print => {
+                let side = side_name(to);
+                let val_idx = val.absolute_val_idx;
+                let val_path = &val.path;
+                writeln!(
+                    f,
+                    "print_val(\"{side}\", {val_idx}, \"{val_path}\", {path});"
+                )?;
+            }
+            WriteImpl::Noop => {
+                // Noop, do nothing
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_tag_field(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        to: &str,
+        path: &str,
+        variant_idx: usize,
+        val: &ValueRef,
+    ) -> Result<(), GenerateError> {
+        match state.options.val_writer {
+            WriteImpl::HarnessCallback => {
+                // Convenience for triggering test failures
+                if path.contains("abicafepoison") && to.contains(CALLEE_VALS) {
+                    return self.write_error_tag_field(f, state, to, val);
+                }
+                let val_idx = val.absolute_val_idx;
+                writeln!(f, "write_val({to}, {val_idx}, @as(u32, {variant_idx}));")?;
+            }
+            WriteImpl::Assert => {
+                // Noop, being in this branch is the assertion
+            }
+            WriteImpl::Print => {
+                self.write_print_tag(f, to, val, variant_idx as u32)?;
+            }
+            WriteImpl::Noop => {
+                // Noop, do nothing
+            }
+        }
+        Ok(())
+    }
+
+    /// Like [`Self::write_tag_field`][] but also reports the raw bytes of the enum,
+    /// so that the check can catch the two sides disagreeing on its size/discriminants.
+    pub fn write_enum_tag_field(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        to: &str,
+        path: &str,
+        variant_idx: usize,
+        val: &ValueRef,
+    ) -> Result<(), GenerateError> {
+        match state.options.val_writer {
+            WriteImpl::HarnessCallback => {
+                // Convenience for triggering test failures
+                if path.contains("abicafepoison") && to.contains(CALLEE_VALS) {
+                    return self.write_error_tag_field(f, state, to, val);
+                }
+                let val_idx = val.absolute_val_idx;
+                writeln!(
+                    f,
+                    "write_enum_val({to}, {val_idx}, @as(u32, {variant_idx}), {path});"
+                )?;
+            }
+            _ => self.write_tag_field(f, state, to, path, variant_idx, val)?,
+        }
+        Ok(())
+    }
+
+    pub fn write_error_tag_field(
+        &self,
+        f: &mut Fivemat,
+        state: &TestState,
+        to: &str,
+        val: &ValueRef,
+    ) -> Result<(), GenerateError> {
+        match state.options.val_writer {
+            WriteImpl::HarnessCallback => {
+                let val_idx = val.absolute_val_idx;
+                writeln!(f, "write_val({to}, {val_idx}, @as(u32, {}));", u32::MAX)?;
+            }
+            WriteImpl::Assert => {
+                let side = side_name(to);
+                let func_idx = val.func_idx;
+                let val_idx = val.absolute_val_idx;
+                let val_path = &val.path;
+                writeln!(
+                    f,
+                    "assert_failed(\"{side}\", {func_idx}, {val_idx}, \"{val_path}\");"
+                )?;
+            }
+            WriteImpl::Print => {
+                self.write_print_tag(f, to, val, u32::MAX)?;
+            }
+            WriteImpl::Noop => {
+                // Noop, do nothing
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_set_function(
+        &self,
+        f: &mut dyn Write,
+        state: &TestState,
+        vals: &str,
+        idx: usize,
+    ) -> Result<(), GenerateError> {
+        match state.options.val_writer {
+            WriteImpl::HarnessCallback => {
+                writeln!(f, "set_func({vals}, {idx});")?;
+            }
+            WriteImpl::Print => {
+                let side = side_name(vals);
+                let func_name = &state.types.realize_func(idx).name;
+                writeln!(f, "set_func(\"{side}\", {idx}, \"{func_name}\");")?;
+            }
+            WriteImpl::Noop | WriteImpl::Assert => {
+                // Noop
+            }
+        }
+        Ok(())
+    }
+
+    /// Print the variant index of a tag
+    fn write_print_tag(
+        &self,
+        f: &mut Fivemat,
+        to: &str,
+        val: &ValueRef,
+        variant_idx: u32,
+    ) -> Result<(), GenerateError> {
+        let side = side_name(to);
+        let val_idx = val.absolute_val_idx;
+        let val_path = &val.path;
+        writeln!(
+            f,
+            "print_val(\"{side}\", {val_idx}, \"{val_path}\", @as(u32, {variant_idx}));"
+        )?;
+        Ok(())
+    }
+}
+
+/// The name the print/assert writers use for the side a values buffer belongs to
+fn side_name(vals: &str) -> &'static str {
+    if vals == CALLER_VALS {
+        "caller"
+    } else {
+        "callee"
+    }
+}